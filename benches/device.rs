@@ -0,0 +1,53 @@
+//! Benchmarks against a real device. These require elevated privileges, so they are skipped
+//! unless `TUNIO_BENCH_DEVICE` environment variable is set to the name of interface to create.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use etherparse::PacketBuilder;
+use std::env;
+use std::io::Write;
+use tunio::traits::{DriverT, InterfaceT};
+use tunio::{DefaultDriver, DefaultInterface};
+
+const PAYLOAD_SIZES: [usize; 3] = [0, 512, 1400];
+
+fn udp_packet(payload_size: usize) -> Vec<u8> {
+    // Destination is in TEST-NET-1, so the kernel drops these packets right after routing
+    let builder = PacketBuilder::ipv4([192, 0, 2, 1], [192, 0, 2, 2], 64).udp(8080, 8080);
+
+    let payload = vec![0u8; payload_size];
+    let mut packet = Vec::with_capacity(builder.size(payload.len()));
+    builder.write(&mut packet, &payload).unwrap();
+    packet
+}
+
+fn device_write(c: &mut Criterion) {
+    let name = match env::var("TUNIO_BENCH_DEVICE") {
+        Ok(name) => name,
+        Err(_) => {
+            eprintln!("TUNIO_BENCH_DEVICE is not set, skipping device benchmarks");
+            return;
+        }
+    };
+
+    let mut driver = DefaultDriver::new().unwrap();
+    let if_config = DefaultInterface::config_builder()
+        .name(name)
+        .build()
+        .unwrap();
+    let mut interface = DefaultInterface::new_up(&mut driver, if_config).unwrap();
+
+    let mut group = c.benchmark_group("device/write");
+    for payload_size in PAYLOAD_SIZES {
+        let packet = udp_packet(payload_size);
+
+        group.throughput(Throughput::Bytes(packet.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(packet.len()),
+            &packet,
+            |bencher, packet| bencher.iter(|| interface.write(packet).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, device_write);
+criterion_main!(benches);
@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::executor::block_on;
+use futures::{AsyncReadExt, AsyncWriteExt};
+use std::io::{Read, Write};
+use tunio::platform::mock::{
+    AsyncInterface, Driver, Interface, MockInterface, PipeQueueT, PlatformIfConfig,
+};
+use tunio::traits::{DriverT, InterfaceT};
+use tunio::IfConfig;
+
+const PACKET_SIZES: [usize; 3] = [64, 512, 1500];
+
+fn pair<Q: PipeQueueT>() -> (MockInterface<Q>, MockInterface<Q>) {
+    let mut driver = Driver::new().unwrap();
+    let config = |name: &str| -> IfConfig<PlatformIfConfig> {
+        MockInterface::<Q>::config_builder()
+            .name(name.to_string())
+            .build()
+            .unwrap()
+    };
+
+    let (mut a, mut b) =
+        MockInterface::new_pair(&mut driver, config("mock0"), config("mock1")).unwrap();
+    a.up().unwrap();
+    b.up().unwrap();
+    (a, b)
+}
+
+fn sync_roundtrip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mock/sync");
+    for size in PACKET_SIZES {
+        let (mut a, mut b): (Interface, Interface) = pair();
+        let packet = vec![0u8; size];
+        let mut buf = vec![0u8; 4096];
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |bencher, _| {
+            bencher.iter(|| {
+                a.write_all(&packet).unwrap();
+                b.read(&mut buf).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn async_roundtrip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mock/async");
+    for size in PACKET_SIZES {
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair();
+        let packet = vec![0u8; size];
+        let mut buf = vec![0u8; 4096];
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |bencher, _| {
+            bencher.iter(|| {
+                block_on(async {
+                    a.write_all(&packet).await.unwrap();
+                    b.read(&mut buf).await.unwrap()
+                })
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, sync_roundtrip, async_roundtrip);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+//! Interface addresses together with their labels and flags, for backends, that configure
+//! more than a bare network.
+use netconfig::ipnet::IpNet;
+use std::time::Duration;
+
+/// Address of an interface, as added and listed by backends, that support flags.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Address {
+    pub net: IpNet,
+    /// Label of an IPv4 address, like `tun0:1` on Linux, that tells aliases of the same
+    /// interface apart. It must start with the interface name. Ignored for IPv6 addresses.
+    pub label: Option<String>,
+    pub flags: AddressFlags,
+    /// Time, after which the address is deprecated: it stays on the interface, but is not
+    /// chosen as a source for new connections. `None` keeps it preferred forever, or for the
+    /// valid lifetime, if that one is set.
+    pub preferred_lifetime: Option<Duration>,
+    /// Time, after which the address is removed. `None` keeps it forever.
+    pub valid_lifetime: Option<Duration>,
+}
+
+impl Address {
+    pub fn new(net: IpNet) -> Self {
+        Self {
+            net,
+            label: None,
+            flags: AddressFlags::default(),
+            preferred_lifetime: None,
+            valid_lifetime: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_flags(mut self, flags: AddressFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Sets both lifetimes, for example, of temporary addresses. Adding the address again
+    /// with new lifetimes refreshes it.
+    pub fn with_lifetimes(mut self, preferred: Duration, valid: Duration) -> Self {
+        self.preferred_lifetime = Some(preferred);
+        self.valid_lifetime = Some(valid);
+        self
+    }
+}
+
+impl From<IpNet> for Address {
+    fn from(net: IpNet) -> Self {
+        Self::new(net)
+    }
+}
+
+/// Flags of an address. Flags, that are only reported by the system, are ignored, when an
+/// address is added.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct AddressFlags {
+    /// No route to the prefix of the address is added, so that routes are managed by the
+    /// application.
+    pub no_prefix_route: bool,
+    /// Address is not the first one of its prefix on the interface. Reported only.
+    pub secondary: bool,
+    /// IPv6 address is used without duplicate address detection, so that it is usable
+    /// immediately. Tunnels usually have no other hosts on the link.
+    pub no_dad: bool,
+    /// IPv6 privacy address, as in RFC 8981, that is preferred as a source over public ones,
+    /// if the system is configured so.
+    pub temporary: bool,
+    /// Preferred lifetime of the address has expired. Reported only.
+    pub deprecated: bool,
+}
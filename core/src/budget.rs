@@ -0,0 +1,51 @@
+use std::task::{Context, Poll};
+
+/// Default number of packets, that an async queue returns in a row before yielding to the runtime.
+pub const DEFAULT_POLL_BUDGET: usize = 128;
+
+/// Limits the number of consecutive ready polls, so a busy queue does not starve other tasks,
+/// running on the same worker.
+#[derive(Debug, Clone)]
+pub struct PollBudget {
+    limit: Option<usize>,
+    used: usize,
+}
+
+impl PollBudget {
+    /// Creates new budget. `None` disables the limit.
+    pub fn new(limit: Option<usize>) -> Self {
+        Self { limit, used: 0 }
+    }
+
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+        self.used = 0;
+    }
+
+    /// Must be called before every attempt to take a packet from the queue. Returns `Pending`,
+    /// after waking the task, once the budget is exhausted.
+    pub fn poll_proceed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match self.limit {
+            Some(limit) if self.used >= limit => {
+                self.used = 0;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            _ => {
+                self.used += 1;
+                Poll::Ready(())
+            }
+        }
+    }
+
+    /// Must be called when the queue has no packets ready, so the budget is replenished.
+    pub fn reset(&mut self) {
+        self.used = 0;
+    }
+}
+
+impl Default for PollBudget {
+    fn default() -> Self {
+        Self::new(Some(DEFAULT_POLL_BUDGET))
+    }
+}
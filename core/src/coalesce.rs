@@ -0,0 +1,74 @@
+use futures_timer::Delay;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Read-side micro-batching settings.
+///
+/// When the previous batch of packets, read without waiting, had at least `min_batch` packets,
+/// the queue defers the next wakeup by up to `max_delay`, so more packets accumulate and are
+/// read with a single wakeup. Low-rate traffic, that does not reach `min_batch`, is never
+/// delayed.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ReadCoalescing {
+    pub max_delay: Duration,
+    pub min_batch: usize,
+}
+
+/// Coalescing state of a single async queue.
+#[derive(Default)]
+pub struct Coalescer {
+    config: Option<ReadCoalescing>,
+    batch: usize,
+    last_batch: usize,
+    delay: Option<Delay>,
+}
+
+impl Coalescer {
+    /// Creates new state. `None` disables coalescing.
+    pub fn new(config: Option<ReadCoalescing>) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_config(&mut self, config: Option<ReadCoalescing>) {
+        *self = Self::new(config);
+    }
+
+    /// Must be called when the queue is ready to be read. Returns `Pending` while the first
+    /// read of a batch is deferred, and the task is woken once the delay expires.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let config = match self.config {
+            Some(config) if self.batch == 0 && self.last_batch >= config.min_batch => config,
+            _ => return Poll::Ready(()),
+        };
+
+        let delay = self
+            .delay
+            .get_or_insert_with(|| Delay::new(config.max_delay));
+        match Pin::new(delay).poll(cx) {
+            Poll::Ready(()) => {
+                self.delay = None;
+                self.last_batch = 0;
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Must be called after each packet, returned by the queue.
+    pub fn packet_read(&mut self) {
+        self.batch += 1;
+    }
+
+    /// Must be called when the queue has no packets ready, so the current batch is finished.
+    pub fn batch_finished(&mut self) {
+        if self.batch > 0 {
+            self.last_batch = self.batch;
+            self.batch = 0;
+        }
+    }
+}
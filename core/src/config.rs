@@ -1,22 +1,120 @@
+use crate::budget::DEFAULT_POLL_BUDGET;
+use crate::coalesce::ReadCoalescing;
+use crate::sampling::Sampling;
 use crate::traits::PlatformIfConfigT;
 use derive_builder::Builder;
+use std::fmt;
+pub use tunio_packet::Layer;
 
+/// Handling of an existing interface with the requested name on creation.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
-pub enum Layer {
-    /// TAP, Ethernet-like interface with L2 capabilities
-    L2,
-    /// TUN, point-to-point IP interface
+pub enum NameConflict {
+    /// Creation fails with [`Error::NameTaken`](crate::Error::NameTaken).
     #[default]
-    L3,
+    Fail,
+    /// Existing interface is attached to, if the platform can take it over: persistent
+    /// TUN/TAP devices on Linux and Wintun adapters on Windows.
+    Adopt,
+    /// Number is appended to the name, or the trailing number is incremented, until the name
+    /// is free.
+    Suffix,
+}
+
+/// Handling of names, that are longer than the platform limit. See
+/// [`InterfaceT::max_name_len`](crate::traits::InterfaceT::max_name_len).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum NameOverflow {
+    /// Creation fails with [`Error::InterfaceNameTooLong`](crate::Error::InterfaceNameTooLong).
+    #[default]
+    Fail,
+    /// Name is truncated to the limit, and a warning is logged.
+    Truncate,
+}
+
+/// What happened to the requested interface name on creation.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NameOutcome {
+    /// New interface is created with the requested name.
+    Created,
+    /// Existing interface with the requested name is adopted.
+    Adopted,
+    /// New interface is created with a suffixed name.
+    Suffixed,
+}
+
+/// Setting, that the platform does not accept, as reported by [`IfConfig::validate`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Violation {
+    pub name: String,
+    pub value: String,
+    pub reason: String,
+}
+
+impl Violation {
+    pub fn new(name: &str, value: impl ToString, reason: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={} ({})", self.name, self.value, self.reason)
+    }
 }
 
 #[derive(Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct IfConfig<P: PlatformIfConfigT> {
     /// Interface name on Unix and interface alias on Windows.
     pub name: String,
     /// Interface type: TUN or TAP.
     #[builder(default = "Layer::default()")]
     pub layer: Layer,
+    /// What to do, if an interface with the requested name already exists.
+    #[builder(default = "NameConflict::default()")]
+    pub name_conflict: NameConflict,
+    /// What to do, if the name is longer than the platform allows.
+    #[builder(default = "NameOverflow::default()")]
+    pub name_overflow: NameOverflow,
+    /// Maximum number of packets, that an async queue reads in a row before yielding to the
+    /// runtime. `None` disables the limit.
+    #[builder(default = "Some(DEFAULT_POLL_BUDGET)")]
+    pub poll_budget: Option<usize>,
+    /// Read-side micro-batching for async queues. Disabled by default.
+    #[builder(default = "None")]
+    pub read_coalescing: Option<ReadCoalescing>,
+    /// Application-defined tag, that is stored with the interface in the system, so that
+    /// devices of an application can be found with [`DeviceInfo::tag`](crate::device::DeviceInfo::tag)
+    /// after a restart. Stored as an alternative name on Linux and as a registry value on
+    /// Windows.
+    #[builder(default = "None")]
+    pub tag: Option<String>,
+    /// Disables IPv4 on the interface, for IPv6-only tunnels. IPv4 binding of the adapter is
+    /// removed on Windows. On Linux, IPv4 addresses of the interface are removed, and IPv4
+    /// packets, including ARP on TAP interfaces, are dropped in both directions, as they are
+    /// on mock interfaces. Not supported by utun interfaces.
+    #[builder(default = "false")]
+    pub ipv6_only: bool,
+    /// Collects properties of the device, that take extra system calls, like the driver
+    /// version, for [`Diagnostics`](crate::diagnostics::Diagnostics) of the interface.
+    #[builder(default = "false")]
+    pub detailed_diagnostics: bool,
+    /// Takes an advisory lock of the name, before the interface is created, so that another
+    /// instance of the application, that creates an interface of the same name, fails fast
+    /// with [`Error::InterfaceLocked`](crate::Error::InterfaceLocked), instead of adopting
+    /// it. Lock is a file under the runtime directory on Linux and a named mutex on Windows.
+    /// With [`NameConflict::Suffix`], the suffixed name is locked too, once it is resolved.
+    /// Not supported by utun interfaces.
+    #[builder(default = "false")]
+    pub instance_lock: bool,
+    /// Which data path anomalies, like truncated reads and packets, dropped because the ring
+    /// is full, are logged.
+    #[builder(default)]
+    pub anomaly_sampling: Sampling,
 
     #[allow(dead_code)]
     #[builder(setter(custom))]
@@ -24,7 +122,63 @@ pub struct IfConfig<P: PlatformIfConfigT> {
     pub platform: P,
 }
 
+impl<P: PlatformIfConfigT> IfConfig<P> {
+    /// Checks settings against the limits of the platform, without any system calls, and
+    /// returns all violations. Builders fail with the same violations.
+    pub fn validate(&self) -> Vec<Violation> {
+        violations(&self.name, self.name_overflow, self.layer, &self.platform)
+    }
+}
+
+fn violations<P: PlatformIfConfigT>(
+    name: &str,
+    overflow: NameOverflow,
+    layer: Layer,
+    platform: &P,
+) -> Vec<Violation> {
+    let mut violations = vec![];
+    if P::name_len(name) > P::MAX_NAME_LEN && overflow == NameOverflow::Fail {
+        let reason = format!("exceeds the platform limit of {}", P::MAX_NAME_LEN);
+        violations.push(Violation::new("name", name, reason));
+    }
+    if let Some(reason) = P::check_name(name) {
+        violations.push(Violation::new("name", name, reason));
+    }
+    if !P::LAYERS.contains(&layer) {
+        violations.push(Violation::new(
+            "layer",
+            format!("{layer:?}"),
+            "is not supported by the platform",
+        ));
+    }
+    platform.validate(&mut violations);
+    violations
+}
+
 impl<P: PlatformIfConfigT> IfConfigBuilder<P> {
+    fn validate(&self) -> Result<(), String> {
+        // Missing name is reported by the builder itself
+        let name = match &self.name {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        let platform = self.platform.clone().unwrap_or_default();
+        let violations = violations(
+            name,
+            self.name_overflow.unwrap_or_default(),
+            self.layer.unwrap_or_default(),
+            &platform,
+        );
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(violations
+                .iter()
+                .map(Violation::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")),
+        }
+    }
+
     /// Platform-specific settings
     pub fn platform<F, E>(&mut self, f: F) -> Result<&mut Self, E>
     where
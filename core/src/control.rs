@@ -0,0 +1,195 @@
+//! Out-of-band control packets, like keepalives and link probes, that bypass the egress
+//! scheduler and the rate limiter of an interface with strict priority.
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::collections::VecDeque;
+use std::io;
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Default limit of control packets, that wait to be sent.
+pub const DEFAULT_CONTROL_CAPACITY: usize = 16;
+
+struct Inner {
+    packets: VecDeque<Vec<u8>>,
+    capacity: usize,
+    waker: Option<Waker>,
+}
+
+fn lock(inner: &Mutex<Inner>) -> MutexGuard<'_, Inner> {
+    inner.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Control packets of an interface. They are sent before any other packet on each read, write
+/// and flush of the interface, so control packets of an idle interface are sent, while it is
+/// read.
+pub struct ControlChannel {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for ControlChannel {
+    fn default() -> Self {
+        Self::new(DEFAULT_CONTROL_CAPACITY)
+    }
+}
+
+impl ControlChannel {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                packets: VecDeque::new(),
+                capacity: capacity.max(1),
+                waker: None,
+            })),
+        }
+    }
+
+    pub fn sender(&self) -> ControlSender {
+        ControlSender {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Sends queued packets with `send`, in the order, they were queued. A task, that polls
+    /// this, is woken, when more packets are queued.
+    pub fn poll_send_all<F>(&self, cx: &mut Context<'_>, mut send: F) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<()>>,
+    {
+        loop {
+            // Lock is not held while sending, so that senders are not blocked by the device
+            let packet = {
+                let mut inner = lock(&self.inner);
+                match inner.packets.pop_front() {
+                    Some(packet) => packet,
+                    None => {
+                        inner.waker = Some(cx.waker().clone());
+                        return Poll::Ready(Ok(()));
+                    }
+                }
+            };
+            match send(cx, &packet) {
+                Poll::Ready(result) => result?,
+                Poll::Pending => {
+                    lock(&self.inner).packets.push_front(packet);
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    /// Sends queued packets with blocking `send`, for sync queues.
+    pub fn send_all(&self, mut send: impl FnMut(&[u8]) -> io::Result<()>) -> io::Result<()> {
+        loop {
+            let packet = lock(&self.inner).packets.pop_front();
+            match packet {
+                Some(packet) => send(&packet)?,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Cloneable handle, that queues control packets of an interface, from any task or thread.
+#[derive(Clone)]
+pub struct ControlSender {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl ControlSender {
+    /// Queues a packet and wakes the task, that last used the interface, to send it. Fails
+    /// with [`WouldBlock`](io::ErrorKind::WouldBlock), if the channel is full.
+    pub fn send(&self, packet: impl Into<Vec<u8>>) -> io::Result<()> {
+        let waker = {
+            let mut inner = lock(&self.inner);
+            if inner.packets.len() >= inner.capacity {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            inner.packets.push_back(packet.into());
+            inner.waker.take()
+        };
+        waker.into_iter().for_each(Waker::wake);
+        Ok(())
+    }
+
+    /// Number of packets, that are queued and not sent yet.
+    pub fn pending(&self) -> usize {
+        lock(&self.inner).packets.len()
+    }
+}
+
+/// Thread, that sends a control packet at an interval, until it is dropped. Ticks are skipped,
+/// while the channel is full, so keepalives of a stalled interface do not pile up.
+pub struct Keepalive {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Keepalive {
+    pub fn spawn(sender: ControlSender, packet: Vec<u8>, interval: Duration) -> io::Result<Self> {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("tunio-keepalive".to_string())
+                .spawn(move || {
+                    let (stopped, wakeup) = &*stop;
+                    let mut stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+                    while !*stopped {
+                        let _ = sender.send(packet.clone());
+                        stopped = wakeup
+                            .wait_timeout(stopped, interval)
+                            .map_or_else(|e| e.into_inner().0, |(stopped, _)| stopped);
+                    }
+                })?
+        };
+        Ok(Self {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Keepalive {
+    fn drop(&mut self) {
+        let (stopped, wakeup) = &*self.stop;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wakeup.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    #[test]
+    fn packets_wait_for_the_device() {
+        let channel = ControlChannel::new(2);
+        let sender = channel.sender();
+        sender.send(vec![1]).unwrap();
+        sender.send(vec![2]).unwrap();
+        assert_eq!(
+            sender.send(vec![3]).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(channel
+            .poll_send_all(&mut cx, |_, _| Poll::Pending)
+            .is_pending());
+        assert_eq!(sender.pending(), 2);
+        let mut sent = vec![];
+        assert!(channel
+            .poll_send_all(&mut cx, |_, packet| {
+                sent.push(packet.to_vec());
+                Poll::Ready(Ok(()))
+            })
+            .is_ready());
+        assert_eq!(sent, [vec![1], vec![2]]);
+    }
+}
@@ -0,0 +1,40 @@
+//! Existing devices of a driver, as listed by [`DriverT::enumerate`](crate::traits::DriverT::enumerate).
+use crate::config::Layer;
+
+/// Device, that exists in the system, whether it is created by this process or not.
+///
+/// Fields, that a platform does not report, are `None`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeviceInfo {
+    pub name: String,
+    /// OS interface index.
+    pub index: u32,
+    pub layer: Layer,
+    /// Driver of the device, like `tun`, `wintun` or `utun`.
+    pub driver: &'static str,
+    /// User, that may attach to the device without privileges.
+    pub owner: Option<u32>,
+    /// Group, that may attach to the device without privileges.
+    pub group: Option<u32>,
+    /// Device stays, when no descriptors are attached to it, and may be adopted with
+    /// [`NameConflict::Adopt`](crate::config::NameConflict::Adopt).
+    pub persistent: Option<bool>,
+    /// Tag, that the device was created with, see [`IfConfig::tag`](crate::config::IfConfig::tag).
+    pub tag: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Creates a description with platform-specific fields unknown.
+    pub fn new(name: String, index: u32, layer: Layer, driver: &'static str) -> Self {
+        Self {
+            name,
+            index,
+            layer,
+            driver,
+            owner: None,
+            group: None,
+            persistent: None,
+            tag: None,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Reports of what backends did while creating interfaces, to be attached to bug reports.
+use std::fmt;
+
+/// Single action of a backend, like a system call, and its result.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Step {
+    pub action: String,
+    pub result: String,
+}
+
+/// Report, returned by `diagnostics()` of interfaces.
+///
+/// Steps of the creation are always recorded. Properties, that take extra system calls to
+/// collect, like driver versions, are only present with
+/// [`IfConfig::detailed_diagnostics`](crate::config::IfConfig::detailed_diagnostics).
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Diagnostics {
+    pub backend: String,
+    /// Steps in the order, they were made.
+    pub steps: Vec<Step>,
+    pub properties: Vec<(String, String)>,
+}
+
+impl Diagnostics {
+    pub fn new(backend: &str) -> Self {
+        Self {
+            backend: backend.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn step(&mut self, action: impl Into<String>, result: impl fmt::Display) {
+        self.steps.push(Step {
+            action: action.into(),
+            result: result.to_string(),
+        });
+    }
+
+    pub fn property(&mut self, name: impl Into<String>, value: impl fmt::Display) {
+        self.properties.push((name.into(), value.to_string()));
+    }
+
+    /// Value of the first property, named `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(property, _)| property == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Plain text, one step or property per line.
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "backend: {}", self.backend)?;
+        for step in &self.steps {
+            writeln!(f, "{}: {}", step.action, step.result)?;
+        }
+        for (name, value) in &self.properties {
+            writeln!(f, "{name} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_steps_and_properties() {
+        let mut diagnostics = Diagnostics::new("linux");
+        diagnostics.step("open /dev/net/tun", "blocking");
+        diagnostics.property("kernel", "6.1.0");
+        assert_eq!(diagnostics.get("kernel"), Some("6.1.0"));
+        assert_eq!(
+            diagnostics.to_string(),
+            "backend: linux\nopen /dev/net/tun: blocking\nkernel = 6.1.0\n"
+        );
+    }
+}
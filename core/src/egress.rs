@@ -0,0 +1,141 @@
+use crate::config::Layer;
+use crate::packet::traffic_class;
+use std::collections::VecDeque;
+use std::io;
+use std::task::{ready, Context, Poll};
+
+/// DSCP Expedited Forwarding, see RFC 3246.
+const DSCP_EF: u8 = 46;
+
+/// Default limit of packets, held by [`EgressScheduler`].
+pub const DEFAULT_BACKLOG: usize = 256;
+
+/// Selects packets, that bypass bulk traffic, while the interface is congested.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum EgressPriority {
+    /// Packets are written in order. Scheduler is disabled.
+    #[default]
+    None,
+    /// Packets of up to `max_len` bytes are prioritized.
+    SmallPackets { max_len: usize },
+    /// IP packets, marked with DSCP EF, are prioritized.
+    Expedited,
+    /// Both small packets and packets, marked with DSCP EF, are prioritized.
+    SmallOrExpedited { max_len: usize },
+}
+
+impl EgressPriority {
+    pub fn is_priority(&self, packet: &[u8], layer: Layer) -> bool {
+        let expedited = || traffic_class(packet, layer).map_or(false, |tc| tc >> 2 == DSCP_EF);
+        match *self {
+            EgressPriority::None => false,
+            EgressPriority::SmallPackets { max_len } => packet.len() <= max_len,
+            EgressPriority::Expedited => expedited(),
+            EgressPriority::SmallOrExpedited { max_len } => packet.len() <= max_len || expedited(),
+        }
+    }
+}
+
+/// Two-level egress queue of an async interface.
+///
+/// While the interface accepts packets, they are written directly. Once a write is pending,
+/// packets are accepted into a backlog of up to `backlog` packets, and priority packets are sent
+/// ahead of bulk ones, as the interface drains. Backlog is sent by subsequent writes and flushes,
+/// so callers must flush the interface to deliver the last packets.
+pub struct EgressScheduler {
+    policy: EgressPriority,
+    layer: Layer,
+    backlog: usize,
+    priority: VecDeque<Vec<u8>>,
+    bulk: VecDeque<Vec<u8>>,
+}
+
+impl Default for EgressScheduler {
+    fn default() -> Self {
+        Self::new(Layer::default())
+    }
+}
+
+impl EgressScheduler {
+    pub fn new(layer: Layer) -> Self {
+        Self {
+            policy: EgressPriority::None,
+            layer,
+            backlog: DEFAULT_BACKLOG,
+            priority: VecDeque::new(),
+            bulk: VecDeque::new(),
+        }
+    }
+
+    /// Changes the policy. Packets, that are already queued, keep their order.
+    pub fn set_policy(&mut self, policy: EgressPriority, backlog: usize) {
+        self.policy = policy;
+        self.backlog = backlog.max(1);
+    }
+
+    pub fn policy(&self) -> EgressPriority {
+        self.policy
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.policy != EgressPriority::None || !self.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.priority.is_empty() && self.bulk.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.priority.len() + self.bulk.len()
+    }
+
+    /// Writes `buf` with `send`, or queues it, if the backlog is not empty or `send` is pending.
+    /// Returns `Pending`, when the backlog is full.
+    pub fn poll_write<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+        mut send: F,
+    ) -> Poll<io::Result<usize>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        match self.poll_drain(cx, &mut send) {
+            Poll::Ready(Ok(())) => match send(cx, buf) {
+                Poll::Pending => {}
+                result => return result,
+            },
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending if self.len() >= self.backlog => return Poll::Pending,
+            Poll::Pending => {}
+        }
+
+        match self.policy.is_priority(buf, self.layer) {
+            true => self.priority.push_back(buf.to_vec()),
+            false => self.bulk.push_back(buf.to_vec()),
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Sends queued packets, priority ones first. An error is returned for a packet, that could
+    /// not be sent, and the packet is dropped.
+    pub fn poll_drain<F>(&mut self, cx: &mut Context<'_>, mut send: F) -> Poll<io::Result<()>>
+    where
+        F: FnMut(&mut Context<'_>, &[u8]) -> Poll<io::Result<usize>>,
+    {
+        loop {
+            let queue = match self.priority.is_empty() {
+                false => &mut self.priority,
+                true => &mut self.bulk,
+            };
+            let packet = match queue.front() {
+                Some(packet) => packet,
+                None => return Poll::Ready(Ok(())),
+            };
+
+            let result = ready!(send(cx, packet));
+            queue.pop_front();
+            result?;
+        }
+    }
+}
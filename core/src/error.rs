@@ -6,7 +6,40 @@ use thiserror::Error as ThisError;
 #[derive(Debug, ThisError)]
 pub enum Error {
     #[error("I/O error: {0}")]
-    Io(io::Error),
+    Io(#[source] io::Error),
+    /// System call or driver function failed. `call` names it with its main arguments, like
+    /// `TUNSETIFF(tun0, L3)`. Windows calls fail with [`Error::Win32`] instead.
+    #[error("{call} failed: {source}")]
+    Call {
+        call: String,
+        #[source]
+        source: io::Error,
+    },
+    /// Windows function failed. `message` is the text of `code`, in the language of the user,
+    /// as the system formatted it, when the error happened.
+    #[error("{call} failed: {message} (code {code:#x})")]
+    Win32 {
+        call: String,
+        code: u32,
+        message: String,
+        #[source]
+        source: io::Error,
+    },
+    /// Change of a [`Transaction`](crate::transaction::Transaction) failed, and the changes,
+    /// applied before it, are rolled back. `rollback` lists the undo steps, that failed too,
+    /// so it is empty, if the interface is back to its previous state.
+    #[error("{change} failed: {source}{}", rollback_summary(.rollback))]
+    TransactionFailed {
+        change: String,
+        #[source]
+        source: Box<Error>,
+        rollback: Vec<String>,
+    },
+    /// Interface name is locked by another instance of the application, see
+    /// [`IfConfig::instance_lock`](crate::config::IfConfig::instance_lock). `owner` is the
+    /// process ID of the instance, if the platform tells it.
+    #[error("interface {name} is owned by another instance{}", owner_summary(.owner))]
+    InterfaceLocked { name: String, owner: Option<u32> },
     #[error("interface name is not valid Unicode")]
     InterfaceNameUnicodeError,
     #[error("interface name too long: {0} > {1}")]
@@ -15,10 +48,26 @@ pub enum Error {
     InterfaceNameInvalid,
     #[error("library not loaded: {reason}")]
     LibraryNotLoaded { reason: String },
+    #[error("library {path} is built for {found}, but the process runs on {expected}")]
+    LibraryArchMismatch {
+        path: String,
+        found: String,
+        expected: String,
+    },
+    #[error("administrator rights are required (driver install needed: {driver_install})")]
+    NeedsElevation { driver_install: bool },
+    #[error("missing capability {capability}: {hint}")]
+    MissingCapability { capability: String, hint: String },
+    #[error("device node {path} is missing: {hint}")]
+    DeviceNodeMissing { path: String, hint: String },
+    #[error("device node {path} is not accessible: {hint}")]
+    DeviceNodeInaccessible { path: String, hint: String },
     #[error("netconfig error: {0}")]
-    NetConfigError(netconfig::Error),
+    NetConfigError(#[source] netconfig::Error),
     #[error("interface name error: {0}")]
     InterfaceNameError(String),
+    #[error("interface name is already taken: {0}")]
+    NameTaken(String),
     #[error("config value is invalid ({reason}): {name}={value}")]
     InvalidConfigValue {
         name: String,
@@ -27,6 +76,65 @@ pub enum Error {
     },
     #[error("layer is unsupported: {0:?}")]
     LayerUnsupported(Layer),
+    #[error("privileged helper failed: {0}")]
+    HelperFailed(String),
+    #[error("backend is not registered: {0}")]
+    BackendNotFound(String),
+    #[error(
+        "backend {backend} is written against backend API version {found}, expected {expected}"
+    )]
+    BackendVersionMismatch {
+        backend: String,
+        found: u32,
+        expected: u32,
+    },
+}
+
+impl Error {
+    pub fn call(call: impl Into<String>, source: impl Into<io::Error>) -> Self {
+        Error::Call {
+            call: call.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Underlying OS error, if this error is caused by one.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Error::Io(err) | Error::Call { source: err, .. } | Error::Win32 { source: err, .. } => {
+                Some(err)
+            }
+            _ => None,
+        }
+    }
+
+    /// OS error code, like `errno` or a Win32 error code, if this error is caused by one.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.io_error().and_then(io::Error::raw_os_error)
+    }
+}
+
+fn owner_summary(owner: &Option<u32>) -> String {
+    owner.map_or_else(String::new, |pid| format!(" (PID {pid})"))
+}
+
+fn rollback_summary(rollback: &[String]) -> String {
+    match rollback.is_empty() {
+        true => String::new(),
+        false => format!(", rollback failed: {}", rollback.join(", ")),
+    }
+}
+
+/// Attaches the failed call to errors, that convert into I/O errors, like `nix` and `windows`
+/// errors.
+pub trait ResultExt<T> {
+    fn context<C: Into<String>>(self, call: impl FnOnce() -> C) -> Result<T, Error>;
+}
+
+impl<T, E: Into<io::Error>> ResultExt<T> for Result<T, E> {
+    fn context<C: Into<String>>(self, call: impl FnOnce() -> C) -> Result<T, Error> {
+        self.map_err(|err| Error::call(call(), err))
+    }
 }
 
 impl From<io::Error> for Error {
@@ -40,3 +148,277 @@ impl From<netconfig::Error> for Error {
         Error::NetConfigError(err)
     }
 }
+
+/// [`Error`] in a form, that is `Send`, so that calls on other threads, like the ones of
+/// [`Offload`](crate::offload::Offload), return it. Causes of [`netconfig::Error::Unknown`] are
+/// boxed without `Send`, so only their message is kept.
+#[derive(Debug)]
+pub(crate) struct SendError(Repr);
+
+#[derive(Debug)]
+enum Repr {
+    Io(io::Error),
+    Call {
+        call: String,
+        source: io::Error,
+    },
+    Win32 {
+        call: String,
+        code: u32,
+        message: String,
+        source: io::Error,
+    },
+    TransactionFailed {
+        change: String,
+        source: Box<SendError>,
+        rollback: Vec<String>,
+    },
+    InterfaceLocked {
+        name: String,
+        owner: Option<u32>,
+    },
+    InterfaceNameUnicodeError,
+    InterfaceNameTooLong(usize, usize),
+    InterfaceNameInvalid,
+    LibraryNotLoaded {
+        reason: String,
+    },
+    LibraryArchMismatch {
+        path: String,
+        found: String,
+        expected: String,
+    },
+    NeedsElevation {
+        driver_install: bool,
+    },
+    MissingCapability {
+        capability: String,
+        hint: String,
+    },
+    DeviceNodeMissing {
+        path: String,
+        hint: String,
+    },
+    DeviceNodeInaccessible {
+        path: String,
+        hint: String,
+    },
+    NetConfigError(NetConfigRepr),
+    InterfaceNameError(String),
+    NameTaken(String),
+    InvalidConfigValue {
+        name: String,
+        value: String,
+        reason: String,
+    },
+    LayerUnsupported(Layer),
+    HelperFailed(String),
+    BackendNotFound(String),
+    BackendVersionMismatch {
+        backend: String,
+        found: u32,
+        expected: u32,
+    },
+}
+
+#[derive(Debug)]
+enum NetConfigRepr {
+    InvalidParameter,
+    UnexpectedMetadata,
+    InterfaceNotFound,
+    Unknown(String),
+    Io(io::Error),
+}
+
+impl From<Error> for SendError {
+    fn from(err: Error) -> Self {
+        SendError(match err {
+            Error::Io(err) => Repr::Io(err),
+            Error::Call { call, source } => Repr::Call { call, source },
+            Error::Win32 {
+                call,
+                code,
+                message,
+                source,
+            } => Repr::Win32 {
+                call,
+                code,
+                message,
+                source,
+            },
+            Error::TransactionFailed {
+                change,
+                source,
+                rollback,
+            } => Repr::TransactionFailed {
+                change,
+                source: Box::new((*source).into()),
+                rollback,
+            },
+            Error::InterfaceLocked { name, owner } => Repr::InterfaceLocked { name, owner },
+            Error::InterfaceNameUnicodeError => Repr::InterfaceNameUnicodeError,
+            Error::InterfaceNameTooLong(len, max) => Repr::InterfaceNameTooLong(len, max),
+            Error::InterfaceNameInvalid => Repr::InterfaceNameInvalid,
+            Error::LibraryNotLoaded { reason } => Repr::LibraryNotLoaded { reason },
+            Error::LibraryArchMismatch {
+                path,
+                found,
+                expected,
+            } => Repr::LibraryArchMismatch {
+                path,
+                found,
+                expected,
+            },
+            Error::NeedsElevation { driver_install } => Repr::NeedsElevation { driver_install },
+            Error::MissingCapability { capability, hint } => {
+                Repr::MissingCapability { capability, hint }
+            }
+            Error::DeviceNodeMissing { path, hint } => Repr::DeviceNodeMissing { path, hint },
+            Error::DeviceNodeInaccessible { path, hint } => {
+                Repr::DeviceNodeInaccessible { path, hint }
+            }
+            Error::NetConfigError(err) => Repr::NetConfigError(match err {
+                netconfig::Error::InvalidParameter => NetConfigRepr::InvalidParameter,
+                netconfig::Error::UnexpectedMetadata => NetConfigRepr::UnexpectedMetadata,
+                netconfig::Error::InterfaceNotFound => NetConfigRepr::InterfaceNotFound,
+                netconfig::Error::Io(err) => NetConfigRepr::Io(err),
+                err => NetConfigRepr::Unknown(match err {
+                    netconfig::Error::Unknown(cause) => cause.to_string(),
+                    err => err.to_string(),
+                }),
+            }),
+            Error::InterfaceNameError(reason) => Repr::InterfaceNameError(reason),
+            Error::NameTaken(name) => Repr::NameTaken(name),
+            Error::InvalidConfigValue {
+                name,
+                value,
+                reason,
+            } => Repr::InvalidConfigValue {
+                name,
+                value,
+                reason,
+            },
+            Error::LayerUnsupported(layer) => Repr::LayerUnsupported(layer),
+            Error::HelperFailed(reason) => Repr::HelperFailed(reason),
+            Error::BackendNotFound(name) => Repr::BackendNotFound(name),
+            Error::BackendVersionMismatch {
+                backend,
+                found,
+                expected,
+            } => Repr::BackendVersionMismatch {
+                backend,
+                found,
+                expected,
+            },
+        })
+    }
+}
+
+impl From<SendError> for Error {
+    fn from(SendError(repr): SendError) -> Self {
+        match repr {
+            Repr::Io(err) => Error::Io(err),
+            Repr::Call { call, source } => Error::Call { call, source },
+            Repr::Win32 {
+                call,
+                code,
+                message,
+                source,
+            } => Error::Win32 {
+                call,
+                code,
+                message,
+                source,
+            },
+            Repr::TransactionFailed {
+                change,
+                source,
+                rollback,
+            } => Error::TransactionFailed {
+                change,
+                source: Box::new((*source).into()),
+                rollback,
+            },
+            Repr::InterfaceLocked { name, owner } => Error::InterfaceLocked { name, owner },
+            Repr::InterfaceNameUnicodeError => Error::InterfaceNameUnicodeError,
+            Repr::InterfaceNameTooLong(len, max) => Error::InterfaceNameTooLong(len, max),
+            Repr::InterfaceNameInvalid => Error::InterfaceNameInvalid,
+            Repr::LibraryNotLoaded { reason } => Error::LibraryNotLoaded { reason },
+            Repr::LibraryArchMismatch {
+                path,
+                found,
+                expected,
+            } => Error::LibraryArchMismatch {
+                path,
+                found,
+                expected,
+            },
+            Repr::NeedsElevation { driver_install } => Error::NeedsElevation { driver_install },
+            Repr::MissingCapability { capability, hint } => {
+                Error::MissingCapability { capability, hint }
+            }
+            Repr::DeviceNodeMissing { path, hint } => Error::DeviceNodeMissing { path, hint },
+            Repr::DeviceNodeInaccessible { path, hint } => {
+                Error::DeviceNodeInaccessible { path, hint }
+            }
+            Repr::NetConfigError(err) => Error::NetConfigError(match err {
+                NetConfigRepr::InvalidParameter => netconfig::Error::InvalidParameter,
+                NetConfigRepr::UnexpectedMetadata => netconfig::Error::UnexpectedMetadata,
+                NetConfigRepr::InterfaceNotFound => netconfig::Error::InterfaceNotFound,
+                NetConfigRepr::Unknown(message) => netconfig::Error::Unknown(message.into()),
+                NetConfigRepr::Io(err) => netconfig::Error::Io(err),
+            }),
+            Repr::InterfaceNameError(reason) => Error::InterfaceNameError(reason),
+            Repr::NameTaken(name) => Error::NameTaken(name),
+            Repr::InvalidConfigValue {
+                name,
+                value,
+                reason,
+            } => Error::InvalidConfigValue {
+                name,
+                value,
+                reason,
+            },
+            Repr::LayerUnsupported(layer) => Error::LayerUnsupported(layer),
+            Repr::HelperFailed(reason) => Error::HelperFailed(reason),
+            Repr::BackendNotFound(name) => Error::BackendNotFound(name),
+            Repr::BackendVersionMismatch {
+                backend,
+                found,
+                expected,
+            } => Error::BackendVersionMismatch {
+                backend,
+                found,
+                expected,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn call_keeps_os_error_as_source() {
+        let result: io::Result<()> = Err(io::Error::from_raw_os_error(16));
+        let err = result.context(|| "TUNSETIFF(tun0)").unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(16));
+        assert!(err.to_string().starts_with("TUNSETIFF(tun0) failed: "));
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.raw_os_error(), Some(16));
+    }
+
+    #[test]
+    fn netconfig_error_keeps_variant_across_threads() {
+        let err = Error::NetConfigError(netconfig::Error::Unknown("EBUSY".into()));
+        let sent = SendError::from(err);
+        let sent = std::thread::spawn(move || sent).join().unwrap();
+        let err = Error::from(sent);
+        assert!(matches!(
+            &err,
+            Error::NetConfigError(netconfig::Error::Unknown(cause)) if cause.to_string() == "EBUSY"
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use futures::channel::mpsc;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Lifecycle event of an interface, emitted separately from the packet path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Name of the interface, this event belongs to.
+    pub interface: String,
+    pub kind: EventKind,
+}
+
+#[non_exhaustive]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// Interface is created.
+    Created,
+    /// Driver session is started, interface is ready for packet I/O.
+    SessionStarted,
+    /// Driver ring is recreated with a new capacity.
+    RingResized { capacity: u32 },
+    /// Interface is going down or being destroyed.
+    ShutdownRequested,
+    /// Internal reader task has exited, no more packets will be read from this session.
+    ReaderExited,
+    /// Packet could not be written to the interface.
+    WriteFailed(io::ErrorKind),
+    /// System is going to sleep.
+    Suspended,
+    /// System has resumed from sleep. Driver session is restarted before the next packet I/O.
+    Resumed,
+    /// Interface MTU is changed by the system or by the application.
+    MtuChanged { mtu: u32 },
+}
+
+pub type EventReceiver = mpsc::UnboundedReceiver<Event>;
+
+/// Broadcasts events to all subscribers. Cloned emitters share the same set of subscribers.
+#[derive(Clone, Default)]
+pub struct EventEmitter {
+    subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<Event>>>>,
+}
+
+impl EventEmitter {
+    pub fn subscribe(&self) -> EventReceiver {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(tx);
+        rx
+    }
+
+    pub fn emit(&self, interface: &str, kind: EventKind) {
+        let event = Event {
+            interface: interface.to_string(),
+            kind,
+        };
+
+        // Subscribers, that dropped their receivers, are forgotten here
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,371 @@
+//! Packet queue over a byte stream, for integration tests and stdio transports.
+use crate::traits::AsyncQueueT;
+use futures::{AsyncRead, AsyncWrite};
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+const LEN_PREFIX: usize = 2;
+/// Largest packet, whose length fits into the prefix.
+pub const MAX_FRAME_LEN: usize = u16::MAX as usize;
+
+/// Adapts any [`AsyncRead`] + [`AsyncWrite`] stream, like one end of a Unix socket pair, to
+/// [`AsyncQueueT`], so that code, written against queues, runs without a device.
+///
+/// Every packet is framed with its length as a big-endian `u16`. Like on devices, a single
+/// read returns a single packet, truncated to the buffer, and a single write sends a single
+/// packet. Empty packets are not sent, as a zero-length read means end of stream.
+///
+/// Both directions are cancel-safe: partially read frames are kept in the queue, and an
+/// accepted packet is sent before the next one, or on flush.
+pub struct FramedQueue<S> {
+    stream: S,
+    read_prefix: [u8; LEN_PREFIX],
+    read_prefix_len: usize,
+    read_frame: Vec<u8>,
+    read_frame_len: usize,
+    write_buf: Vec<u8>,
+    write_pos: usize,
+}
+
+impl<S> FramedQueue<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            read_prefix: [0; LEN_PREFIX],
+            read_prefix_len: 0,
+            read_frame: Vec::new(),
+            read_frame_len: 0,
+            write_buf: Vec::new(),
+            write_pos: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns the underlying stream. Reading or writing it directly breaks framing.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Consumes the queue, returning the stream. Partially read frames and packets, that are
+    /// not flushed yet, are lost.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: AsyncWrite + Unpin> FramedQueue<S> {
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.write_pos < self.write_buf.len() {
+            let n = ready!(
+                Pin::new(&mut self.stream).poll_write(cx, &self.write_buf[self.write_pos..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_pos += n;
+        }
+        self.write_buf.clear();
+        self.write_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncQueueT for FramedQueue<S> {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        self.get_mut().poll_recv_frame(cx, buf)
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        self.get_mut().poll_send_frame(cx, packet)
+    }
+}
+
+impl<S: AsyncRead + Unpin> FramedQueue<S> {
+    fn poll_recv_frame(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        loop {
+            while self.read_prefix_len < LEN_PREFIX {
+                let prefix = &mut self.read_prefix[self.read_prefix_len..];
+                let n = ready!(Pin::new(&mut self.stream).poll_read(cx, prefix))?;
+                match (n, self.read_prefix_len) {
+                    // Stream ended between frames
+                    (0, 0) => return Poll::Ready(Ok((0, false))),
+                    (0, _) => return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into())),
+                    _ => self.read_prefix_len += n,
+                }
+                if self.read_prefix_len == LEN_PREFIX {
+                    let len = usize::from(u16::from_be_bytes(self.read_prefix));
+                    self.read_frame.resize(len, 0);
+                }
+            }
+
+            while self.read_frame_len < self.read_frame.len() {
+                let frame = &mut self.read_frame[self.read_frame_len..];
+                let n = ready!(Pin::new(&mut self.stream).poll_read(cx, frame))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                self.read_frame_len += n;
+            }
+
+            let frame = &self.read_frame;
+            let n = frame.len().min(buf.len());
+            buf[..n].copy_from_slice(&frame[..n]);
+            self.read_prefix_len = 0;
+            self.read_frame_len = 0;
+            // Empty frames are skipped, as they are indistinguishable from end of stream
+            if !frame.is_empty() {
+                return Poll::Ready(Ok((n, frame.len() > n)));
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FramedQueue<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_recv_frame(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> FramedQueue<S> {
+    fn poll_send_frame(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<()>> {
+        if buf.len() > MAX_FRAME_LEN {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet is too large for framing",
+            )));
+        }
+        ready!(self.poll_write_pending(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+
+        self.write_buf
+            .extend_from_slice(&(buf.len() as u16).to_be_bytes());
+        self.write_buf.extend_from_slice(buf);
+        // Packet is accepted and sent later, if the stream is not ready
+        let _ = self.poll_write_pending(cx)?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FramedQueue<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_send_frame(cx, buf)
+            .map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_write_pending(cx))?;
+        Pin::new(&mut self_mut.stream).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_write_pending(cx))?;
+        Pin::new(&mut self_mut.stream).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker_ref;
+    use std::collections::VecDeque;
+
+    /// One end of a pipe: reads return the queued chunks, and writes are accepted up to
+    /// `capacity` bytes, staying pending after that.
+    #[derive(Default)]
+    struct Pipe {
+        chunks: VecDeque<Vec<u8>>,
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl Pipe {
+        fn reading(chunks: &[&[u8]]) -> Self {
+            Self {
+                chunks: chunks.iter().map(|chunk| chunk.to_vec()).collect(),
+                ..Self::default()
+            }
+        }
+
+        fn writing(capacity: usize) -> Self {
+            Self {
+                capacity,
+                ..Self::default()
+            }
+        }
+    }
+
+    impl AsyncRead for Pipe {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let chunk = match self.chunks.pop_front() {
+                Some(chunk) => chunk,
+                None => return Poll::Ready(Ok(0)),
+            };
+            let n = chunk.len().min(buf.len());
+            buf[..n].copy_from_slice(&chunk[..n]);
+            if n < chunk.len() {
+                self.chunks.push_front(chunk[n..].to_vec());
+            }
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    impl AsyncWrite for Pipe {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let n = buf.len().min(self.capacity - self.written.len());
+            if n == 0 {
+                return Poll::Pending;
+            }
+            self.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn recv(queue: &mut FramedQueue<Pipe>, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        match Pin::new(queue).poll_recv(&mut cx, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("pipe never blocks reads"),
+        }
+    }
+
+    fn send(queue: &mut FramedQueue<Pipe>, packet: &[u8]) -> io::Result<()> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        match Pin::new(queue).poll_send(&mut cx, packet) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("accepted packet is sent later"),
+        }
+    }
+
+    #[test]
+    fn packets_round_trip() {
+        let mut writer = FramedQueue::new(Pipe::writing(1024));
+        for packet in [&b"first"[..], b"", b"second"] {
+            send(&mut writer, packet).unwrap();
+        }
+        let written = writer.into_inner().written;
+        assert_eq!(written, b"\0\x05first\0\x06second");
+
+        let mut reader = FramedQueue::new(Pipe::reading(&[&written]));
+        let mut buf = [0; 16];
+        assert_eq!(recv(&mut reader, &mut buf).unwrap(), (5, false));
+        assert_eq!(buf[..5], *b"first");
+        assert_eq!(recv(&mut reader, &mut buf).unwrap(), (6, false));
+        assert_eq!(buf[..6], *b"second");
+        // End of stream between frames
+        assert_eq!(recv(&mut reader, &mut buf).unwrap(), (0, false));
+    }
+
+    #[test]
+    fn prefix_and_frame_split_across_reads() {
+        let chunks: [&[u8]; 5] = [b"\0", b"\x05fi", b"r", b"st\0", b"\x01x"];
+        let mut queue = FramedQueue::new(Pipe::reading(&chunks));
+        let mut buf = [0; 16];
+        assert_eq!(recv(&mut queue, &mut buf).unwrap(), (5, false));
+        assert_eq!(buf[..5], *b"first");
+        assert_eq!(recv(&mut queue, &mut buf).unwrap(), (1, false));
+        assert_eq!(buf[0], b'x');
+    }
+
+    #[test]
+    fn short_buffer_truncates_packet() {
+        let mut queue = FramedQueue::new(Pipe::reading(&[b"\0\x05first\0\x01x"]));
+        let mut buf = [0; 3];
+        assert_eq!(recv(&mut queue, &mut buf).unwrap(), (3, true));
+        assert_eq!(buf, *b"fir");
+        // Rest of the frame is discarded
+        assert_eq!(recv(&mut queue, &mut buf).unwrap(), (1, false));
+        assert_eq!(buf[0], b'x');
+    }
+
+    #[test]
+    fn empty_frames_are_skipped() {
+        let mut queue = FramedQueue::new(Pipe::reading(&[b"\0\0\0\0\0\x01x"]));
+        let mut buf = [0; 16];
+        assert_eq!(recv(&mut queue, &mut buf).unwrap(), (1, false));
+        assert_eq!(buf[0], b'x');
+    }
+
+    #[test]
+    fn end_of_stream_inside_frame_fails() {
+        for stream in [&b"\0"[..], b"\0\x05fir"] {
+            let mut queue = FramedQueue::new(Pipe::reading(&[stream]));
+            let error = recv(&mut queue, &mut [0; 16]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn oversized_packet_is_refused() {
+        let mut queue = FramedQueue::new(Pipe::writing(usize::MAX));
+        let error = send(&mut queue, &vec![0; MAX_FRAME_LEN + 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(queue.get_ref().written.is_empty());
+
+        send(&mut queue, &vec![0; MAX_FRAME_LEN]).unwrap();
+        assert_eq!(queue.get_ref().written[..2], [0xff, 0xff]);
+    }
+
+    #[test]
+    fn pending_write_completes_on_flush() {
+        let mut queue = FramedQueue::new(Pipe::writing(3));
+        send(&mut queue, b"first").unwrap();
+        assert_eq!(queue.get_ref().written, b"\0\x05f");
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(Pin::new(&mut queue).poll_flush(&mut cx).is_pending());
+        // Next packet waits for the pending one
+        assert!(Pin::new(&mut queue).poll_send(&mut cx, b"x").is_pending());
+
+        queue.get_mut().capacity = 1024;
+        assert!(matches!(
+            Pin::new(&mut queue).poll_flush(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        assert_eq!(queue.get_ref().written, b"\0\x05first");
+    }
+}
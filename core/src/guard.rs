@@ -0,0 +1,95 @@
+//! Guards, that revert system changes on drop, including unwinding after a panic.
+use crate::snapshot::Snapshot;
+use crate::Error;
+use log::warn;
+use netconfig::ipnet::IpNet;
+
+enum Revert {
+    AddAddress(IpNet),
+    RemoveAddress(IpNet),
+    SetMtu(u32),
+    Restore(Snapshot),
+}
+
+/// Reverts a single change of an interface, made by its constructor, when dropped.
+///
+/// Changes, that were not made, because the system was already configured this way, are not
+/// reverted. Failures of reverting are logged, as they cannot be returned from `drop`.
+#[must_use = "change is reverted immediately, if the guard is dropped"]
+pub struct ConfigGuard {
+    handle: netconfig::Interface,
+    revert: Option<Revert>,
+}
+
+impl ConfigGuard {
+    /// Adds an address, which is removed on drop.
+    pub fn add_address(handle: netconfig::Interface, address: IpNet) -> Result<Self, Error> {
+        let revert = match handle.addresses()?.contains(&address) {
+            true => None,
+            false => {
+                handle.add_address(address)?;
+                Some(Revert::RemoveAddress(address))
+            }
+        };
+        Ok(Self { handle, revert })
+    }
+
+    /// Removes an address, which is added back on drop.
+    pub fn remove_address(handle: netconfig::Interface, address: IpNet) -> Result<Self, Error> {
+        let revert = match handle.addresses()?.contains(&address) {
+            true => {
+                handle.remove_address(address)?;
+                Some(Revert::AddAddress(address))
+            }
+            false => None,
+        };
+        Ok(Self { handle, revert })
+    }
+
+    /// Sets MTU, which is set back to the current value on drop.
+    pub fn set_mtu(handle: netconfig::Interface, mtu: u32) -> Result<Self, Error> {
+        let previous = handle.mtu()?;
+        let revert = match previous == mtu {
+            true => None,
+            false => {
+                handle.set_mtu(mtu)?;
+                Some(Revert::SetMtu(previous))
+            }
+        };
+        Ok(Self { handle, revert })
+    }
+
+    /// Takes a [`Snapshot`], which is restored on drop, reverting any changes of MTU and
+    /// addresses, made while the guard is held.
+    pub fn snapshot(handle: netconfig::Interface) -> Result<Self, Error> {
+        let snapshot = Snapshot::capture(&handle)?;
+        Ok(Self {
+            handle,
+            revert: Some(Revert::Restore(snapshot)),
+        })
+    }
+
+    /// Keeps the change in place.
+    pub fn defuse(mut self) {
+        self.revert = None;
+    }
+
+    fn revert(&self, revert: &Revert) -> Result<(), Error> {
+        match revert {
+            Revert::AddAddress(address) => Ok(self.handle.add_address(*address)?),
+            Revert::RemoveAddress(address) => Ok(self.handle.remove_address(*address)?),
+            Revert::SetMtu(mtu) => Ok(self.handle.set_mtu(*mtu)?),
+            Revert::Restore(snapshot) => snapshot.restore(&self.handle),
+        }
+    }
+}
+
+impl Drop for ConfigGuard {
+    fn drop(&mut self) {
+        if let Some(revert) = self.revert.take() {
+            if let Err(err) = self.revert(&revert) {
+                warn!("Failed to revert interface configuration: {err}");
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+use super::{HookContext, PacketHook, Verdict};
+use crate::packet::checksum;
+
+/// Recomputes IPv4 header and TCP/UDP/ICMP checksums of packets, written by the application,
+/// so that synthetic packets may be written with checksums left blank. Packets of other
+/// protocols are written unchanged.
+///
+/// Devices do not validate checksums of written packets, so this replaces the offload, that
+/// a virtio-net header would otherwise request from the kernel.
+#[derive(Default)]
+pub struct FillChecksums;
+
+impl PacketHook for FillChecksums {
+    fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        checksum::fill(packet, ctx.layer());
+        Verdict::Pass
+    }
+}
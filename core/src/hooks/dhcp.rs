@@ -0,0 +1,508 @@
+use super::{HookContext, PacketHook, Verdict, DEFAULT_MAC};
+use crate::config::Layer;
+use crate::packet::{
+    ethernet, ethertype, ipv4, transport, udp, ETHERTYPE_IPV4, ETHER_BROADCAST, ETHER_HEADER_LEN,
+    PROTO_UDP,
+};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+const OPTIONS_OFFSET: usize = 240;
+const FLAG_BROADCAST: u16 = 0x8000;
+/// BOOTP messages are padded to this length for compatibility with old clients.
+const MIN_MESSAGE_LEN: usize = 300;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS: u8 = 6;
+const OPTION_MTU: u8 = 26;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+/// DNS servers, that fit into the 255 bytes of an option.
+const MAX_DNS_SERVERS: usize = 63;
+
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+const DHCPINFORM: u8 = 8;
+
+/// Minimal DHCP server for [`Layer::L2`] queues, that leases a single configured address
+/// to whatever client asks for it, like the OS stack or a VM behind the TAP device.
+///
+/// DHCP messages from clients are answered through the device and are not returned to the
+/// application. Other frames pass unchanged.
+pub struct DhcpServer {
+    address: Ipv4Addr,
+    netmask: Ipv4Addr,
+    server: Ipv4Addr,
+    server_mac: [u8; 6],
+    gateway: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    mtu: Option<u16>,
+    lease_time: Duration,
+}
+
+impl DhcpServer {
+    /// Leases `address` with `netmask`, answering from `server` address.
+    pub fn new(address: Ipv4Addr, netmask: Ipv4Addr, server: Ipv4Addr) -> Self {
+        Self {
+            address,
+            netmask,
+            server,
+            server_mac: DEFAULT_MAC,
+            gateway: None,
+            dns: vec![],
+            mtu: None,
+            lease_time: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    pub fn gateway(mut self, gateway: Ipv4Addr) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    /// DNS servers, of which the first 63 fit into replies.
+    pub fn dns(mut self, servers: Vec<Ipv4Addr>) -> Self {
+        self.dns = servers;
+        self
+    }
+
+    pub fn mtu(mut self, mtu: u16) -> Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    pub fn lease_time(mut self, lease_time: Duration) -> Self {
+        self.lease_time = lease_time;
+        self
+    }
+
+    /// Source MAC address of replies. Defaults to [`DEFAULT_MAC`].
+    pub fn server_mac(mut self, mac: [u8; 6]) -> Self {
+        self.server_mac = mac;
+        self
+    }
+
+    /// Returns DHCP message of a client, if the frame carries one.
+    fn client_message(frame: &[u8]) -> Option<&[u8]> {
+        if ethertype(frame)? != ETHERTYPE_IPV4 {
+            return None;
+        }
+        let ip = &frame[ETHER_HEADER_LEN..];
+        let (protocol, offset) = transport(ip)?;
+        let datagram = ip.get(offset..)?;
+        if protocol != PROTO_UDP || datagram.len() < 8 {
+            return None;
+        }
+        let dst_port = u16::from_be_bytes([datagram[2], datagram[3]]);
+        let message = &datagram[8..];
+        match dst_port == SERVER_PORT
+            && message.len() > OPTIONS_OFFSET
+            && message[0] == 1
+            && message[236..240] == MAGIC_COOKIE
+        {
+            true => Some(message),
+            false => None,
+        }
+    }
+
+    fn reply(&self, request: &[u8]) -> Option<Vec<u8>> {
+        let options = Options(&request[OPTIONS_OFFSET..]);
+        let client_address = Ipv4Addr::new(request[12], request[13], request[14], request[15]);
+        let requested = options
+            .get(OPTION_REQUESTED_ADDRESS)
+            .and_then(|value| <[u8; 4]>::try_from(value).ok())
+            .map(Ipv4Addr::from)
+            .or(Some(client_address))
+            .filter(|address| !address.is_unspecified());
+
+        let request_type = *options.get(OPTION_MESSAGE_TYPE)?.first()?;
+        let other_server = options
+            .get(OPTION_SERVER_ID)
+            .map_or(false, |server| server != self.server.octets());
+        if request_type == DHCPREQUEST && other_server {
+            // Client has chosen an offer of another server
+            return None;
+        }
+        let message_type = match request_type {
+            DHCPDISCOVER => DHCPOFFER,
+            // Client has an address of its own and only asks for configuration
+            DHCPINFORM => DHCPACK,
+            DHCPREQUEST if requested.map_or(true, |addr| addr == self.address) => DHCPACK,
+            DHCPREQUEST => DHCPNAK,
+            // Releases and declines need no reply, as the address is reserved for the client
+            _ => return None,
+        };
+        let leases = message_type != DHCPNAK && request_type != DHCPINFORM;
+
+        let mut message = vec![0; OPTIONS_OFFSET];
+        message[..4].copy_from_slice(&[2, 1, 6, 0]);
+        // Transaction ID, seconds and flags
+        message[4..12].copy_from_slice(&request[4..12]);
+        if leases {
+            message[16..20].copy_from_slice(&self.address.octets());
+        }
+        message[20..24].copy_from_slice(&self.server.octets());
+        // Relay agent address and client hardware address
+        message[24..44].copy_from_slice(&request[24..44]);
+        message[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut push = |code: u8, value: &[u8]| {
+            message.extend_from_slice(&[code, value.len() as u8]);
+            message.extend_from_slice(value);
+        };
+        push(OPTION_MESSAGE_TYPE, &[message_type]);
+        push(OPTION_SERVER_ID, &self.server.octets());
+        if message_type != DHCPNAK {
+            push(OPTION_SUBNET_MASK, &self.netmask.octets());
+            if leases {
+                let lease_time = u32::try_from(self.lease_time.as_secs()).unwrap_or(u32::MAX);
+                push(OPTION_LEASE_TIME, &lease_time.to_be_bytes());
+            }
+            if let Some(gateway) = self.gateway {
+                push(OPTION_ROUTER, &gateway.octets());
+            }
+            if !self.dns.is_empty() {
+                let servers: Vec<u8> = self
+                    .dns
+                    .iter()
+                    .take(MAX_DNS_SERVERS)
+                    .flat_map(|addr| addr.octets())
+                    .collect();
+                push(OPTION_DNS, &servers);
+            }
+            if let Some(mtu) = self.mtu {
+                push(OPTION_MTU, &mtu.to_be_bytes());
+            }
+        }
+        message.push(OPTION_END);
+        if message.len() < MIN_MESSAGE_LEN {
+            message.resize(MIN_MESSAGE_LEN, OPTION_PAD);
+        }
+
+        let flags = u16::from_be_bytes([request[10], request[11]]);
+        let client_mac: [u8; 6] = request[28..34].try_into().ok()?;
+        // Informing clients are answered at the address, they have
+        let client = match request_type {
+            DHCPINFORM => client_address,
+            _ => self.address,
+        };
+        let broadcast = flags & FLAG_BROADCAST != 0 || message_type == DHCPNAK;
+        let (dst, dst_mac) = match broadcast || client.is_unspecified() {
+            true => (Ipv4Addr::BROADCAST, ETHER_BROADCAST),
+            false => (client, client_mac),
+        };
+        let datagram = udp(SERVER_PORT, CLIENT_PORT, &message);
+        let packet = ipv4(self.server, dst, PROTO_UDP, 64, &datagram);
+        Some(ethernet(dst_mac, self.server_mac, ETHERTYPE_IPV4, &packet))
+    }
+}
+
+/// DHCP options of a message.
+struct Options<'a>(&'a [u8]);
+
+impl<'a> Options<'a> {
+    fn get(&self, code: u8) -> Option<&'a [u8]> {
+        let mut options = self.0;
+        loop {
+            match *options.first()? {
+                OPTION_END => return None,
+                OPTION_PAD => options = &options[1..],
+                kind => {
+                    let len = usize::from(*options.get(1)?);
+                    let value = options.get(2..2 + len)?;
+                    if kind == code {
+                        return Some(value);
+                    }
+                    options = &options[2 + len..];
+                }
+            }
+        }
+    }
+}
+
+impl PacketHook for DhcpServer {
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        if ctx.layer() != Layer::L2 {
+            return Verdict::Pass;
+        }
+        let request = match Self::client_message(packet) {
+            Some(request) => request,
+            None => return Verdict::Pass,
+        };
+        if let Some(reply) = self.reply(request) {
+            ctx.send_to_device(reply);
+        }
+        Verdict::Drop
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::ETHERTYPE_ARP;
+
+    const CLIENT_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+    const ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const DHCPRELEASE: u8 = 7;
+    /// Offset of the DHCP message in frames without IP options.
+    const MESSAGE_OFFSET: usize = ETHER_HEADER_LEN + 20 + 8;
+
+    fn server() -> DhcpServer {
+        DhcpServer::new(ADDRESS, Ipv4Addr::new(255, 255, 255, 0), SERVER)
+            .gateway(SERVER)
+            .dns(vec![Ipv4Addr::new(1, 1, 1, 1), Ipv4Addr::new(8, 8, 8, 8)])
+            .mtu(1400)
+            .lease_time(Duration::from_secs(3600))
+    }
+
+    /// Frame with a client message, which options are followed by the end option.
+    fn client_frame(client_address: Ipv4Addr, flags: u16, options: &[u8]) -> Vec<u8> {
+        let mut message = vec![0; OPTIONS_OFFSET];
+        message[..4].copy_from_slice(&[1, 1, 6, 0]);
+        message[4..8].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        message[10..12].copy_from_slice(&flags.to_be_bytes());
+        message[12..16].copy_from_slice(&client_address.octets());
+        message[28..34].copy_from_slice(&CLIENT_MAC);
+        message[236..240].copy_from_slice(&MAGIC_COOKIE);
+        message.extend_from_slice(options);
+        message.push(OPTION_END);
+
+        let datagram = udp(CLIENT_PORT, SERVER_PORT, &message);
+        let packet = ipv4(
+            client_address,
+            Ipv4Addr::BROADCAST,
+            PROTO_UDP,
+            64,
+            &datagram,
+        );
+        ethernet(ETHER_BROADCAST, CLIENT_MAC, ETHERTYPE_IPV4, &packet)
+    }
+
+    fn request(message_type: u8, requested: Option<Ipv4Addr>) -> Vec<u8> {
+        let mut options = vec![OPTION_MESSAGE_TYPE, 1, message_type];
+        if let Some(address) = requested {
+            options.extend_from_slice(&[OPTION_REQUESTED_ADDRESS, 4]);
+            options.extend_from_slice(&address.octets());
+        }
+        client_frame(Ipv4Addr::UNSPECIFIED, 0, &options)
+    }
+
+    /// Returns the verdict and the replies, that were sent to the device.
+    fn serve(frame: &[u8]) -> (Verdict, Vec<Vec<u8>>) {
+        let mut ctx = HookContext::new(Layer::L2);
+        let verdict = server().on_read(&mut frame.to_vec(), &mut ctx);
+        (verdict, ctx.to_device.into())
+    }
+
+    fn serve_one(frame: &[u8]) -> Vec<u8> {
+        let (verdict, mut replies) = serve(frame);
+        assert_eq!(verdict, Verdict::Drop);
+        assert_eq!(replies.len(), 1);
+        replies.remove(0)
+    }
+
+    fn reply_options(reply: &[u8]) -> Options<'_> {
+        Options(&reply[MESSAGE_OFFSET + OPTIONS_OFFSET..])
+    }
+
+    fn message_type(reply: &[u8]) -> u8 {
+        reply_options(reply).get(OPTION_MESSAGE_TYPE).unwrap()[0]
+    }
+
+    fn your_address(reply: &[u8]) -> Ipv4Addr {
+        let at = MESSAGE_OFFSET + 16;
+        Ipv4Addr::new(reply[at], reply[at + 1], reply[at + 2], reply[at + 3])
+    }
+
+    #[test]
+    fn discover_is_offered() {
+        let reply = serve_one(&request(DHCPDISCOVER, None));
+        assert_eq!(message_type(&reply), DHCPOFFER);
+        assert_eq!(your_address(&reply), ADDRESS);
+        // Unicast to the client, from the server
+        assert_eq!(reply[..6], CLIENT_MAC);
+        assert_eq!(reply[6..12], DEFAULT_MAC);
+        assert_eq!(reply[26..34], [10, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!(reply[34..38], [0, 67, 0, 68]);
+        // Transaction ID and client hardware address are echoed
+        assert_eq!(
+            reply[MESSAGE_OFFSET + 4..MESSAGE_OFFSET + 8],
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(reply[MESSAGE_OFFSET + 28..MESSAGE_OFFSET + 34], CLIENT_MAC);
+        assert!(reply.len() - MESSAGE_OFFSET >= MIN_MESSAGE_LEN);
+
+        let options = reply_options(&reply);
+        assert_eq!(options.get(OPTION_SERVER_ID), Some(&[10, 0, 0, 1][..]));
+        assert_eq!(
+            options.get(OPTION_SUBNET_MASK),
+            Some(&[255, 255, 255, 0][..])
+        );
+        assert_eq!(options.get(OPTION_ROUTER), Some(&[10, 0, 0, 1][..]));
+        assert_eq!(options.get(OPTION_DNS), Some(&[1, 1, 1, 1, 8, 8, 8, 8][..]));
+        assert_eq!(options.get(OPTION_MTU), Some(&1400u16.to_be_bytes()[..]));
+        assert_eq!(
+            options.get(OPTION_LEASE_TIME),
+            Some(&3600u32.to_be_bytes()[..])
+        );
+    }
+
+    #[test]
+    fn broadcast_flag_is_honored() {
+        let options = [OPTION_MESSAGE_TYPE, 1, DHCPDISCOVER];
+        let frame = client_frame(Ipv4Addr::UNSPECIFIED, FLAG_BROADCAST, &options);
+        let reply = serve_one(&frame);
+        assert_eq!(reply[..6], ETHER_BROADCAST);
+        assert_eq!(reply[30..34], [255; 4]);
+    }
+
+    #[test]
+    fn request_for_leased_address_is_acked() {
+        let reply = serve_one(&request(DHCPREQUEST, Some(ADDRESS)));
+        assert_eq!(message_type(&reply), DHCPACK);
+        assert_eq!(your_address(&reply), ADDRESS);
+        assert_eq!(reply[..6], CLIENT_MAC);
+    }
+
+    #[test]
+    fn request_for_other_address_is_refused_by_broadcast() {
+        let reply = serve_one(&request(DHCPREQUEST, Some(Ipv4Addr::new(10, 0, 0, 9))));
+        assert_eq!(message_type(&reply), DHCPNAK);
+        assert_eq!(your_address(&reply), Ipv4Addr::UNSPECIFIED);
+        assert_eq!(reply[..6], ETHER_BROADCAST);
+        assert_eq!(reply[30..34], [255; 4]);
+        // Refusals carry no configuration
+        assert_eq!(reply_options(&reply).get(OPTION_SUBNET_MASK), None);
+        assert_eq!(reply_options(&reply).get(OPTION_LEASE_TIME), None);
+    }
+
+    #[test]
+    fn renewal_of_leased_address_is_acked() {
+        // Renewing clients put their address into the message instead of an option
+        let options = [OPTION_MESSAGE_TYPE, 1, DHCPREQUEST];
+        let reply = serve_one(&client_frame(ADDRESS, 0, &options));
+        assert_eq!(message_type(&reply), DHCPACK);
+    }
+
+    #[test]
+    fn inform_is_acked_without_lease() {
+        let client = Ipv4Addr::new(10, 0, 0, 7);
+        let options = [OPTION_MESSAGE_TYPE, 1, DHCPINFORM];
+        let reply = serve_one(&client_frame(client, 0, &options));
+        assert_eq!(message_type(&reply), DHCPACK);
+        assert_eq!(your_address(&reply), Ipv4Addr::UNSPECIFIED);
+        // Unicast to the address of the client
+        assert_eq!(reply[..6], CLIENT_MAC);
+        assert_eq!(reply[30..34], [10, 0, 0, 7]);
+        assert_eq!(
+            reply_options(&reply).get(OPTION_DNS),
+            Some(&[1, 1, 1, 1, 8, 8, 8, 8][..])
+        );
+        assert_eq!(reply_options(&reply).get(OPTION_LEASE_TIME), None);
+    }
+
+    #[test]
+    fn request_for_other_server_is_not_answered() {
+        let mut options = vec![OPTION_MESSAGE_TYPE, 1, DHCPREQUEST, OPTION_SERVER_ID, 4];
+        options.extend_from_slice(&[10, 0, 0, 254]);
+        let frame = client_frame(Ipv4Addr::UNSPECIFIED, 0, &options);
+        assert_eq!(serve(&frame), (Verdict::Drop, vec![]));
+
+        // Selected server is this one
+        options[5..9].copy_from_slice(&SERVER.octets());
+        let reply = serve_one(&client_frame(Ipv4Addr::UNSPECIFIED, 0, &options));
+        assert_eq!(message_type(&reply), DHCPACK);
+    }
+
+    #[test]
+    fn dns_servers_are_capped_to_option_length() {
+        let servers = (0..100).map(|i| Ipv4Addr::new(10, 1, 0, i)).collect();
+        let mut server = server().dns(servers);
+        let mut ctx = HookContext::new(Layer::L2);
+        server.on_read(&mut request(DHCPDISCOVER, None), &mut ctx);
+
+        let reply = ctx.to_device.pop_front().unwrap();
+        let dns = reply_options(&reply).get(OPTION_DNS).unwrap();
+        assert_eq!(dns.len(), MAX_DNS_SERVERS * 4);
+        assert_eq!(dns[dns.len() - 4..], [10, 1, 0, 62]);
+        // Options after it are intact
+        assert_eq!(
+            reply_options(&reply).get(OPTION_MTU),
+            Some(&1400u16.to_be_bytes()[..])
+        );
+    }
+
+    #[test]
+    fn release_is_not_answered() {
+        assert_eq!(serve(&request(DHCPRELEASE, None)), (Verdict::Drop, vec![]));
+    }
+
+    #[test]
+    fn malformed_option_lengths_are_not_answered() {
+        // Length of the message type runs past the message
+        let frame = client_frame(
+            Ipv4Addr::UNSPECIFIED,
+            0,
+            &[OPTION_PAD, OPTION_MESSAGE_TYPE, 200],
+        );
+        assert_eq!(serve(&frame), (Verdict::Drop, vec![]));
+        // Message type is empty
+        let frame = client_frame(Ipv4Addr::UNSPECIFIED, 0, &[OPTION_MESSAGE_TYPE, 0]);
+        assert_eq!(serve(&frame), (Verdict::Drop, vec![]));
+    }
+
+    #[test]
+    fn malformed_requested_address_is_ignored() {
+        let options = [
+            OPTION_MESSAGE_TYPE,
+            1,
+            DHCPREQUEST,
+            OPTION_REQUESTED_ADDRESS,
+            3,
+            10,
+            0,
+            9,
+        ];
+        let reply = serve_one(&client_frame(Ipv4Addr::UNSPECIFIED, 0, &options));
+        assert_eq!(message_type(&reply), DHCPACK);
+    }
+
+    #[test]
+    fn non_dhcp_frames_pass() {
+        let dns = ipv4(
+            ADDRESS,
+            SERVER,
+            PROTO_UDP,
+            64,
+            &udp(CLIENT_PORT, 53, &[0; 300]),
+        );
+        let dns = ethernet(DEFAULT_MAC, CLIENT_MAC, ETHERTYPE_IPV4, &dns);
+        let arp = ethernet(ETHER_BROADCAST, CLIENT_MAC, ETHERTYPE_ARP, &[0; 28]);
+        let mut no_cookie = request(DHCPDISCOVER, None);
+        no_cookie[MESSAGE_OFFSET + 236] = 0;
+        let mut short = request(DHCPDISCOVER, None);
+        short.truncate(MESSAGE_OFFSET + OPTIONS_OFFSET);
+
+        for frame in [dns, arp, no_cookie, short] {
+            assert_eq!(serve(&frame), (Verdict::Pass, vec![]));
+        }
+
+        // L3 queues carry no DHCP
+        let mut ctx = HookContext::new(Layer::L3);
+        let mut packet = request(DHCPDISCOVER, None);
+        assert_eq!(server().on_read(&mut packet, &mut ctx), Verdict::Pass);
+        assert!(ctx.to_device.is_empty());
+    }
+}
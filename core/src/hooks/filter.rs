@@ -0,0 +1,243 @@
+use super::{HookContext, PacketHook, Verdict};
+use crate::mtu::MtuWatch;
+use crate::packet::icmp::IcmpError;
+use crate::packet::ip_offset;
+
+const IPV4_FLAG_DF: u8 = 0x40;
+
+/// Direction of a packet through a queue.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Direction {
+    /// Packet is read from the device.
+    Read,
+    /// Packet is written to the device.
+    Write,
+}
+
+/// Packet filter, deciding on packets with a closure. Packets are rejected with
+/// [`Verdict::Reject`], when the sender must be told, and dropped silently otherwise.
+pub struct Filter<F> {
+    decide: F,
+}
+
+impl<F> Filter<F>
+where
+    F: FnMut(&[u8], Direction) -> Verdict + Send,
+{
+    pub fn new(decide: F) -> Self {
+        Self { decide }
+    }
+}
+
+impl<F> PacketHook for Filter<F>
+where
+    F: FnMut(&[u8], Direction) -> Verdict + Send,
+{
+    fn on_read(&mut self, packet: &mut Vec<u8>, _ctx: &mut HookContext) -> Verdict {
+        (self.decide)(packet, Direction::Read)
+    }
+
+    fn on_write(&mut self, packet: &mut Vec<u8>, _ctx: &mut HookContext) -> Verdict {
+        (self.decide)(packet, Direction::Write)
+    }
+}
+
+/// Rejects IP packets, larger than `mtu`, in both directions with Packet Too Big, so that
+/// Path MTU Discovery works through the tunnel.
+///
+/// IPv4 packets without Don't Fragment flag pass, to be fragmented further down the path.
+/// Optionally, ICMP errors may be turned off to drop oversized packets silently.
+pub struct MtuLimit {
+    mtu: MtuWatch,
+    report: bool,
+}
+
+impl MtuLimit {
+    pub fn new(mtu: u16) -> Self {
+        Self::tracking(MtuWatch::new(u32::from(mtu)))
+    }
+
+    /// Creates a limit, that follows MTU changes of an interface.
+    pub fn tracking(mtu: MtuWatch) -> Self {
+        Self { mtu, report: true }
+    }
+
+    /// Drops oversized packets without ICMP errors.
+    pub fn silent(mut self) -> Self {
+        self.report = false;
+        self
+    }
+
+    fn check(&self, packet: &[u8], ctx: &HookContext) -> Verdict {
+        let ip = match ip_offset(packet, ctx.layer()) {
+            Some(offset) => &packet[offset..],
+            None => return Verdict::Pass,
+        };
+        let mtu = self.mtu.get_u16();
+        if ip.len() <= usize::from(mtu) {
+            return Verdict::Pass;
+        }
+
+        match ip[0] >> 4 {
+            4 if ip.get(6).map_or(true, |flags| flags & IPV4_FLAG_DF == 0) => Verdict::Pass,
+            4 | 6 if self.report => Verdict::Reject(IcmpError::PacketTooBig {
+                mtu: u32::from(mtu),
+            }),
+            4 | 6 => Verdict::Drop,
+            _ => Verdict::Pass,
+        }
+    }
+}
+
+impl PacketHook for MtuLimit {
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        self.check(packet, ctx)
+    }
+
+    fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        self.check(packet, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Layer;
+    use crate::packet::{ethernet, ipv4, ipv6, udp, ETHERTYPE_ARP, ETHERTYPE_IPV4, PROTO_UDP};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+
+    /// UDP packet over IPv4 of `len` bytes.
+    fn udp4(len: usize, df: bool) -> Vec<u8> {
+        let datagram = udp(1000, 2000, &vec![0; len - 28]);
+        let src = Ipv4Addr::new(10, 0, 0, 1);
+        let mut packet = ipv4(src, Ipv4Addr::new(10, 0, 0, 2), PROTO_UDP, 64, &datagram);
+        if df {
+            packet[6] |= IPV4_FLAG_DF;
+        }
+        packet
+    }
+
+    /// UDP packet over IPv6 of `len` bytes.
+    fn udp6(len: usize) -> Vec<u8> {
+        let datagram = udp(1000, 2000, &vec![0; len - 48]);
+        let (src, dst) = (Ipv6Addr::LOCALHOST, Ipv6Addr::LOCALHOST);
+        ipv6(src, dst, PROTO_UDP, 64, &datagram)
+    }
+
+    fn check(hook: &mut impl PacketHook, mut packet: Vec<u8>, layer: Layer) -> [Verdict; 2] {
+        let mut ctx = HookContext::new(layer);
+        let read = hook.on_read(&mut packet, &mut ctx);
+        let write = hook.on_write(&mut packet, &mut ctx);
+        assert!(ctx.to_device.is_empty() && ctx.to_reader.is_empty());
+        [read, write]
+    }
+
+    #[test]
+    fn filter_decides_by_direction() {
+        let mut seen = Vec::new();
+        let mut filter = Filter::new(|packet: &[u8], direction| {
+            seen.push((packet.len(), direction));
+            match direction {
+                Direction::Read => Verdict::Pass,
+                Direction::Write => Verdict::Drop,
+            }
+        });
+        let verdicts = check(&mut filter, udp4(100, false), Layer::L3);
+        assert_eq!(verdicts, [Verdict::Pass, Verdict::Drop]);
+        assert_eq!(seen, [(100, Direction::Read), (100, Direction::Write)]);
+    }
+
+    #[test]
+    fn filter_sees_truncated_packets() {
+        let mut filter = Filter::new(|packet: &[u8], _| match packet.len() {
+            0..=19 => Verdict::Drop,
+            _ => Verdict::Pass,
+        });
+        assert_eq!(
+            check(&mut filter, vec![0x45], Layer::L3),
+            [Verdict::Drop; 2]
+        );
+    }
+
+    #[test]
+    fn oversized_packets_with_df_are_rejected() {
+        let too_big = Verdict::Reject(IcmpError::PacketTooBig { mtu: 1280 });
+        let mut limit = MtuLimit::new(1280);
+        assert_eq!(check(&mut limit, udp4(1281, true), Layer::L3), [too_big; 2]);
+        assert_eq!(check(&mut limit, udp6(1281), Layer::L3), [too_big; 2]);
+
+        let frame = ethernet(MAC, MAC, ETHERTYPE_IPV4, &udp4(1281, true));
+        assert_eq!(check(&mut limit, frame, Layer::L2), [too_big; 2]);
+    }
+
+    #[test]
+    fn packets_within_mtu_pass() {
+        let mut limit = MtuLimit::new(1280);
+        assert_eq!(
+            check(&mut limit, udp4(1280, true), Layer::L3),
+            [Verdict::Pass; 2]
+        );
+        assert_eq!(check(&mut limit, udp6(1280), Layer::L3), [Verdict::Pass; 2]);
+
+        // Ethernet header does not count
+        let frame = ethernet(MAC, MAC, ETHERTYPE_IPV4, &udp4(1280, true));
+        assert_eq!(check(&mut limit, frame, Layer::L2), [Verdict::Pass; 2]);
+    }
+
+    #[test]
+    fn tracking_limit_follows_mtu() {
+        let mtu = MtuWatch::new(1500);
+        let mut limit = MtuLimit::tracking(mtu.clone());
+        assert_eq!(check(&mut limit, udp6(1400), Layer::L3), [Verdict::Pass; 2]);
+        mtu.set(1280);
+        let too_big = Verdict::Reject(IcmpError::PacketTooBig { mtu: 1280 });
+        assert_eq!(check(&mut limit, udp6(1400), Layer::L3), [too_big; 2]);
+    }
+
+    #[test]
+    fn oversized_packets_without_df_pass() {
+        let mut limit = MtuLimit::new(1280);
+        assert_eq!(
+            check(&mut limit, udp4(1500, false), Layer::L3),
+            [Verdict::Pass; 2]
+        );
+    }
+
+    #[test]
+    fn silent_limit_drops() {
+        let mut limit = MtuLimit::new(1280).silent();
+        assert_eq!(
+            check(&mut limit, udp4(1281, true), Layer::L3),
+            [Verdict::Drop; 2]
+        );
+        assert_eq!(check(&mut limit, udp6(1281), Layer::L3), [Verdict::Drop; 2]);
+    }
+
+    #[test]
+    fn truncated_and_other_packets_pass() {
+        let mut limit = MtuLimit::new(1280).silent();
+        // IPv4 header ends before the flags
+        assert_eq!(
+            check(&mut limit, vec![0x45; 6], Layer::L3),
+            [Verdict::Pass; 2]
+        );
+        assert_eq!(check(&mut limit, vec![], Layer::L3), [Verdict::Pass; 2]);
+        // Neither IPv4 nor IPv6
+        assert_eq!(
+            check(&mut limit, vec![0; 1500], Layer::L3),
+            [Verdict::Pass; 2]
+        );
+
+        let arp = ethernet(MAC, MAC, ETHERTYPE_ARP, &[0; 1500]);
+        assert_eq!(check(&mut limit, arp, Layer::L2), [Verdict::Pass; 2]);
+        // Ethernet header only
+        let empty = ethernet(MAC, MAC, ETHERTYPE_IPV4, &[]);
+        assert_eq!(check(&mut limit, empty, Layer::L2), [Verdict::Pass; 2]);
+        assert_eq!(
+            check(&mut limit, vec![0; 10], Layer::L2),
+            [Verdict::Pass; 2]
+        );
+    }
+}
@@ -0,0 +1,600 @@
+use super::{HookContext, PacketHook, Verdict};
+use crate::mtu::MtuWatch;
+use crate::packet::icmp::IcmpError;
+use crate::packet::{checksum, ip_offset, IPV4_MIN_HEADER_LEN};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const FLAG_DF: u16 = 0x4000;
+const FLAG_MF: u16 = 0x2000;
+const OFFSET_MASK: u16 = 0x1fff;
+/// Options with this bit set are copied into every fragment, see RFC 791.
+const OPTION_COPIED: u8 = 0x80;
+const OPTION_END: u8 = 0;
+const OPTION_NOP: u8 = 1;
+/// Every IPv4 host must accept packets of this size without fragmentation.
+const MIN_MTU: u16 = 68;
+const MAX_DATAGRAM_LEN: usize = u16::MAX as usize;
+
+/// IPv4 header length, total length and flags with fragment offset of a packet.
+struct Ipv4Header {
+    len: usize,
+    total_len: usize,
+    fragment: u16,
+}
+
+impl Ipv4Header {
+    fn parse(ip: &[u8]) -> Option<Self> {
+        if ip.len() < IPV4_MIN_HEADER_LEN || ip[0] >> 4 != 4 {
+            return None;
+        }
+        let len = usize::from(ip[0] & 0x0f) * 4;
+        // Link layer may pad short packets
+        let total_len = usize::from(u16::from_be_bytes([ip[2], ip[3]]));
+        match len >= IPV4_MIN_HEADER_LEN && len <= total_len && total_len <= ip.len() {
+            true => Some(Self {
+                len,
+                total_len,
+                fragment: u16::from_be_bytes([ip[6], ip[7]]),
+            }),
+            false => None,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        usize::from(self.fragment & OFFSET_MASK) * 8
+    }
+
+    fn more_fragments(&self) -> bool {
+        self.fragment & FLAG_MF != 0
+    }
+}
+
+/// Fragments IPv4 packets, written to the device, that are larger than `mtu`, for tunnels,
+/// whose outer path MTU is smaller than the inner one.
+///
+/// Packets with Don't Fragment flag are rejected with Fragmentation Needed instead, so that
+/// Path MTU Discovery keeps working. IPv6 packets pass, as they are only fragmented by their
+/// source.
+pub struct Fragmenter {
+    mtu: MtuWatch,
+}
+
+impl Fragmenter {
+    /// Creates a fragmenter for IP packets of `mtu` bytes, at least 68.
+    pub fn new(mtu: u16) -> Self {
+        Self::tracking(MtuWatch::new(u32::from(mtu)))
+    }
+
+    /// Creates a fragmenter, that follows MTU changes of an interface, see
+    /// [`MtuWatch`].
+    pub fn tracking(mtu: MtuWatch) -> Self {
+        Self { mtu }
+    }
+
+    fn mtu(&self) -> u16 {
+        self.mtu.get_u16().max(MIN_MTU)
+    }
+
+    fn fragments(
+        &self,
+        packet: &[u8],
+        offset: usize,
+        header: &Ipv4Header,
+        mtu: u16,
+    ) -> Vec<Vec<u8>> {
+        let ip = &packet[offset..];
+        let payload = &ip[header.len..header.total_len];
+        let first_header = &ip[..header.len];
+        let other_header = copied_options(first_header);
+
+        let mut fragments = vec![];
+        let mut position = 0;
+        while position < payload.len() {
+            let ip_header = match position {
+                0 => first_header,
+                _ => &other_header,
+            };
+            // Fragment offsets are in 8-byte units
+            let max_len = (usize::from(mtu) - ip_header.len()) & !7;
+            let len = (payload.len() - position).min(max_len);
+            let last = position + len == payload.len();
+
+            let mut fragment = Vec::with_capacity(offset + ip_header.len() + len);
+            fragment.extend_from_slice(&packet[..offset]);
+            fragment.extend_from_slice(ip_header);
+            fragment.extend_from_slice(&payload[position..position + len]);
+
+            // Fragments of a fragment keep its offset and More Fragments flag
+            let mut field = header.fragment & !OFFSET_MASK;
+            field |= ((header.offset() + position) / 8) as u16 & OFFSET_MASK;
+            if !last {
+                field |= FLAG_MF;
+            }
+            let ip = &mut fragment[offset..];
+            ip[2..4].copy_from_slice(&((ip_header.len() + len) as u16).to_be_bytes());
+            ip[6..8].copy_from_slice(&field.to_be_bytes());
+            checksum::fill_ipv4_header(ip);
+
+            fragments.push(fragment);
+            position += len;
+        }
+        fragments
+    }
+}
+
+/// Returns IPv4 header for fragments other than the first one, with options, that are copied
+/// into every fragment.
+fn copied_options(header: &[u8]) -> Vec<u8> {
+    let mut copied = header[..IPV4_MIN_HEADER_LEN].to_vec();
+    let mut options = &header[IPV4_MIN_HEADER_LEN..];
+    while let Some(&kind) = options.first() {
+        let len = match kind {
+            OPTION_END => break,
+            OPTION_NOP => 1,
+            _ => match options.get(1) {
+                Some(&len) if len >= 2 => usize::from(len).min(options.len()),
+                _ => break,
+            },
+        };
+        if kind & OPTION_COPIED != 0 {
+            copied.extend_from_slice(&options[..len]);
+        }
+        options = &options[len..];
+    }
+    copied.resize((copied.len() + 3) & !3, OPTION_END);
+    copied[0] = 0x40 | (copied.len() / 4) as u8;
+    copied
+}
+
+impl PacketHook for Fragmenter {
+    fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        let offset = match ip_offset(packet, ctx.layer()) {
+            Some(offset) => offset,
+            None => return Verdict::Pass,
+        };
+        let mtu = self.mtu();
+        let header = match Ipv4Header::parse(&packet[offset..]) {
+            Some(header) if header.total_len > usize::from(mtu) => header,
+            _ => return Verdict::Pass,
+        };
+        if header.fragment & FLAG_DF != 0 {
+            return Verdict::Reject(IcmpError::PacketTooBig {
+                mtu: u32::from(mtu),
+            });
+        }
+
+        for fragment in self.fragments(packet, offset, &header, mtu) {
+            ctx.send_to_device(fragment);
+        }
+        Verdict::Drop
+    }
+}
+
+/// Reassembles fragmented IPv4 packets, read from the device, so that the application only
+/// sees whole packets.
+///
+/// Fragments are buffered up to a memory limit, evicting the oldest incomplete packets, when
+/// it is exceeded, and incomplete packets are discarded after a timeout. Overlapping
+/// fragments discard the whole packet, see RFC 5722. Other packets pass unchanged.
+pub struct Reassembler {
+    max_bytes: usize,
+    timeout: Duration,
+    buffered: usize,
+    datagrams: HashMap<DatagramId, Datagram>,
+}
+
+/// Fragments of a packet are identified by addresses, protocol and identification field.
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+struct DatagramId {
+    src: [u8; 4],
+    dst: [u8; 4],
+    protocol: u8,
+    id: u16,
+}
+
+struct Datagram {
+    /// Link layer and IP headers of the first fragment.
+    header: Option<Vec<u8>>,
+    /// Payloads of received fragments with their offsets.
+    fragments: Vec<(usize, Vec<u8>)>,
+    /// Payload length, known from the last fragment.
+    len: Option<usize>,
+    bytes: usize,
+    deadline: Instant,
+}
+
+impl Datagram {
+    fn is_complete(&self) -> bool {
+        let received: usize = self.fragments.iter().map(|(_, data)| data.len()).sum();
+        self.header.is_some() && self.len == Some(received)
+    }
+
+    fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.fragments
+            .iter()
+            .any(|(offset, data)| start < offset + data.len() && *offset < end)
+    }
+
+    fn into_packet(mut self, ip_offset: usize) -> Option<Vec<u8>> {
+        let mut packet = self.header?;
+        let len = packet.len() - ip_offset + self.len?;
+        if len > MAX_DATAGRAM_LEN {
+            return None;
+        }
+        self.fragments.sort_unstable_by_key(|(offset, _)| *offset);
+        packet.reserve(self.len?);
+        for (_, data) in &self.fragments {
+            packet.extend_from_slice(data);
+        }
+
+        let ip = &mut packet[ip_offset..];
+        ip[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+        let field = u16::from_be_bytes([ip[6], ip[7]]) & FLAG_DF;
+        ip[6..8].copy_from_slice(&field.to_be_bytes());
+        checksum::fill_ipv4_header(ip);
+        Some(packet)
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self {
+            max_bytes: 4 * 1024 * 1024,
+            timeout: Duration::from_secs(30),
+            buffered: 0,
+            datagrams: HashMap::new(),
+        }
+    }
+}
+
+impl Reassembler {
+    /// Creates a reassembler, buffering up to 4 MiB of fragments for 30 seconds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Memory limit for buffered fragments, including their headers.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Time to wait for missing fragments of a packet.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns number of bytes in buffered fragments.
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    fn remove(&mut self, id: &DatagramId) -> Option<Datagram> {
+        let datagram = self.datagrams.remove(id)?;
+        self.buffered -= datagram.bytes;
+        Some(datagram)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let buffered = &mut self.buffered;
+        self.datagrams.retain(|_, datagram| {
+            let alive = datagram.deadline > now;
+            if !alive {
+                *buffered -= datagram.bytes;
+            }
+            alive
+        });
+    }
+
+    /// Evicts the oldest datagrams, until `bytes` more fit into the limit.
+    fn make_room(&mut self, bytes: usize) {
+        while self.buffered + bytes > self.max_bytes {
+            let oldest = self
+                .datagrams
+                .iter()
+                .min_by_key(|(_, datagram)| datagram.deadline)
+                .map(|(id, _)| *id);
+            match oldest {
+                Some(id) => self.remove(&id),
+                None => return,
+            };
+        }
+    }
+
+    /// Buffers a fragment, returning the packet, when it is complete.
+    fn add(&mut self, packet: &[u8], offset: usize, header: &Ipv4Header) -> Option<Vec<u8>> {
+        let ip = &packet[offset..];
+        let id = DatagramId {
+            src: ip[12..16].try_into().unwrap(),
+            dst: ip[16..20].try_into().unwrap(),
+            protocol: ip[9],
+            id: u16::from_be_bytes([ip[4], ip[5]]),
+        };
+        let data = &ip[header.len..header.total_len];
+        let start = header.offset();
+        let end = start + data.len();
+        let last = !header.more_fragments();
+        // Fragments, except the last one, carry multiples of 8 bytes
+        if data.is_empty()
+            || (!last && data.len() % 8 != 0)
+            || IPV4_MIN_HEADER_LEN + end > MAX_DATAGRAM_LEN
+            || packet.len() > self.max_bytes
+        {
+            self.remove(&id);
+            return None;
+        }
+
+        let now = Instant::now();
+        self.expire(now);
+        // Evicts the oldest datagram, which may be the one of the fragment
+        self.make_room(packet.len());
+        let deadline = now + self.timeout;
+        let datagram = self.datagrams.entry(id).or_insert_with(|| Datagram {
+            header: None,
+            fragments: vec![],
+            len: None,
+            bytes: 0,
+            deadline,
+        });
+        let inconsistent = match datagram.len {
+            Some(len) => end > len || (last && end != len),
+            None => last && datagram.overlaps(end, usize::MAX),
+        };
+        if inconsistent || datagram.overlaps(start, end) {
+            self.remove(&id);
+            return None;
+        }
+
+        if last {
+            datagram.len = Some(end);
+        }
+        if start == 0 {
+            datagram.header = Some(packet[..offset + header.len].to_vec());
+        }
+        datagram.fragments.push((start, data.to_vec()));
+        datagram.bytes += packet.len();
+        self.buffered += packet.len();
+        if !datagram.is_complete() {
+            return None;
+        }
+        self.remove(&id)?.into_packet(offset)
+    }
+}
+
+impl PacketHook for Reassembler {
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        let offset = match ip_offset(packet, ctx.layer()) {
+            Some(offset) => offset,
+            None => return Verdict::Pass,
+        };
+        let header = match Ipv4Header::parse(&packet[offset..]) {
+            Some(header) if header.fragment & (FLAG_MF | OFFSET_MASK) != 0 => header,
+            _ => return Verdict::Pass,
+        };
+
+        match self.add(packet, offset, &header) {
+            Some(reassembled) => {
+                *packet = reassembled;
+                Verdict::Pass
+            }
+            None => Verdict::Drop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Layer;
+    use crate::packet::checksum::is_valid;
+    use crate::packet::{ethernet, ipv4, ipv6, ETHERTYPE_IPV4};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    /// Experimental protocol, whose payload has no checksum.
+    const PROTO_TEST: u8 = 253;
+    const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    fn packet(len: usize) -> Vec<u8> {
+        let (src, dst) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+        ipv4(
+            src,
+            dst,
+            PROTO_TEST,
+            64,
+            &payload(len - IPV4_MIN_HEADER_LEN),
+        )
+    }
+
+    /// Fragment of a packet with 64 payload bytes.
+    fn fragment(start: usize, end: usize, more: bool) -> Vec<u8> {
+        let (src, dst) = (Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2));
+        let mut fragment = ipv4(src, dst, PROTO_TEST, 64, &payload(64)[start..end]);
+        let mut field = (start / 8) as u16;
+        if more {
+            field |= FLAG_MF;
+        }
+        fragment[4..6].copy_from_slice(&0x1234u16.to_be_bytes());
+        fragment[6..8].copy_from_slice(&field.to_be_bytes());
+        checksum::fill_ipv4_header(&mut fragment);
+        fragment
+    }
+
+    fn fragment_all(fragmenter: &mut Fragmenter, packet: &[u8], layer: Layer) -> Vec<Vec<u8>> {
+        let mut ctx = HookContext::new(layer);
+        let verdict = fragmenter.on_write(&mut packet.to_vec(), &mut ctx);
+        assert_eq!(verdict, Verdict::Drop);
+        ctx.to_device.into()
+    }
+
+    /// Reads fragments, returning the packets, that pass.
+    fn reassemble(reassembler: &mut Reassembler, fragments: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let mut ctx = HookContext::new(Layer::L3);
+        fragments
+            .iter()
+            .filter_map(|fragment| {
+                let mut packet = fragment.clone();
+                match reassembler.on_read(&mut packet, &mut ctx) {
+                    Verdict::Pass => Some(packet),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn large_packets_are_fragmented() {
+        let packet = packet(1500);
+        let fragments = fragment_all(&mut Fragmenter::new(576), &packet, Layer::L3);
+
+        let lens: Vec<_> = fragments.iter().map(Vec::len).collect();
+        assert_eq!(lens, [572, 572, 396]);
+        let fields: Vec<_> = fragments
+            .iter()
+            .map(|fragment| u16::from_be_bytes([fragment[6], fragment[7]]))
+            .collect();
+        assert_eq!(fields, [FLAG_MF, FLAG_MF | 69, 138]);
+        for fragment in &fragments {
+            assert_eq!(fragment[2..4], (fragment.len() as u16).to_be_bytes());
+            assert_eq!(fragment[4..6], packet[4..6]);
+            assert!(is_valid(&fragment[..IPV4_MIN_HEADER_LEN]));
+        }
+    }
+
+    #[test]
+    fn fragments_keep_link_layer_header() {
+        let frame = ethernet(MAC, MAC, ETHERTYPE_IPV4, &packet(1500));
+        let fragments = fragment_all(&mut Fragmenter::new(1000), &frame, Layer::L2);
+        assert_eq!(fragments.len(), 2);
+        for fragment in &fragments {
+            assert_eq!(fragment[..14], frame[..14]);
+            assert!(fragment.len() - 14 <= 1000);
+        }
+    }
+
+    #[test]
+    fn packets_with_df_are_rejected() {
+        let mut packet = packet(1500);
+        packet[6] |= (FLAG_DF >> 8) as u8;
+        let mut ctx = HookContext::new(Layer::L3);
+        let verdict = Fragmenter::new(1280).on_write(&mut packet, &mut ctx);
+        assert_eq!(
+            verdict,
+            Verdict::Reject(IcmpError::PacketTooBig { mtu: 1280 })
+        );
+    }
+
+    #[test]
+    fn small_and_ipv6_packets_are_not_fragmented() {
+        let (src, dst) = (Ipv6Addr::LOCALHOST, Ipv6Addr::LOCALHOST);
+        let large_ipv6 = ipv6(src, dst, PROTO_TEST, 64, &[0; 1500]);
+        let mut fragmenter = Fragmenter::new(1280);
+        let mut ctx = HookContext::new(Layer::L3);
+        for mut packet in [packet(1280), large_ipv6] {
+            assert_eq!(fragmenter.on_write(&mut packet, &mut ctx), Verdict::Pass);
+        }
+        assert!(ctx.to_device.is_empty());
+    }
+
+    #[test]
+    fn only_copied_options_are_repeated() {
+        let mut header = packet(IPV4_MIN_HEADER_LEN)[..IPV4_MIN_HEADER_LEN].to_vec();
+        // Record route, which is not copied, and router alert, which is
+        header.extend_from_slice(&[7, 7, 4, 0, 0, 0, 0, 0x94, 4, 0, 0, OPTION_END]);
+        header[0] = 0x48;
+
+        let copied = copied_options(&header);
+        assert_eq!(copied[0], 0x46);
+        assert_eq!(copied[IPV4_MIN_HEADER_LEN..], [0x94, 4, 0, 0]);
+    }
+
+    #[test]
+    fn fragments_are_reassembled_in_order() {
+        let packet = packet(1500);
+        let fragments = fragment_all(&mut Fragmenter::new(576), &packet, Layer::L3);
+        let mut reassembler = Reassembler::new();
+        assert_eq!(reassemble(&mut reassembler, &fragments), [packet]);
+        assert_eq!(reassembler.buffered(), 0);
+    }
+
+    #[test]
+    fn fragments_are_reassembled_out_of_order() {
+        let packet = packet(1500);
+        let mut fragments = fragment_all(&mut Fragmenter::new(576), &packet, Layer::L3);
+        fragments.reverse();
+        fragments.swap(0, 1);
+        let mut reassembler = Reassembler::new();
+        assert_eq!(reassemble(&mut reassembler, &fragments), [packet]);
+        assert_eq!(reassembler.buffered(), 0);
+    }
+
+    #[test]
+    fn overlapping_fragments_discard_packet() {
+        let fragments = [
+            fragment(0, 32, true),
+            fragment(48, 64, false),
+            fragment(24, 48, true),
+        ];
+        let mut reassembler = Reassembler::new();
+        assert!(reassemble(&mut reassembler, &fragments).is_empty());
+        assert_eq!(reassembler.buffered(), 0);
+
+        // Missing fragment is not taken from the discarded packet
+        let rest = [fragment(32, 48, true)];
+        assert!(reassemble(&mut reassembler, &rest).is_empty());
+    }
+
+    #[test]
+    fn incomplete_packets_time_out() {
+        let mut reassembler = Reassembler::new().timeout(Duration::ZERO);
+        let first = [fragment(0, 32, true)];
+        assert!(reassemble(&mut reassembler, &first).is_empty());
+        assert_eq!(reassembler.buffered(), first[0].len());
+
+        let last = [fragment(32, 64, false)];
+        assert!(reassemble(&mut reassembler, &last).is_empty());
+        // Only the last fragment is buffered
+        assert_eq!(reassembler.buffered(), last[0].len());
+    }
+
+    #[test]
+    fn oldest_packets_are_evicted() {
+        let mut reassembler = Reassembler::new().max_bytes(100);
+        let first = [fragment(0, 32, true)];
+        assert!(reassemble(&mut reassembler, &first).is_empty());
+
+        // Fragment of another packet does not fit with the first one
+        let mut other = [fragment(0, 32, true)];
+        other[0][5] = 0x35;
+        checksum::fill_ipv4_header(&mut other[0]);
+        assert!(reassemble(&mut reassembler, &other).is_empty());
+        assert_eq!(reassembler.buffered(), other[0].len());
+
+        let last = [fragment(32, 64, false)];
+        assert!(reassemble(&mut reassembler, &last).is_empty());
+    }
+
+    #[test]
+    fn malformed_fragments_are_dropped() {
+        let mut reassembler = Reassembler::new();
+        // Fragments, except the last one, carry multiples of 8 bytes
+        let fragments = [fragment(0, 30, true), fragment(8, 8, false)];
+        assert!(reassemble(&mut reassembler, &fragments).is_empty());
+        assert_eq!(reassembler.buffered(), 0);
+    }
+
+    #[test]
+    fn whole_and_truncated_packets_pass() {
+        let mut truncated = fragment(0, 32, true);
+        truncated.truncate(30);
+        let packets = [packet(100), truncated, vec![0x45; 4]];
+        let mut reassembler = Reassembler::new();
+        assert_eq!(reassemble(&mut reassembler, &packets), packets);
+        assert_eq!(reassembler.buffered(), 0);
+    }
+}
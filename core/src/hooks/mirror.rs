@@ -0,0 +1,217 @@
+use super::{HookContext, PacketHook, Verdict};
+use crate::hooks::Direction;
+use crate::sampling::{Anomaly, SampledLog, Sampling};
+use crate::sync::{Arc, Condvar, Mutex, MutexGuard};
+use futures::Stream;
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+/// Copy of a packet, that passed the mirrored queue.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MirroredPacket {
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+struct Shared {
+    packets: VecDeque<MirroredPacket>,
+    capacity: usize,
+    dropped: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+struct Inner {
+    shared: Mutex<Shared>,
+    ready: Condvar,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Hook, that copies packets of both directions to a [`MonitorQueue`], without changing
+/// them.
+///
+/// Packets are copied, as they are seen at the position of the hook in the chain: push it
+/// first to see written packets after all other hooks, and last to see read packets after
+/// them. Monitor never slows the data path: when it is full, copies are dropped and counted.
+pub struct Mirror {
+    inner: Arc<Inner>,
+    anomalies: SampledLog,
+}
+
+impl Mirror {
+    /// Creates a hook and its monitor queue, that buffers up to `capacity` packets.
+    pub fn new(capacity: usize) -> (Self, MonitorQueue) {
+        let inner = Arc::new(Inner {
+            shared: Mutex::new(Shared {
+                packets: VecDeque::new(),
+                capacity,
+                dropped: 0,
+                closed: false,
+                waker: None,
+            }),
+            ready: Condvar::new(),
+        });
+        let monitor = MonitorQueue {
+            inner: inner.clone(),
+        };
+        let anomalies = SampledLog::new("monitor", Sampling::default());
+        (Self { inner, anomalies }, monitor)
+    }
+
+    fn mirror(&self, direction: Direction, packet: &[u8]) {
+        let mut shared = self.inner.lock();
+        if shared.packets.len() >= shared.capacity {
+            shared.dropped += 1;
+            drop(shared);
+            self.anomalies
+                .record(Anomaly::QueueFull, || format!("{} bytes", packet.len()));
+            return;
+        }
+        shared.packets.push_back(MirroredPacket {
+            direction,
+            data: packet.to_vec(),
+        });
+        let waker = shared.waker.take();
+        drop(shared);
+        self.inner.ready.notify_one();
+        waker.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl Drop for Mirror {
+    fn drop(&mut self) {
+        let waker = {
+            let mut shared = self.inner.lock();
+            shared.closed = true;
+            shared.waker.take()
+        };
+        self.inner.ready.notify_all();
+        waker.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl PacketHook for Mirror {
+    fn on_read(&mut self, packet: &mut Vec<u8>, _ctx: &mut HookContext) -> Verdict {
+        self.mirror(Direction::Read, packet);
+        Verdict::Pass
+    }
+
+    fn on_write(&mut self, packet: &mut Vec<u8>, _ctx: &mut HookContext) -> Verdict {
+        self.mirror(Direction::Write, packet);
+        Verdict::Pass
+    }
+}
+
+/// Read-only queue, that receives copies of packets from a [`Mirror`] hook. Packets cannot be
+/// injected through it.
+///
+/// Queue ends, when the hook is dropped together with the mirrored queue: buffered packets are
+/// returned, and then [`recv`](Self::recv) returns `None`, reads return 0 and the stream ends.
+pub struct MonitorQueue {
+    inner: Arc<Inner>,
+}
+
+impl MonitorQueue {
+    /// Blocks until a packet is mirrored, or the hook is dropped.
+    pub fn recv(&mut self) -> Option<MirroredPacket> {
+        let mut shared = self.inner.lock();
+        loop {
+            if let Some(packet) = shared.packets.pop_front() {
+                return Some(packet);
+            }
+            if shared.closed {
+                return None;
+            }
+            shared = self
+                .inner
+                .ready
+                .wait(shared)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    pub fn try_recv(&mut self) -> Option<MirroredPacket> {
+        self.inner.lock().packets.pop_front()
+    }
+
+    /// Packets, that were not mirrored, because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.inner.lock().dropped
+    }
+}
+
+/// Reads data of a single packet, truncating it to the buffer.
+impl Read for MonitorQueue {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(match self.recv() {
+            Some(packet) => {
+                let n = packet.data.len().min(buf.len());
+                buf[..n].copy_from_slice(&packet.data[..n]);
+                n
+            }
+            None => 0,
+        })
+    }
+}
+
+impl Stream for MonitorQueue {
+    type Item = MirroredPacket;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared = self.inner.lock();
+        match shared.packets.pop_front() {
+            Some(packet) => Poll::Ready(Some(packet)),
+            None if shared.closed => Poll::Ready(None),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod tests {
+    use super::*;
+    use crate::config::Layer;
+    use futures::StreamExt;
+    use loom::thread;
+
+    #[test]
+    fn mirrored_packet_wakes_stream() {
+        loom::model(|| {
+            let (mut mirror, mut monitor) = Mirror::new(1);
+            let reader = thread::spawn(move || loom::future::block_on(monitor.next()));
+            mirror.on_read(&mut vec![1], &mut HookContext::new(Layer::L3));
+            let packet = reader.join().unwrap().unwrap();
+            assert_eq!(packet.data, [1]);
+        });
+    }
+
+    #[test]
+    fn dropped_hook_ends_blocked_queue() {
+        loom::model(|| {
+            let (mirror, mut monitor) = Mirror::new(1);
+            let reader = thread::spawn(move || monitor.recv());
+            drop(mirror);
+            assert!(reader.join().unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn dropped_hook_ends_stream() {
+        loom::model(|| {
+            let (mirror, mut monitor) = Mirror::new(1);
+            let reader = thread::spawn(move || loom::future::block_on(monitor.next()));
+            drop(mirror);
+            assert!(reader.join().unwrap().is_none());
+        });
+    }
+}
@@ -0,0 +1,729 @@
+//! Packet hooks: opt-in transforms, applied to packets on their way through a queue.
+//!
+//! Hooks are attached to any sync or async queue by wrapping it into [`Hooked`]. Packets,
+//! read from the device, pass [`PacketHook::on_read`] of every hook in order, before they are
+//! returned to the application, and packets, written by the application, pass
+//! [`PacketHook::on_write`], before they reach the device. Hooks can modify packets in place,
+//! drop them and inject new packets in either direction through [`HookContext`].
+mod checksum;
+mod dhcp;
+mod filter;
+mod fragment;
+mod mirror;
+mod mss;
+mod nat64;
+mod neighbor;
+mod ra;
+
+pub use checksum::FillChecksums;
+pub use dhcp::DhcpServer;
+pub use filter::{Direction, Filter, MtuLimit};
+pub use fragment::{Fragmenter, Reassembler};
+pub use mirror::{Mirror, MirroredPacket, MonitorQueue};
+pub use mss::MssClamp;
+pub use nat64::Nat64;
+pub use neighbor::NeighborProxy;
+pub use ra::RouterAdvertiser;
+
+use crate::config::Layer;
+use crate::packet::icmp::{self, IcmpError};
+use crate::sampling::{Anomaly, SampledLog, Sampling};
+use crate::traits::{copy_packet, recv_owned, AsyncQueueT, SyncQueueT, MAX_PACKET_LEN};
+use futures::{AsyncRead, AsyncWrite};
+use futures_timer::Delay;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+
+/// Locally administered MAC address, used by default as the source of frames, generated
+/// by hooks.
+pub const DEFAULT_MAC: [u8; 6] = [0x02, 0x00, 0x5e, 0x00, 0x00, 0x01];
+
+/// Decision of a hook about a packet.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Verdict {
+    /// Packet is passed to the next hook, possibly modified.
+    Pass,
+    /// Packet is silently dropped. Hooks, that replace a packet with other ones, drop it after
+    /// injecting replacements into [`HookContext`].
+    Drop,
+    /// Packet is dropped, and an ICMP error is sent back to its source: through the device for
+    /// read packets and to the reader for written ones.
+    Reject(IcmpError),
+}
+
+/// Injects packets from hooks. Injected packets do not pass through hooks.
+pub struct HookContext {
+    layer: Layer,
+    to_device: VecDeque<Vec<u8>>,
+    to_reader: VecDeque<Vec<u8>>,
+}
+
+impl HookContext {
+    fn new(layer: Layer) -> Self {
+        Self {
+            layer,
+            to_device: VecDeque::new(),
+            to_reader: VecDeque::new(),
+        }
+    }
+
+    /// Layer of the queue: [`Layer::L2`] packets start with an Ethernet header.
+    pub fn layer(&self) -> Layer {
+        self.layer
+    }
+
+    /// Queues a packet to be written to the device, towards the OS network stack.
+    pub fn send_to_device(&mut self, packet: Vec<u8>) {
+        self.to_device.push_back(packet);
+    }
+
+    /// Queues a packet to be returned by a subsequent read, towards the application.
+    pub fn send_to_reader(&mut self, packet: Vec<u8>) {
+        self.to_reader.push_back(packet);
+    }
+}
+
+/// Entry points for fuzz targets, that call hooks directly, without a queue.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+impl HookContext {
+    pub fn detached(layer: Layer) -> Self {
+        Self::new(layer)
+    }
+
+    /// Takes packets, injected towards the device and towards the reader.
+    pub fn take_injected(&mut self) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
+        (
+            mem::take(&mut self.to_device).into(),
+            mem::take(&mut self.to_reader).into(),
+        )
+    }
+}
+
+pub trait PacketHook: Send {
+    /// Called for a packet, read from the device, before it is returned to the application.
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        let _ = (packet, ctx);
+        Verdict::Pass
+    }
+
+    /// Called for a packet, written by the application, before it is written to the device.
+    fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        let _ = (packet, ctx);
+        Verdict::Pass
+    }
+
+    /// Called on the first queue operation and after the returned deadline passes, for hooks,
+    /// that emit packets on their own. Returns the next deadline, if any.
+    ///
+    /// Async queues wake pending reads at the deadline, while sync ones only call timers
+    /// before reads and writes, so deadlines are late, until there is traffic.
+    fn on_timer(&mut self, now: Instant, ctx: &mut HookContext) -> Option<Instant> {
+        let _ = (now, ctx);
+        None
+    }
+}
+
+/// Queue with a chain of [`PacketHook`]s.
+///
+/// Writes report the whole buffer as written, even if hooks modified or dropped the packet.
+/// Reads truncate packets, that do not fit into the buffer, like the devices do.
+pub struct Hooked<Q> {
+    inner: Q,
+    hooks: Vec<Box<dyn PacketHook>>,
+    ctx: HookContext,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    timers: Timers,
+    anomalies: SampledLog,
+}
+
+/// Earliest deadline of hook timers.
+enum Timers {
+    /// Timers were not called yet, since hooks were added.
+    Unstarted,
+    Idle,
+    Armed(Instant, Option<Delay>),
+}
+
+impl<Q> Hooked<Q> {
+    /// Wraps a queue of given layer without hooks.
+    pub fn new(inner: Q, layer: Layer) -> Self {
+        Self {
+            inner,
+            hooks: vec![],
+            ctx: HookContext::new(layer),
+            read_buf: vec![],
+            write_buf: vec![],
+            timers: Timers::Unstarted,
+            anomalies: SampledLog::new("hooks", Sampling::default()),
+        }
+    }
+
+    /// Logs packets, that hooks dropped or rejected, as `name` with given sampling, instead of
+    /// the default one.
+    pub fn anomaly_log(mut self, name: impl Into<String>, sampling: Sampling) -> Self {
+        self.anomalies = SampledLog::new(name, sampling);
+        self
+    }
+
+    pub fn anomalies(&self) -> &SampledLog {
+        &self.anomalies
+    }
+
+    /// Appends a hook to the chain.
+    pub fn push(&mut self, hook: impl PacketHook + 'static) {
+        self.hooks.push(Box::new(hook));
+        self.timers = Timers::Unstarted;
+    }
+
+    /// Appends a [`Mirror`] hook and returns its read-only queue, that receives copies of
+    /// packets of both directions, buffering up to `capacity` of them.
+    pub fn monitor(&mut self, capacity: usize) -> MonitorQueue {
+        let (mirror, monitor) = Mirror::new(capacity);
+        self.push(mirror);
+        monitor
+    }
+
+    pub fn with(mut self, hook: impl PacketHook + 'static) -> Self {
+        self.push(hook);
+        self
+    }
+
+    pub fn get_ref(&self) -> &Q {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut Q {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped queue. Injected packets, that were not delivered yet, are lost.
+    pub fn into_inner(self) -> Q {
+        self.inner
+    }
+
+    /// Calls hook timers, that are due.
+    fn run_timers(&mut self) {
+        let now = Instant::now();
+        match self.timers {
+            Timers::Unstarted => {}
+            Timers::Armed(deadline, _) if deadline <= now => {}
+            Timers::Armed(..) | Timers::Idle => return,
+        }
+
+        let deadline = self
+            .hooks
+            .iter_mut()
+            .filter_map(|hook| hook.on_timer(now, &mut self.ctx))
+            .min();
+        self.timers = match deadline {
+            Some(deadline) => Timers::Armed(deadline, None),
+            None => Timers::Idle,
+        };
+    }
+
+    /// Returns `Ready`, when a timer is due, registering a wakeup otherwise.
+    fn poll_timers(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.timers {
+            Timers::Unstarted => Poll::Ready(()),
+            Timers::Idle => Poll::Pending,
+            Timers::Armed(deadline, delay) => {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                ready!(Pin::new(delay.get_or_insert_with(|| Delay::new(wait))).poll(cx));
+                // Timer may fire early, it is due anyway
+                *deadline = Instant::now().min(*deadline);
+                Poll::Ready(())
+            }
+        }
+    }
+
+    /// Returns `true`, if the read packet passed all hooks. Rejected packets are answered
+    /// through the device.
+    fn run_read_hooks(&mut self) -> bool {
+        for hook in &mut self.hooks {
+            let verdict = hook.on_read(&mut self.read_buf, &mut self.ctx);
+            if verdict != Verdict::Pass {
+                let len = self.read_buf.len();
+                self.anomalies
+                    .record(Anomaly::Filtered, || format!("read, {len} bytes"));
+            }
+            match verdict {
+                Verdict::Pass => {}
+                Verdict::Drop => return false,
+                Verdict::Reject(error) => {
+                    if let Some(reply) = icmp::error_for(&self.read_buf, self.ctx.layer, error) {
+                        self.ctx.send_to_device(reply);
+                    }
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Returns `true`, if the written packet passed all hooks. Rejected packets are answered
+    /// to the reader.
+    fn run_write_hooks(&mut self, buf: &[u8]) -> bool {
+        self.write_buf.clear();
+        self.write_buf.extend_from_slice(buf);
+        for hook in &mut self.hooks {
+            let verdict = hook.on_write(&mut self.write_buf, &mut self.ctx);
+            if verdict != Verdict::Pass {
+                let len = self.write_buf.len();
+                self.anomalies
+                    .record(Anomaly::Filtered, || format!("write, {len} bytes"));
+            }
+            match verdict {
+                Verdict::Pass => {}
+                Verdict::Drop => return false,
+                Verdict::Reject(error) => {
+                    if let Some(reply) = icmp::error_for(&self.write_buf, self.ctx.layer, error) {
+                        self.ctx.send_to_reader(reply);
+                    }
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl<Q: Write> Hooked<Q> {
+    fn flush_to_device(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.ctx.to_device.pop_front() {
+            self.inner.write_all(&packet)?;
+        }
+        Ok(())
+    }
+}
+
+impl<Q: Read + Write> Hooked<Q> {
+    fn recv_packet(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        loop {
+            self.run_timers();
+            self.flush_to_device()?;
+            if let Some(packet) = self.ctx.to_reader.pop_front() {
+                return Ok(copy_packet(&packet, buf));
+            }
+
+            self.read_buf.resize(MAX_PACKET_LEN, 0);
+            let n = self.inner.read(&mut self.read_buf)?;
+            if n == 0 {
+                return Ok((0, false));
+            }
+            self.read_buf.truncate(n);
+            if self.run_read_hooks() {
+                return Ok(copy_packet(&self.read_buf, buf));
+            }
+        }
+    }
+}
+
+impl<Q: Read + Write> Read for Hooked<Q> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv_packet(buf).map(|(n, _)| n)
+    }
+}
+
+impl<Q: Write> Write for Hooked<Q> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.run_timers();
+        if self.run_write_hooks(buf) {
+            self.inner.write_all(&self.write_buf)?;
+        }
+        self.flush_to_device()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_to_device()?;
+        self.inner.flush()
+    }
+}
+
+impl<Q: SyncQueueT> SyncQueueT for Hooked<Q> {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.recv_packet(buf)
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.write(packet).map(drop)
+    }
+
+    type PacketRef<'a>
+        = Vec<u8>
+    where
+        Q: 'a;
+
+    fn recv_ref(&mut self) -> io::Result<Vec<u8>> {
+        recv_owned(self)
+    }
+
+    /// Discards packets, injected for the reader, and packets of the device. Discarded
+    /// packets do not pass the hooks.
+    fn drain(&mut self) -> io::Result<usize> {
+        let injected = mem::take(&mut self.ctx.to_reader).len();
+        Ok(injected + SyncQueueT::drain(&mut self.inner)?)
+    }
+}
+
+impl<Q: AsyncWrite + Unpin> Hooked<Q> {
+    fn poll_flush_to_device(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(packet) = self.ctx.to_device.front() {
+            let result = ready!(Pin::new(&mut self.inner).poll_write(cx, packet));
+            self.ctx.to_device.pop_front();
+            result?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<Q: AsyncRead + AsyncWrite + Unpin> Hooked<Q> {
+    fn poll_recv_packet(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        loop {
+            self.run_timers();
+            // Injected packets are delivered as the device accepts them, without blocking reads
+            if let Poll::Ready(Err(e)) = self.poll_flush_to_device(cx) {
+                return Poll::Ready(Err(e));
+            }
+            if let Some(packet) = self.ctx.to_reader.pop_front() {
+                return Poll::Ready(Ok(copy_packet(&packet, buf)));
+            }
+
+            self.read_buf.resize(MAX_PACKET_LEN, 0);
+            let n = match Pin::new(&mut self.inner).poll_read(cx, &mut self.read_buf) {
+                Poll::Ready(result) => result?,
+                Poll::Pending => match self.poll_timers(cx) {
+                    Poll::Ready(()) => continue,
+                    Poll::Pending => return Poll::Pending,
+                },
+            };
+            if n == 0 {
+                return Poll::Ready(Ok((0, false)));
+            }
+            self.read_buf.truncate(n);
+            if self.run_read_hooks() {
+                return Poll::Ready(Ok(copy_packet(&self.read_buf, buf)));
+            }
+        }
+    }
+}
+
+impl<Q: AsyncRead + AsyncWrite + Unpin> AsyncRead for Hooked<Q> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_recv_packet(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl<Q: AsyncWrite + Unpin> AsyncWrite for Hooked<Q> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let self_mut = self.get_mut();
+        self_mut.run_timers();
+        ready!(self_mut.poll_flush_to_device(cx))?;
+
+        if self_mut.run_write_hooks(buf) {
+            match Pin::new(&mut self_mut.inner).poll_write(cx, &self_mut.write_buf) {
+                Poll::Ready(result) => {
+                    result?;
+                }
+                Poll::Pending => {
+                    let packet = mem::take(&mut self_mut.write_buf);
+                    self_mut.ctx.to_device.push_front(packet);
+                }
+            }
+        }
+        if let Poll::Ready(Err(e)) = self_mut.poll_flush_to_device(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_flush_to_device(cx))?;
+        Pin::new(&mut self_mut.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_flush_to_device(cx))?;
+        Pin::new(&mut self_mut.inner).poll_close(cx)
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncQueueT for Hooked<Q> {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        self.get_mut().poll_recv_packet(cx, buf)
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        self.poll_write(cx, packet).map_ok(drop)
+    }
+
+    /// Injected packets are ready at once. Packets of the inner queue may still be dropped by
+    /// read hooks.
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        loop {
+            self_mut.run_timers();
+            if !self_mut.ctx.to_reader.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            match Pin::new(&mut self_mut.inner).poll_recv_ready(cx) {
+                Poll::Ready(result) => return Poll::Ready(result),
+                Poll::Pending => match self_mut.poll_timers(cx) {
+                    Poll::Ready(()) => continue,
+                    Poll::Pending => return Poll::Pending,
+                },
+            }
+        }
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        let injected = mem::take(&mut self.ctx.to_reader).len();
+        Ok(injected + AsyncQueueT::drain(&mut self.inner)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::icmp::Unreachable;
+    use crate::packet::{ipv4, udp, PROTO_ICMP};
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Sync queue, that returns scripted packets, and then end of stream, and keeps written
+    /// ones.
+    #[derive(Default)]
+    struct Wire {
+        incoming: VecDeque<Vec<u8>>,
+        written: Vec<Vec<u8>>,
+    }
+
+    impl SyncQueueT for Wire {
+        fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+            match self.incoming.pop_front() {
+                Some(packet) => Ok(copy_packet(&packet, buf)),
+                None => Ok((0, false)),
+            }
+        }
+
+        fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+            self.written.push(packet.to_vec());
+            Ok(())
+        }
+
+        type PacketRef<'a> = Vec<u8>;
+
+        fn recv_ref(&mut self) -> io::Result<Vec<u8>> {
+            recv_owned(self)
+        }
+    }
+
+    impl Read for Wire {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.recv(buf).map(|(n, _)| n)
+        }
+    }
+
+    impl Write for Wire {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.send(buf).map(|()| buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn wire(incoming: &[&[u8]]) -> Wire {
+        Wire {
+            incoming: incoming.iter().map(|packet| packet.to_vec()).collect(),
+            written: vec![],
+        }
+    }
+
+    /// Appends its tag to packets of both directions and counts them.
+    struct Tag(u8, Arc<AtomicUsize>);
+
+    impl Tag {
+        fn new(tag: u8) -> (Self, Arc<AtomicUsize>) {
+            let calls = Arc::new(AtomicUsize::new(0));
+            (Self(tag, calls.clone()), calls)
+        }
+    }
+
+    impl PacketHook for Tag {
+        fn on_read(&mut self, packet: &mut Vec<u8>, _ctx: &mut HookContext) -> Verdict {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            packet.push(self.0);
+            Verdict::Pass
+        }
+
+        fn on_write(&mut self, packet: &mut Vec<u8>, _ctx: &mut HookContext) -> Verdict {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            packet.push(self.0);
+            Verdict::Pass
+        }
+    }
+
+    /// Applies its verdict to packets, that start with the byte, and passes others.
+    struct Judge(u8, Verdict);
+
+    impl PacketHook for Judge {
+        fn on_read(&mut self, packet: &mut Vec<u8>, _ctx: &mut HookContext) -> Verdict {
+            match packet.first() == Some(&self.0) {
+                true => self.1,
+                false => Verdict::Pass,
+            }
+        }
+
+        fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+            self.on_read(packet, ctx)
+        }
+    }
+
+    /// Answers written packets to the device instead of passing them, like a responder.
+    struct Echo;
+
+    impl PacketHook for Echo {
+        fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+            let mut reply = packet.clone();
+            reply.reverse();
+            ctx.send_to_reader(reply);
+            ctx.send_to_device(vec![0xEE]);
+            Verdict::Drop
+        }
+    }
+
+    fn read(queue: &mut Hooked<Wire>) -> Vec<u8> {
+        let mut buf = [0u8; 64];
+        let (n, truncated) = queue.recv(&mut buf).unwrap();
+        assert!(!truncated);
+        buf[..n].to_vec()
+    }
+
+    #[test]
+    fn hooks_run_in_order() {
+        let (first, _) = Tag::new(1);
+        let (second, _) = Tag::new(2);
+        let mut queue = Hooked::new(wire(&[&[0xAA]]), Layer::L3)
+            .with(first)
+            .with(second);
+
+        assert_eq!(read(&mut queue), [0xAA, 1, 2]);
+        queue.send(&[0xBB]).unwrap();
+        assert_eq!(queue.get_ref().written, [vec![0xBB, 1, 2]]);
+    }
+
+    #[test]
+    fn dropped_packets_skip_later_hooks() {
+        let (tag, calls) = Tag::new(1);
+        let mut queue = Hooked::new(wire(&[&[7, 0], &[8, 0]]), Layer::L3)
+            .with(Judge(7, Verdict::Drop))
+            .with(tag);
+
+        // Dropped read is skipped, and the next packet is returned
+        assert_eq!(read(&mut queue), [8, 0, 1]);
+        queue.send(&[7]).unwrap();
+        queue.send(&[9]).unwrap();
+        assert_eq!(queue.get_ref().written, [vec![9, 1]]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(read(&mut queue), []);
+    }
+
+    #[test]
+    fn injected_packets_bypass_hooks() {
+        let (tag, calls) = Tag::new(1);
+        let mut queue = Hooked::new(wire(&[]), Layer::L3).with(Echo).with(tag);
+
+        // Writes report the whole packet as written, even when it is dropped
+        assert_eq!(queue.write(&[1, 2, 3]).unwrap(), 3);
+        assert_eq!(queue.get_ref().written, [vec![0xEE]]);
+        assert_eq!(read(&mut queue), [3, 2, 1]);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn injected_packets_are_truncated_like_device_reads() {
+        let mut queue = Hooked::new(wire(&[]), Layer::L3).with(Echo);
+        queue.send(&[1, 2, 3]).unwrap();
+        let mut buf = [0u8; 2];
+        assert_eq!(queue.recv(&mut buf).unwrap(), (2, true));
+        assert_eq!(buf, [3, 2]);
+    }
+
+    #[test]
+    fn rejected_write_is_answered_to_reader() {
+        let packet = ipv4(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            17,
+            64,
+            &udp(1000, 53, &[0; 4]),
+        );
+        let verdict = Verdict::Reject(IcmpError::Unreachable(Unreachable::Port));
+        let mut queue = Hooked::new(wire(&[]), Layer::L3).with(Judge(packet[0], verdict));
+
+        queue.send(&packet).unwrap();
+        assert!(queue.get_ref().written.is_empty());
+        assert_eq!(queue.anomalies().count(Anomaly::Filtered), 1);
+
+        let mut buf = [0u8; MAX_PACKET_LEN];
+        let (n, _) = queue.recv(&mut buf).unwrap();
+        let reply = &buf[..n];
+        assert_eq!(reply[9], PROTO_ICMP);
+        assert_eq!(reply[12..16], packet[16..20]);
+        assert_eq!(reply[20..22], [3, 3]);
+        // Original packet is quoted after the ICMP header
+        assert_eq!(reply[28..], packet[..]);
+    }
+
+    #[test]
+    fn rejected_read_is_answered_to_device() {
+        let packet = ipv4(
+            Ipv4Addr::new(10, 0, 0, 2),
+            Ipv4Addr::new(10, 0, 0, 1),
+            17,
+            64,
+            &udp(53, 1000, &[]),
+        );
+        let verdict = Verdict::Reject(IcmpError::Unreachable(Unreachable::Prohibited));
+        let mut queue = Hooked::new(wire(&[&packet]), Layer::L3).with(Judge(packet[0], verdict));
+
+        // Reply is flushed before the next read from the device, that reaches end of stream
+        assert_eq!(read(&mut queue), []);
+        let written = &queue.get_ref().written;
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0][16..20], packet[12..16]);
+        assert_eq!(written[0][20..22], [3, 13]);
+    }
+}
@@ -0,0 +1,255 @@
+use super::{HookContext, PacketHook, Verdict};
+use crate::mtu::MtuWatch;
+use crate::packet::{checksum, ip_offset, transport, PROTO_TCP};
+
+const TCP_FLAG_SYN: u8 = 0x02;
+const TCP_OPTION_END: u8 = 0;
+const TCP_OPTION_NOP: u8 = 1;
+const TCP_OPTION_MSS: u8 = 2;
+const TCP_CHECKSUM_OFFSET: usize = 16;
+
+/// Lowers MSS option of TCP SYN segments in both directions, so that segments of the
+/// connection fit into the tunnel MTU without fragmentation. TCP checksum is fixed up
+/// incrementally.
+///
+/// Only the options of SYN segments are changed. In IPv6 packets TCP header must directly
+/// follow the fixed header.
+pub struct MssClamp {
+    mtu: MtuWatch,
+}
+
+impl MssClamp {
+    /// Clamps MSS to `mtu` minus IP and TCP header sizes: 40 bytes for IPv4 and 60 bytes for
+    /// IPv6.
+    pub fn new(mtu: u16) -> Self {
+        Self::tracking(MtuWatch::new(u32::from(mtu)))
+    }
+
+    /// Creates a clamp, that follows MTU changes of an interface.
+    pub fn tracking(mtu: MtuWatch) -> Self {
+        Self { mtu }
+    }
+
+    fn clamp(&self, packet: &mut [u8], ctx: &HookContext) {
+        let ip_start = match ip_offset(packet, ctx.layer()) {
+            Some(offset) => offset,
+            None => return,
+        };
+        let mtu = self.mtu.get_u16();
+        let ip = &mut packet[ip_start..];
+        let (max_mss, tcp_start) = match transport(ip) {
+            Some((PROTO_TCP, offset)) if ip[0] >> 4 == 4 => (mtu.saturating_sub(40), offset),
+            Some((PROTO_TCP, offset)) => (mtu.saturating_sub(60), offset),
+            _ => return,
+        };
+        let tcp = &mut ip[tcp_start..];
+        if tcp.len() < 20 || tcp[13] & TCP_FLAG_SYN == 0 {
+            return;
+        }
+
+        let header_len = (usize::from(tcp[12] >> 4) * 4).min(tcp.len());
+        let mut i = 20;
+        while i < header_len {
+            match tcp[i] {
+                TCP_OPTION_END => return,
+                TCP_OPTION_NOP => i += 1,
+                kind => {
+                    let len = match tcp.get(i + 1) {
+                        Some(&len) if len >= 2 && i + usize::from(len) <= header_len => len,
+                        _ => return,
+                    };
+                    if kind == TCP_OPTION_MSS && len == 4 {
+                        let mss = u16::from_be_bytes([tcp[i + 2], tcp[i + 3]]);
+                        if mss > max_mss {
+                            for (k, byte) in max_mss.to_be_bytes().into_iter().enumerate() {
+                                set_byte(tcp, i + 2 + k, byte);
+                            }
+                        }
+                        return;
+                    }
+                    i += usize::from(len);
+                }
+            }
+        }
+    }
+}
+
+/// Sets a byte of TCP header, updating the checksum of the 16-bit word, containing it.
+fn set_byte(tcp: &mut [u8], pos: usize, byte: u8) {
+    let word = pos & !1;
+    let old = u16::from_be_bytes([tcp[word], tcp[word + 1]]);
+    tcp[pos] = byte;
+    let new = u16::from_be_bytes([tcp[word], tcp[word + 1]]);
+
+    let sum = u16::from_be_bytes([tcp[TCP_CHECKSUM_OFFSET], tcp[TCP_CHECKSUM_OFFSET + 1]]);
+    let sum = checksum::update(sum, old, new);
+    tcp[TCP_CHECKSUM_OFFSET..TCP_CHECKSUM_OFFSET + 2].copy_from_slice(&sum.to_be_bytes());
+}
+
+impl PacketHook for MssClamp {
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        self.clamp(packet, ctx);
+        Verdict::Pass
+    }
+
+    fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        self.clamp(packet, ctx);
+        Verdict::Pass
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Layer;
+    use crate::packet::ETHERTYPE_IPV4;
+
+    /// Sums 16-bit words of the data, see RFC 1071.
+    fn sum(data: &[u8]) -> u32 {
+        data.chunks(2)
+            .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+            .sum()
+    }
+
+    fn fold(mut sum: u32) -> u16 {
+        while sum > 0xffff {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        sum as u16
+    }
+
+    /// TCP header with given flags and options, followed by a payload byte.
+    fn tcp(flags: u8, options: &[u8]) -> Vec<u8> {
+        let header_len = 20 + options.len();
+        let mut segment = vec![0x30, 0x39, 0x00, 0x50, 0, 0, 0, 1, 0, 0, 0, 0];
+        segment.extend_from_slice(&[((header_len / 4) as u8) << 4, flags, 0xff, 0xff]);
+        segment.extend_from_slice(&[0, 0, 0, 0]);
+        segment.extend_from_slice(options);
+        segment.push(0x42);
+        segment
+    }
+
+    /// IPv4 packet from 10.0.0.1 to 10.0.0.2 with a TCP segment and correct checksums.
+    fn ipv4(segment: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 0, 0, 0, 0, 0, 64, PROTO_TCP, 0, 0];
+        packet[2..4].copy_from_slice(&((20 + segment.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend_from_slice(segment);
+        let header_sum = !fold(sum(&packet[..20]));
+        packet[10..12].copy_from_slice(&header_sum.to_be_bytes());
+        let tcp_sum = !fold(sum(&packet[12..20]) + tcp_pseudo(segment.len()) + sum(segment));
+        packet[20 + TCP_CHECKSUM_OFFSET..][..2].copy_from_slice(&tcp_sum.to_be_bytes());
+        packet
+    }
+
+    /// IPv6 packet from fd00::1 to fd00::2 with a TCP segment and correct checksum.
+    fn ipv6(segment: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x60, 0, 0, 0];
+        packet.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[PROTO_TCP, 64]);
+        for last in [1, 2] {
+            packet.extend_from_slice(&[0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, last]);
+        }
+        packet.extend_from_slice(segment);
+        let tcp_sum = !fold(sum(&packet[8..40]) + tcp_pseudo(segment.len()) + sum(segment));
+        packet[40 + TCP_CHECKSUM_OFFSET..][..2].copy_from_slice(&tcp_sum.to_be_bytes());
+        packet
+    }
+
+    /// Protocol and length of the pseudo-header.
+    fn tcp_pseudo(len: usize) -> u32 {
+        u32::from(PROTO_TCP) + len as u32
+    }
+
+    fn syn_v4(mss: u16) -> Vec<u8> {
+        let mut options = vec![TCP_OPTION_NOP, TCP_OPTION_NOP, 4, 2, TCP_OPTION_MSS, 4];
+        options.extend_from_slice(&mss.to_be_bytes());
+        ipv4(&tcp(TCP_FLAG_SYN, &options))
+    }
+
+    fn run(clamp: &mut MssClamp, packet: &mut Vec<u8>, layer: Layer) {
+        let mut ctx = HookContext::new(layer);
+        assert_eq!(clamp.on_read(packet, &mut ctx), Verdict::Pass);
+    }
+
+    /// MSS option of a packet, built by [`syn_v4`], at given offset.
+    fn mss(packet: &[u8], option_offset: usize) -> u16 {
+        u16::from_be_bytes([packet[option_offset + 2], packet[option_offset + 3]])
+    }
+
+    /// Checks TCP checksum of an IP packet, built by [`ipv4`] or [`ipv6`].
+    fn assert_checksum_valid(ip: &[u8]) {
+        let (addresses, segment) = match ip[0] >> 4 {
+            4 => (&ip[12..20], &ip[20..]),
+            _ => (&ip[8..40], &ip[40..]),
+        };
+        let total = sum(addresses) + tcp_pseudo(segment.len()) + sum(segment);
+        assert_eq!(fold(total), 0xffff);
+    }
+
+    #[test]
+    fn ipv4_syn_is_clamped_with_checksum_fixed() {
+        let mut packet = syn_v4(1460);
+        run(&mut MssClamp::new(1400), &mut packet, Layer::L3);
+        assert_eq!(mss(&packet, 20 + 24), 1360);
+        assert_checksum_valid(&packet);
+    }
+
+    #[test]
+    fn clamp_follows_mtu_changes() {
+        let watch = MtuWatch::new(1500);
+        let mut clamp = MssClamp::tracking(watch.clone());
+        watch.set(1300);
+        let mut packet = syn_v4(1460);
+        run(&mut clamp, &mut packet, Layer::L3);
+        assert_eq!(mss(&packet, 20 + 24), 1260);
+        assert_checksum_valid(&packet);
+    }
+
+    #[test]
+    fn ipv6_syn_is_clamped_with_checksum_fixed() {
+        let mut options = vec![TCP_OPTION_MSS, 4];
+        options.extend_from_slice(&1440u16.to_be_bytes());
+        let mut packet = ipv6(&tcp(TCP_FLAG_SYN | 0x10, &options));
+        run(&mut MssClamp::new(1280), &mut packet, Layer::L3);
+        assert_eq!(mss(&packet, 40 + 20), 1220);
+        assert_checksum_valid(&packet);
+    }
+
+    #[test]
+    fn frame_is_clamped_after_ethernet_header() {
+        let mut frame = [[2; 6], [4; 6]].concat();
+        frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame.extend_from_slice(&syn_v4(1460));
+        run(&mut MssClamp::new(1400), &mut frame, Layer::L2);
+        assert_eq!(mss(&frame, 14 + 20 + 24), 1360);
+        assert_checksum_valid(&frame[14..]);
+    }
+
+    #[test]
+    fn lower_mss_is_kept() {
+        let mut packet = syn_v4(536);
+        let original = packet.clone();
+        run(&mut MssClamp::new(1400), &mut packet, Layer::L3);
+        assert_eq!(packet, original);
+    }
+
+    #[test]
+    fn segments_without_syn_are_kept() {
+        let mut options = vec![TCP_OPTION_MSS, 4];
+        options.extend_from_slice(&1460u16.to_be_bytes());
+        let mut packet = ipv4(&tcp(0x10, &options));
+        let original = packet.clone();
+        run(&mut MssClamp::new(1400), &mut packet, Layer::L3);
+        assert_eq!(packet, original);
+    }
+
+    #[test]
+    fn malformed_options_are_kept() {
+        // Option length runs past the header
+        let mut packet = ipv4(&tcp(TCP_FLAG_SYN, &[TCP_OPTION_NOP, 8, 6, 0]));
+        let original = packet.clone();
+        run(&mut MssClamp::new(1400), &mut packet, Layer::L3);
+        assert_eq!(packet, original);
+    }
+}
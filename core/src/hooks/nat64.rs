@@ -0,0 +1,363 @@
+use super::{HookContext, PacketHook, Verdict};
+use crate::config::Layer;
+use crate::packet::checksum::{fill_ipv4_header, fill_transport};
+use crate::packet::{
+    IPV4_MIN_HEADER_LEN, IPV6_HEADER_LEN, PROTO_ICMP, PROTO_ICMPV6, PROTO_TCP, PROTO_UDP,
+};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const IPV4_FLAG_DF: u16 = 0x4000;
+const DEFAULT_HOP_LIMIT: u8 = 64;
+
+/// Stateless IPv4/IPv6 translator (SIIT, RFC 7915) with a /96 prefix, as used by the
+/// customer-side translator of 464XLAT (RFC 6877).
+///
+/// IPv4 packets, read from the device, are translated to IPv6 and IPv6 packets, written to the
+/// device, are translated back, if their addresses are mapped. IPv4 addresses are embedded
+/// into the prefix (RFC 6052), except for the local address, which is mapped to a dedicated
+/// IPv6 one. Native IPv6 traffic passes unchanged.
+///
+/// Only TCP, UDP and ICMP echo are translated, other IPv4 packets, including fragments,
+/// are dropped. IPv6 extension headers are not supported. Translated packets grow by 20 bytes,
+/// so read buffers must have room for them. Works on [`Layer::L3`] queues; frames of
+/// [`Layer::L2`] ones pass unchanged.
+pub struct Nat64 {
+    prefix: [u8; 12],
+    local_v4: Ipv4Addr,
+    local_v6: Ipv6Addr,
+}
+
+impl Nat64 {
+    /// Creates a translator, using the upper 96 bits of `prefix`, e.g. the well-known
+    /// `64:ff9b::/96`, and mapping `local_v4` to `local_v6`.
+    pub fn new(prefix: Ipv6Addr, local_v4: Ipv4Addr, local_v6: Ipv6Addr) -> Self {
+        let mut bytes = [0; 12];
+        bytes.copy_from_slice(&prefix.octets()[..12]);
+        Self {
+            prefix: bytes,
+            local_v4,
+            local_v6,
+        }
+    }
+
+    pub fn well_known_prefix() -> Ipv6Addr {
+        Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0)
+    }
+
+    fn map_v4(&self, addr: Ipv4Addr) -> Ipv6Addr {
+        if addr == self.local_v4 {
+            return self.local_v6;
+        }
+        let mut bytes = [0; 16];
+        bytes[..12].copy_from_slice(&self.prefix);
+        bytes[12..].copy_from_slice(&addr.octets());
+        bytes.into()
+    }
+
+    fn map_v6(&self, addr: Ipv6Addr) -> Option<Ipv4Addr> {
+        if addr == self.local_v6 {
+            return Some(self.local_v4);
+        }
+        let bytes = addr.octets();
+        match bytes[..12] == self.prefix {
+            true => Some(Ipv4Addr::new(bytes[12], bytes[13], bytes[14], bytes[15])),
+            false => None,
+        }
+    }
+
+    fn to_ipv6(&self, packet: &mut Vec<u8>) -> Verdict {
+        let ip = &packet[..];
+        let header_len = usize::from(ip[0] & 0x0f) * 4;
+        let total_len = usize::from(u16::from_be_bytes([ip[2], ip[3]]));
+        let fragment = u16::from_be_bytes([ip[6], ip[7]]);
+        // Fragments carry MF flag or non-zero offset
+        if header_len < IPV4_MIN_HEADER_LEN
+            || total_len < header_len
+            || total_len > ip.len()
+            || fragment & 0x3fff != 0
+        {
+            return Verdict::Drop;
+        }
+        let next_header = match (ip[9], ip.get(header_len)) {
+            (PROTO_TCP | PROTO_UDP, _) => ip[9],
+            (PROTO_ICMP, Some(&(ICMP_ECHO_REQUEST | ICMP_ECHO_REPLY))) => PROTO_ICMPV6,
+            _ => return Verdict::Drop,
+        };
+
+        let src = self.map_v4(Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]));
+        let dst = self.map_v4(Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]));
+        let payload = &ip[header_len..total_len];
+        let tos = ip[1];
+
+        let mut translated = Vec::with_capacity(IPV6_HEADER_LEN + payload.len());
+        translated.extend_from_slice(&[0x60 | tos >> 4, tos << 4, 0, 0]);
+        translated.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        translated.extend_from_slice(&[next_header, ip[8]]);
+        translated.extend_from_slice(&src.octets());
+        translated.extend_from_slice(&dst.octets());
+        translated.extend_from_slice(payload);
+        if next_header == PROTO_ICMPV6 {
+            translated[IPV6_HEADER_LEN] = match translated[IPV6_HEADER_LEN] {
+                ICMP_ECHO_REQUEST => ICMPV6_ECHO_REQUEST,
+                _ => ICMPV6_ECHO_REPLY,
+            };
+        }
+
+        fill_transport(&mut translated);
+        *packet = translated;
+        Verdict::Pass
+    }
+
+    fn to_ipv4(&self, packet: &mut Vec<u8>) -> Verdict {
+        let ip = &packet[..];
+        if ip.len() < IPV6_HEADER_LEN {
+            return Verdict::Pass;
+        }
+        let octets = |range: Range<usize>| {
+            let mut bytes = [0; 16];
+            bytes.copy_from_slice(&ip[range]);
+            Ipv6Addr::from(bytes)
+        };
+        let (src, dst) = match (self.map_v6(octets(8..24)), self.map_v6(octets(24..40))) {
+            (Some(src), Some(dst)) => (src, dst),
+            _ => return Verdict::Pass,
+        };
+
+        let payload_len = usize::from(u16::from_be_bytes([ip[4], ip[5]]));
+        if IPV6_HEADER_LEN + payload_len > ip.len() {
+            return Verdict::Drop;
+        }
+        let payload = &ip[IPV6_HEADER_LEN..IPV6_HEADER_LEN + payload_len];
+        let protocol = match (ip[6], payload.first()) {
+            (PROTO_TCP | PROTO_UDP, _) => ip[6],
+            (PROTO_ICMPV6, Some(&(ICMPV6_ECHO_REQUEST | ICMPV6_ECHO_REPLY))) => PROTO_ICMP,
+            _ => return Verdict::Drop,
+        };
+
+        let total_len = IPV4_MIN_HEADER_LEN + payload.len();
+        let tos = ip[0] << 4 | ip[1] >> 4;
+        let hop_limit = match ip[7] {
+            0 => DEFAULT_HOP_LIMIT,
+            hop_limit => hop_limit,
+        };
+
+        let mut translated = Vec::with_capacity(total_len);
+        translated.extend_from_slice(&[0x45, tos]);
+        translated.extend_from_slice(&(total_len as u16).to_be_bytes());
+        translated.extend_from_slice(&[0, 0]);
+        translated.extend_from_slice(&IPV4_FLAG_DF.to_be_bytes());
+        translated.extend_from_slice(&[hop_limit, protocol, 0, 0]);
+        translated.extend_from_slice(&src.octets());
+        translated.extend_from_slice(&dst.octets());
+        translated.extend_from_slice(payload);
+        if protocol == PROTO_ICMP {
+            translated[IPV4_MIN_HEADER_LEN] = match translated[IPV4_MIN_HEADER_LEN] {
+                ICMPV6_ECHO_REQUEST => ICMP_ECHO_REQUEST,
+                _ => ICMP_ECHO_REPLY,
+            };
+        }
+
+        fill_ipv4_header(&mut translated);
+        fill_transport(&mut translated);
+        *packet = translated;
+        Verdict::Pass
+    }
+}
+
+impl PacketHook for Nat64 {
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        match (ctx.layer(), packet.first().map(|byte| byte >> 4)) {
+            (Layer::L3, Some(4)) if packet.len() >= IPV4_MIN_HEADER_LEN => self.to_ipv6(packet),
+            _ => Verdict::Pass,
+        }
+    }
+
+    fn on_write(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        match (ctx.layer(), packet.first().map(|byte| byte >> 4)) {
+            (Layer::L3, Some(6)) => self.to_ipv4(packet),
+            _ => Verdict::Pass,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::checksum::is_valid;
+    use crate::packet::{ipv4, ipv6, udp};
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+    const SERVER: Ipv4Addr = Ipv4Addr::new(198, 51, 100, 2);
+
+    fn nat64() -> Nat64 {
+        Nat64::new(
+            Nat64::well_known_prefix(),
+            CLIENT,
+            "2001:db8::1".parse().unwrap(),
+        )
+    }
+
+    /// Packet, as the translator returns it: translated packets have DF set.
+    fn ipv4_df(protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = ipv4(CLIENT, SERVER, protocol, 64, payload);
+        packet[6] = 0x40;
+        packet[1] = 0xb8;
+        fill_ipv4_header(&mut packet);
+        packet
+    }
+
+    fn echo(icmp_type: u8) -> Vec<u8> {
+        let mut message = vec![icmp_type, 0, 0, 0, 0x12, 0x34, 0, 1];
+        message.extend_from_slice(b"ping");
+        message
+    }
+
+    fn tcp_syn() -> Vec<u8> {
+        let mut segment = vec![0x30, 0x39, 0x00, 0x50, 0, 0, 0, 1, 0, 0, 0, 0];
+        segment.extend_from_slice(&[0x50, 0x02, 0xff, 0xff, 0, 0, 0, 0]);
+        segment
+    }
+
+    fn read(nat: &mut Nat64, packet: &mut Vec<u8>) -> Verdict {
+        nat.on_read(packet, &mut HookContext::new(Layer::L3))
+    }
+
+    fn write(nat: &mut Nat64, packet: &mut Vec<u8>) -> Verdict {
+        nat.on_write(packet, &mut HookContext::new(Layer::L3))
+    }
+
+    fn assert_round_trip(original: Vec<u8>, next_header: u8) {
+        let mut nat = nat64();
+        let mut packet = original.clone();
+        assert_eq!(read(&mut nat, &mut packet), Verdict::Pass);
+        assert_eq!(packet.len(), original.len() + 20);
+        assert_eq!(packet[0] >> 4, 6);
+        assert_eq!(packet[6], next_header);
+        assert_eq!(packet[7], 64);
+        // Traffic Class is carried over from TOS
+        assert_eq!(packet[0] << 4 | packet[1] >> 4, 0xb8);
+        assert_eq!(
+            packet[8..24],
+            "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets()
+        );
+        assert_eq!(
+            packet[24..40],
+            "64:ff9b::c633:6402".parse::<Ipv6Addr>().unwrap().octets()
+        );
+
+        assert_eq!(write(&mut nat, &mut packet), Verdict::Pass);
+        assert_eq!(packet, original);
+        assert!(is_valid(&packet[..20]));
+    }
+
+    #[test]
+    fn tcp_round_trip() {
+        assert_round_trip(ipv4_df(PROTO_TCP, &tcp_syn()), PROTO_TCP);
+    }
+
+    #[test]
+    fn udp_round_trip() {
+        assert_round_trip(ipv4_df(PROTO_UDP, &udp(1000, 53, b"abcd")), PROTO_UDP);
+    }
+
+    #[test]
+    fn icmp_echo_round_trip() {
+        assert_round_trip(ipv4_df(PROTO_ICMP, &echo(ICMP_ECHO_REQUEST)), PROTO_ICMPV6);
+
+        let mut reply = ipv4_df(PROTO_ICMP, &echo(ICMP_ECHO_REPLY));
+        read(&mut nat64(), &mut reply);
+        assert_eq!(reply[IPV6_HEADER_LEN], ICMPV6_ECHO_REPLY);
+    }
+
+    // Checksums are computed independently, with the pseudo-header of RFC 8200 for IPv6
+    #[test]
+    fn checksums_match_known_packets() {
+        let mut packet = ipv4(CLIENT, SERVER, PROTO_UDP, 64, &udp(1000, 53, b"abcd"));
+        assert_eq!(packet[26..28], [0x4a, 0xbb]);
+        read(&mut nat64(), &mut packet);
+        assert_eq!(packet[46..48], [0xdf, 0x02]);
+
+        let mut packet = ipv4(CLIENT, SERVER, PROTO_ICMP, 64, &echo(ICMP_ECHO_REQUEST));
+        assert_eq!(packet[22..24], [0x06, 0xfa]);
+        read(&mut nat64(), &mut packet);
+        assert_eq!(packet[40..44], [ICMPV6_ECHO_REQUEST, 0, 0x36, 0xc3]);
+
+        // And back, from a packet of the translated addresses
+        let mut packet = ipv6(
+            "64:ff9b::c633:6402".parse().unwrap(),
+            "2001:db8::1".parse().unwrap(),
+            PROTO_UDP,
+            64,
+            &udp(53, 1000, b"abcd"),
+        );
+        write(&mut nat64(), &mut packet);
+        assert_eq!(packet[12..20], [198, 51, 100, 2, 192, 0, 2, 1]);
+        assert_eq!(packet[26..28], [0x4a, 0xbb]);
+    }
+
+    #[test]
+    fn fragments_are_dropped() {
+        for fragment in [0x2000u16, 0x0001, 0x4001] {
+            let mut packet = ipv4(CLIENT, SERVER, PROTO_UDP, 64, &udp(1000, 53, b"abcd"));
+            packet[6..8].copy_from_slice(&fragment.to_be_bytes());
+            fill_ipv4_header(&mut packet);
+            assert_eq!(read(&mut nat64(), &mut packet), Verdict::Drop);
+        }
+    }
+
+    #[test]
+    fn unmapped_protocols_are_dropped() {
+        // GRE and ICMP errors are not translated in either direction
+        let mut gre = ipv4(CLIENT, SERVER, 47, 64, &[0; 8]);
+        assert_eq!(read(&mut nat64(), &mut gre), Verdict::Drop);
+        let mut unreachable = ipv4(CLIENT, SERVER, PROTO_ICMP, 64, &[3, 3, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(read(&mut nat64(), &mut unreachable), Verdict::Drop);
+
+        let mut gre = ipv6(
+            "64:ff9b::c633:6402".parse().unwrap(),
+            "2001:db8::1".parse().unwrap(),
+            47,
+            64,
+            &[0; 8],
+        );
+        assert_eq!(write(&mut nat64(), &mut gre), Verdict::Drop);
+    }
+
+    #[test]
+    fn truncated_packets_are_dropped() {
+        let mut packet = ipv4(CLIENT, SERVER, PROTO_UDP, 64, &udp(1000, 53, b"abcd"));
+        packet.truncate(30);
+        assert_eq!(read(&mut nat64(), &mut packet), Verdict::Drop);
+    }
+
+    #[test]
+    fn native_ipv6_passes() {
+        let native = ipv6(
+            "2001:db8::1".parse().unwrap(),
+            "2001:db8:1::2".parse().unwrap(),
+            PROTO_UDP,
+            64,
+            &udp(1000, 53, b"abcd"),
+        );
+        let mut packet = native.clone();
+        assert_eq!(write(&mut nat64(), &mut packet), Verdict::Pass);
+        assert_eq!(read(&mut nat64(), &mut packet), Verdict::Pass);
+        assert_eq!(packet, native);
+    }
+
+    #[test]
+    fn frames_pass() {
+        let ip = ipv4(CLIENT, SERVER, PROTO_TCP, 64, &tcp_syn());
+        // Destination MAC starts like an IPv4 header
+        let mut frame = crate::packet::ethernet([0x45; 6], [4; 6], 0x0800, &ip);
+        let original = frame.clone();
+        let mut ctx = HookContext::new(Layer::L2);
+        assert_eq!(nat64().on_read(&mut frame, &mut ctx), Verdict::Pass);
+        assert_eq!(frame, original);
+    }
+}
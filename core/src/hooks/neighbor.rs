@@ -0,0 +1,270 @@
+use super::{HookContext, PacketHook, Verdict, DEFAULT_MAC};
+use crate::config::Layer;
+use crate::packet::{
+    ethernet, ethertype, ipv6, ETHERTYPE_ARP, ETHERTYPE_IPV6, ETHER_HEADER_LEN, IPV6_HEADER_LEN,
+    PROTO_ICMPV6,
+};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+const ARP_LEN: usize = 28;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+const NA_FLAG_SOLICITED: u8 = 0x40;
+const NA_FLAG_OVERRIDE: u8 = 0x20;
+const NDP_OPTION_TARGET_LINK_ADDRESS: u8 = 2;
+/// Neighbor Discovery messages are only valid with the maximum hop limit, see RFC 4861.
+const NDP_HOP_LIMIT: u8 = 255;
+
+/// Answers ARP requests and IPv6 neighbor solicitations for configured addresses with a MAC
+/// address of the proxy, so that a point-to-point TAP tunnel reaches remote addresses without
+/// a bridge.
+///
+/// Answered requests are not returned to the application. Solicitations for duplicate address
+/// detection are passed through, as the proxy must not defend addresses of the host. Works on
+/// [`Layer::L2`] queues only.
+pub struct NeighborProxy {
+    mac: [u8; 6],
+    ipv4: Vec<Ipv4Addr>,
+    ipv6: Vec<Ipv6Addr>,
+}
+
+impl Default for NeighborProxy {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAC)
+    }
+}
+
+impl NeighborProxy {
+    /// Creates a proxy, answering with `mac`, without addresses.
+    pub fn new(mac: [u8; 6]) -> Self {
+        Self {
+            mac,
+            ipv4: vec![],
+            ipv6: vec![],
+        }
+    }
+
+    pub fn ipv4(mut self, address: Ipv4Addr) -> Self {
+        self.ipv4.push(address);
+        self
+    }
+
+    pub fn ipv6(mut self, address: Ipv6Addr) -> Self {
+        self.ipv6.push(address);
+        self
+    }
+
+    fn arp_reply(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let arp = frame.get(ETHER_HEADER_LEN..ETHER_HEADER_LEN + ARP_LEN)?;
+        // Ethernet and IPv4 addresses only
+        if arp[..6] != [0, 1, 0x08, 0x00, 6, 4]
+            || u16::from_be_bytes([arp[6], arp[7]]) != ARP_REQUEST
+        {
+            return None;
+        }
+        let target = Ipv4Addr::new(arp[24], arp[25], arp[26], arp[27]);
+        if !self.ipv4.contains(&target) {
+            return None;
+        }
+
+        let mut reply = Vec::with_capacity(ARP_LEN);
+        reply.extend_from_slice(&arp[..6]);
+        reply.extend_from_slice(&ARP_REPLY.to_be_bytes());
+        reply.extend_from_slice(&self.mac);
+        reply.extend_from_slice(&target.octets());
+        // Sender hardware and protocol addresses of the request
+        reply.extend_from_slice(&arp[8..18]);
+        let requester: [u8; 6] = arp[8..14].try_into().ok()?;
+        Some(ethernet(requester, self.mac, ETHERTYPE_ARP, &reply))
+    }
+
+    fn neighbor_advertisement(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let ip = frame.get(ETHER_HEADER_LEN..)?;
+        let icmp = ip.get(IPV6_HEADER_LEN..)?;
+        if ip[6] != PROTO_ICMPV6
+            || ip[7] != NDP_HOP_LIMIT
+            || icmp.len() < 24
+            || icmp[0] != ICMPV6_NEIGHBOR_SOLICITATION
+        {
+            return None;
+        }
+        let address = |bytes: &[u8]| Ipv6Addr::from(<[u8; 16]>::try_from(bytes).unwrap());
+        let source = address(&ip[8..24]);
+        let target = address(&icmp[8..24]);
+        if source.is_unspecified() || !self.ipv6.contains(&target) {
+            return None;
+        }
+
+        let mut advertisement = vec![
+            ICMPV6_NEIGHBOR_ADVERTISEMENT,
+            0,
+            0,
+            0,
+            NA_FLAG_SOLICITED | NA_FLAG_OVERRIDE,
+            0,
+            0,
+            0,
+        ];
+        advertisement.extend_from_slice(&target.octets());
+        advertisement.extend_from_slice(&[NDP_OPTION_TARGET_LINK_ADDRESS, 1]);
+        advertisement.extend_from_slice(&self.mac);
+
+        let packet = ipv6(target, source, PROTO_ICMPV6, NDP_HOP_LIMIT, &advertisement);
+        let requester: [u8; 6] = frame[6..12].try_into().ok()?;
+        Some(ethernet(requester, self.mac, ETHERTYPE_IPV6, &packet))
+    }
+}
+
+impl PacketHook for NeighborProxy {
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        if ctx.layer() != Layer::L2 {
+            return Verdict::Pass;
+        }
+        let reply = match ethertype(packet) {
+            Some(ETHERTYPE_ARP) => self.arp_reply(packet),
+            Some(ETHERTYPE_IPV6) => self.neighbor_advertisement(packet),
+            _ => None,
+        };
+        match reply {
+            Some(reply) => {
+                ctx.send_to_device(reply);
+                Verdict::Drop
+            }
+            None => Verdict::Pass,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::checksum::is_valid;
+    use crate::packet::ETHER_BROADCAST;
+
+    const HOST_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+    const PROXY_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x42];
+    const HOST_V4: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const REMOTE_V4: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    fn host_v6() -> Ipv6Addr {
+        "fe80::5054:ff:fe12:3456".parse().unwrap()
+    }
+
+    fn remote_v6() -> Ipv6Addr {
+        "fe80::1".parse().unwrap()
+    }
+
+    fn proxy() -> NeighborProxy {
+        NeighborProxy::new(PROXY_MAC)
+            .ipv4(REMOTE_V4)
+            .ipv6(remote_v6())
+    }
+
+    fn arp(operation: u16, target: Ipv4Addr) -> Vec<u8> {
+        let mut arp = vec![0, 1, 0x08, 0x00, 6, 4];
+        arp.extend_from_slice(&operation.to_be_bytes());
+        arp.extend_from_slice(&HOST_MAC);
+        arp.extend_from_slice(&HOST_V4.octets());
+        arp.extend_from_slice(&[0; 6]);
+        arp.extend_from_slice(&target.octets());
+        ethernet(ETHER_BROADCAST, HOST_MAC, ETHERTYPE_ARP, &arp)
+    }
+
+    fn solicitation(source: Ipv6Addr, target: Ipv6Addr, hop_limit: u8) -> Vec<u8> {
+        let mut icmp = vec![ICMPV6_NEIGHBOR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+        icmp.extend_from_slice(&target.octets());
+        // Source link-layer address option
+        icmp.extend_from_slice(&[1, 1]);
+        icmp.extend_from_slice(&HOST_MAC);
+        // Solicited-node multicast address of the target
+        let mut group = [0u8; 16];
+        group[..2].copy_from_slice(&[0xff, 0x02]);
+        group[11..13].copy_from_slice(&[0x01, 0xff]);
+        group[13..].copy_from_slice(&target.octets()[13..]);
+        let packet = ipv6(source, group.into(), PROTO_ICMPV6, hop_limit, &icmp);
+        let group_mac = [0x33, 0x33, 0xff, group[13], group[14], group[15]];
+        ethernet(group_mac, HOST_MAC, ETHERTYPE_IPV6, &packet)
+    }
+
+    /// Returns the verdict and the replies, that were sent to the device.
+    fn answer(frame: &[u8]) -> (Verdict, Vec<Vec<u8>>) {
+        let mut ctx = HookContext::new(Layer::L2);
+        let verdict = proxy().on_read(&mut frame.to_vec(), &mut ctx);
+        (verdict, ctx.to_device.into())
+    }
+
+    #[test]
+    fn arp_request_is_answered() {
+        let (verdict, replies) = answer(&arp(ARP_REQUEST, REMOTE_V4));
+        assert_eq!(verdict, Verdict::Drop);
+
+        let mut expected = vec![0, 1, 0x08, 0x00, 6, 4, 0, 2];
+        expected.extend_from_slice(&PROXY_MAC);
+        expected.extend_from_slice(&REMOTE_V4.octets());
+        expected.extend_from_slice(&HOST_MAC);
+        expected.extend_from_slice(&HOST_V4.octets());
+        assert_eq!(
+            replies,
+            [ethernet(HOST_MAC, PROXY_MAC, ETHERTYPE_ARP, &expected)]
+        );
+    }
+
+    #[test]
+    fn neighbor_solicitation_is_answered() {
+        let frame = solicitation(host_v6(), remote_v6(), NDP_HOP_LIMIT);
+        let (verdict, replies) = answer(&frame);
+        assert_eq!(verdict, Verdict::Drop);
+        assert_eq!(replies.len(), 1);
+
+        let reply = &replies[0];
+        assert_eq!(reply[..6], HOST_MAC);
+        assert_eq!(reply[6..12], PROXY_MAC);
+        let ip = &reply[ETHER_HEADER_LEN..];
+        assert_eq!(ip[6..8], [PROTO_ICMPV6, NDP_HOP_LIMIT]);
+        assert_eq!(ip[8..24], remote_v6().octets());
+        assert_eq!(ip[24..40], host_v6().octets());
+
+        let icmp = &ip[IPV6_HEADER_LEN..];
+        assert_eq!(icmp[0], ICMPV6_NEIGHBOR_ADVERTISEMENT);
+        assert_eq!(icmp[4], NA_FLAG_SOLICITED | NA_FLAG_OVERRIDE);
+        assert_eq!(icmp[8..24], remote_v6().octets());
+        assert_eq!(icmp[24..26], [NDP_OPTION_TARGET_LINK_ADDRESS, 1]);
+        assert_eq!(icmp[26..32], PROXY_MAC);
+
+        // Checksum covers the pseudo-header
+        let mut pseudo = ip[8..40].to_vec();
+        pseudo.extend_from_slice(&(icmp.len() as u32).to_be_bytes());
+        pseudo.extend_from_slice(&[0, 0, 0, PROTO_ICMPV6]);
+        pseudo.extend_from_slice(icmp);
+        assert!(is_valid(&pseudo));
+    }
+
+    #[test]
+    fn frames_not_for_proxy_pass() {
+        let other_v6: Ipv6Addr = "fe80::2".parse().unwrap();
+        let frames = [
+            arp(ARP_REQUEST, Ipv4Addr::new(10, 0, 0, 3)),
+            arp(ARP_REPLY, REMOTE_V4),
+            solicitation(host_v6(), other_v6, NDP_HOP_LIMIT),
+            // Solicitations, that were forwarded, are invalid
+            solicitation(host_v6(), remote_v6(), 64),
+            // Duplicate address detection
+            solicitation(Ipv6Addr::UNSPECIFIED, remote_v6(), NDP_HOP_LIMIT),
+            ethernet(ETHER_BROADCAST, HOST_MAC, ETHERTYPE_ARP, &[0, 1]),
+        ];
+        for frame in frames {
+            assert_eq!(answer(&frame), (Verdict::Pass, vec![]));
+        }
+    }
+
+    #[test]
+    fn l3_packets_pass() {
+        let mut ctx = HookContext::new(Layer::L3);
+        let mut packet = arp(ARP_REQUEST, REMOTE_V4);
+        assert_eq!(proxy().on_read(&mut packet, &mut ctx), Verdict::Pass);
+        assert!(ctx.to_device.is_empty());
+    }
+}
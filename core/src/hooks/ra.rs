@@ -0,0 +1,312 @@
+use super::{HookContext, PacketHook, Verdict, DEFAULT_MAC};
+use crate::config::Layer;
+use crate::packet::{ethernet, ip_offset, ipv6, ETHERTYPE_IPV6, IPV6_HEADER_LEN, PROTO_ICMPV6};
+use std::net::Ipv6Addr;
+use std::time::{Duration, Instant};
+
+const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+const NDP_OPTION_SOURCE_LINK_ADDRESS: u8 = 1;
+const NDP_OPTION_PREFIX_INFORMATION: u8 = 3;
+const NDP_OPTION_MTU: u8 = 5;
+/// On-link and autonomous address configuration flags of a prefix.
+const PREFIX_FLAGS_LA: u8 = 0xc0;
+const NDP_HOP_LIMIT: u8 = 255;
+const CUR_HOP_LIMIT: u8 = 64;
+/// Maximum router lifetime, see RFC 4861.
+const MAX_ROUTER_LIFETIME: u64 = 9000;
+const ALL_NODES_MAC: [u8; 6] = [0x33, 0x33, 0, 0, 0, 1];
+
+/// Sends IPv6 Router Advertisements with a prefix for SLAAC, so that hosts on the other side
+/// of the device configure addresses without an external router daemon.
+///
+/// Advertisements are sent on the first queue operation, periodically after that, and in
+/// response to Router Solicitations, which are not returned to the application. Works on
+/// [`Layer::L2`] queues, where frames come from a configurable MAC address, and on
+/// [`Layer::L3`] ones, if the OS accepts advertisements on point-to-point interfaces.
+pub struct RouterAdvertiser {
+    prefix: Ipv6Addr,
+    prefix_len: u8,
+    source: Ipv6Addr,
+    mac: [u8; 6],
+    mtu: Option<u32>,
+    router_lifetime: Duration,
+    valid_lifetime: Duration,
+    preferred_lifetime: Duration,
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl RouterAdvertiser {
+    /// Advertises `prefix` of `prefix_len` bits, which must be 64 for SLAAC, from `fe80::1`.
+    pub fn new(prefix: Ipv6Addr, prefix_len: u8) -> Self {
+        Self {
+            prefix,
+            prefix_len: prefix_len.min(128),
+            source: Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+            mac: DEFAULT_MAC,
+            mtu: None,
+            router_lifetime: Duration::from_secs(1800),
+            valid_lifetime: Duration::from_secs(24 * 60 * 60),
+            preferred_lifetime: Duration::from_secs(4 * 60 * 60),
+            interval: Duration::from_secs(200),
+            next: None,
+        }
+    }
+
+    /// Link-local source address of advertisements.
+    pub fn source(mut self, source: Ipv6Addr) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Source MAC address of [`Layer::L2`] advertisements. Defaults to [`DEFAULT_MAC`].
+    pub fn mac(mut self, mac: [u8; 6]) -> Self {
+        self.mac = mac;
+        self
+    }
+
+    /// Includes MTU option.
+    pub fn mtu(mut self, mtu: u32) -> Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// Lifetime of the default route. Zero lifetime advertises the prefix only.
+    pub fn router_lifetime(mut self, lifetime: Duration) -> Self {
+        self.router_lifetime = lifetime;
+        self
+    }
+
+    /// Valid and preferred lifetimes of the prefix.
+    pub fn prefix_lifetimes(mut self, valid: Duration, preferred: Duration) -> Self {
+        self.valid_lifetime = valid;
+        self.preferred_lifetime = preferred.min(valid);
+        self
+    }
+
+    /// Interval between unsolicited advertisements.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    fn advertisement(&self, layer: Layer) -> Vec<u8> {
+        let secs = |duration: Duration| u32::try_from(duration.as_secs()).unwrap_or(u32::MAX);
+        let router_lifetime = self.router_lifetime.as_secs().min(MAX_ROUTER_LIFETIME) as u16;
+
+        let mut message = vec![ICMPV6_ROUTER_ADVERTISEMENT, 0, 0, 0, CUR_HOP_LIMIT, 0];
+        message.extend_from_slice(&router_lifetime.to_be_bytes());
+        // Reachable time and retransmission timer are left unspecified
+        message.extend_from_slice(&[0; 8]);
+        if layer == Layer::L2 {
+            message.extend_from_slice(&[NDP_OPTION_SOURCE_LINK_ADDRESS, 1]);
+            message.extend_from_slice(&self.mac);
+        }
+        if let Some(mtu) = self.mtu {
+            message.extend_from_slice(&[NDP_OPTION_MTU, 1, 0, 0]);
+            message.extend_from_slice(&mtu.to_be_bytes());
+        }
+
+        let mask = u128::MAX
+            .checked_shl(128 - u32::from(self.prefix_len))
+            .unwrap_or(0);
+        let prefix = Ipv6Addr::from(u128::from(self.prefix) & mask);
+        message.extend_from_slice(&[
+            NDP_OPTION_PREFIX_INFORMATION,
+            4,
+            self.prefix_len,
+            PREFIX_FLAGS_LA,
+        ]);
+        message.extend_from_slice(&secs(self.valid_lifetime).to_be_bytes());
+        message.extend_from_slice(&secs(self.preferred_lifetime).to_be_bytes());
+        message.extend_from_slice(&[0; 4]);
+        message.extend_from_slice(&prefix.octets());
+
+        let all_nodes = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+        let packet = ipv6(
+            self.source,
+            all_nodes,
+            PROTO_ICMPV6,
+            NDP_HOP_LIMIT,
+            &message,
+        );
+        match layer {
+            Layer::L2 => ethernet(ALL_NODES_MAC, self.mac, ETHERTYPE_IPV6, &packet),
+            Layer::L3 => packet,
+        }
+    }
+
+    fn is_solicitation(packet: &[u8], layer: Layer) -> bool {
+        let ip = match ip_offset(packet, layer) {
+            Some(offset) => &packet[offset..],
+            None => return false,
+        };
+        ip.len() > IPV6_HEADER_LEN
+            && ip[0] >> 4 == 6
+            && ip[6] == PROTO_ICMPV6
+            && ip[7] == NDP_HOP_LIMIT
+            && ip[IPV6_HEADER_LEN] == ICMPV6_ROUTER_SOLICITATION
+    }
+}
+
+impl PacketHook for RouterAdvertiser {
+    fn on_read(&mut self, packet: &mut Vec<u8>, ctx: &mut HookContext) -> Verdict {
+        match Self::is_solicitation(packet, ctx.layer()) {
+            true => {
+                ctx.send_to_device(self.advertisement(ctx.layer()));
+                Verdict::Drop
+            }
+            false => Verdict::Pass,
+        }
+    }
+
+    fn on_timer(&mut self, now: Instant, ctx: &mut HookContext) -> Option<Instant> {
+        if self.next.map_or(true, |next| next <= now) {
+            ctx.send_to_device(self.advertisement(ctx.layer()));
+            self.next = Some(now + self.interval);
+        }
+        self.next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::checksum::is_valid;
+    use crate::packet::ETHER_HEADER_LEN;
+
+    const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x42];
+
+    fn prefix() -> Ipv6Addr {
+        "2001:db8:1:2::".parse().unwrap()
+    }
+
+    fn icmp_is_valid(ip: &[u8]) -> bool {
+        let mut pseudo = ip[8..40].to_vec();
+        let icmp = &ip[IPV6_HEADER_LEN..];
+        pseudo.extend_from_slice(&(icmp.len() as u32).to_be_bytes());
+        pseudo.extend_from_slice(&[0, 0, 0, PROTO_ICMPV6]);
+        pseudo.extend_from_slice(icmp);
+        is_valid(&pseudo)
+    }
+
+    fn solicitation(hop_limit: u8) -> Vec<u8> {
+        let all_routers = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 2);
+        let message = [ICMPV6_ROUTER_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+        let source = "fe80::2".parse().unwrap();
+        ipv6(source, all_routers, PROTO_ICMPV6, hop_limit, &message)
+    }
+
+    #[test]
+    fn l3_advertisement_layout() {
+        let advertiser = RouterAdvertiser::new("2001:db8:1:2:3::".parse().unwrap(), 64)
+            .mtu(1280)
+            .router_lifetime(Duration::from_secs(20000))
+            .prefix_lifetimes(Duration::from_secs(600), Duration::from_secs(900));
+        let ip = advertiser.advertisement(Layer::L3);
+
+        assert_eq!(ip[6..8], [PROTO_ICMPV6, NDP_HOP_LIMIT]);
+        assert_eq!(
+            ip[8..24],
+            Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1).octets()
+        );
+        assert_eq!(
+            ip[24..40],
+            Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1).octets()
+        );
+        assert!(icmp_is_valid(&ip));
+
+        let icmp = &ip[IPV6_HEADER_LEN..];
+        assert_eq!(icmp.len(), 16 + 8 + 32);
+        assert_eq!(icmp[0..2], [ICMPV6_ROUTER_ADVERTISEMENT, 0]);
+        assert_eq!(icmp[4], CUR_HOP_LIMIT);
+        // Router lifetime is capped
+        assert_eq!(icmp[6..8], 9000u16.to_be_bytes());
+        assert_eq!(icmp[8..16], [0; 8]);
+
+        // No source link-layer address option without a link layer
+        assert_eq!(icmp[16..20], [NDP_OPTION_MTU, 1, 0, 0]);
+        assert_eq!(icmp[20..24], 1280u32.to_be_bytes());
+
+        let option = &icmp[24..];
+        assert_eq!(
+            option[..4],
+            [NDP_OPTION_PREFIX_INFORMATION, 4, 64, PREFIX_FLAGS_LA]
+        );
+        assert_eq!(option[4..8], 600u32.to_be_bytes());
+        // Preferred lifetime does not exceed the valid one
+        assert_eq!(option[8..12], 600u32.to_be_bytes());
+        assert_eq!(option[12..16], [0; 4]);
+        // Host bits are cleared
+        assert_eq!(option[16..32], prefix().octets());
+    }
+
+    #[test]
+    fn l2_advertisement_layout() {
+        let source = "fe80::42".parse().unwrap();
+        let advertiser = RouterAdvertiser::new(prefix(), 64).source(source).mac(MAC);
+        let frame = advertiser.advertisement(Layer::L2);
+
+        assert_eq!(frame[..6], ALL_NODES_MAC);
+        assert_eq!(frame[6..12], MAC);
+        assert_eq!(frame[12..14], ETHERTYPE_IPV6.to_be_bytes());
+
+        let ip = &frame[ETHER_HEADER_LEN..];
+        assert_eq!(ip[8..24], source.octets());
+        assert!(icmp_is_valid(ip));
+
+        let icmp = &ip[IPV6_HEADER_LEN..];
+        assert_eq!(icmp.len(), 16 + 8 + 32);
+        assert_eq!(icmp[6..8], 1800u16.to_be_bytes());
+        assert_eq!(icmp[16..18], [NDP_OPTION_SOURCE_LINK_ADDRESS, 1]);
+        assert_eq!(icmp[18..24], MAC);
+        assert_eq!(icmp[24], NDP_OPTION_PREFIX_INFORMATION);
+        assert_eq!(icmp[40..56], prefix().octets());
+    }
+
+    #[test]
+    fn solicitation_is_answered() {
+        let mut advertiser = RouterAdvertiser::new(prefix(), 64);
+        let mut ctx = HookContext::new(Layer::L3);
+        let verdict = advertiser.on_read(&mut solicitation(NDP_HOP_LIMIT), &mut ctx);
+        assert_eq!(verdict, Verdict::Drop);
+        let replies: Vec<_> = ctx.to_device.into();
+        assert_eq!(replies, [advertiser.advertisement(Layer::L3)]);
+    }
+
+    #[test]
+    fn other_packets_pass() {
+        let mut advertiser = RouterAdvertiser::new(prefix(), 64);
+        let mut ctx = HookContext::new(Layer::L3);
+        let echo = [128, 0, 0, 0, 0, 0, 0, 0];
+        let ping = ipv6(
+            "fe80::2".parse().unwrap(),
+            "fe80::1".parse().unwrap(),
+            PROTO_ICMPV6,
+            64,
+            &echo,
+        );
+        for mut packet in [solicitation(64), ping, vec![0x60; 4]] {
+            assert_eq!(advertiser.on_read(&mut packet, &mut ctx), Verdict::Pass);
+        }
+        assert!(ctx.to_device.is_empty());
+    }
+
+    #[test]
+    fn unsolicited_advertisements_follow_interval() {
+        let interval = Duration::from_secs(30);
+        let mut advertiser = RouterAdvertiser::new(prefix(), 64).interval(interval);
+        let mut ctx = HookContext::new(Layer::L3);
+        let start = Instant::now();
+
+        assert_eq!(advertiser.on_timer(start, &mut ctx), Some(start + interval));
+        assert_eq!(ctx.to_device.len(), 1);
+        let early = start + Duration::from_secs(10);
+        assert_eq!(advertiser.on_timer(early, &mut ctx), Some(start + interval));
+        assert_eq!(ctx.to_device.len(), 1);
+        let late = start + interval;
+        assert_eq!(advertiser.on_timer(late, &mut ctx), Some(late + interval));
+        assert_eq!(ctx.to_device.len(), 2);
+    }
+}
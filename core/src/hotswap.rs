@@ -0,0 +1,254 @@
+//! Replacing the device under a live interface, for example, to recreate it with new settings
+//! or after the driver is reinstalled, without losing packets, that are already queued.
+use crate::config::IfConfig;
+use crate::traits::{copy_packet, AsyncQueueT, InterfaceT, MAX_PACKET_LEN};
+use crate::Error;
+use futures::future::poll_fn;
+use futures::{AsyncRead, AsyncWrite};
+use std::collections::VecDeque;
+use std::io;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Async interface, which device can be replaced with [`migrate`](Self::migrate).
+///
+/// Packets, that the old device has received, but that are not read yet, are buffered during
+/// migration and returned by reads before packets of the new device.
+pub struct HotSwap<I> {
+    interface: I,
+    backlog: VecDeque<Vec<u8>>,
+}
+
+impl<I: InterfaceT + AsyncQueueT> HotSwap<I> {
+    pub fn new(interface: I) -> Self {
+        Self {
+            interface,
+            backlog: VecDeque::new(),
+        }
+    }
+
+    /// Replaces the device with a new one, created with `params`, and brings it up.
+    ///
+    /// New device is created, before the old one is closed, so that the interface is not left
+    /// without a device, if creation fails. Its name must not conflict with the old one,
+    /// unless [`NameConflict`](crate::config::NameConflict) allows it. Writes of the old
+    /// device are flushed, and packets, queued for reading on it, are buffered, before it is
+    /// closed. Packets, that arrive to the old device after that, are lost.
+    pub async fn migrate(
+        &mut self,
+        driver: &mut I::PlatformDriver,
+        params: IfConfig<I::PlatformIfConfig>,
+    ) -> Result<(), Error> {
+        let mut interface = I::new(driver, params)?;
+        interface.up()?;
+
+        // Writes to a device, that is already gone, are lost anyway
+        match poll_fn(|cx| Pin::new(&mut self.interface).poll_flush(cx)).await {
+            Err(err) if !is_gone(&err) => return Err(err.into()),
+            _ => {}
+        }
+        poll_fn(|cx| self.poll_drain(cx)).await?;
+        drop(mem::replace(&mut self.interface, interface));
+        Ok(())
+    }
+}
+
+impl<I: AsyncQueueT> HotSwap<I> {
+    /// Moves packets, that are ready on the current device, to the backlog. Device at end of
+    /// stream, or one, that is already gone, has nothing more to drain.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut buf = vec![0u8; MAX_PACKET_LEN];
+        loop {
+            match Pin::new(&mut self.interface).poll_recv(cx, &mut buf) {
+                Poll::Ready(Ok((0, _))) => return Poll::Ready(Ok(())),
+                Poll::Ready(Ok((n, _))) => self.backlog.push_back(buf[..n].to_vec()),
+                Poll::Ready(Err(err))
+                    if err.kind() == io::ErrorKind::WouldBlock || is_gone(&err) =>
+                {
+                    return Poll::Ready(Ok(()))
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+fn is_gone(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe | io::ErrorKind::NotConnected
+    )
+}
+
+impl<I> HotSwap<I> {
+    pub fn interface(&self) -> &I {
+        &self.interface
+    }
+
+    pub fn interface_mut(&mut self) -> &mut I {
+        &mut self.interface
+    }
+
+    /// Packets of previous devices, that are not read yet.
+    pub fn backlog(&self) -> usize {
+        self.backlog.len()
+    }
+
+    pub fn into_inner(self) -> I {
+        self.interface
+    }
+}
+
+impl<I: AsyncQueueT> AsyncQueueT for HotSwap<I> {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        let self_mut = self.get_mut();
+        match self_mut.backlog.pop_front() {
+            Some(packet) => Poll::Ready(Ok(copy_packet(&packet, buf))),
+            None => Pin::new(&mut self_mut.interface).poll_recv(cx, buf),
+        }
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().interface).poll_send(cx, packet)
+    }
+
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        match self_mut.backlog.is_empty() {
+            true => Pin::new(&mut self_mut.interface).poll_recv_ready(cx),
+            false => Poll::Ready(Ok(())),
+        }
+    }
+
+    /// Discards the backlog of previous devices together with packets of the current one.
+    fn drain(&mut self) -> io::Result<usize> {
+        let backlog = mem::take(&mut self.backlog).len();
+        Ok(backlog + self.interface.drain()?)
+    }
+}
+
+impl<I: AsyncQueueT> AsyncRead for HotSwap<I> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl<I: AsyncQueueT> AsyncWrite for HotSwap<I> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().interface).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().interface).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    /// Queue, that returns scripted reads, and then end of stream forever, like a closed
+    /// Wintun session.
+    struct Scripted(VecDeque<io::Result<Vec<u8>>>);
+
+    impl AsyncQueueT for Scripted {
+        fn poll_recv(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<(usize, bool)>> {
+            match self.get_mut().0.pop_front() {
+                Some(read) => Poll::Ready(read.map(|packet| copy_packet(&packet, buf))),
+                None => Poll::Ready(Ok((0, false))),
+            }
+        }
+
+        fn poll_send(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _packet: &[u8],
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncRead for Scripted {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+        }
+    }
+
+    impl AsyncWrite for Scripted {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn drain(reads: Vec<io::Result<Vec<u8>>>) -> (Poll<io::Result<()>>, usize) {
+        let mut swap = HotSwap {
+            interface: Scripted(reads.into()),
+            backlog: VecDeque::new(),
+        };
+        let waker = noop_waker();
+        let poll = swap.poll_drain(&mut Context::from_waker(&waker));
+        (poll, swap.backlog())
+    }
+
+    #[test]
+    fn drain_stops_at_end_of_stream() {
+        let (poll, backlog) = drain(vec![Ok(vec![1]), Ok(vec![2, 3])]);
+        assert!(matches!(poll, Poll::Ready(Ok(()))));
+        assert_eq!(backlog, 2);
+    }
+
+    #[test]
+    fn device_that_is_gone_is_drained() {
+        let gone = io::Error::from(io::ErrorKind::BrokenPipe);
+        let (poll, backlog) = drain(vec![Ok(vec![1]), Err(gone)]);
+        assert!(matches!(poll, Poll::Ready(Ok(()))));
+        assert_eq!(backlog, 1);
+
+        let failed = io::Error::from(io::ErrorKind::InvalidData);
+        let (poll, _) = drain(vec![Err(failed)]);
+        assert!(matches!(poll, Poll::Ready(Err(_))));
+    }
+}
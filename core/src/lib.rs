@@ -1,7 +1,46 @@
+#![deny(unsafe_code)]
+
+pub mod address;
+pub mod budget;
+pub mod coalesce;
 pub mod config;
+pub mod control;
+pub mod device;
+pub mod diagnostics;
+pub mod egress;
 mod error;
+pub mod events;
+pub mod framed;
+pub mod guard;
+pub mod hooks;
+pub mod hotswap;
+pub mod mtu;
+pub mod name;
+pub mod offload;
+pub mod pause;
+pub mod plugin;
 #[cfg(unix)]
 pub mod queue;
+pub mod ready;
+mod recv_many;
+pub mod sampling;
+pub mod shaper;
+pub mod snapshot;
+pub mod socket;
+pub mod spec;
+pub mod stats;
+#[doc(hidden)]
+pub mod sync;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod timeout;
+mod timestamp;
 pub mod traits;
+pub mod transaction;
+pub mod writers;
 
-pub use error::Error;
+pub use error::{Error, ResultExt};
+pub use recv_many::RecvMany;
+pub use timeout::RecvTimeout;
+pub use timestamp::RecvTimestamped;
+pub use tunio_packet as packet;
@@ -0,0 +1,34 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+/// Current MTU of an interface, that backends keep in sync with the system.
+///
+/// Cloned watches share the same value, so hooks can follow MTU changes of the interface,
+/// they are attached to. When the value changes, interface also emits
+/// [`EventKind::MtuChanged`](crate::events::EventKind::MtuChanged).
+#[derive(Clone, Debug)]
+pub struct MtuWatch {
+    mtu: Arc<AtomicU32>,
+}
+
+impl MtuWatch {
+    pub fn new(mtu: u32) -> Self {
+        Self {
+            mtu: Arc::new(AtomicU32::new(mtu)),
+        }
+    }
+
+    pub fn get(&self) -> u32 {
+        self.mtu.load(Ordering::Relaxed)
+    }
+
+    /// Stores a new MTU, returning `true`, if it differs from the previous one.
+    pub fn set(&self, mtu: u32) -> bool {
+        self.mtu.swap(mtu, Ordering::Relaxed) != mtu
+    }
+
+    /// Current MTU, limited to the range of IP packet lengths.
+    pub(crate) fn get_u16(&self) -> u16 {
+        u16::try_from(self.get()).unwrap_or(u16::MAX)
+    }
+}
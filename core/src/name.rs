@@ -0,0 +1,75 @@
+//! Interface name handling, shared by platform backends.
+use crate::config::{NameConflict, NameOutcome, NameOverflow};
+use crate::traits::PlatformIfConfigT;
+use crate::Error;
+use log::warn;
+
+/// Number of suffixed names, that are tried before giving up.
+const MAX_SUFFIX_ATTEMPTS: u32 = 1000;
+
+/// Applies `overflow` to a name, that is longer than the limit of the platform `P`, so that
+/// long names fail early with a typed error instead of deep inside system calls. Names are
+/// truncated at character boundaries.
+pub fn normalize<P: PlatformIfConfigT>(
+    name: &str,
+    overflow: NameOverflow,
+) -> Result<String, Error> {
+    let len = P::name_len(name);
+    if len <= P::MAX_NAME_LEN {
+        return Ok(name.to_string());
+    }
+
+    match overflow {
+        NameOverflow::Fail => Err(Error::InterfaceNameTooLong(len, P::MAX_NAME_LEN)),
+        NameOverflow::Truncate => {
+            let end = name
+                .char_indices()
+                .map(|(i, c)| i + c.len_utf8())
+                .take_while(|end| P::name_len(&name[..*end]) <= P::MAX_NAME_LEN)
+                .last()
+                .unwrap_or(0);
+            let truncated = &name[..end];
+            warn!("Interface name \"{name}\" is too long, truncated to \"{truncated}\"");
+            Ok(truncated.to_string())
+        }
+    }
+}
+
+/// Applies `policy` to the requested `name`, if `exists` reports it as taken. Returns the name
+/// to create or attach to, which is at most `max_len` bytes long for suffixed names.
+///
+/// Empty names and templates, that are expanded by the OS, never conflict.
+pub fn resolve(
+    name: &str,
+    policy: NameConflict,
+    max_len: usize,
+    exists: impl Fn(&str) -> bool,
+) -> Result<(String, NameOutcome), Error> {
+    if name.is_empty() || !exists(name) {
+        return Ok((name.to_string(), NameOutcome::Created));
+    }
+
+    match policy {
+        NameConflict::Fail => Err(Error::NameTaken(name.to_string())),
+        NameConflict::Adopt => Ok((name.to_string(), NameOutcome::Adopted)),
+        NameConflict::Suffix => {
+            let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+            let first = name[base.len()..]
+                .parse::<u32>()
+                .map_or(1, |number| number.saturating_add(1));
+
+            (first..first.saturating_add(MAX_SUFFIX_ATTEMPTS))
+                .map(|number| {
+                    let suffix = number.to_string();
+                    let mut base_len = base.len().min(max_len.saturating_sub(suffix.len()));
+                    while !base.is_char_boundary(base_len) {
+                        base_len -= 1;
+                    }
+                    format!("{}{suffix}", &base[..base_len])
+                })
+                .find(|candidate| !exists(candidate))
+                .map(|candidate| (candidate, NameOutcome::Suffixed))
+                .ok_or_else(|| Error::NameTaken(name.to_string()))
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Configuration calls, that block, like netlink requests and IP Helper calls, which take
+//! hundreds of milliseconds on Windows, run on a thread pool, so that they do not stall async
+//! runtimes.
+use crate::error::SendError;
+use crate::Error;
+use blocking::Task;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+type Call<T> = Box<dyn FnOnce() -> Result<T, Error> + Send>;
+
+// Errors are sent back from pool threads, so they are kept in their `Send` form
+enum State<T> {
+    Idle(Call<T>),
+    Running(Task<Result<T, SendError>>),
+    Ready(Result<T, SendError>),
+    Done,
+}
+
+/// Future of a blocking call, that runs on the pool of the `blocking` crate.
+///
+/// Call is submitted, when the future is first polled. Dropping the future cancels the call,
+/// if no pool thread has started it yet. Call, that already runs, cannot be interrupted: it
+/// completes, and its result is discarded, so the change may still be applied.
+#[must_use = "calls are only submitted, when the future is polled"]
+pub struct Offload<T> {
+    state: State<T>,
+}
+
+impl<T: Send + 'static> Offload<T> {
+    pub fn new(call: impl FnOnce() -> Result<T, Error> + Send + 'static) -> Self {
+        Self {
+            state: State::Idle(Box::new(call)),
+        }
+    }
+
+    /// Future, that resolves to `result` without a call, for operations, that fail before
+    /// anything blocks, like unsupported ones.
+    pub fn ready(result: Result<T, Error>) -> Self {
+        Self {
+            state: State::Ready(result.map_err(SendError::from)),
+        }
+    }
+}
+
+// State is never pinned: results are moved out, and tasks are polled by their own `Unpin`
+impl<T> Unpin for Offload<T> {}
+
+impl<T: Send + 'static> Future for Offload<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Idle(call) => {
+                    let task = blocking::unblock(move || call().map_err(SendError::from));
+                    self.state = State::Running(task);
+                }
+                State::Running(mut task) => {
+                    return match Pin::new(&mut task).poll(cx) {
+                        Poll::Ready(result) => Poll::Ready(result.map_err(Error::from)),
+                        Poll::Pending => {
+                            self.state = State::Running(task);
+                            Poll::Pending
+                        }
+                    };
+                }
+                State::Ready(result) => return Poll::Ready(result.map_err(Error::from)),
+                State::Done => panic!("Offload polled after completion"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn call_runs_on_another_thread_only_when_polled() {
+        let caller = thread::current().id();
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+        drop(Offload::new(move || Ok(flag.swap(true, Ordering::SeqCst))));
+
+        let other = block_on(Offload::new(move || Ok(thread::current().id() != caller)));
+        assert!(other.unwrap());
+        assert!(!ran.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,317 @@
+//! Registration of out-of-tree backends.
+//!
+//! A backend implements [`BackendT`] and [`BackendDeviceT`] for its devices and is registered
+//! once with [`register`]. [`PluginInterface`] then opens devices of any registered backend
+//! and adds the interface machinery of tunio on top of them: events, stats, pausing and
+//! datagram queue traits, so that hooks and framing work with them unchanged.
+//!
+//! Backend traits are versioned with [`BACKEND_API_VERSION`]. Backends report the version,
+//! they are written against, and are rejected on registration, if it differs, instead of
+//! misbehaving at runtime.
+use crate::config::{IfConfig, Layer};
+use crate::device::DeviceInfo;
+use crate::events::{EventEmitter, EventKind, EventReceiver};
+use crate::pause::PauseHandle;
+use crate::stats::{QueueStats, StatsCounters};
+use crate::traits::{recv_owned, DriverT, InterfaceT, PlatformIfConfigT, SyncQueueT};
+use crate::Error;
+use derive_builder::Builder;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, RwLock};
+
+/// Version of [`BackendT`] and [`BackendDeviceT`]. It changes, when any of them changes
+/// incompatibly.
+pub const BACKEND_API_VERSION: u32 = 1;
+
+static REGISTRY: RwLock<Vec<Arc<dyn BackendT>>> = RwLock::new(Vec::new());
+
+/// Out-of-tree backend, that creates devices.
+pub trait BackendT: Send + Sync + 'static {
+    /// Version of the backend API, this backend is written against. Implementations return
+    /// the value of [`BACKEND_API_VERSION`] of the tunio version, they are built with.
+    fn api_version(&self) -> u32;
+
+    /// Unique name of the backend, that selects it in [`PlatformIfConfig::backend`].
+    fn name(&self) -> &'static str;
+
+    /// Layers, that devices of the backend can have.
+    fn layers(&self) -> &'static [Layer] {
+        &[Layer::L2, Layer::L3]
+    }
+
+    /// Creates or attaches to a device. Backend checks the name itself.
+    fn open(&self, params: &BackendParams) -> Result<Box<dyn BackendDeviceT>, Error>;
+
+    /// Lists existing devices of the backend, see [`DriverT::enumerate`].
+    fn enumerate(&self) -> Result<Vec<DeviceInfo>, Error> {
+        Err(io::Error::from(io::ErrorKind::Unsupported).into())
+    }
+}
+
+/// Settings of a device, passed to [`BackendT::open`].
+#[derive(Debug, Clone)]
+pub struct BackendParams {
+    pub name: String,
+    pub layer: Layer,
+    /// Backend-specific options of [`PlatformIfConfig::options`].
+    pub options: BTreeMap<String, String>,
+}
+
+/// Device of an out-of-tree backend, that sends and receives single packets.
+pub trait BackendDeviceT: Send {
+    /// Name of the device, as it is assigned by the OS.
+    fn name(&self) -> &str;
+
+    /// OS handle of the device.
+    fn handle(&self) -> netconfig::Interface;
+
+    fn up(&mut self) -> Result<(), Error>;
+    fn down(&mut self) -> Result<(), Error>;
+
+    /// Blocks until a packet is received into `buf`, see [`SyncQueueT::recv`].
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)>;
+
+    /// Blocks until `packet` is sent as a whole.
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+
+    /// Discards packets, that are buffered for reading, see [`SyncQueueT::drain`].
+    fn drain(&mut self) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Registers a backend for [`PluginInterface`]. Fails, if the backend is written against
+/// another version of the backend API, or if a backend with the same name is registered.
+pub fn register(backend: impl BackendT) -> Result<(), Error> {
+    if backend.api_version() != BACKEND_API_VERSION {
+        return Err(Error::BackendVersionMismatch {
+            backend: backend.name().to_string(),
+            found: backend.api_version(),
+            expected: BACKEND_API_VERSION,
+        });
+    }
+
+    let mut registry = REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    if registry.iter().any(|other| other.name() == backend.name()) {
+        return Err(Error::InvalidConfigValue {
+            name: "backend".to_string(),
+            value: backend.name().to_string(),
+            reason: "is already registered".to_string(),
+        });
+    }
+    registry.push(Arc::new(backend));
+    Ok(())
+}
+
+/// Names of registered backends, in the order of registration.
+pub fn backends() -> Vec<&'static str> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry.iter().map(|backend| backend.name()).collect()
+}
+
+/// Finds a registered backend by name, or the first registered one, if `name` is empty.
+fn find(name: &str) -> Result<Arc<dyn BackendT>, Error> {
+    let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner());
+    registry
+        .iter()
+        .find(|backend| name.is_empty() || backend.name() == name)
+        .cloned()
+        .ok_or_else(|| Error::BackendNotFound(name.to_string()))
+}
+
+pub struct Driver {
+    events: EventEmitter,
+}
+
+/// It is generally better to use [`PlatformIfConfigBuilder`] to create a new PlatformIfConfig instance.
+#[derive(Builder, Clone, Default)]
+pub struct PlatformIfConfig {
+    /// Name of a registered backend. First registered backend is used, if it is empty.
+    #[builder(default, setter(into))]
+    pub backend: String,
+    /// Options, that are passed to the backend as they are.
+    #[builder(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+impl PlatformIfConfigT for PlatformIfConfig {
+    type Builder = PlatformIfConfigBuilder;
+
+    /// Names are checked by backends, when devices are opened.
+    const MAX_NAME_LEN: usize = usize::MAX;
+
+    fn check_name(_name: &str) -> Option<String> {
+        None
+    }
+}
+
+impl DriverT for Driver {
+    type PlatformIfConfig = PlatformIfConfig;
+
+    fn new() -> Result<Self, Error> {
+        Ok(Self {
+            events: EventEmitter::default(),
+        })
+    }
+
+    fn subscribe(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+
+    /// Lists devices of all registered backends, that can list them.
+    fn enumerate(&self) -> Result<Vec<DeviceInfo>, Error> {
+        let registry = REGISTRY.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let mut devices = vec![];
+        for backend in registry {
+            match backend.enumerate() {
+                Ok(found) => devices.extend(found),
+                Err(Error::Io(err)) if err.kind() == io::ErrorKind::Unsupported => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(devices)
+    }
+}
+
+/// Interface, backed by a device of a registered backend.
+pub struct PluginInterface {
+    name: String,
+    backend: &'static str,
+    events: EventEmitter,
+    pause: PauseHandle,
+    stats: StatsCounters,
+    device: Box<dyn BackendDeviceT>,
+}
+
+pub type Interface = PluginInterface;
+
+impl PluginInterface {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Name of the backend, that the device belongs to.
+    pub fn backend(&self) -> &'static str {
+        self.backend
+    }
+
+    /// Packet and ECN counters of this interface.
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+
+    /// Stops taking packets from the device, until [`resume`](Self::resume) is called.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns a handle, that pauses and resumes this interface from another thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    fn write_failed(&self, err: io::Error) -> io::Error {
+        self.events
+            .emit(&self.name, EventKind::WriteFailed(err.kind()));
+        err
+    }
+}
+
+impl Drop for PluginInterface {
+    fn drop(&mut self) {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+    }
+}
+
+impl InterfaceT for PluginInterface {
+    type PlatformDriver = Driver;
+    type PlatformIfConfig = PlatformIfConfig;
+
+    fn new(
+        driver: &mut Self::PlatformDriver,
+        params: IfConfig<Self::PlatformIfConfig>,
+    ) -> Result<Self, Error> {
+        let backend = find(&params.platform.backend)?;
+        if !backend.layers().contains(&params.layer) {
+            return Err(Error::LayerUnsupported(params.layer));
+        }
+
+        let device = backend.open(&BackendParams {
+            name: params.name,
+            layer: params.layer,
+            options: params.platform.options,
+        })?;
+        let name = device.name().to_string();
+        driver.events.emit(&name, EventKind::Created);
+
+        Ok(Self {
+            name,
+            backend: backend.name(),
+            events: driver.events.clone(),
+            pause: PauseHandle::default(),
+            stats: StatsCounters::new(params.layer),
+            device,
+        })
+    }
+
+    fn up(&mut self) -> Result<(), Error> {
+        self.device.up()?;
+        self.events.emit(&self.name, EventKind::SessionStarted);
+        Ok(())
+    }
+
+    fn down(&mut self) -> Result<(), Error> {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+        self.device.down()
+    }
+
+    fn handle(&self) -> netconfig::Interface {
+        self.device.handle()
+    }
+}
+
+impl SyncQueueT for PluginInterface {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.pause.wait_resumed();
+        let (n, truncated) = self.device.recv(buf)?;
+        self.stats.record_rx(&buf[..n]);
+        Ok((n, truncated))
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.device.send(packet).map_err(|e| self.write_failed(e))?;
+        self.stats.record_tx(packet);
+        Ok(())
+    }
+
+    type PacketRef<'a> = Vec<u8>;
+
+    fn recv_ref(&mut self) -> io::Result<Self::PacketRef<'_>> {
+        recv_owned(self)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        self.device.drain()
+    }
+}
+
+impl Read for PluginInterface {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
+    }
+}
+
+impl Write for PluginInterface {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -1,11 +1,34 @@
-use std::os::unix::io::OwnedFd;
+use crate::coalesce::ReadCoalescing;
+use crate::config::Layer;
+use std::io;
+use std::os::unix::io::{AsFd, AsRawFd, OwnedFd};
 
 pub mod syncfd;
 #[cfg(feature = "tokio")]
 pub mod tokiofd;
 
-pub trait FdQueueT {
+/// Packet queue over a device file descriptor.
+///
+/// Descriptor is exposed through [`AsFd`]/[`AsRawFd`] for advanced users, who want to drive
+/// readiness themselves. Reading or writing it directly while the queue is in use may break
+/// packet boundaries, expected by the queue, and must be avoided.
+pub trait FdQueueT: AsFd + AsRawFd + Sized {
     const BLOCKING: bool;
 
-    fn new(device: OwnedFd) -> Self;
+    /// Fails, if an async queue cannot register the descriptor with its reactor.
+    fn new(device: OwnedFd) -> io::Result<Self>;
+
+    /// Consumes the queue, returning the device descriptor. Blocking mode of descriptor
+    /// is preserved.
+    fn into_fd(self) -> OwnedFd;
+
+    /// Sets the number of packets, read in a row before yielding. Only used by async queues.
+    fn set_poll_budget(&mut self, _limit: Option<usize>) {}
+
+    /// Enables read-side micro-batching. Only used by async queues.
+    fn set_read_coalescing(&mut self, _config: Option<ReadCoalescing>) {}
+
+    /// Makes reads skip IPv4 packets of `layer`, as seen by
+    /// [`is_ipv4`](crate::packet::is_ipv4), for IPv6-only interfaces. `None` keeps them.
+    fn set_drop_ipv4(&mut self, layer: Option<Layer>);
 }
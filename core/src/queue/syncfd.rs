@@ -1,34 +1,113 @@
+use crate::config::Layer;
+use crate::packet::is_ipv4;
 use crate::queue::FdQueueT;
-use crate::traits::SyncQueueT;
+use crate::traits::{recv_owned, SyncQueueT, MAX_PACKET_LEN};
 use delegate::delegate;
 use std::fs;
-use std::io::{self, Read, Write};
-use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::io::{self, IoSliceMut, Read, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
 
-pub struct SyncFdQueue(fs::File);
+pub struct SyncFdQueue {
+    device: fs::File,
+    drop_ipv4: Option<Layer>,
+}
+
+impl SyncFdQueue {
+    fn recv_any(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        // Devices discard the remainder of a packet silently, so a spare byte detects it
+        let mut spare = [0u8; 1];
+        let n = self
+            .device
+            .read_vectored(&mut [IoSliceMut::new(buf), IoSliceMut::new(&mut spare)])?;
+        Ok((n.min(buf.len()), n > buf.len()))
+    }
+}
 
-impl SyncQueueT for SyncFdQueue {}
+impl SyncQueueT for SyncFdQueue {
+    /// Skipped IPv4 packets are not returned: blocking reads wait for the next packet, and
+    /// non-blocking ones fail with `WouldBlock`, if there is none.
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        loop {
+            let (n, truncated) = self.recv_any(buf)?;
+            match self.drop_ipv4 {
+                Some(layer) if is_ipv4(&buf[..n], layer) => continue,
+                _ => return Ok((n, truncated)),
+            }
+        }
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.device.write(packet).map(drop)
+    }
+
+    type PacketRef<'a> = Vec<u8>;
+
+    fn recv_ref(&mut self) -> io::Result<Vec<u8>> {
+        recv_owned(self)
+    }
+
+    /// Blocking mode of the descriptor is not changed: packets are read, while `poll` reports,
+    /// that there are more.
+    fn drain(&mut self) -> io::Result<usize> {
+        let mut buf = vec![0u8; MAX_PACKET_LEN];
+        let mut drained = 0;
+        while is_readable(self.device.as_raw_fd())? {
+            match self.recv_any(&mut buf) {
+                Ok(_) => drained += 1,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(drained)
+    }
+}
+
+#[allow(unsafe_code)]
+fn is_readable(fd: RawFd) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: a single pollfd lives through the call
+    match unsafe { libc::poll(&mut pollfd, 1, 0) } {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(pollfd.revents & libc::POLLIN != 0),
+    }
+}
 
 impl FdQueueT for SyncFdQueue {
     const BLOCKING: bool = true;
 
-    fn new(device: OwnedFd) -> Self {
-        Self(device.into())
+    fn new(device: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            device: device.into(),
+            drop_ipv4: None,
+        })
+    }
+
+    fn into_fd(self) -> OwnedFd {
+        self.device.into()
+    }
+
+    fn set_drop_ipv4(&mut self, layer: Option<Layer>) {
+        self.drop_ipv4 = layer;
     }
 }
 
 impl Read for SyncFdQueue {
-    delegate! {
-        to self.0 {
-            fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error>;
-        }
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
     }
 }
 
 impl Write for SyncFdQueue {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
     delegate! {
-        to self.0 {
-            fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+        to self.device {
             fn flush(&mut self) -> io::Result<()>;
         }
     }
@@ -36,6 +115,53 @@ impl Write for SyncFdQueue {
 
 impl AsRawFd for SyncFdQueue {
     fn as_raw_fd(&self) -> RawFd {
-        self.0.as_raw_fd()
+        self.device.as_raw_fd()
+    }
+}
+
+impl AsFd for SyncFdQueue {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.device.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    /// Datagram socket keeps packet boundaries, just like a device.
+    fn queue() -> (SyncFdQueue, UnixDatagram) {
+        let (device, peer) = UnixDatagram::pair().unwrap();
+        (SyncFdQueue::new(device.into()).unwrap(), peer)
+    }
+
+    #[test]
+    fn partial_reads() {
+        let (mut queue, peer) = queue();
+        for packet in [&[1, 2][..], &[3, 4, 5, 6], &[7]] {
+            peer.send(packet).unwrap();
+        }
+
+        let mut buf = [0u8; 4];
+        assert_eq!(queue.recv(&mut buf[..3]).unwrap(), (2, false));
+        assert_eq!(buf[..2], [1, 2]);
+        assert_eq!(queue.recv(&mut buf[..3]).unwrap(), (3, true));
+        assert_eq!(buf[..3], [3, 4, 5]);
+        assert_eq!(queue.read(&mut []).unwrap(), 0);
+    }
+
+    #[test]
+    fn ipv4_packets_are_skipped() {
+        let (mut queue, peer) = queue();
+        queue.set_drop_ipv4(Some(Layer::L3));
+        for packet in [&[0x45, 1][..], &[0x60, 2], &[0x45, 3]] {
+            peer.send(packet).unwrap();
+        }
+
+        let mut buf = [0u8; 4];
+        assert_eq!(queue.recv(&mut buf).unwrap(), (2, false));
+        assert_eq!(buf[..2], [0x60, 2]);
+        assert_eq!(queue.drain().unwrap(), 1);
     }
 }
@@ -1,70 +1,176 @@
+use crate::budget::PollBudget;
+use crate::coalesce::{Coalescer, ReadCoalescing};
+use crate::config::Layer;
 use crate::queue::syncfd::SyncFdQueue;
 use crate::queue::FdQueueT;
-use crate::traits::AsyncQueueT;
+use crate::traits::{AsyncQueueT, SyncQueueT};
 use futures::{AsyncRead, AsyncWrite};
-use std::io::{self, Read, Write};
-use std::os::unix::io::OwnedFd;
+use std::io::{self, Write};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
 use std::pin::Pin;
 use std::task::{ready, Context, Poll};
-use tokio::io::unix::AsyncFd;
+pub use tokio::io::unix::AsyncFd;
 
 pub struct TokioFdQueue {
     inner: AsyncFd<SyncFdQueue>,
+    budget: PollBudget,
+    coalescer: Coalescer,
 }
 
-impl AsyncQueueT for TokioFdQueue {}
-
 impl FdQueueT for TokioFdQueue {
     const BLOCKING: bool = false;
 
-    fn new(device: OwnedFd) -> Self {
-        Self {
-            inner: AsyncFd::new(SyncFdQueue::new(device)).unwrap(),
-        }
+    fn new(device: OwnedFd) -> io::Result<Self> {
+        Ok(Self {
+            inner: AsyncFd::new(SyncFdQueue::new(device)?)?,
+            budget: PollBudget::default(),
+            coalescer: Coalescer::default(),
+        })
+    }
+
+    fn into_fd(self) -> OwnedFd {
+        self.inner.into_inner().into_fd()
+    }
+
+    fn set_poll_budget(&mut self, limit: Option<usize>) {
+        self.budget.set_limit(limit);
+    }
+
+    fn set_read_coalescing(&mut self, config: Option<ReadCoalescing>) {
+        self.coalescer.set_config(config);
+    }
+
+    fn set_drop_ipv4(&mut self, layer: Option<Layer>) {
+        self.inner.get_mut().set_drop_ipv4(layer);
     }
 }
 
-impl AsyncRead for TokioFdQueue {
-    fn poll_read(
+impl TokioFdQueue {
+    /// Device, registered with the reactor of the runtime, for read loops, that are driven
+    /// by readiness directly. Packets, read through it, bypass the poll budget and read
+    /// coalescing.
+    pub fn async_fd(&self) -> &AsyncFd<SyncFdQueue> {
+        &self.inner
+    }
+
+    pub fn async_fd_mut(&mut self) -> &mut AsyncFd<SyncFdQueue> {
+        &mut self.inner
+    }
+}
+
+impl AsRawFd for TokioFdQueue {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.get_ref().as_raw_fd()
+    }
+}
+
+impl AsFd for TokioFdQueue {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.get_ref().as_fd()
+    }
+}
+
+impl AsyncQueueT for TokioFdQueue {
+    fn poll_recv(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut [u8],
-    ) -> Poll<io::Result<usize>> {
+    ) -> Poll<io::Result<(usize, bool)>> {
         let self_mut = self.get_mut();
+        ready!(self_mut.budget.poll_proceed(cx));
         loop {
-            let mut guard = ready!(self_mut.inner.poll_read_ready_mut(cx))?;
+            let mut guard = match self_mut.inner.poll_read_ready_mut(cx) {
+                Poll::Ready(guard) => guard?,
+                Poll::Pending => {
+                    self_mut.budget.reset();
+                    self_mut.coalescer.batch_finished();
+                    return Poll::Pending;
+                }
+            };
+
+            // Readiness is kept, when guard is dropped without clearing it
+            ready!(self_mut.coalescer.poll_ready(cx));
 
-            match guard.try_io(|inner| inner.get_mut().read(buf)) {
-                Ok(Ok(n)) => {
-                    return Poll::Ready(Ok(n));
+            match guard.try_io(|inner| inner.get_mut().recv(buf)) {
+                Ok(Ok((n, truncated))) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(len = n, truncated, "packet read");
+                    self_mut.coalescer.packet_read();
+                    return Poll::Ready(Ok((n, truncated)));
+                }
+                Ok(Err(e)) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(error = %e, "read failed");
+                    return Poll::Ready(Err(e));
+                }
+                Err(_) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!("read readiness cleared, waiting");
+                    continue;
                 }
-                Ok(Err(e)) => return Poll::Ready(Err(e)),
-                Err(_) => continue,
             }
         }
     }
-}
 
-impl AsyncWrite for TokioFdQueue {
-    fn poll_write(
+    fn poll_send(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
-        buf: &[u8],
-    ) -> Poll<io::Result<usize>> {
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
         let self_mut = self.get_mut();
         loop {
             let mut guard = ready!(self_mut.inner.poll_write_ready_mut(cx))?;
 
-            match guard.try_io(|inner| inner.get_mut().write(buf)) {
-                Ok(Ok(n)) => {
-                    return Poll::Ready(Ok(n));
+            match guard.try_io(|inner| inner.get_mut().send(packet)) {
+                Ok(Ok(())) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(len = packet.len(), "packet written");
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(e)) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(error = %e, "write failed");
+                    return Poll::Ready(Err(e));
+                }
+                Err(_) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!("write readiness cleared, waiting");
+                    continue;
                 }
-                Ok(Err(e)) => return Poll::Ready(Err(e)),
-                Err(_) => continue,
             }
         }
     }
 
+    /// Readiness is kept, until a read finds the device empty.
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.inner.poll_read_ready(cx).map_ok(drop)
+    }
+
+    /// Readiness is cleared by the next read, that finds the device empty.
+    fn drain(&mut self) -> io::Result<usize> {
+        self.inner.get_mut().drain()
+    }
+}
+
+impl AsyncRead for TokioFdQueue {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl AsyncWrite for TokioFdQueue {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         let self_mut = self.get_mut();
         loop {
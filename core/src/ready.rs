@@ -0,0 +1,157 @@
+//! Readiness of interfaces, so that services report being ready only when the tunnel is
+//! usable, not when the device merely exists.
+use crate::sync::{Arc, Mutex, MutexGuard};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct State {
+    session: bool,
+    holds: usize,
+    wakers: Vec<Waker>,
+    callbacks: Vec<Callback>,
+}
+
+impl State {
+    fn is_ready(&self) -> bool {
+        self.session && self.holds == 0
+    }
+}
+
+/// Interface is ready, when its driver session is started, and no [`ReadyHold`] is held, like
+/// the one of an application, that applies addresses and routes.
+///
+/// Cloned handles share the same state.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    inner: Arc<Mutex<State>>,
+}
+
+fn lock(inner: &Mutex<State>) -> MutexGuard<'_, State> {
+    inner.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        lock(&self.inner).is_ready()
+    }
+
+    /// Keeps the interface from being ready, until the hold is dropped. Applications take it
+    /// before bringing the interface up, and drop it, once they have configured it.
+    pub fn hold(&self) -> ReadyHold {
+        lock(&self.inner).holds += 1;
+        ReadyHold {
+            readiness: self.clone(),
+        }
+    }
+
+    /// Future, that resolves, while the interface is ready. It is pending again after the
+    /// session is stopped.
+    pub fn wait(&self) -> ReadyNotify {
+        ReadyNotify {
+            readiness: self.clone(),
+        }
+    }
+
+    /// Runs `f` once, when the interface first becomes ready, or at once, if it is ready.
+    pub fn on_ready(&self, f: impl FnOnce() + Send + 'static) {
+        let mut state = lock(&self.inner);
+        match state.is_ready() {
+            true => {
+                drop(state);
+                f();
+            }
+            false => state.callbacks.push(Box::new(f)),
+        }
+    }
+
+    /// Records, that the driver session is started or stopped. Called by backends.
+    pub fn set_session(&self, started: bool) {
+        self.update(|state| state.session = started);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut State)) {
+        let (wakers, callbacks) = {
+            let mut state = lock(&self.inner);
+            f(&mut state);
+            match state.is_ready() {
+                true => (
+                    std::mem::take(&mut state.wakers),
+                    std::mem::take(&mut state.callbacks),
+                ),
+                false => Default::default(),
+            }
+        };
+        // Callbacks may block, like writes to the systemd socket, so the lock is not held
+        wakers.into_iter().for_each(Waker::wake);
+        callbacks.into_iter().for_each(|f| f());
+    }
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = lock(&self.inner);
+        if state.is_ready() {
+            return Poll::Ready(());
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Guard of [`Readiness::hold`].
+pub struct ReadyHold {
+    readiness: Readiness,
+}
+
+impl Drop for ReadyHold {
+    fn drop(&mut self) {
+        self.readiness.update(|state| state.holds -= 1);
+    }
+}
+
+/// Future of [`Readiness::wait`].
+pub struct ReadyNotify {
+    readiness: Readiness,
+}
+
+impl Future for ReadyNotify {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        self.readiness.poll_ready(cx)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn ready_after_session_and_holds() {
+        let readiness = Readiness::default();
+        let notified = Arc::new(AtomicBool::new(false));
+        {
+            let notified = notified.clone();
+            readiness.on_ready(move || notified.store(true, Ordering::SeqCst));
+        }
+        let hold = readiness.hold();
+        let mut wait = readiness.wait();
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        readiness.set_session(true);
+        assert!(Pin::new(&mut wait).poll(&mut cx).is_pending());
+        drop(hold);
+        assert!(Pin::new(&mut wait).poll(&mut cx).is_ready());
+        assert!(notified.load(Ordering::SeqCst));
+
+        readiness.set_session(false);
+        assert!(!readiness.is_ready());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::traits::{AsyncQueueT, MAX_PACKET_LEN};
+use bytes::{Bytes, BytesMut};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Allocation, that received packets are split off. Packets keep their chunk alive.
+///
+/// Chunks are zeroed once, when they are allocated, which large allocations get from the
+/// system for free, so that receive buffers are not filled before each packet.
+const CHUNK_LEN: usize = 4 * MAX_PACKET_LEN;
+
+/// Future, returned by [`AsyncQueueExt::recv_many`](crate::traits::AsyncQueueExt::recv_many).
+///
+/// Packets are appended to `out`, as soon as they are received, so dropping this future before
+/// completion does not lose any packets.
+pub struct RecvMany<'a, Q: ?Sized> {
+    queue: &'a mut Q,
+    out: &'a mut Vec<(Bytes, bool)>,
+    limit: usize,
+    received: usize,
+    /// Packets are received into it and split off, so that small packets share allocations.
+    /// All of its length is initialized.
+    buf: BytesMut,
+}
+
+impl<'a, Q: ?Sized> RecvMany<'a, Q> {
+    pub(crate) fn new(queue: &'a mut Q, out: &'a mut Vec<(Bytes, bool)>, limit: usize) -> Self {
+        Self {
+            queue,
+            out,
+            limit,
+            received: 0,
+            buf: BytesMut::new(),
+        }
+    }
+}
+
+impl<Q: AsyncQueueT + ?Sized> Future for RecvMany<'_, Q> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let self_mut = self.get_mut();
+        while self_mut.received < self_mut.limit {
+            if self_mut.buf.len() < MAX_PACKET_LEN {
+                self_mut.buf = BytesMut::zeroed(CHUNK_LEN);
+            }
+            let buf = &mut self_mut.buf[..MAX_PACKET_LEN];
+            match Pin::new(&mut *self_mut.queue).poll_recv(cx, buf) {
+                // End of stream
+                Poll::Ready(Ok((0, _))) => break,
+                Poll::Ready(Ok((n, truncated))) => {
+                    let packet = self_mut.buf.split_to(n).freeze();
+                    self_mut.out.push((packet, truncated));
+                    self_mut.received += 1;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                // Only the first packet is waited for
+                Poll::Pending if self_mut.received == 0 => return Poll::Pending,
+                Poll::Pending => break,
+            }
+        }
+        Poll::Ready(Ok(self_mut.received))
+    }
+}
@@ -0,0 +1,124 @@
+//! Sampled logging of data path anomalies, like truncated or dropped packets, so that floods
+//! of them neither spam the log nor go unnoticed.
+use log::Level;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Which anomalies of a kind are logged: the `first` ones, and then each `every`-th one.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Sampling {
+    pub first: u64,
+    /// Zero logs no anomalies after the first ones.
+    pub every: u64,
+}
+
+impl Sampling {
+    /// No anomalies are logged.
+    pub const OFF: Self = Self { first: 0, every: 0 };
+    /// Each anomaly is logged.
+    pub const ALL: Self = Self { first: 0, every: 1 };
+
+    /// Returns `true`, if the `n`-th anomaly, counted from one, is logged.
+    pub fn is_sampled(&self, n: u64) -> bool {
+        n <= self.first || (self.every != 0 && (n - self.first) % self.every == 0)
+    }
+}
+
+/// First 10 anomalies, then each 1000-th one.
+impl Default for Sampling {
+    fn default() -> Self {
+        Self {
+            first: 10,
+            every: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Anomaly {
+    /// Packet did not fit into the read buffer.
+    Truncated,
+    /// Packet was dropped, because a queue or a ring was full.
+    QueueFull,
+    /// Packet was dropped or rejected by a hook, like a filter. It is logged at debug level,
+    /// as filters drop packets on purpose.
+    Filtered,
+}
+
+impl Anomaly {
+    const ALL: [Anomaly; 3] = [Anomaly::Truncated, Anomaly::QueueFull, Anomaly::Filtered];
+
+    fn level(self) -> Level {
+        match self {
+            Anomaly::Truncated | Anomaly::QueueFull => Level::Warn,
+            Anomaly::Filtered => Level::Debug,
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Anomaly::Truncated => "packet truncated",
+            Anomaly::QueueFull => "packet dropped, queue is full",
+            Anomaly::Filtered => "packet filtered",
+        })
+    }
+}
+
+/// Counts anomalies of a queue by kind, and logs the sampled ones with their total count.
+#[derive(Debug)]
+pub struct SampledLog {
+    name: String,
+    sampling: Sampling,
+    counts: [AtomicU64; Anomaly::ALL.len()],
+}
+
+impl SampledLog {
+    /// Log of the queue `name`, that is included into messages.
+    pub fn new(name: impl Into<String>, sampling: Sampling) -> Self {
+        Self {
+            name: name.into(),
+            sampling,
+            counts: Default::default(),
+        }
+    }
+
+    /// Counts an anomaly and logs it, if it is sampled. `detail`, like the packet length, is
+    /// only formatted for logged anomalies.
+    pub fn record<D: fmt::Display>(&self, anomaly: Anomaly, detail: impl FnOnce() -> D) {
+        let n = self.counts[anomaly as usize].fetch_add(1, Ordering::Relaxed) + 1;
+        if self.sampling.is_sampled(n) {
+            log::log!(
+                anomaly.level(),
+                "{}: {anomaly} ({}), {n} so far",
+                self.name,
+                detail()
+            );
+        }
+    }
+
+    /// Number of recorded anomalies of the kind, including the ones, that were not logged.
+    pub fn count(&self, anomaly: Anomaly) -> u64 {
+        self.counts[anomaly as usize].load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_anomalies_then_each_nth_are_sampled() {
+        let sampling = Sampling { first: 2, every: 3 };
+        let sampled: Vec<u64> = (1..=9).filter(|n| sampling.is_sampled(*n)).collect();
+        assert_eq!(sampled, [1, 2, 5, 8]);
+        assert!(!Sampling::OFF.is_sampled(1));
+        assert!(Sampling::ALL.is_sampled(7));
+
+        let log = SampledLog::new("tun0", Sampling::OFF);
+        log.record(Anomaly::Truncated, || 1500);
+        assert_eq!(log.count(Anomaly::Truncated), 1);
+        assert_eq!(log.count(Anomaly::QueueFull), 0);
+    }
+}
@@ -0,0 +1,211 @@
+use futures_timer::Delay;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Token-bucket rate limiter of a data path direction. Disabled by default.
+///
+/// Packets are admitted while the bucket has enough tokens for them, and tokens are replenished
+/// at `bytes_per_sec`, up to `burst`. A packet, larger than `burst`, is admitted when the bucket
+/// is full.
+#[derive(Default)]
+pub struct RateLimiter {
+    bucket: Option<Bucket>,
+}
+
+struct Bucket {
+    bytes_per_sec: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+    delay: Option<Delay>,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec).min(self.burst);
+        self.updated = now;
+    }
+
+    /// Returns the time, until `len` bytes can be admitted.
+    fn wait_time(&mut self, len: usize) -> Option<Duration> {
+        self.refill();
+        let needed = (len as f64).min(self.burst);
+        match needed - self.tokens {
+            deficit if deficit > 0.0 => Some(Duration::from_secs_f64(deficit / self.bytes_per_sec)),
+            _ => None,
+        }
+    }
+}
+
+impl RateLimiter {
+    /// Limits throughput to `bytes_per_sec` with bursts of up to `burst` bytes. Bucket starts
+    /// full. Zero `bytes_per_sec` removes the limit.
+    pub fn set(&mut self, bytes_per_sec: u64, burst: u64) {
+        self.bucket = match bytes_per_sec {
+            0 => None,
+            _ => Some(Bucket {
+                bytes_per_sec: bytes_per_sec as f64,
+                burst: burst.max(1) as f64,
+                tokens: burst.max(1) as f64,
+                updated: Instant::now(),
+                delay: None,
+            }),
+        };
+    }
+
+    pub fn clear(&mut self) {
+        self.bucket = None;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.bucket.is_some()
+    }
+
+    /// Returns `Ready`, when a packet of `len` bytes can be admitted. Task is woken by a timer
+    /// otherwise. Tokens are not taken until [`consume`](Self::consume) is called, so a packet,
+    /// that could not be written, is not accounted.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>, len: usize) -> Poll<()> {
+        let bucket = match &mut self.bucket {
+            Some(bucket) => bucket,
+            None => return Poll::Ready(()),
+        };
+
+        loop {
+            if let Some(delay) = &mut bucket.delay {
+                match Pin::new(delay).poll(cx) {
+                    Poll::Ready(()) => bucket.delay = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            match bucket.wait_time(len) {
+                Some(wait) => bucket.delay = Some(Delay::new(wait)),
+                None => return Poll::Ready(()),
+            }
+        }
+    }
+
+    /// Blocks current thread, until a packet of `len` bytes can be admitted.
+    pub fn wait_ready(&mut self, len: usize) {
+        if let Some(bucket) = &mut self.bucket {
+            while let Some(wait) = bucket.wait_time(len) {
+                thread::sleep(wait);
+            }
+        }
+    }
+
+    /// Takes tokens for `len` bytes, that were actually written.
+    pub fn consume(&mut self, len: usize) {
+        if let Some(bucket) = &mut self.bucket {
+            bucket.tokens -= len as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker_ref;
+
+    fn bucket(limiter: &mut RateLimiter) -> &mut Bucket {
+        limiter.bucket.as_mut().unwrap()
+    }
+
+    /// Moves the last refill back, as if `elapsed` has passed since then.
+    fn advance(limiter: &mut RateLimiter, elapsed: Duration) {
+        let bucket = bucket(limiter);
+        bucket.updated = bucket.updated.checked_sub(elapsed).unwrap();
+    }
+
+    #[test]
+    fn disabled_limiter_admits_everything() {
+        let mut limiter = RateLimiter::default();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        assert!(!limiter.is_enabled());
+        assert_eq!(limiter.poll_ready(&mut cx, usize::MAX), Poll::Ready(()));
+        limiter.consume(usize::MAX);
+
+        limiter.set(0, 1000);
+        assert!(!limiter.is_enabled());
+    }
+
+    #[test]
+    fn burst_is_admitted_at_once() {
+        let mut limiter = RateLimiter::default();
+        limiter.set(1000, 3000);
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        for _ in 0..3 {
+            assert_eq!(limiter.poll_ready(&mut cx, 1000), Poll::Ready(()));
+            limiter.consume(1000);
+        }
+        assert_eq!(limiter.poll_ready(&mut cx, 1000), Poll::Pending);
+        let wait = bucket(&mut limiter).wait_time(1000).unwrap();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn readiness_does_not_take_tokens() {
+        let mut limiter = RateLimiter::default();
+        limiter.set(1000, 1000);
+        let mut cx = Context::from_waker(noop_waker_ref());
+        for _ in 0..3 {
+            assert_eq!(limiter.poll_ready(&mut cx, 1000), Poll::Ready(()));
+        }
+    }
+
+    #[test]
+    fn tokens_are_refilled_over_elapsed_time() {
+        let mut limiter = RateLimiter::default();
+        limiter.set(1000, 2000);
+        limiter.consume(2000);
+        assert!(bucket(&mut limiter).wait_time(500).is_some());
+
+        advance(&mut limiter, Duration::from_millis(500));
+        assert_eq!(bucket(&mut limiter).wait_time(500), None);
+        assert!(bucket(&mut limiter).wait_time(600).is_some());
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let mut limiter = RateLimiter::default();
+        limiter.set(1000, 2000);
+        advance(&mut limiter, Duration::from_secs(10));
+        bucket(&mut limiter).refill();
+        assert_eq!(bucket(&mut limiter).tokens, 2000.0);
+    }
+
+    #[test]
+    fn packet_larger_than_burst_waits_for_full_bucket() {
+        let mut limiter = RateLimiter::default();
+        limiter.set(1000, 1500);
+        assert_eq!(bucket(&mut limiter).wait_time(4000), None);
+
+        limiter.consume(1000);
+        let wait = bucket(&mut limiter).wait_time(4000).unwrap();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn consume_drives_balance_negative() {
+        let mut limiter = RateLimiter::default();
+        limiter.set(1000, 1500);
+        limiter.consume(4000);
+        assert_eq!(bucket(&mut limiter).tokens, -2500.0);
+
+        // Debt of 2500 bytes is repaid before even a single byte is admitted
+        let wait = bucket(&mut limiter).wait_time(1).unwrap();
+        assert!(wait > Duration::from_millis(2400) && wait <= Duration::from_millis(2501));
+    }
+
+    #[test]
+    fn zero_burst_still_admits_packets() {
+        let mut limiter = RateLimiter::default();
+        limiter.set(1000, 0);
+        assert_eq!(bucket(&mut limiter).wait_time(100), None);
+    }
+}
@@ -0,0 +1,126 @@
+//! Saved interface configuration, for reverting changes on exit or after a crash.
+use crate::Error;
+use netconfig::ipnet::IpNet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Configuration of an interface, as set by tunio: MTU and addresses.
+///
+/// Routes, metric and DNS servers are not captured. Daemons, that manage routes, reapply
+/// them with an [`InterfaceSpec`](crate::spec::InterfaceSpec).
+///
+/// Snapshot is persisted in a line-based text format, one `key value` pair per line, so that
+/// a process, restarted after a crash, may restore the state, saved by its predecessor.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    pub name: String,
+    pub mtu: u32,
+    pub addresses: Vec<IpNet>,
+}
+
+impl Snapshot {
+    /// Captures the current configuration of the interface. IPv6 link-local addresses are
+    /// managed by the OS and not captured.
+    pub fn capture(handle: &netconfig::Interface) -> Result<Self, Error> {
+        Ok(Self {
+            name: handle.name()?,
+            mtu: handle.mtu()?,
+            addresses: handle
+                .addresses()?
+                .into_iter()
+                .filter(|address| !is_link_local(address))
+                .collect(),
+        })
+    }
+
+    /// Applies the snapshot to the interface: sets MTU, removes addresses, that were added
+    /// after the snapshot was taken, and adds missing ones. IPv6 link-local addresses are
+    /// left in place.
+    pub fn restore(&self, handle: &netconfig::Interface) -> Result<(), Error> {
+        if handle.mtu()? != self.mtu {
+            handle.set_mtu(self.mtu)?;
+        }
+        let current = handle.addresses()?;
+        for address in &current {
+            if !self.addresses.contains(address) && !is_link_local(address) {
+                handle.remove_address(*address)?;
+            }
+        }
+        for address in &self.addresses {
+            if !current.contains(address) {
+                handle.add_address(*address)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the snapshot to the interface with the saved name.
+    pub fn restore_by_name(&self) -> Result<(), Error> {
+        self.restore(&netconfig::Interface::try_from_name(&self.name)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        Ok(fs::write(path, self.to_string())?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+pub(crate) fn is_link_local(address: &IpNet) -> bool {
+    match address {
+        IpNet::V4(_) => false,
+        IpNet::V6(net) => (net.addr().segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "name {}", self.name)?;
+        writeln!(f, "mtu {}", self.mtu)?;
+        for address in &self.addresses {
+            writeln!(f, "address {address}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |name: &str, value: &str, reason: String| Error::InvalidConfigValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            reason,
+        };
+        let (mut name, mut mtu, mut addresses) = (None, None, vec![]);
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "name" => name = Some(value.to_string()),
+                "mtu" => {
+                    mtu = Some(
+                        value
+                            .parse()
+                            .map_err(|err| invalid(key, value, format!("{err}")))?,
+                    )
+                }
+                "address" => addresses.push(
+                    value
+                        .parse()
+                        .map_err(|err| invalid(key, value, format!("{err}")))?,
+                ),
+                _ => return Err(invalid(key, value, "unknown key".to_string())),
+            }
+        }
+        Ok(Self {
+            name: name.ok_or_else(|| invalid("name", "", "missing".to_string()))?,
+            mtu: mtu.ok_or_else(|| invalid("mtu", "", "missing".to_string()))?,
+            addresses,
+        })
+    }
+}
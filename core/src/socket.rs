@@ -0,0 +1,9 @@
+/// Address family of a socket, that is bound to an interface.
+///
+/// Windows and macOS use separate socket options for IPv4 and IPv6, so family must be known
+/// in advance. Dual-stack IPv6 sockets should use [`SocketFamily::Ipv6`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SocketFamily {
+    Ipv4,
+    Ipv6,
+}
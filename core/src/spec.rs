@@ -0,0 +1,194 @@
+//! Declarative configuration of interfaces, for daemons, that re-read their configuration at
+//! runtime: backends compare the live interface to a spec and apply only the differences.
+use crate::address::{Address, AddressFlags};
+use crate::snapshot::is_link_local;
+use netconfig::ipnet::IpNet;
+use std::net::IpAddr;
+
+/// Desired state of an interface. Addresses and routes are complete lists: the ones, that are
+/// not in the spec, are removed, except IPv6 link-local addresses, that are managed by the OS.
+/// `None` leaves the property, as it is.
+///
+/// Backends capture the live state in the same form. Properties, that a backend does not
+/// read, are `None`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct InterfaceSpec {
+    pub up: Option<bool>,
+    pub mtu: Option<u32>,
+    /// Addresses with lifetimes are added again on each apply, which refreshes them.
+    pub addresses: Vec<Address>,
+    /// Routes through the interface without a gateway, like `ip route add <net> dev <name>`.
+    /// Routes, that the system adds for prefixes of addresses, are not managed.
+    pub routes: Vec<IpNet>,
+    /// DNS servers of the interface. Backends, that do not configure DNS, fail with
+    /// [`Unsupported`](std::io::ErrorKind::Unsupported), if it is set.
+    pub dns: Option<Vec<IpAddr>>,
+    pub flags: LinkFlags,
+}
+
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct LinkFlags {
+    pub promiscuous: Option<bool>,
+    pub allmulti: Option<bool>,
+    pub arp: Option<bool>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LinkFlag {
+    Promiscuous,
+    AllMulti,
+    Arp,
+}
+
+/// Single change, that brings an interface closer to its spec.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Change {
+    SetUp(bool),
+    SetMtu(u32),
+    /// Adds an address, or updates the label and flags of an existing one.
+    AddAddress(Address),
+    RemoveAddress(IpNet),
+    AddRoute(IpNet),
+    RemoveRoute(IpNet),
+    SetDns(Vec<IpAddr>),
+    SetFlag(LinkFlag, bool),
+}
+
+impl InterfaceSpec {
+    /// Changes, that bring `current` to this spec, in the order, they are to be applied.
+    ///
+    /// MTU is set before addresses, as the kernel refuses IPv6 addresses on links with a low
+    /// MTU. Interface is brought up before routes are added, and down after everything else.
+    pub fn diff(&self, current: &InterfaceSpec) -> Vec<Change> {
+        let mut changes = vec![];
+        if let Some(mtu) = self.mtu.filter(|mtu| current.mtu != Some(*mtu)) {
+            changes.push(Change::SetMtu(mtu));
+        }
+
+        for address in &current.addresses {
+            let kept = self.addresses.iter().any(|a| a.net == address.net);
+            if !kept && !is_link_local(&address.net) {
+                changes.push(Change::RemoveAddress(address.net));
+            }
+        }
+        for address in &self.addresses {
+            let applied = current.addresses.iter().any(|a| is_applied(address, a));
+            if !applied {
+                changes.push(Change::AddAddress(address.clone()));
+            }
+        }
+
+        let up = self.up.filter(|up| current.up != Some(*up));
+        if up == Some(true) {
+            changes.push(Change::SetUp(true));
+        }
+        for route in &current.routes {
+            if !self.routes.contains(route) {
+                changes.push(Change::RemoveRoute(*route));
+            }
+        }
+        for route in &self.routes {
+            if !current.routes.contains(route) {
+                changes.push(Change::AddRoute(*route));
+            }
+        }
+
+        if let Some(dns) = self
+            .dns
+            .as_ref()
+            .filter(|dns| current.dns.as_ref() != Some(dns))
+        {
+            changes.push(Change::SetDns(dns.clone()));
+        }
+        let flags = [
+            (
+                LinkFlag::Promiscuous,
+                self.flags.promiscuous,
+                current.flags.promiscuous,
+            ),
+            (
+                LinkFlag::AllMulti,
+                self.flags.allmulti,
+                current.flags.allmulti,
+            ),
+            (LinkFlag::Arp, self.flags.arp, current.flags.arp),
+        ];
+        for (flag, desired, current) in flags {
+            if let Some(enabled) = desired.filter(|enabled| current != Some(*enabled)) {
+                changes.push(Change::SetFlag(flag, enabled));
+            }
+        }
+
+        if up == Some(false) {
+            changes.push(Change::SetUp(false));
+        }
+        changes
+    }
+}
+
+/// Returns `true`, if `current` has the label and the settable flags of `desired`. Flags,
+/// that are only reported, are not compared.
+fn is_applied(desired: &Address, current: &Address) -> bool {
+    let settable = |flags: AddressFlags| (flags.no_prefix_route, flags.no_dad, flags.temporary);
+    desired.net == current.net
+        && desired.preferred_lifetime.is_none()
+        && desired.valid_lifetime.is_none()
+        && (desired.net.addr().is_ipv6() || desired.label == current.label)
+        && settable(desired.flags) == settable(current.flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(net: &str) -> Address {
+        Address::new(net.parse().unwrap())
+    }
+
+    #[test]
+    fn only_differences_are_applied() {
+        let current = InterfaceSpec {
+            up: Some(false),
+            mtu: Some(1500),
+            addresses: vec![
+                address("10.0.0.1/24"),
+                address("fe80::1/64"),
+                address("fd00::1/64"),
+            ],
+            routes: vec!["10.1.0.0/16".parse().unwrap()],
+            flags: LinkFlags {
+                arp: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let spec = InterfaceSpec {
+            up: Some(true),
+            mtu: Some(1420),
+            addresses: vec![address("10.0.0.1/24"), address("10.0.0.2/24")],
+            routes: vec!["10.2.0.0/16".parse().unwrap()],
+            flags: LinkFlags {
+                arp: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            spec.diff(&current),
+            [
+                Change::SetMtu(1420),
+                Change::RemoveAddress("fd00::1/64".parse().unwrap()),
+                Change::AddAddress(address("10.0.0.2/24")),
+                Change::SetUp(true),
+                Change::RemoveRoute("10.1.0.0/16".parse().unwrap()),
+                Change::AddRoute("10.2.0.0/16".parse().unwrap()),
+            ]
+        );
+
+        let applied = InterfaceSpec {
+            addresses: vec![address("10.0.0.1/24"), address("10.0.0.2/24")],
+            ..spec.clone()
+        };
+        assert!(spec.diff(&applied).is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use crate::config::Layer;
+use crate::packet::traffic_class;
+#[cfg(target_has_atomic = "64")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(not(target_has_atomic = "64"))]
+use std::sync::Mutex;
+
+/// ECN codepoint of an IP packet, see RFC 3168.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Ecn {
+    NotEct,
+    Ect1,
+    Ect0,
+    Ce,
+}
+
+impl Ecn {
+    /// Extracts ECN codepoint from an IPv4 or IPv6 packet. For [`Layer::L2`] packet must start
+    /// with an Ethernet header. Returns `None` for non-IP or truncated packets.
+    pub fn of_packet(packet: &[u8], layer: Layer) -> Option<Self> {
+        Some(match traffic_class(packet, layer)? & 0b11 {
+            0b00 => Ecn::NotEct,
+            0b01 => Ecn::Ect1,
+            0b10 => Ecn::Ect0,
+            _ => Ecn::Ce,
+        })
+    }
+}
+
+/// Packet counters of a single direction.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct DirectionStats {
+    pub packets: u64,
+    pub bytes: u64,
+    /// Packets, marked as ECN-capable with ECT(0).
+    pub ect0: u64,
+    /// Packets, marked as ECN-capable with ECT(1).
+    pub ect1: u64,
+    /// Packets, marked with Congestion Experienced.
+    pub ce: u64,
+}
+
+/// Snapshot of interface counters. `rx` counts packets, read from the interface, `tx` counts
+/// packets, written to it.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct QueueStats {
+    pub rx: DirectionStats,
+    pub tx: DirectionStats,
+}
+
+/// Packets, buffered in memory between the device and the reader, and their total length.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct BufferUsage {
+    pub packets: usize,
+    pub bytes: usize,
+}
+
+/// 64-bit counter. 32-bit MIPS and PowerPC targets have no 64-bit atomics, so counters are
+/// guarded by a mutex there.
+#[derive(Default)]
+struct Counter(
+    #[cfg(target_has_atomic = "64")] AtomicU64,
+    #[cfg(not(target_has_atomic = "64"))] Mutex<u64>,
+);
+
+impl Counter {
+    #[cfg(target_has_atomic = "64")]
+    fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[cfg(target_has_atomic = "64")]
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    #[cfg(not(target_has_atomic = "64"))]
+    fn add(&self, n: u64) {
+        let mut value = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        *value = value.wrapping_add(n);
+    }
+
+    #[cfg(not(target_has_atomic = "64"))]
+    fn get(&self) -> u64 {
+        *self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[derive(Default)]
+struct DirectionCounters {
+    packets: Counter,
+    bytes: Counter,
+    ect0: Counter,
+    ect1: Counter,
+    ce: Counter,
+}
+
+impl DirectionCounters {
+    fn record(&self, packet: &[u8], layer: Layer) {
+        self.packets.add(1);
+        self.bytes.add(packet.len() as u64);
+
+        let counter = match Ecn::of_packet(packet, layer) {
+            Some(Ecn::Ect0) => &self.ect0,
+            Some(Ecn::Ect1) => &self.ect1,
+            Some(Ecn::Ce) => &self.ce,
+            Some(Ecn::NotEct) | None => return,
+        };
+        counter.add(1);
+    }
+
+    fn snapshot(&self) -> DirectionStats {
+        DirectionStats {
+            packets: self.packets.get(),
+            bytes: self.bytes.get(),
+            ect0: self.ect0.get(),
+            ect1: self.ect1.get(),
+            ce: self.ce.get(),
+        }
+    }
+}
+
+/// Interface counters, updated on each packet.
+pub struct StatsCounters {
+    layer: Layer,
+    rx: DirectionCounters,
+    tx: DirectionCounters,
+}
+
+impl StatsCounters {
+    pub fn new(layer: Layer) -> Self {
+        Self {
+            layer,
+            rx: Default::default(),
+            tx: Default::default(),
+        }
+    }
+
+    pub fn record_rx(&self, packet: &[u8]) {
+        self.rx.record(packet, self.layer);
+    }
+
+    pub fn record_tx(&self, packet: &[u8]) {
+        self.tx.record(packet, self.layer);
+    }
+
+    pub fn snapshot(&self) -> QueueStats {
+        QueueStats {
+            rx: self.rx.snapshot(),
+            tx: self.tx.snapshot(),
+        }
+    }
+}
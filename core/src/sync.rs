@@ -0,0 +1,13 @@
+//! Synchronization primitives of the crate and of the backends, that are replaced with the
+//! ones of [`loom`](https://docs.rs/loom), when built with `--cfg loom`, so that their models
+//! can check interleavings of readers, wakers and shutdown.
+#[cfg(loom)]
+pub use loom::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex, MutexGuard,
+};
+#[cfg(not(loom))]
+pub use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex, MutexGuard,
+};
@@ -0,0 +1,168 @@
+//! Helpers for integration tests of code, built on tunio: generated packets are sent through
+//! one queue and must come out of another one intact and in order.
+//!
+//! Queues may be real devices, routed to each other, in privileged tests, or mock interfaces
+//! otherwise.
+use crate::packet;
+use crate::traits::{AsyncQueueExt, AsyncQueueT, MAX_PACKET_LEN};
+use futures::{try_join, AsyncWriteExt};
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Time to wait for each packet in [`roundtrip`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Endless generator of well-formed UDP packets with random ports and payloads, for L3
+/// queues. Same seed generates the same packets.
+///
+/// Payload lengths are random, but empty and longest payloads are generated more often, as
+/// they are the usual edge cases.
+pub struct Packets {
+    state: u64,
+    source: IpAddr,
+    destination: IpAddr,
+    max_payload: usize,
+}
+
+impl Packets {
+    /// Panics, if addresses are of different families.
+    pub fn new(seed: u64, source: IpAddr, destination: IpAddr) -> Self {
+        assert_eq!(
+            source.is_ipv4(),
+            destination.is_ipv4(),
+            "addresses must be of the same family"
+        );
+        Self {
+            state: seed,
+            source,
+            destination,
+            max_payload: 1400,
+        }
+    }
+
+    /// Longest payload of generated packets, 1400 bytes by default, so that packets fit into
+    /// the usual MTU.
+    pub fn max_payload(mut self, max_payload: usize) -> Self {
+        self.max_payload = max_payload;
+        self
+    }
+
+    /// SplitMix64, so that generated packets do not depend on a random number crate.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn payload_len(&mut self) -> usize {
+        match self.next_u64() % 8 {
+            0 => 0,
+            1 => self.max_payload,
+            _ => (self.next_u64() % (self.max_payload as u64 + 1)) as usize,
+        }
+    }
+}
+
+impl Iterator for Packets {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        let ports = self.next_u64();
+        let payload: Vec<u8> = (0..self.payload_len())
+            .map(|_| self.next_u64() as u8)
+            .collect();
+        // Ephemeral ports, so that generated packets do not hit real services
+        let src_port = 49152 + (ports as u16) % 16384;
+        let dst_port = 49152 + ((ports >> 16) as u16) % 16384;
+        let datagram = packet::udp(src_port, dst_port, &payload);
+        Some(match (self.source, self.destination) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => packet::ipv4(src, dst, 17, 64, &datagram),
+            (IpAddr::V6(src), IpAddr::V6(dst)) => packet::ipv6(src, dst, 17, 64, &datagram),
+            _ => unreachable!(),
+        })
+    }
+}
+
+/// Result of a successful [`roundtrip`].
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Roundtrip {
+    pub received: usize,
+    /// Packets, received from the second queue, that were not sent, like neighbor
+    /// solicitations of real devices. They are skipped.
+    pub unexpected: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RoundtripError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("packet {index} was not received")]
+    Missing { index: usize },
+    #[error("packet {index} was received twice")]
+    Duplicated { index: usize },
+}
+
+/// Sends `packets` through `a` and checks, that `b` receives each of them once, intact and in
+/// order, waiting for [`DEFAULT_TIMEOUT`] for each packet.
+///
+/// Packets are sent and received concurrently, so any number of them fits into bounded
+/// queues.
+pub async fn roundtrip<A: AsyncQueueT, B: AsyncQueueT>(
+    a: &mut A,
+    b: &mut B,
+    packets: &[Vec<u8>],
+) -> Result<Roundtrip, RoundtripError> {
+    roundtrip_timeout(a, b, packets, DEFAULT_TIMEOUT).await
+}
+
+/// Like [`roundtrip`], but waits for `timeout` for each packet.
+pub async fn roundtrip_timeout<A: AsyncQueueT, B: AsyncQueueT>(
+    a: &mut A,
+    b: &mut B,
+    packets: &[Vec<u8>],
+    timeout: Duration,
+) -> Result<Roundtrip, RoundtripError> {
+    let send = async {
+        for packet in packets {
+            a.write_all(packet).await?;
+        }
+        Ok(())
+    };
+    let (_, result) = try_join!(send, receive(b, packets, timeout))?;
+    Ok(result)
+}
+
+async fn receive<B: AsyncQueueT>(
+    b: &mut B,
+    packets: &[Vec<u8>],
+    timeout: Duration,
+) -> Result<Roundtrip, RoundtripError> {
+    let mut result = Roundtrip::default();
+    let mut buf = vec![0u8; MAX_PACKET_LEN];
+    while result.received < packets.len() {
+        let next = result.received;
+        let n = match b.recv_timeout(&mut buf, timeout).await {
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                return Err(RoundtripError::Missing { index: next })
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let received = &buf[..n];
+        if packets[next] == received {
+            result.received += 1;
+        } else if let Some(index) = packets[..next].iter().position(|p| p == received) {
+            return Err(RoundtripError::Duplicated { index });
+        } else if packets[next..].iter().any(|p| p == received) {
+            // A later packet overtook the expected one, that is lost or reordered
+            return Err(RoundtripError::Missing { index: next });
+        } else {
+            result.unexpected += 1;
+        }
+    }
+    Ok(result)
+}
@@ -0,0 +1,46 @@
+use futures::AsyncRead;
+use futures_timer::Delay;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Future, returned by [`AsyncQueueExt::recv_timeout`](crate::traits::AsyncQueueExt::recv_timeout).
+///
+/// Dropping this future before completion does not lose any packets: packet is only taken from
+/// the queue when it is copied to `buf` and the future resolves.
+pub struct RecvTimeout<'a, Q: ?Sized> {
+    queue: &'a mut Q,
+    buf: &'a mut [u8],
+    delay: Delay,
+}
+
+impl<'a, Q: ?Sized> RecvTimeout<'a, Q> {
+    pub(crate) fn new(queue: &'a mut Q, buf: &'a mut [u8], timeout: Duration) -> Self {
+        Self {
+            queue,
+            buf,
+            delay: Delay::new(timeout),
+        }
+    }
+}
+
+impl<Q: AsyncRead + Unpin + ?Sized> Future for RecvTimeout<'_, Q> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let self_mut = self.get_mut();
+
+        // Queue is polled first, so a packet, that is already available, is never discarded
+        // because of an expired timer.
+        if let Poll::Ready(result) = Pin::new(&mut *self_mut.queue).poll_read(cx, self_mut.buf) {
+            return Poll::Ready(result);
+        }
+
+        match Pin::new(&mut self_mut.delay).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use crate::traits::AsyncQueueT;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+/// Future, returned by [`AsyncQueueExt::recv_timestamped`](crate::traits::AsyncQueueExt::recv_timestamped).
+pub struct RecvTimestamped<'a, Q: ?Sized> {
+    queue: &'a mut Q,
+    buf: &'a mut [u8],
+}
+
+impl<'a, Q: ?Sized> RecvTimestamped<'a, Q> {
+    pub(crate) fn new(queue: &'a mut Q, buf: &'a mut [u8]) -> Self {
+        Self { queue, buf }
+    }
+}
+
+impl<Q: AsyncQueueT + ?Sized> Future for RecvTimestamped<'_, Q> {
+    type Output = io::Result<(usize, Instant)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let self_mut = self.get_mut();
+        Pin::new(&mut *self_mut.queue).poll_recv_timestamped(cx, self_mut.buf)
+    }
+}
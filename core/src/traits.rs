@@ -1,16 +1,79 @@
-use crate::config::{IfConfig, IfConfigBuilder};
+use crate::address::Address;
+use crate::config::{IfConfig, IfConfigBuilder, Layer, Violation};
+use crate::device::DeviceInfo;
+use crate::events::EventReceiver;
+use crate::offload::Offload;
+use crate::packet::ETHER_HEADER_LEN;
+use crate::recv_many::RecvMany;
+use crate::snapshot::Snapshot;
+use crate::timeout::RecvTimeout;
+use crate::timestamp::RecvTimestamped;
 use crate::Error;
+use bytes::Bytes;
 use futures::{AsyncRead, AsyncWrite};
-use std::io::{Read, Write};
+use netconfig::ipnet::IpNet;
+use std::io::{self, Read, Write};
+use std::ops::Deref;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Largest packet of any queue: an IP packet of the largest size with an Ethernet header.
+pub const MAX_PACKET_LEN: usize = u16::MAX as usize + ETHER_HEADER_LEN;
 
 pub trait PlatformIfConfigT: Default + Clone {
     type Builder: Default;
+
+    /// Longest interface name, accepted by the platform, as counted by
+    /// [`name_len`](Self::name_len).
+    const MAX_NAME_LEN: usize;
+    /// Layers, that interfaces of the platform can have.
+    const LAYERS: &'static [Layer] = &[Layer::L2, Layer::L3];
+
+    /// Length of a name in units of the platform: bytes by default.
+    fn name_len(name: &str) -> usize {
+        name.len()
+    }
+
+    /// Returns the reason, why the platform rejects `name`. Default rules are the ones of
+    /// Unix: a name must not be `.` or `..` and must not contain `/`, `:` or whitespace.
+    /// Empty names are accepted, as they are assigned by the OS.
+    fn check_name(name: &str) -> Option<String> {
+        let invalid_char = name.contains(|c: char| c == '/' || c == ':' || c.is_whitespace());
+        match name == "." || name == ".." || invalid_char {
+            true => Some("must not be . or .., and must not contain /, : or whitespace".into()),
+            false => None,
+        }
+    }
+
+    /// Adds violations of platform-specific settings.
+    fn validate(&self, violations: &mut Vec<Violation>) {
+        let _ = violations;
+    }
 }
 
 pub trait DriverT: Sized {
     type PlatformIfConfig: PlatformIfConfigT;
 
     fn new() -> Result<Self, Error>;
+
+    /// Subscribes to lifecycle events of all interfaces, created by this driver.
+    fn subscribe(&self) -> EventReceiver;
+
+    /// Lists existing devices of this driver, including the ones, created by other processes.
+    /// Fails with [`Unsupported`](std::io::ErrorKind::Unsupported), if the driver cannot
+    /// list devices.
+    fn enumerate(&self) -> Result<Vec<DeviceInfo>, Error> {
+        Err(io::Error::from(io::ErrorKind::Unsupported).into())
+    }
+
+    /// Removes a device, listed by [`enumerate`](Self::enumerate), even if another process
+    /// uses it. Fails with [`Unsupported`](std::io::ErrorKind::Unsupported), if devices of
+    /// this driver only live as long as the process, that created them.
+    fn delete(&self, device: &DeviceInfo) -> Result<(), Error> {
+        let _ = device;
+        Err(io::Error::from(io::ErrorKind::Unsupported).into())
+    }
 }
 
 pub trait InterfaceT: Sized {
@@ -34,10 +97,246 @@ pub trait InterfaceT: Sized {
     fn down(&mut self) -> Result<(), Error>;
     fn handle(&self) -> netconfig::Interface;
 
+    /// OS interface index, as used by routing tables and `IP_UNICAST_IF`/`IPV6_UNICAST_IF`
+    /// socket options.
+    fn index(&self) -> Result<u32, Error> {
+        Ok(self.handle().index()?)
+    }
+
+    /// Captures MTU and addresses, so that they can be reverted with [`restore`](Self::restore).
+    fn snapshot(&self) -> Result<Snapshot, Error> {
+        Snapshot::capture(&self.handle())
+    }
+
+    /// Reverts MTU and addresses to a snapshot, taken earlier.
+    fn restore(&self, snapshot: &Snapshot) -> Result<(), Error> {
+        snapshot.restore(&self.handle())
+    }
+
+    /// Sets the MTU on the blocking pool, so that async runtimes are not stalled. Calls of
+    /// this kind are cancelled, if their [`Offload`] future is dropped before they start.
+    fn set_mtu_async(&self, mtu: u32) -> Offload<()> {
+        let handle = self.handle();
+        Offload::new(move || Ok(handle.set_mtu(mtu)?))
+    }
+
+    /// Adds an address on the blocking pool. By default, only the prefix is added, backends,
+    /// that set labels, flags and lifetimes, override it.
+    fn add_address_async(&self, address: Address) -> Offload<()> {
+        let handle = self.handle();
+        Offload::new(move || Ok(handle.add_address(address.net)?))
+    }
+
+    fn remove_address_async(&self, net: IpNet) -> Offload<()> {
+        let handle = self.handle();
+        Offload::new(move || Ok(handle.remove_address(net)?))
+    }
+
+    /// Adds a route through the interface without a gateway on the blocking pool, like
+    /// [`Change::AddRoute`](crate::spec::Change::AddRoute). Fails with
+    /// [`Unsupported`](std::io::ErrorKind::Unsupported), if the backend does not manage routes.
+    fn add_route_async(&self, net: IpNet) -> Offload<()> {
+        let _ = net;
+        Offload::ready(Err(io::Error::from(io::ErrorKind::Unsupported).into()))
+    }
+
+    fn remove_route_async(&self, net: IpNet) -> Offload<()> {
+        let _ = net;
+        Offload::ready(Err(io::Error::from(io::ErrorKind::Unsupported).into()))
+    }
+
+    /// Longest interface name, accepted by the platform: bytes on Unix and UTF-16 code units
+    /// on Windows.
+    fn max_name_len() -> usize {
+        Self::PlatformIfConfig::MAX_NAME_LEN
+    }
+
     fn config_builder() -> IfConfigBuilder<Self::PlatformIfConfig> {
         IfConfigBuilder::default()
     }
 }
 
-pub trait SyncQueueT: Read + Write {}
-pub trait AsyncQueueT: AsyncRead + AsyncWrite + Unpin {}
+/// Blocking packet queue.
+///
+/// Packets are received and sent as datagrams with [`recv`](Self::recv) and
+/// [`send`](Self::send). [`Read`] and [`Write`] are derived from them, so that queues fit
+/// byte-stream APIs: every read returns a single packet, and every write sends a single packet.
+///
+/// Packets of a queue are received in the order, the driver delivers them, and are sent in the
+/// order of writes. Only packets, that hooks inject, and packets, that the egress scheduler
+/// lets bypass the bulk backlog, are reordered, as configured.
+///
+/// End of stream, a zero-length read, is only returned after the queue was shut down
+/// explicitly. Queues, that lost their device or peer, fail with
+/// [`BrokenPipe`](io::ErrorKind::BrokenPipe) instead, or with
+/// [`NotConnected`](io::ErrorKind::NotConnected), if they are detached from the device, so that
+/// callers do not mistake it for a clean shutdown.
+pub trait SyncQueueT: Read + Write {
+    /// Receives a single packet into `buf`, returning its length and `true`, if the packet was
+    /// longer than `buf` and its remainder is discarded.
+    ///
+    /// `buf` may be of any length, shorter or longer than the packet: returned length is the
+    /// number of bytes, copied into `buf`, and never exceeds it. Rest of `buf` is left as is.
+    /// Implementations, that receive into their own buffers, copy with [`copy_packet`].
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)>;
+
+    /// Sends `packet` as a whole.
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+
+    /// Packet, returned by [`recv_ref`](Self::recv_ref). Zero-copy queues borrow it from the
+    /// driver until it is dropped, other queues return an owned buffer.
+    type PacketRef<'a>: Deref<Target = [u8]>
+    where
+        Self: 'a;
+
+    /// Receives a single packet without copying it, if the queue supports it. Queue is
+    /// borrowed, until the packet is dropped.
+    fn recv_ref(&mut self) -> io::Result<Self::PacketRef<'_>>;
+
+    /// Discards packets, that are buffered for reading, without waiting for new ones, and
+    /// returns their number. Used after rekeying or reconnecting, so that packets of the old
+    /// session are not processed with the new one. Fails with
+    /// [`Unsupported`](std::io::ErrorKind::Unsupported), if the queue cannot discard packets.
+    fn drain(&mut self) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Reads a single packet into `buf`, returning its length and monotonic receive timestamp.
+    ///
+    /// Unless the queue captures timestamps itself, packet is stamped when it is taken from
+    /// the driver.
+    fn recv_timestamped(&mut self, buf: &mut [u8]) -> io::Result<(usize, Instant)> {
+        let (n, _) = self.recv(buf)?;
+        Ok((n, Instant::now()))
+    }
+}
+
+/// Receives a packet into a new buffer, for [`SyncQueueT::recv_ref`] of queues without
+/// zero-copy receive.
+pub fn recv_owned<Q: SyncQueueT + ?Sized>(queue: &mut Q) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; MAX_PACKET_LEN];
+    let (n, _) = queue.recv(&mut buf)?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+/// Copies as much of `packet`, as fits, into `buf`, like [`SyncQueueT::recv`] does, returning the
+/// copied length and `true`, if the packet was truncated.
+pub fn copy_packet(packet: &[u8], buf: &mut [u8]) -> (usize, bool) {
+    let n = packet.len().min(buf.len());
+    buf[..n].copy_from_slice(&packet[..n]);
+    (n, packet.len() > n)
+}
+
+/// Asynchronous packet queue.
+///
+/// Like [`SyncQueueT`], it is datagram-oriented: [`AsyncRead`] and [`AsyncWrite`] are derived
+/// from [`poll_recv`](Self::poll_recv) and [`poll_send`](Self::poll_send).
+///
+/// Implementations must be cancel-safe: a read future, that is dropped before completion (for
+/// example, a losing branch of `select!`), must not lose a packet, and the waker from the most
+/// recent poll must be the one, that is woken.
+///
+/// Ordering and end of stream are the same as of [`SyncQueueT`], including reads, that were
+/// cancelled and retried, and reads, that were woken by a packet, written concurrently.
+pub trait AsyncQueueT: AsyncRead + AsyncWrite + Unpin {
+    /// Polls for a single packet, returning its length and `true`, if the packet was longer
+    /// than `buf` and its remainder is discarded.
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>>;
+
+    /// Polls for sending `packet` as a whole.
+    fn poll_send(self: Pin<&mut Self>, cx: &mut Context<'_>, packet: &[u8])
+        -> Poll<io::Result<()>>;
+
+    /// Discards packets, that are buffered for reading, like [`SyncQueueT::drain`].
+    fn drain(&mut self) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Polls, until a packet can be received, without taking it, for schedulers, that check
+    /// several queues and then receive from the ready ones in their own order.
+    ///
+    /// Readiness may be spurious: [`poll_recv`](Self::poll_recv) may still return `Pending`,
+    /// for example, when hooks drop the packet. Queue is also ready at end of stream and on
+    /// errors, which `poll_recv` returns then. Queues, that cannot check readiness, like byte
+    /// streams, fail with [`Unsupported`](io::ErrorKind::Unsupported).
+    fn poll_recv_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Err(io::ErrorKind::Unsupported.into()))
+    }
+
+    /// Polls for a single packet, returning its length and monotonic receive timestamp.
+    ///
+    /// Unless the queue captures timestamps itself, packet is stamped when it is taken from
+    /// the driver.
+    fn poll_recv_timestamped(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, Instant)>> {
+        let (n, _) = ready!(self.as_mut().poll_recv(cx, buf))?;
+        Poll::Ready(Ok((n, Instant::now())))
+    }
+}
+
+pub trait AsyncQueueExt: AsyncQueueT {
+    /// Reads a single packet into `buf`, failing with [`TimedOut`](std::io::ErrorKind::TimedOut)
+    /// if no packet arrives in `timeout`.
+    fn recv_timeout<'a>(
+        &'a mut self,
+        buf: &'a mut [u8],
+        timeout: Duration,
+    ) -> RecvTimeout<'a, Self> {
+        RecvTimeout::new(self, buf, timeout)
+    }
+
+    /// Reads a single packet into `buf`, returning its length and monotonic receive timestamp.
+    fn recv_timestamped<'a>(&'a mut self, buf: &'a mut [u8]) -> RecvTimestamped<'a, Self> {
+        RecvTimestamped::new(self, buf)
+    }
+
+    /// Waits for a packet and appends it to `out` together with up to `limit - 1` packets, that
+    /// are ready without waiting, returning their number, like `recv_many` of Tokio channels.
+    /// Each packet is paired with `true`, if it was longer than [`MAX_PACKET_LEN`] and its
+    /// remainder is discarded, like in [`poll_recv`](AsyncQueueT::poll_recv).
+    ///
+    /// Returns 0 at end of stream, or if `limit` is 0. On error, packets, that were received
+    /// before it, stay in `out`.
+    fn recv_many<'a>(
+        &'a mut self,
+        out: &'a mut Vec<(Bytes, bool)>,
+        limit: usize,
+    ) -> RecvMany<'a, Self> {
+        RecvMany::new(self, out, limit)
+    }
+}
+
+impl<Q: AsyncQueueT + ?Sized> AsyncQueueExt for Q {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_packet_leaves_rest_of_buffer() {
+        let mut buf = [0xFF; 4];
+        assert_eq!(copy_packet(&[1, 2], &mut buf), (2, false));
+        assert_eq!(buf, [1, 2, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn long_packet_is_truncated() {
+        let mut buf = [0; 2];
+        assert_eq!(copy_packet(&[1, 2, 3], &mut buf), (2, true));
+        assert_eq!(buf, [1, 2]);
+    }
+
+    #[test]
+    fn empty_buffer_and_packet() {
+        assert_eq!(copy_packet(&[1], &mut []), (0, true));
+        assert_eq!(copy_packet(&[], &mut [0; 2]), (0, false));
+    }
+}
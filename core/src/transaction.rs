@@ -0,0 +1,140 @@
+//! All-or-nothing configuration of interfaces: if a change fails, the ones, applied before it,
+//! are undone in reverse order, so that a failed setup does not leave a half-configured
+//! interface behind.
+use crate::spec::{Change, InterfaceSpec};
+use crate::Error;
+
+/// Changes, applied to an interface so far, and the state, they are undone to.
+pub struct Transaction {
+    before: InterfaceSpec,
+    applied: Vec<Change>,
+}
+
+impl Transaction {
+    /// Transaction of an interface in the state `before`, captured before the first change.
+    pub fn new(before: InterfaceSpec) -> Self {
+        Self {
+            before,
+            applied: vec![],
+        }
+    }
+
+    /// Applies `changes` in order with `apply`. If one fails, the applied ones are rolled
+    /// back, and [`Error::TransactionFailed`] tells the failed change, its cause, and the
+    /// changes, that could not be undone.
+    pub fn run(
+        mut self,
+        changes: Vec<Change>,
+        mut apply: impl FnMut(&Change) -> Result<(), Error>,
+    ) -> Result<Vec<Change>, Error> {
+        for change in changes {
+            if let Err(err) = self.apply(change.clone(), &mut apply) {
+                return Err(self.rollback(&change, err, apply));
+            }
+        }
+        Ok(self.commit())
+    }
+
+    /// Applies a single change, and records it, if it succeeds.
+    pub fn apply(
+        &mut self,
+        change: Change,
+        apply: impl FnOnce(&Change) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        apply(&change)?;
+        self.applied.push(change);
+        Ok(())
+    }
+
+    /// Undoes the applied changes in reverse order, and returns the error of the `failed`
+    /// change. Undo steps, that fail, are skipped, so that the others are still undone.
+    pub fn rollback(
+        self,
+        failed: &Change,
+        source: Error,
+        mut apply: impl FnMut(&Change) -> Result<(), Error>,
+    ) -> Error {
+        let mut rollback = vec![];
+        for undo in self.applied.iter().rev().filter_map(|c| self.inverse(c)) {
+            if let Err(err) = apply(&undo) {
+                rollback.push(format!("{undo:?}: {err}"));
+            }
+        }
+        Error::TransactionFailed {
+            change: format!("{failed:?}"),
+            source: Box::new(source),
+            rollback,
+        }
+    }
+
+    /// Keeps the applied changes, and returns them.
+    pub fn commit(self) -> Vec<Change> {
+        self.applied
+    }
+
+    /// Change, that restores the state before `change`. Properties, that were not captured,
+    /// like DNS of backends, that do not read it, are not restored.
+    fn inverse(&self, change: &Change) -> Option<Change> {
+        let before = &self.before;
+        let address = |net| before.addresses.iter().find(|a| a.net == net).cloned();
+        match change {
+            Change::SetUp(up) => Some(Change::SetUp(!up)),
+            Change::SetMtu(_) => before.mtu.map(Change::SetMtu),
+            Change::AddAddress(added) => Some(match address(added.net) {
+                Some(old) => Change::AddAddress(old),
+                None => Change::RemoveAddress(added.net),
+            }),
+            Change::RemoveAddress(net) => address(*net).map(Change::AddAddress),
+            Change::AddRoute(net) => {
+                (!before.routes.contains(net)).then_some(Change::RemoveRoute(*net))
+            }
+            Change::RemoveRoute(net) => Some(Change::AddRoute(*net)),
+            Change::SetDns(_) => before.dns.clone().map(Change::SetDns),
+            Change::SetFlag(flag, enabled) => Some(Change::SetFlag(*flag, !enabled)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use std::io;
+
+    #[test]
+    fn failed_change_rolls_back_applied_ones() {
+        let before = InterfaceSpec {
+            mtu: Some(1500),
+            addresses: vec![Address::new("10.0.0.1/24".parse().unwrap())],
+            ..Default::default()
+        };
+        let route = "10.1.0.0/16".parse().unwrap();
+        let changes = vec![
+            Change::SetMtu(1420),
+            Change::RemoveAddress("10.0.0.1/24".parse().unwrap()),
+            Change::AddRoute(route),
+        ];
+
+        let mut log = vec![];
+        let err = Transaction::new(before.clone())
+            .run(changes, |change| {
+                log.push(change.clone());
+                match change {
+                    Change::AddRoute(_) => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+                    _ => Ok(()),
+                }
+            })
+            .unwrap_err();
+        assert!(matches!(
+            &err,
+            Error::TransactionFailed { rollback, .. } if rollback.is_empty()
+        ));
+        assert_eq!(
+            log[3..],
+            [
+                Change::AddAddress(before.addresses[0].clone()),
+                Change::SetMtu(1500),
+            ]
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Cloneable writer handles of a single queue, so that several tasks write to the same device.
+//! Writers, that wait for ring space, send in turns, so that a task, that writes a lot, does
+//! not starve the others, like control-plane traffic.
+use crate::sync::{Arc, Mutex, MutexGuard};
+use crate::traits::AsyncQueueT;
+use futures::AsyncRead;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+/// Order, in which writers, that wait for ring space, send.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum WriterPolicy {
+    /// Waiting writers race for ring space, once it is free.
+    Unordered,
+    /// Waiting writers send a packet each in turn.
+    #[default]
+    RoundRobin,
+    /// Like `RoundRobin`, but each writer sends up to its weight of packets in a round.
+    Weighted,
+}
+
+struct Waiter {
+    id: u64,
+    waker: Waker,
+}
+
+/// Waiting writers and the packets, that they may still send in the current round.
+struct Turns {
+    policy: WriterPolicy,
+    next_id: u64,
+    /// Weight and remaining credit of each writer.
+    credits: HashMap<u64, (u32, u32)>,
+    waiting: VecDeque<Waiter>,
+}
+
+impl Turns {
+    fn new(policy: WriterPolicy) -> Self {
+        Self {
+            policy,
+            next_id: 0,
+            credits: HashMap::new(),
+            waiting: VecDeque::new(),
+        }
+    }
+
+    fn add_writer(&mut self, weight: u32) -> u64 {
+        let weight = match self.policy {
+            WriterPolicy::Weighted => weight,
+            _ => 1,
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.credits.insert(id, (weight, weight));
+        id
+    }
+
+    fn remove_writer(&mut self, id: u64) {
+        self.credits.remove(&id);
+    }
+
+    fn credit(&self, id: u64) -> u32 {
+        self.credits.get(&id).map_or(0, |(_, credit)| *credit)
+    }
+
+    /// Returns the first waiting writer, that has credit left, or the first one, if all of
+    /// them have spent it.
+    fn next(&self) -> Option<&Waiter> {
+        let mut waiting = self.waiting.iter();
+        waiting
+            .clone()
+            .find(|w| self.credit(w.id) > 0)
+            .or_else(|| waiting.next())
+    }
+
+    /// Returns the writer, whose turn it is. A new round starts, when all waiting writers
+    /// have spent their credits.
+    fn turn(&mut self) -> Option<u64> {
+        let next = self.next()?.id;
+        if self.credit(next) == 0 {
+            for (weight, credit) in self.credits.values_mut() {
+                *credit = *weight;
+            }
+        }
+        Some(next)
+    }
+
+    fn may_send(&mut self, id: u64) -> bool {
+        self.policy == WriterPolicy::Unordered || self.turn() == Some(id)
+    }
+
+    fn is_contended(&self) -> bool {
+        self.policy != WriterPolicy::Unordered && !self.waiting.is_empty()
+    }
+
+    fn wait(&mut self, id: u64, waker: &Waker) {
+        match self.waiting.iter_mut().find(|w| w.id == id) {
+            Some(waiter) => waiter.waker.clone_from(waker),
+            None => self.waiting.push_back(Waiter {
+                id,
+                waker: waker.clone(),
+            }),
+        }
+    }
+
+    /// Records a sent packet, or a cancelled wait, and wakes the writers, that may send next.
+    fn finish(&mut self, id: u64, sent: bool) {
+        self.waiting.retain(|w| w.id != id);
+        if let Some((_, credit)) = self.credits.get_mut(&id).filter(|_| sent) {
+            *credit = credit.saturating_sub(1);
+        }
+        match self.policy {
+            WriterPolicy::Unordered => self.waiting.iter().for_each(|w| w.waker.wake_by_ref()),
+            _ => self.next().iter().for_each(|w| w.waker.wake_by_ref()),
+        }
+    }
+}
+
+struct State<Q> {
+    queue: Q,
+    turns: Turns,
+}
+
+/// Queue, that is written through cloneable [`Writer`]s, and read through this handle.
+pub struct SharedQueue<Q> {
+    state: Arc<Mutex<State<Q>>>,
+}
+
+impl<Q: AsyncQueueT> SharedQueue<Q> {
+    pub fn new(queue: Q, policy: WriterPolicy) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                queue,
+                turns: Turns::new(policy),
+            })),
+        }
+    }
+
+    pub fn writer(&self) -> Writer<Q> {
+        self.weighted_writer(1)
+    }
+
+    /// Writer, that sends up to `weight` packets in a round with [`WriterPolicy::Weighted`].
+    /// Weight is ignored with other policies. Panics, if `weight` is zero.
+    pub fn weighted_writer(&self, weight: u32) -> Writer<Q> {
+        assert!(weight > 0, "writer weight must be positive");
+        let id = lock(&self.state).turns.add_writer(weight);
+        Writer {
+            state: self.state.clone(),
+            id,
+            weight,
+        }
+    }
+
+    pub fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        Pin::new(&mut lock(&self.state).queue).poll_recv(cx, buf)
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncRead for SharedQueue<Q> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+fn lock<Q>(state: &Mutex<State<Q>>) -> MutexGuard<'_, State<Q>> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Handle, that sends packets to a [`SharedQueue`]. Clones are separate writers of the same
+/// weight, that take their own turns.
+pub struct Writer<Q> {
+    state: Arc<Mutex<State<Q>>>,
+    id: u64,
+    weight: u32,
+}
+
+impl<Q: AsyncQueueT> Writer<Q> {
+    /// Sends `packet` as a whole, waiting for the turn of this writer, while others wait for
+    /// ring space. Turn is given up, if the returned future is dropped.
+    pub fn send<'a>(&'a mut self, packet: &'a [u8]) -> SendPacket<'a, Q> {
+        SendPacket {
+            writer: self,
+            packet,
+            waiting: false,
+        }
+    }
+}
+
+impl<Q> Clone for Writer<Q> {
+    fn clone(&self) -> Self {
+        let id = lock(&self.state).turns.add_writer(self.weight);
+        Self {
+            state: self.state.clone(),
+            id,
+            weight: self.weight,
+        }
+    }
+}
+
+impl<Q> Drop for Writer<Q> {
+    fn drop(&mut self) {
+        lock(&self.state).turns.remove_writer(self.id);
+    }
+}
+
+/// Future of [`Writer::send`].
+pub struct SendPacket<'a, Q> {
+    writer: &'a mut Writer<Q>,
+    packet: &'a [u8],
+    waiting: bool,
+}
+
+impl<Q: AsyncQueueT> Future for SendPacket<'_, Q> {
+    type Output = io::Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let id = this.writer.id;
+        let mut state = lock(&this.writer.state);
+        let state = &mut *state;
+
+        // Uncontended writes go straight to the queue
+        if this.waiting || state.turns.is_contended() {
+            state.turns.wait(id, cx.waker());
+            this.waiting = true;
+            if !state.turns.may_send(id) {
+                return Poll::Pending;
+            }
+        }
+
+        // Only the writer, whose turn it is, registers with the queue, so its wakeup is not
+        // taken over by the others
+        match Pin::new(&mut state.queue).poll_send(cx, this.packet) {
+            Poll::Ready(result) => {
+                this.waiting = false;
+                state.turns.finish(id, true);
+                Poll::Ready(result)
+            }
+            Poll::Pending => {
+                state.turns.wait(id, cx.waker());
+                this.waiting = true;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<Q> Drop for SendPacket<'_, Q> {
+    fn drop(&mut self) {
+        if self.waiting {
+            lock(&self.writer.state).turns.finish(self.writer.id, false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::task::noop_waker;
+
+    /// Sends packets, while every writer keeps waiting, returning the writers in order.
+    fn order(turns: &mut Turns, writers: &[u64], packets: usize) -> Vec<u64> {
+        let waker = noop_waker();
+        (0..packets)
+            .map(|_| {
+                writers.iter().for_each(|id| turns.wait(*id, &waker));
+                let id = turns.turn().unwrap();
+                turns.finish(id, true);
+                id
+            })
+            .collect()
+    }
+
+    #[test]
+    fn waiting_writers_send_in_turns() {
+        let mut turns = Turns::new(WriterPolicy::RoundRobin);
+        let writers = [turns.add_writer(3), turns.add_writer(1)];
+        assert_eq!(order(&mut turns, &writers, 4), [0, 1, 0, 1]);
+
+        let mut turns = Turns::new(WriterPolicy::Weighted);
+        let writers = [turns.add_writer(3), turns.add_writer(1)];
+        // Writers take turns, while both have credit, so packets of the lighter one are not
+        // delayed by the whole credit of the heavier one
+        assert_eq!(order(&mut turns, &writers, 8), [0, 1, 0, 0, 1, 0, 0, 0]);
+    }
+}
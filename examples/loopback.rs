@@ -0,0 +1,82 @@
+//! Throughput and latency measurement over a pair of mock interfaces.
+//!
+//! Usage: `cargo run --release --example loopback --features mock -- [packets] [packet_size]`
+use std::env;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use tunio::platform::mock::{Driver, Interface};
+use tunio::traits::{DriverT, InterfaceT};
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let packets: usize = args.next().map_or(100_000, |s| s.parse().unwrap());
+    let packet_size: usize = args.next().map_or(1500, |s| s.parse().unwrap());
+
+    let mut driver = Driver::new().unwrap();
+    let config = |name: &str| {
+        Interface::config_builder()
+            .name(name.to_string())
+            .build()
+            .unwrap()
+    };
+
+    // Throughput: one thread writes packets as fast as possible, another one reads them.
+    let (mut a, mut b) =
+        Interface::new_pair(&mut driver, config("mock0"), config("mock1")).unwrap();
+    a.up().unwrap();
+    b.up().unwrap();
+
+    let start = Instant::now();
+    let writer = thread::spawn(move || {
+        let packet = vec![0u8; packet_size];
+        for _ in 0..packets {
+            a.write_all(&packet).unwrap();
+        }
+        a
+    });
+
+    let mut buf = vec![0u8; packet_size];
+    for _ in 0..packets {
+        let n = b.read(&mut buf).unwrap();
+        assert_eq!(n, packet_size);
+    }
+    let elapsed = start.elapsed();
+    let mut a = writer.join().unwrap();
+
+    let pps = packets as f64 / elapsed.as_secs_f64();
+    println!(
+        "throughput: {packets} packets of {packet_size} bytes in {elapsed:?}: {pps:.0} pps, {:.1} Mbit/s",
+        pps * packet_size as f64 * 8.0 / 1_000_000.0
+    );
+
+    // Latency: every packet is echoed back by another thread before the next one is sent.
+    let echo = thread::spawn(move || {
+        let mut buf = vec![0u8; packet_size];
+        while let Ok(n @ 1..) = b.read(&mut buf) {
+            b.write_all(&buf[..n]).unwrap();
+        }
+    });
+
+    let packet = vec![0u8; packet_size];
+    let mut samples: Vec<Duration> = Vec::with_capacity(packets);
+    for _ in 0..packets {
+        let sent = Instant::now();
+        a.write_all(&packet).unwrap();
+        let n = a.read(&mut buf).unwrap();
+        assert_eq!(n, packet_size);
+        samples.push(sent.elapsed());
+    }
+    drop(a);
+    echo.join().unwrap();
+
+    samples.sort();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+    println!(
+        "latency (round trip): p50={:?} p90={:?} p99={:?} max={:?}",
+        percentile(50),
+        percentile(90),
+        percentile(99),
+        samples[samples.len() - 1]
+    );
+}
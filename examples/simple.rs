@@ -39,7 +39,7 @@ async fn main() {
         let mut packet = Vec::with_capacity(builder.size(0));
         builder.write(&mut packet, &[]).unwrap();
 
-        interface.write(&*packet).await;
+        let _ = interface.write(&packet).await;
 
         sleep(Duration::from_secs(1));
     }
@@ -51,5 +51,5 @@ async fn main() {
         buf.resize(4096, 0u8);
     }
 
-    tokio::signal::ctrl_c().await;
+    let _ = tokio::signal::ctrl_c().await;
 }
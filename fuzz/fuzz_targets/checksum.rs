@@ -0,0 +1,26 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tunio_packet::{checksum, ip_offset, Layer};
+
+fuzz_target!(|input: (bool, Vec<u8>)| {
+    let (l2, mut packet) = input;
+    let layer = if l2 { Layer::L2 } else { Layer::L3 };
+
+    if checksum::fill(&mut packet, layer) {
+        let ip = &packet[ip_offset(&packet, layer).unwrap()..];
+        if ip[0] >> 4 == 4 {
+            let header_len = usize::from(ip[0] & 0x0f) * 4;
+            assert!(checksum::is_valid(&ip[..header_len]));
+        }
+    }
+
+    // Incremental update matches recalculation, up to the sign of zero
+    if packet.len() >= 4 {
+        let before = checksum::checksum(&packet);
+        let old = u16::from_be_bytes([packet[0], packet[1]]);
+        let new = u16::from_be_bytes([packet[2], packet[3]]);
+        packet[..2].copy_from_slice(&new.to_be_bytes());
+        let updated = checksum::update(before, old, new);
+        assert_eq!(updated % 0xffff, checksum::checksum(&packet) % 0xffff);
+    }
+});
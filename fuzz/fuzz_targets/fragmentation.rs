@@ -0,0 +1,52 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tunio_core::config::Layer;
+use tunio_core::hooks::{Fragmenter, HookContext, PacketHook, Reassembler, Verdict};
+use tunio_packet::{ipv4, PROTO_UDP};
+
+const MIN_MTU: u16 = 68;
+
+fuzz_target!(|input: (u16, bool, Vec<u8>)| {
+    let (mtu, raw, data) = input;
+    // Well-formed packets reach fragmentation far more often, than arbitrary bytes
+    let packet = match raw {
+        true => data,
+        false => ipv4(
+            [10, 0, 0, 1].into(),
+            [10, 0, 0, 2].into(),
+            PROTO_UDP,
+            64,
+            &data,
+        ),
+    };
+    let mut fragmenter = Fragmenter::new(mtu);
+    let mut ctx = HookContext::detached(Layer::L3);
+
+    let mut written = packet.clone();
+    let verdict = fragmenter.on_write(&mut written, &mut ctx);
+    let (fragments, _) = ctx.take_injected();
+    if verdict != Verdict::Drop {
+        assert!(fragments.is_empty());
+        return;
+    }
+
+    let mtu = usize::from(mtu.max(MIN_MTU));
+    let mut reassembler = Reassembler::new();
+    let mut reassembled = None;
+    for mut fragment in fragments {
+        assert!(fragment.len() <= mtu);
+        if reassembler.on_read(&mut fragment, &mut ctx) == Verdict::Pass {
+            assert!(reassembled.replace(fragment).is_none());
+        }
+    }
+
+    // Fragments of a whole packet reassemble into it, with a recalculated header checksum
+    let whole = u16::from_be_bytes([packet[6], packet[7]]) == 0;
+    if whole {
+        let reassembled = reassembled.expect("fragments are not reassembled");
+        let total_len = usize::from(u16::from_be_bytes([packet[2], packet[3]]));
+        assert_eq!(reassembled.len(), total_len);
+        assert_eq!(reassembled[..10], packet[..10]);
+        assert_eq!(reassembled[12..], packet[12..total_len]);
+    }
+});
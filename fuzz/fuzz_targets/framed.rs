@@ -0,0 +1,38 @@
+#![no_main]
+use futures::executor::block_on;
+use futures::future::poll_fn;
+use futures::io::Cursor;
+use libfuzzer_sys::fuzz_target;
+use std::pin::Pin;
+use tunio_core::framed::{FramedQueue, MAX_FRAME_LEN};
+use tunio_core::traits::AsyncQueueT;
+
+fuzz_target!(|input: (Vec<Vec<u8>>, u16, Vec<u8>)| {
+    let (packets, buf_len, trailer) = input;
+    let mut queue = FramedQueue::new(Cursor::new(Vec::new()));
+
+    let packets: Vec<_> = packets
+        .into_iter()
+        .filter(|packet| !packet.is_empty() && packet.len() <= MAX_FRAME_LEN)
+        .collect();
+    for packet in &packets {
+        block_on(poll_fn(|cx| Pin::new(&mut queue).poll_send(cx, packet))).unwrap();
+    }
+    let mut stream = queue.into_inner().into_inner();
+    stream.extend_from_slice(&trailer);
+
+    let mut queue = FramedQueue::new(Cursor::new(stream));
+    let mut buf = vec![0u8; usize::from(buf_len)];
+    let mut recv = || block_on(poll_fn(|cx| Pin::new(&mut queue).poll_recv(cx, &mut buf)));
+    for packet in &packets {
+        let (n, truncated) = recv().unwrap();
+        assert_eq!(n, packet.len().min(usize::from(buf_len)));
+        assert_eq!(truncated, packet.len() > n);
+    }
+    // Trailing bytes are arbitrary frames, that end cleanly or with an error
+    while let Ok((n, _)) = recv() {
+        if n == 0 {
+            break;
+        }
+    }
+});
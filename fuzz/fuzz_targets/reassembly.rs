@@ -0,0 +1,26 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use tunio_core::config::Layer;
+use tunio_core::hooks::{HookContext, PacketHook, Reassembler, Verdict};
+use tunio_packet::{checksum, ip_offset};
+
+const MAX_BYTES: usize = 64 * 1024;
+
+fuzz_target!(|input: (bool, Vec<Vec<u8>>)| {
+    let (l2, packets) = input;
+    let layer = if l2 { Layer::L2 } else { Layer::L3 };
+    let mut reassembler = Reassembler::new().max_bytes(MAX_BYTES);
+    let mut ctx = HookContext::detached(layer);
+
+    for mut packet in packets {
+        let original = packet.clone();
+        if reassembler.on_read(&mut packet, &mut ctx) == Verdict::Pass && packet != original {
+            let ip = &packet[ip_offset(&packet, layer).unwrap()..];
+            let header_len = usize::from(ip[0] & 0x0f) * 4;
+            assert_eq!(usize::from(u16::from_be_bytes([ip[2], ip[3]])), ip.len());
+            assert!(checksum::is_valid(&ip[..header_len]));
+        }
+        assert!(reassembler.buffered() <= MAX_BYTES);
+        assert_eq!(ctx.take_injected(), (vec![], vec![]));
+    }
+});
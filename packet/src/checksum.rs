@@ -0,0 +1,238 @@
+//! Internet checksum, see RFC 1071.
+//!
+//! Helpers fill checksums of packets, generated in userspace and written to a device, that
+//! does not offload checksum calculation.
+use super::{ip_offset, transport, PROTO_ICMP, PROTO_ICMPV6, PROTO_TCP, PROTO_UDP};
+use crate::Layer;
+
+const IPV4_CHECKSUM_OFFSET: usize = 10;
+
+/// Computes checksum of `data`.
+pub fn checksum(data: &[u8]) -> u16 {
+    !fold(sum(data, 0))
+}
+
+/// Updates checksum after a 16-bit word of the covered data changes from `old` to `new`,
+/// see RFC 1624.
+pub fn update(checksum: u16, old: u16, new: u16) -> u16 {
+    let sum = u32::from(!checksum) + u32::from(!old) + u32::from(new);
+    !fold(sum)
+}
+
+/// Fills header checksum of an IPv4 packet. Returns `false`, if `ip` is not a valid IPv4 packet.
+pub fn fill_ipv4_header(ip: &mut [u8]) -> bool {
+    let header_len = match ip.first() {
+        Some(&byte) if byte >> 4 == 4 => usize::from(byte & 0x0f) * 4,
+        _ => return false,
+    };
+    if header_len < 20 || ip.len() < header_len {
+        return false;
+    }
+
+    ip[IPV4_CHECKSUM_OFFSET..IPV4_CHECKSUM_OFFSET + 2].fill(0);
+    let value = checksum(&ip[..header_len]);
+    ip[IPV4_CHECKSUM_OFFSET..IPV4_CHECKSUM_OFFSET + 2].copy_from_slice(&value.to_be_bytes());
+    true
+}
+
+/// Fills TCP, UDP, ICMP or ICMPv6 checksum of an IPv4 or IPv6 packet, including the
+/// pseudo-header. Returns `false` for other protocols, fragments and truncated packets.
+pub fn fill_transport(ip: &mut [u8]) -> bool {
+    let (protocol, start) = match transport(ip) {
+        Some(transport) => transport,
+        None => return false,
+    };
+    let end = match ip[0] >> 4 {
+        4 => usize::from(u16::from_be_bytes([ip[2], ip[3]])),
+        _ => usize::from(u16::from_be_bytes([ip[4], ip[5]])) + start,
+    };
+    let offset = match protocol {
+        PROTO_TCP => 16,
+        PROTO_UDP => 6,
+        PROTO_ICMP | PROTO_ICMPV6 => 2,
+        _ => return false,
+    };
+    if end > ip.len() || end < start + offset + 2 {
+        return false;
+    }
+
+    let (header, segment) = ip[..end].split_at_mut(start);
+    segment[offset..offset + 2].fill(0);
+    let initial = match protocol {
+        PROTO_ICMP => 0,
+        _ => pseudo_header_sum(header, protocol, segment.len()),
+    };
+    let value = match !fold(sum(segment, initial)) {
+        // Zero UDP checksum means no checksum
+        0 if protocol == PROTO_UDP => 0xffff,
+        value => value,
+    };
+    segment[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+    true
+}
+
+/// Fills all checksums of a packet: IPv4 header and transport ones. Returns `false`, if
+/// packet is not IP or its transport is not supported.
+pub fn fill(packet: &mut [u8], layer: Layer) -> bool {
+    let ip = match ip_offset(packet, layer) {
+        Some(offset) => &mut packet[offset..],
+        None => return false,
+    };
+    if ip.first().map(|byte| byte >> 4) == Some(4) && !fill_ipv4_header(ip) {
+        return false;
+    }
+    fill_transport(ip)
+}
+
+/// Checks checksum of `data` with the checksum field in place.
+pub fn is_valid(data: &[u8]) -> bool {
+    fold(sum(data, 0)) == 0xffff
+}
+
+fn pseudo_header_sum(header: &[u8], protocol: u8, len: usize) -> u32 {
+    let addresses = match header[0] >> 4 {
+        4 => &header[12..20],
+        _ => &header[8..40],
+    };
+    sum(addresses, u32::from(protocol) + len as u32)
+}
+
+fn sum(data: &[u8], initial: u32) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    let mut sum = u64::from(initial);
+    for chunk in &mut chunks {
+        sum += u64::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+    }
+    if let [last] = chunks.remainder() {
+        sum += u64::from(*last) << 8;
+    }
+    while sum > 0xffff_ffff {
+        sum = (sum & 0xffff_ffff) + (sum >> 32);
+    }
+    sum as u32
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ETHERTYPE_IPV4, ETHER_HEADER_LEN};
+
+    /// IPv4 header from 192.168.0.1 to 192.168.0.199 with checksum 0xb861.
+    const IPV4_HEADER: [u8; 20] = [
+        0x45, 0, 0, 0x73, 0, 0, 0x40, 0, 0x40, 0x11, 0xb8, 0x61, 192, 168, 0, 1, 192, 168, 0, 199,
+    ];
+
+    /// UDP datagram from port 1234 to port 80 with `payload` and blank checksum.
+    fn udp4(payload: [u8; 2]) -> [u8; 30] {
+        let mut packet = [0; 30];
+        packet[..20].copy_from_slice(&IPV4_HEADER);
+        packet[2..4].copy_from_slice(&30u16.to_be_bytes());
+        packet[20..28].copy_from_slice(&[0x04, 0xd2, 0, 80, 0, 10, 0, 0]);
+        packet[28..].copy_from_slice(&payload);
+        packet
+    }
+
+    #[test]
+    fn checksum_of_rfc_1071_example() {
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&data), !0xddf2);
+        // Odd byte is padded with zero
+        assert_eq!(checksum(&[0x01]), 0xfeff);
+        assert_eq!(checksum(&[]), 0xffff);
+    }
+
+    #[test]
+    fn known_ipv4_header_is_valid() {
+        assert!(is_valid(&IPV4_HEADER));
+        let mut header = IPV4_HEADER;
+        header[10..12].fill(0);
+        assert_eq!(checksum(&header), 0xb861);
+        assert!(fill_ipv4_header(&mut header));
+        assert_eq!(header, IPV4_HEADER);
+    }
+
+    #[test]
+    fn update_matches_recomputed_checksum() {
+        let mut header = IPV4_HEADER;
+        let old = u16::from_be_bytes([header[8], header[9]]);
+        // Decrements TTL
+        header[8] -= 1;
+        let new = u16::from_be_bytes([header[8], header[9]]);
+        let updated = update(0xb861, old, new);
+
+        assert!(fill_ipv4_header(&mut header));
+        assert_eq!(updated.to_be_bytes(), header[10..12]);
+    }
+
+    #[test]
+    fn invalid_ipv4_headers_are_not_filled() {
+        let mut ipv6 = IPV4_HEADER;
+        ipv6[0] = 0x60;
+        assert!(!fill_ipv4_header(&mut ipv6));
+        let mut short = IPV4_HEADER;
+        short[0] = 0x44;
+        assert!(!fill_ipv4_header(&mut short));
+        // Options do not fit
+        let mut truncated = IPV4_HEADER;
+        truncated[0] = 0x46;
+        assert!(!fill_ipv4_header(&mut truncated));
+        assert!(!fill_ipv4_header(&mut []));
+    }
+
+    #[test]
+    fn udp_checksum_covers_pseudo_header() {
+        let mut packet = udp4(*b"hi");
+        assert!(fill_transport(&mut packet));
+        assert_eq!(packet[26..28], [0x10, 0x36]);
+    }
+
+    #[test]
+    fn zero_udp_checksum_is_sent_as_ones() {
+        let mut packet = udp4([0x78, 0x9f]);
+        assert!(fill_transport(&mut packet));
+        assert_eq!(packet[26..28], [0xff, 0xff]);
+    }
+
+    #[test]
+    fn unsupported_packets_are_not_filled() {
+        let mut fragment = udp4(*b"hi");
+        fragment[6..8].copy_from_slice(&[0x20, 0x01]);
+        assert!(!fill_transport(&mut fragment));
+
+        let mut unknown = udp4(*b"hi");
+        unknown[9] = 253;
+        assert!(!fill_transport(&mut unknown));
+
+        // Total length exceeds the buffer
+        let mut truncated = udp4(*b"hi");
+        truncated[2..4].copy_from_slice(&40u16.to_be_bytes());
+        assert!(!fill_transport(&mut truncated));
+
+        // UDP header does not fit
+        assert!(!fill_transport(&mut udp4(*b"hi")[..24]));
+    }
+
+    #[test]
+    fn fill_covers_header_and_transport() {
+        let mut frame = [0; ETHER_HEADER_LEN + 30];
+        frame[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        frame[ETHER_HEADER_LEN..].copy_from_slice(&udp4(*b"hi"));
+        frame[ETHER_HEADER_LEN + 10..ETHER_HEADER_LEN + 12].fill(0);
+
+        assert!(fill(&mut frame, Layer::L2));
+        let ip = &frame[ETHER_HEADER_LEN..];
+        assert!(is_valid(&ip[..20]));
+        assert_eq!(ip[26..28], [0x10, 0x36]);
+
+        // Ethernet header is not IP
+        assert!(!fill(&mut frame, Layer::L3));
+        assert!(!fill(&mut [0; 4], Layer::L2));
+    }
+}
@@ -0,0 +1,309 @@
+//! ICMP and ICMPv6 error messages, see RFC 792 and RFC 4443.
+use super::{build_ipv4, build_ipv6, ethernet, ethertype, ip_offset, transport};
+use super::{PROTO_ICMP, PROTO_ICMPV6};
+use crate::Layer;
+use alloc::vec;
+use alloc::vec::Vec;
+
+const ICMP_DEST_UNREACHABLE: u8 = 3;
+const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+const ICMPV6_PACKET_TOO_BIG: u8 = 2;
+/// ICMP error types, that must not be answered with errors, see RFC 1122.
+const ICMP_ERROR_TYPES: [u8; 5] = [3, 4, 5, 11, 12];
+/// Error messages are limited to the minimum reassembly size of IPv4.
+const ICMP_MAX_LEN: usize = 576;
+/// Error messages are limited to the minimum MTU of IPv6.
+const ICMPV6_MAX_LEN: usize = 1280;
+const ERROR_TTL: u8 = 64;
+
+/// Reason of a Destination Unreachable message.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Unreachable {
+    Network,
+    Host,
+    Port,
+    /// Communication is administratively prohibited, as by a firewall.
+    Prohibited,
+}
+
+/// ICMP error, reporting a packet, that could not be delivered.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IcmpError {
+    Unreachable(Unreachable),
+    /// Fragmentation Needed for IPv4 or Packet Too Big for IPv6, carrying the path MTU.
+    PacketTooBig {
+        mtu: u32,
+    },
+}
+
+impl IcmpError {
+    fn icmp_type_code(&self) -> (u8, u8) {
+        match self {
+            IcmpError::Unreachable(reason) => (
+                ICMP_DEST_UNREACHABLE,
+                match reason {
+                    Unreachable::Network => 0,
+                    Unreachable::Host => 1,
+                    Unreachable::Port => 3,
+                    Unreachable::Prohibited => 13,
+                },
+            ),
+            IcmpError::PacketTooBig { .. } => (ICMP_DEST_UNREACHABLE, 4),
+        }
+    }
+
+    fn icmpv6_type_code(&self) -> (u8, u8) {
+        match self {
+            IcmpError::Unreachable(reason) => (
+                ICMPV6_DEST_UNREACHABLE,
+                match reason {
+                    Unreachable::Network => 0,
+                    Unreachable::Prohibited => 1,
+                    Unreachable::Host => 3,
+                    Unreachable::Port => 4,
+                },
+            ),
+            IcmpError::PacketTooBig { .. } => (ICMPV6_PACKET_TOO_BIG, 0),
+        }
+    }
+}
+
+/// Builds an error message, reporting `packet` back to its source. The message comes from the
+/// original destination address, and for [`Layer::L2`] Ethernet addresses are swapped.
+///
+/// Returns `None`, when no error must be sent: for non-IP packets, errors, multicast and
+/// broadcast packets and non-first IPv4 fragments. IPv6 extension headers are not followed.
+pub fn error_for(packet: &[u8], layer: Layer, error: IcmpError) -> Option<Vec<u8>> {
+    let ip = &packet[ip_offset(packet, layer)?..];
+    let reply = match ip.first()? >> 4 {
+        4 => ipv4_error(ip, error)?,
+        6 => ipv6_error(ip, error)?,
+        _ => return None,
+    };
+    match layer {
+        Layer::L3 => Some(reply),
+        // Group bit is set for multicast and broadcast frames
+        Layer::L2 if packet[0] & 1 != 0 => None,
+        Layer::L2 => {
+            let dst = packet[6..12].try_into().ok()?;
+            let src = packet[..6].try_into().ok()?;
+            Some(ethernet(dst, src, ethertype(packet)?, &reply))
+        }
+    }
+}
+
+fn ipv4_error(ip: &[u8], error: IcmpError) -> Option<Vec<u8>> {
+    let (protocol, offset) = transport(ip)?;
+    if protocol == PROTO_ICMP && ICMP_ERROR_TYPES.contains(ip.get(offset)?) {
+        return None;
+    }
+    let src: [u8; 4] = ip[12..16].try_into().ok()?;
+    let dst: [u8; 4] = ip[16..20].try_into().ok()?;
+    // Multicast addresses are 224.0.0.0/4
+    let broadcast_or_multicast = |address: [u8; 4]| address == [0xff; 4] || address[0] >> 4 == 14;
+    if src == [0; 4] || broadcast_or_multicast(src) || broadcast_or_multicast(dst) {
+        return None;
+    }
+
+    let (icmp_type, code) = error.icmp_type_code();
+    let mut message = vec![icmp_type, code, 0, 0, 0, 0];
+    let mtu = match error {
+        IcmpError::PacketTooBig { mtu } => u16::try_from(mtu).unwrap_or(u16::MAX),
+        IcmpError::Unreachable(_) => 0,
+    };
+    message.extend_from_slice(&mtu.to_be_bytes());
+    let quoted = ip.len().min(ICMP_MAX_LEN - 20 - message.len());
+    message.extend_from_slice(&ip[..quoted]);
+    Some(build_ipv4(dst, src, PROTO_ICMP, ERROR_TTL, &message))
+}
+
+fn ipv6_error(ip: &[u8], error: IcmpError) -> Option<Vec<u8>> {
+    let (next_header, offset) = transport(ip)?;
+    // Informational messages have the high bit set
+    if next_header == PROTO_ICMPV6 && *ip.get(offset)? < 128 {
+        return None;
+    }
+    let src: [u8; 16] = ip[8..24].try_into().ok()?;
+    let dst: [u8; 16] = ip[24..40].try_into().ok()?;
+    // Errors about multicast packets must come from a unicast address, which is unknown here.
+    // Multicast addresses are ff00::/8
+    if src == [0; 16] || src[0] == 0xff || dst[0] == 0xff {
+        return None;
+    }
+
+    let (icmp_type, code) = error.icmpv6_type_code();
+    let mut message = vec![icmp_type, code, 0, 0];
+    let mtu = match error {
+        IcmpError::PacketTooBig { mtu } => mtu,
+        IcmpError::Unreachable(_) => 0,
+    };
+    message.extend_from_slice(&mtu.to_be_bytes());
+    let quoted = ip.len().min(ICMPV6_MAX_LEN - 40 - message.len());
+    message.extend_from_slice(&ip[..quoted]);
+    Some(build_ipv6(dst, src, PROTO_ICMPV6, ERROR_TTL, &message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::is_valid;
+    use crate::{udp, ETHERTYPE_IPV4, ETHER_HEADER_LEN, IPV6_HEADER_LEN, PROTO_UDP};
+
+    const HOST: [u8; 4] = [10, 0, 0, 1];
+    const REMOTE: [u8; 4] = [10, 0, 0, 2];
+    const HOST_V6: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    const REMOTE_V6: [u8; 16] = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+    const PORT_UNREACHABLE: IcmpError = IcmpError::Unreachable(Unreachable::Port);
+
+    fn udp4(src: [u8; 4], dst: [u8; 4], len: usize) -> Vec<u8> {
+        build_ipv4(src, dst, PROTO_UDP, 64, &udp(1000, 53, &vec![0; len - 28]))
+    }
+
+    fn udp6(src: [u8; 16], dst: [u8; 16], len: usize) -> Vec<u8> {
+        build_ipv6(src, dst, PROTO_UDP, 64, &udp(1000, 53, &vec![0; len - 48]))
+    }
+
+    fn icmpv6_is_valid(ip: &[u8]) -> bool {
+        let mut pseudo = ip[8..40].to_vec();
+        let icmp = &ip[IPV6_HEADER_LEN..];
+        pseudo.extend_from_slice(&(icmp.len() as u32).to_be_bytes());
+        pseudo.extend_from_slice(&[0, 0, 0, PROTO_ICMPV6]);
+        pseudo.extend_from_slice(icmp);
+        is_valid(&pseudo)
+    }
+
+    #[test]
+    fn ipv4_port_unreachable() {
+        let packet = udp4(HOST, REMOTE, 100);
+        let reply = error_for(&packet, Layer::L3, PORT_UNREACHABLE).unwrap();
+
+        assert_eq!(reply[8..10], [ERROR_TTL, PROTO_ICMP]);
+        assert_eq!(reply[12..16], REMOTE);
+        assert_eq!(reply[16..20], HOST);
+        assert!(is_valid(&reply[..20]));
+        let icmp = &reply[20..];
+        assert_eq!(icmp[..2], [ICMP_DEST_UNREACHABLE, 3]);
+        assert_eq!(icmp[4..8], [0; 4]);
+        assert_eq!(icmp[8..], packet);
+        assert!(is_valid(icmp));
+    }
+
+    #[test]
+    fn ipv4_fragmentation_needed_is_truncated() {
+        let packet = udp4(HOST, REMOTE, 1500);
+        let error = IcmpError::PacketTooBig { mtu: 1400 };
+        let reply = error_for(&packet, Layer::L3, error).unwrap();
+
+        assert_eq!(reply.len(), ICMP_MAX_LEN);
+        let icmp = &reply[20..];
+        assert_eq!(icmp[..2], [ICMP_DEST_UNREACHABLE, 4]);
+        assert_eq!(icmp[6..8], 1400u16.to_be_bytes());
+        assert_eq!(icmp[8..], packet[..ICMP_MAX_LEN - 28]);
+        assert!(is_valid(icmp));
+    }
+
+    #[test]
+    fn ipv6_packet_too_big_is_truncated() {
+        let packet = udp6(HOST_V6, REMOTE_V6, 1500);
+        let error = IcmpError::PacketTooBig { mtu: 1400 };
+        let reply = error_for(&packet, Layer::L3, error).unwrap();
+
+        assert_eq!(reply.len(), ICMPV6_MAX_LEN);
+        assert_eq!(reply[6..8], [PROTO_ICMPV6, ERROR_TTL]);
+        assert_eq!(reply[8..24], REMOTE_V6);
+        assert_eq!(reply[24..40], HOST_V6);
+        let icmp = &reply[IPV6_HEADER_LEN..];
+        assert_eq!(icmp[..2], [ICMPV6_PACKET_TOO_BIG, 0]);
+        assert_eq!(icmp[4..8], 1400u32.to_be_bytes());
+        assert_eq!(icmp[8..], packet[..ICMPV6_MAX_LEN - 48]);
+        assert!(icmpv6_is_valid(&reply));
+    }
+
+    #[test]
+    fn codes_of_unreachable_reasons() {
+        let reasons = [
+            (Unreachable::Network, 0, 0),
+            (Unreachable::Host, 1, 3),
+            (Unreachable::Port, 3, 4),
+            (Unreachable::Prohibited, 13, 1),
+        ];
+        for (reason, code, code_v6) in reasons {
+            let error = IcmpError::Unreachable(reason);
+            let reply = error_for(&udp4(HOST, REMOTE, 100), Layer::L3, error).unwrap();
+            assert_eq!(reply[20..22], [ICMP_DEST_UNREACHABLE, code]);
+            let reply = error_for(&udp6(HOST_V6, REMOTE_V6, 100), Layer::L3, error).unwrap();
+            assert_eq!(reply[40..42], [ICMPV6_DEST_UNREACHABLE, code_v6]);
+        }
+    }
+
+    #[test]
+    fn l2_errors_swap_ethernet_addresses() {
+        let (host_mac, remote_mac) = ([0x02, 0, 0, 0, 0, 1], [0x02, 0, 0, 0, 0, 2]);
+        let frame = ethernet(
+            remote_mac,
+            host_mac,
+            ETHERTYPE_IPV4,
+            &udp4(HOST, REMOTE, 100),
+        );
+        let reply = error_for(&frame, Layer::L2, PORT_UNREACHABLE).unwrap();
+        assert_eq!(reply[..6], host_mac);
+        assert_eq!(reply[6..12], remote_mac);
+        assert_eq!(ethertype(&reply), Some(ETHERTYPE_IPV4));
+        assert_eq!(reply[ETHER_HEADER_LEN + 12..ETHER_HEADER_LEN + 16], REMOTE);
+
+        let broadcast = ethernet(
+            [0xff; 6],
+            host_mac,
+            ETHERTYPE_IPV4,
+            &udp4(HOST, REMOTE, 100),
+        );
+        assert_eq!(error_for(&broadcast, Layer::L2, PORT_UNREACHABLE), None);
+    }
+
+    #[test]
+    fn errors_are_not_sent_about_errors() {
+        let unreachable = [ICMP_DEST_UNREACHABLE, 3, 0, 0, 0, 0, 0, 0];
+        let packet = build_ipv4(HOST, REMOTE, PROTO_ICMP, 64, &unreachable);
+        assert_eq!(error_for(&packet, Layer::L3, PORT_UNREACHABLE), None);
+        let packet = build_ipv6(HOST_V6, REMOTE_V6, PROTO_ICMPV6, 64, &unreachable[..4]);
+        assert_eq!(error_for(&packet, Layer::L3, PORT_UNREACHABLE), None);
+
+        // Echo requests are answered
+        let echo = [8, 0, 0, 0, 0, 0, 0, 0];
+        let packet = build_ipv4(HOST, REMOTE, PROTO_ICMP, 64, &echo);
+        assert!(error_for(&packet, Layer::L3, PORT_UNREACHABLE).is_some());
+        let echo = [128, 0, 0, 0, 0, 0, 0, 0];
+        let packet = build_ipv6(HOST_V6, REMOTE_V6, PROTO_ICMPV6, 64, &echo);
+        assert!(error_for(&packet, Layer::L3, PORT_UNREACHABLE).is_some());
+    }
+
+    #[test]
+    fn errors_are_not_sent_for_group_or_unspecified_addresses() {
+        let mut multicast_v6 = [0; 16];
+        multicast_v6[..2].copy_from_slice(&[0xff, 0x02]);
+        let packets = [
+            udp4([0; 4], REMOTE, 100),
+            udp4(HOST, [255; 4], 100),
+            udp4(HOST, [224, 0, 0, 251], 100),
+            udp4([239, 1, 1, 1], REMOTE, 100),
+            udp6([0; 16], REMOTE_V6, 100),
+            udp6(HOST_V6, multicast_v6, 100),
+        ];
+        for packet in packets {
+            assert_eq!(error_for(&packet, Layer::L3, PORT_UNREACHABLE), None);
+        }
+    }
+
+    #[test]
+    fn errors_are_not_sent_for_other_packets() {
+        let mut fragment = udp4(HOST, REMOTE, 100);
+        fragment[6..8].copy_from_slice(&[0x20, 0x01]);
+        let arp = ethernet([0xff; 6], [0x02, 0, 0, 0, 0, 1], 0x0806, &[0; 28]);
+
+        assert_eq!(error_for(&fragment, Layer::L3, PORT_UNREACHABLE), None);
+        assert_eq!(error_for(&arp, Layer::L2, PORT_UNREACHABLE), None);
+        assert_eq!(error_for(&[0; 40], Layer::L3, PORT_UNREACHABLE), None);
+        assert_eq!(error_for(&[0x45; 10], Layer::L3, PORT_UNREACHABLE), None);
+        assert_eq!(error_for(&[], Layer::L3, PORT_UNREACHABLE), None);
+    }
+}
@@ -0,0 +1,288 @@
+//! Minimal parsing of Ethernet frames and IP packets, as seen on TUN/TAP devices.
+//!
+//! Crate is `no_std`, so that tunnel logic can be shared with embedded targets. Parsing and
+//! checksums need no allocator, packet builders and ICMP errors need the `alloc` feature, and
+//! builders, taking `std::net` addresses, need the default `std` feature.
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod checksum;
+#[cfg(feature = "alloc")]
+pub mod icmp;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+pub const ETHER_HEADER_LEN: usize = 14;
+pub const ETHER_BROADCAST: [u8; 6] = [0xff; 6];
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
+
+pub const IPV4_MIN_HEADER_LEN: usize = 20;
+pub const IPV6_HEADER_LEN: usize = 40;
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+pub const PROTO_ICMPV6: u8 = 58;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Layer {
+    /// TAP, Ethernet-like interface with L2 capabilities
+    L2,
+    /// TUN, point-to-point IP interface
+    #[default]
+    L3,
+}
+
+/// Ethertype of an Ethernet frame.
+pub fn ethertype(frame: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes([*frame.get(12)?, *frame.get(13)?]))
+}
+
+/// Returns offset of IP header in a packet of given layer. For [`Layer::L2`] packet must start
+/// with an Ethernet header, and `None` is returned for non-IP frames.
+pub fn ip_offset(packet: &[u8], layer: Layer) -> Option<usize> {
+    match layer {
+        Layer::L3 => Some(0),
+        Layer::L2 => match ethertype(packet)? {
+            ETHERTYPE_IPV4 | ETHERTYPE_IPV6 if packet.len() > ETHER_HEADER_LEN => {
+                Some(ETHER_HEADER_LEN)
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Whether a packet is IPv4 or, on [`Layer::L2`], an ARP frame, which only IPv4 uses.
+pub fn is_ipv4(packet: &[u8], layer: Layer) -> bool {
+    match layer {
+        Layer::L3 => packet.first().map_or(false, |b| b >> 4 == 4),
+        Layer::L2 => matches!(ethertype(packet), Some(ETHERTYPE_IPV4 | ETHERTYPE_ARP)),
+    }
+}
+
+/// Returns IPv4 TOS or IPv6 Traffic Class byte of a packet: DSCP in upper 6 bits and ECN
+/// in lower 2 bits.
+pub fn traffic_class(packet: &[u8], layer: Layer) -> Option<u8> {
+    let ip = &packet[ip_offset(packet, layer)?..];
+    match ip.first()? >> 4 {
+        4 => ip.get(1).copied(),
+        // Traffic Class spans the first two bytes
+        6 => Some(ip[0] << 4 | ip.get(1)? >> 4),
+        _ => None,
+    }
+}
+
+/// Returns protocol number and offset of the transport header of an IP packet.
+///
+/// IPv6 extension headers are not followed, and `None` is returned for IPv4 fragments other
+/// than the first one, as they carry no transport header.
+pub fn transport(ip: &[u8]) -> Option<(u8, usize)> {
+    match ip.first()? >> 4 {
+        4 => {
+            let header_len = usize::from(ip[0] & 0x0f) * 4;
+            let fragment_offset = u16::from_be_bytes([*ip.get(6)?, *ip.get(7)?]) & 0x1fff;
+            match header_len >= IPV4_MIN_HEADER_LEN && fragment_offset == 0 {
+                true => Some((*ip.get(9)?, header_len)).filter(|_| ip.len() >= header_len),
+                false => None,
+            }
+        }
+        6 => Some((*ip.get(6)?, IPV6_HEADER_LEN)).filter(|_| ip.len() >= IPV6_HEADER_LEN),
+        _ => None,
+    }
+}
+
+/// Builds an Ethernet frame.
+#[cfg(feature = "alloc")]
+pub fn ethernet(dst: [u8; 6], src: [u8; 6], ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETHER_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&dst);
+    frame.extend_from_slice(&src);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Builds an IPv4 packet without options, with checksums filled.
+#[cfg(feature = "std")]
+pub fn ipv4(src: Ipv4Addr, dst: Ipv4Addr, protocol: u8, ttl: u8, payload: &[u8]) -> Vec<u8> {
+    build_ipv4(src.octets(), dst.octets(), protocol, ttl, payload)
+}
+
+/// Builds an IPv6 packet without extension headers, with transport checksum filled.
+#[cfg(feature = "std")]
+pub fn ipv6(
+    src: Ipv6Addr,
+    dst: Ipv6Addr,
+    next_header: u8,
+    hop_limit: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    build_ipv6(src.octets(), dst.octets(), next_header, hop_limit, payload)
+}
+
+// Address types are not available in `core` for the supported Rust versions
+#[cfg(feature = "alloc")]
+pub(crate) fn build_ipv4(
+    src: [u8; 4],
+    dst: [u8; 4],
+    protocol: u8,
+    ttl: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let total_len = (IPV4_MIN_HEADER_LEN + payload.len()) as u16;
+    let mut packet = Vec::with_capacity(usize::from(total_len));
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&total_len.to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0, 0, ttl, protocol, 0, 0]);
+    packet.extend_from_slice(&src);
+    packet.extend_from_slice(&dst);
+    packet.extend_from_slice(payload);
+    checksum::fill_ipv4_header(&mut packet);
+    checksum::fill_transport(&mut packet);
+    packet
+}
+
+#[cfg(feature = "alloc")]
+pub(crate) fn build_ipv6(
+    src: [u8; 16],
+    dst: [u8; 16],
+    next_header: u8,
+    hop_limit: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(IPV6_HEADER_LEN + payload.len());
+    packet.extend_from_slice(&[0x60, 0, 0, 0]);
+    packet.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[next_header, hop_limit]);
+    packet.extend_from_slice(&src);
+    packet.extend_from_slice(&dst);
+    packet.extend_from_slice(payload);
+    checksum::fill_transport(&mut packet);
+    packet
+}
+
+/// Builds a UDP datagram with blank checksum, to be filled by an IP builder.
+#[cfg(feature = "alloc")]
+pub fn udp(src_port: u16, dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(8 + payload.len());
+    datagram.extend_from_slice(&src_port.to_be_bytes());
+    datagram.extend_from_slice(&dst_port.to_be_bytes());
+    datagram.extend_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+    datagram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimum size Ethernet frame of `ethertype`.
+    fn frame(ethertype: u16) -> [u8; 60] {
+        let mut frame = [0; 60];
+        frame[12..14].copy_from_slice(&ethertype.to_be_bytes());
+        frame
+    }
+
+    #[test]
+    fn ip_offset_of_layers() {
+        assert_eq!(ip_offset(&[], Layer::L3), Some(0));
+        assert_eq!(ip_offset(&frame(ETHERTYPE_IPV4), Layer::L2), Some(14));
+        assert_eq!(ip_offset(&frame(ETHERTYPE_IPV6), Layer::L2), Some(14));
+        assert_eq!(ip_offset(&frame(ETHERTYPE_ARP), Layer::L2), None);
+        // Ethernet header without payload
+        assert_eq!(ip_offset(&frame(ETHERTYPE_IPV4)[..14], Layer::L2), None);
+        assert_eq!(ip_offset(&[0; 13], Layer::L2), None);
+    }
+
+    #[test]
+    fn ipv4_and_arp_are_ipv4() {
+        assert!(is_ipv4(&[0x45], Layer::L3));
+        assert!(!is_ipv4(&[0x60], Layer::L3));
+        assert!(!is_ipv4(&[], Layer::L3));
+        assert!(is_ipv4(&frame(ETHERTYPE_ARP), Layer::L2));
+        assert!(is_ipv4(&frame(ETHERTYPE_IPV4), Layer::L2));
+        assert!(!is_ipv4(&frame(ETHERTYPE_IPV6), Layer::L2));
+    }
+
+    #[test]
+    fn traffic_class_of_ipv4_and_ipv6() {
+        assert_eq!(traffic_class(&[0x45, 0xb8], Layer::L3), Some(0xb8));
+        // Traffic Class spans the version and the flow label
+        assert_eq!(traffic_class(&[0x6b, 0x8f], Layer::L3), Some(0xb8));
+        assert_eq!(traffic_class(&[0x6b], Layer::L3), None);
+        assert_eq!(traffic_class(&[0x45], Layer::L3), None);
+        assert_eq!(traffic_class(&[0x00, 0xb8], Layer::L3), None);
+        assert_eq!(traffic_class(&[], Layer::L3), None);
+    }
+
+    #[test]
+    fn transport_of_ipv4_and_ipv6() {
+        let mut ipv4 = [0; 24];
+        ipv4[0] = 0x45;
+        ipv4[9] = PROTO_UDP;
+        assert_eq!(transport(&ipv4), Some((PROTO_UDP, 20)));
+        // Options
+        ipv4[0] = 0x46;
+        assert_eq!(transport(&ipv4), Some((PROTO_UDP, 24)));
+        assert_eq!(transport(&ipv4[..22]), None);
+        ipv4[0] = 0x44;
+        assert_eq!(transport(&ipv4), None);
+        // Non-first fragment carries no transport header
+        ipv4[0] = 0x45;
+        ipv4[6..8].copy_from_slice(&[0x20, 0x01]);
+        assert_eq!(transport(&ipv4), None);
+        assert_eq!(transport(&ipv4[..8]), None);
+
+        let mut ipv6 = [0; 40];
+        ipv6[0] = 0x60;
+        ipv6[6] = PROTO_TCP;
+        assert_eq!(transport(&ipv6), Some((PROTO_TCP, 40)));
+        assert_eq!(transport(&ipv6[..39]), None);
+        assert_eq!(transport(&[0x10]), None);
+        assert_eq!(transport(&[]), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn builders_fill_headers() {
+        let datagram = udp(1234, 80, b"hi");
+        assert_eq!(datagram, [0x04, 0xd2, 0, 80, 0, 10, 0, 0, b'h', b'i']);
+
+        let src = Ipv4Addr::new(192, 168, 0, 1);
+        let dst = Ipv4Addr::new(192, 168, 0, 199);
+        let packet = ipv4(src, dst, PROTO_UDP, 64, &datagram);
+        assert_eq!(packet[..10], [0x45, 0, 0, 30, 0, 0, 0, 0, 64, PROTO_UDP]);
+        assert_eq!(packet[12..20], [192, 168, 0, 1, 192, 168, 0, 199]);
+        assert!(checksum::is_valid(&packet[..20]));
+        assert_eq!(packet[26..28], [0x10, 0x36]);
+
+        let packet = ipv6(
+            Ipv6Addr::LOCALHOST,
+            Ipv6Addr::UNSPECIFIED,
+            PROTO_UDP,
+            255,
+            &datagram,
+        );
+        assert_eq!(packet[..8], [0x60, 0, 0, 0, 0, 10, PROTO_UDP, 255]);
+        assert_eq!(packet[8..24], Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(packet[24..40], [0; 16]);
+        assert_ne!(packet[46..48], [0, 0]);
+
+        let mac = [0x02, 0, 0, 0, 0, 1];
+        let frame = ethernet(ETHER_BROADCAST, mac, ETHERTYPE_IPV6, &packet);
+        assert_eq!(frame[..6], ETHER_BROADCAST);
+        assert_eq!(frame[6..12], mac);
+        assert_eq!(ethertype(&frame), Some(ETHERTYPE_IPV6));
+        assert_eq!(frame[ETHER_HEADER_LEN..], packet);
+    }
+}
@@ -0,0 +1,55 @@
+//! Listing of TUN/TAP devices through sysfs, where the driver exposes flags and owners of
+//! every device.
+use crate::netlink::{alt_names, find_tag};
+use std::fs;
+use std::path::Path;
+use tunio_core::config::Layer;
+use tunio_core::device::DeviceInfo;
+use tunio_core::Error;
+
+const SYSFS_NET: &str = "/sys/class/net";
+
+pub(crate) fn enumerate() -> Result<Vec<DeviceInfo>, Error> {
+    let mut devices = vec![];
+    for entry in fs::read_dir(SYSFS_NET)? {
+        let entry = entry?;
+        // Interfaces may disappear while they are listed
+        if let Some(device) = device_info(&entry.path()) {
+            devices.push(device);
+        }
+    }
+    devices.sort_unstable_by_key(|device| device.index);
+    Ok(devices)
+}
+
+/// Returns `None` for interfaces of other drivers, which have no `tun_flags`.
+fn device_info(path: &Path) -> Option<DeviceInfo> {
+    let flags = read(path, "tun_flags")?;
+    let flags = i64::from_str_radix(flags.trim_start_matches("0x"), 16).ok()?;
+    let layer = match flags & i64::from(libc::IFF_TAP) {
+        0 => Layer::L3,
+        _ => Layer::L2,
+    };
+    let name = path.file_name()?.to_str()?.to_string();
+    let index = read(path, "ifindex")?.parse().ok()?;
+
+    let mut device = DeviceInfo::new(name, index, layer, "tun");
+    device.owner = read_id(path, "owner");
+    device.group = read_id(path, "group");
+    device.persistent = Some(flags & i64::from(libc::IFF_PERSIST) != 0);
+    // Alternative names are not supported before Linux 5.5
+    device.tag = alt_names(index)
+        .ok()
+        .and_then(|alt_names| find_tag(&alt_names, &device.name));
+    Some(device)
+}
+
+fn read(path: &Path, attribute: &str) -> Option<String> {
+    let value = fs::read_to_string(path.join(attribute)).ok()?;
+    Some(value.trim().to_string())
+}
+
+/// Owner and group are -1, when they are not set.
+fn read_id(path: &Path, attribute: &str) -> Option<u32> {
+    read(path, attribute)?.parse().ok()
+}
@@ -0,0 +1,282 @@
+//! Privileged helper, that creates devices on behalf of unprivileged applications.
+//!
+//! The helper runs with `CAP_NET_ADMIN`, for example through `pkexec` or as a system service,
+//! creates the device and passes its descriptor over a Unix socket with `SCM_RIGHTS`. The
+//! application then uses the interface as usual, without running as root. A helper binary only
+//! needs to call [`main`] from its own `main`:
+//!
+//! ```no_run
+//! tunio_linux::helper::main();
+//! ```
+//!
+//! Protocol is a single exchange of text lines over a stream socket. Request is
+//! `tunio/1 <l2|l3> <fail|adopt|suffix> <name>`, and reply is either
+//! `ok <created|adopted|suffixed> <name>` with the descriptor attached, or `err <message>`.
+use crate::interface::LinuxInterface;
+use crate::sys;
+use crate::{Driver, Interface, PlatformIfConfig};
+use nix::sys::socket::{getsockopt, recvmsg, sendmsg, sockopt, ControlMessage, MsgFlags};
+use std::env;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+use tunio_core::config::{IfConfig, Layer, NameConflict, NameOutcome};
+use tunio_core::queue::FdQueueT;
+use tunio_core::traits::{DriverT, InterfaceT};
+use tunio_core::Error;
+
+const PROTOCOL: &str = "tunio/1";
+/// Requests and replies are short, longer lines are rejected.
+const MAX_LINE_LEN: usize = 256;
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Entry point of a helper binary. Connects to the Unix socket, given as the first argument,
+/// serves a single request and exits with a non-zero status on failure.
+pub fn main() {
+    let path = match env::args_os().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: tunio-helper <socket>");
+            process::exit(2);
+        }
+    };
+
+    let result = UnixStream::connect(path)
+        .map_err(Error::from)
+        .and_then(|stream| serve(&stream));
+    if let Err(err) = result {
+        eprintln!("tunio-helper: {err}");
+        process::exit(1);
+    }
+}
+
+/// Serves a single request on the privileged side. Failures to create the device are reported
+/// to the peer as well as returned.
+///
+/// Services, that accept connections themselves, must authorize peers before serving them,
+/// for example by their `SO_PEERCRED` credentials.
+pub fn serve(stream: &UnixStream) -> Result<(), Error> {
+    let request = read_line(stream, None)?;
+    let result = parse_request(&request).and_then(|params| {
+        let mut driver = Driver::new()?;
+        let interface = Interface::new(&mut driver, params)?;
+        let reply = format!(
+            "ok {} {}\n",
+            outcome_str(interface.name_outcome()),
+            interface.name()
+        );
+        Ok((reply, interface.into_fd()?))
+    });
+
+    match result {
+        Ok((reply, device)) => {
+            let fds = [device.as_raw_fd()];
+            let cmsgs = [ControlMessage::ScmRights(&fds)];
+            sendmsg::<()>(
+                stream.as_raw_fd(),
+                &[IoSlice::new(reply.as_bytes())],
+                &cmsgs,
+                MsgFlags::empty(),
+                None,
+            )
+            .map_err(io::Error::from)?;
+            Ok(())
+        }
+        Err(err) => {
+            let message = err.to_string().replace('\n', " ");
+            (&*stream).write_all(format!("err {message}\n").as_bytes())?;
+            Err(err)
+        }
+    }
+}
+
+/// Requests an interface, as configured by name, layer and name conflict policy of `params`,
+/// from a helper, connected to `stream`, on the unprivileged side.
+///
+/// Helper must run as root or as the same user as the application.
+pub fn request<Q: FdQueueT>(
+    stream: &UnixStream,
+    driver: &mut Driver,
+    params: IfConfig<PlatformIfConfig>,
+) -> Result<LinuxInterface<Q>, Error> {
+    let credentials =
+        getsockopt(stream.as_raw_fd(), sockopt::PeerCredentials).map_err(io::Error::from)?;
+    if credentials.uid() != 0 && credentials.uid() != nix::unistd::geteuid().as_raw() {
+        return Err(io::Error::from(io::ErrorKind::PermissionDenied).into());
+    }
+
+    let layer = match params.layer {
+        Layer::L2 => "l2",
+        Layer::L3 => "l3",
+    };
+    let policy = match params.name_conflict {
+        NameConflict::Fail => "fail",
+        NameConflict::Adopt => "adopt",
+        NameConflict::Suffix => "suffix",
+    };
+    let request = format!("{PROTOCOL} {layer} {policy} {}\n", params.name);
+    (&*stream).write_all(request.as_bytes())?;
+
+    let mut device = None;
+    let reply = read_line(stream, Some(&mut device))?;
+    let outcome = match reply.split_once(' ') {
+        Some(("ok", rest)) => rest.split(' ').next().and_then(parse_outcome),
+        Some(("err", message)) => return Err(Error::HelperFailed(message.to_string())),
+        _ => None,
+    };
+    match (outcome, device) {
+        (Some(outcome), Some(device)) => {
+            let mut interface = LinuxInterface::from_fd(driver, params, device)?;
+            interface.name_outcome = outcome;
+            Ok(interface)
+        }
+        _ => Err(Error::HelperFailed(format!("invalid reply: {reply}"))),
+    }
+}
+
+/// Launches a helper with `command`, like `pkexec /usr/libexec/app-helper`, and requests an
+/// interface from it. Path of a temporary socket is appended to the arguments.
+pub fn spawn<Q: FdQueueT>(
+    mut command: Command,
+    driver: &mut Driver,
+    params: IfConfig<PlatformIfConfig>,
+) -> Result<LinuxInterface<Q>, Error> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let path = env::temp_dir().join(format!(
+        "tunio-helper-{}-{}.sock",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let listener = SocketGuard::bind(path)?;
+
+    let mut child = command.arg(&listener.path).spawn()?;
+    let stream = loop {
+        match listener.listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err.into()),
+        }
+        // Helper may exit without connecting, if authorization is denied
+        if let Some(status) = child.try_wait()? {
+            return Err(Error::HelperFailed(format!("helper exited with {status}")));
+        }
+        thread::sleep(ACCEPT_POLL_INTERVAL);
+    };
+    stream.set_nonblocking(false)?;
+
+    let result = request(&stream, driver, params);
+    let _ = child.wait();
+    result
+}
+
+/// Listening socket, that is removed from the file system on drop.
+struct SocketGuard {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl SocketGuard {
+    fn bind(path: PathBuf) -> io::Result<Self> {
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, path })
+    }
+}
+
+impl Drop for SocketGuard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn parse_request(line: &str) -> Result<IfConfig<PlatformIfConfig>, Error> {
+    let invalid = || Error::HelperFailed(format!("invalid request: {line}"));
+    let mut fields = line.splitn(4, ' ');
+    if fields.next() != Some(PROTOCOL) {
+        return Err(invalid());
+    }
+    let layer = match fields.next() {
+        Some("l2") => Layer::L2,
+        Some("l3") => Layer::L3,
+        _ => return Err(invalid()),
+    };
+    let policy = match fields.next() {
+        Some("fail") => NameConflict::Fail,
+        Some("adopt") => NameConflict::Adopt,
+        Some("suffix") => NameConflict::Suffix,
+        _ => return Err(invalid()),
+    };
+    let name = fields.next().ok_or_else(invalid)?;
+
+    Interface::config_builder()
+        .name(name.to_string())
+        .layer(layer)
+        .name_conflict(policy)
+        .build()
+        .map_err(|err| Error::HelperFailed(err.to_string()))
+}
+
+fn outcome_str(outcome: NameOutcome) -> &'static str {
+    match outcome {
+        NameOutcome::Created => "created",
+        NameOutcome::Adopted => "adopted",
+        NameOutcome::Suffixed => "suffixed",
+    }
+}
+
+fn parse_outcome(outcome: &str) -> Option<NameOutcome> {
+    match outcome {
+        "created" => Some(NameOutcome::Created),
+        "adopted" => Some(NameOutcome::Adopted),
+        "suffixed" => Some(NameOutcome::Suffixed),
+        _ => None,
+    }
+}
+
+/// Reads a line without the terminator. If `device` is given, the first descriptor, passed
+/// along with the line, is stored into it.
+fn read_line(stream: &UnixStream, mut device: Option<&mut Option<OwnedFd>>) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut buf = [0u8; MAX_LINE_LEN];
+    while !line.ends_with(b"\n") {
+        if line.len() > MAX_LINE_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "line is too long",
+            ));
+        }
+        let n = match device.as_deref_mut() {
+            Some(device) if device.is_none() => recv_with_fd(stream.as_raw_fd(), &mut buf, device)?,
+            _ => (&*stream).read(&mut buf)?,
+        };
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        line.extend_from_slice(&buf[..n]);
+    }
+    line.pop();
+    String::from_utf8(line).map_err(|_| io::ErrorKind::InvalidData.into())
+}
+
+fn recv_with_fd(fd: RawFd, buf: &mut [u8], device: &mut Option<OwnedFd>) -> io::Result<usize> {
+    let mut cmsg_buffer = nix::cmsg_space!([RawFd; 1]);
+    let mut iov = [IoSliceMut::new(buf)];
+    let message = recvmsg::<()>(
+        fd,
+        &mut iov,
+        Some(&mut cmsg_buffer),
+        MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .map_err(io::Error::from)?;
+
+    // Extra descriptors are closed
+    if let Some(fd) = sys::received_fds(&message).into_iter().next() {
+        device.get_or_insert(fd);
+    }
+    Ok(message.bytes)
+}
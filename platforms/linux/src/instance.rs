@@ -0,0 +1,89 @@
+//! Advisory locks of interface names, so that two instances of an application do not
+//! configure the same interface. Locks are `flock`s of files in the runtime directory, which
+//! the kernel releases, when the process exits.
+use nix::fcntl::{flock, FlockArg};
+use nix::unistd::getpid;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use tunio_core::{Error, ResultExt};
+
+/// Lock of an interface name, that is held, until it is dropped.
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Locks `name` in `$XDG_RUNTIME_DIR/tunio`, or in `/run/tunio` without a runtime
+    /// directory, like in system services. Fails with [`Error::InterfaceLocked`], if another
+    /// process holds the lock. Network namespaces share the directory, unless `/run` is
+    /// mounted per namespace.
+    pub fn acquire(name: &str) -> Result<Self, Error> {
+        let dir = lock_dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{name}.lock"));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            // Process ID of the owner is kept, until the lock is taken
+            .truncate(false)
+            .open(&path)?;
+
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(nix::errno::Errno::EWOULDBLOCK) => {
+                let mut owner = String::new();
+                let _ = file.read_to_string(&mut owner);
+                return Err(Error::InterfaceLocked {
+                    name: name.to_string(),
+                    owner: owner.trim().parse().ok(),
+                });
+            }
+            Err(err) => return Err(err).context(|| format!("flock({})", path.display())),
+        }
+
+        // Process ID is informational, lock is the flock itself
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", getpid())?;
+        Ok(Self { _file: file })
+    }
+}
+
+fn lock_dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(|| PathBuf::from("/run"), PathBuf::from)
+        .join("tunio")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn second_lock_of_a_name_fails() {
+        let name = format!("tunio-test-{}", std::process::id());
+        let lock = match InstanceLock::acquire(&name) {
+            Ok(lock) => lock,
+            // Runtime directory is not writable in some sandboxes
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::PermissionDenied => return,
+            Err(err) => panic!("{err}"),
+        };
+        // Locks of separate descriptors exclude each other within a process too
+        match InstanceLock::acquire(&name) {
+            Err(Error::InterfaceLocked { owner, .. }) => {
+                assert_eq!(owner, Some(std::process::id()))
+            }
+            result => panic!("unexpected {result:?}"),
+        }
+        drop(lock);
+        assert!(InstanceLock::acquire(&name).is_ok());
+        let _ = fs::remove_file(lock_dir().join(format!("{name}.lock")));
+    }
+}
@@ -1,43 +1,357 @@
-use super::queue::{create_device, Device};
+use super::instance::InstanceLock;
+use super::mtu::MtuMonitor;
+use super::netlink::{self, add_alt_name, set_onlink_route, tag_name};
+#[cfg(not(feature = "netconfig"))]
+use super::queue::set_up;
+use super::queue::{
+    self, attach_device, collect_details, create_device, device_info, open_device, set_blocking,
+    set_dstaddr, set_flag, set_multicast, set_persist, set_vnet_header_len, Device,
+};
+use super::sd_notify;
+use super::sysctl::{self, Sysctl};
+use super::vhost::{Vhost, VhostConfig};
 use super::Driver;
-use super::PlatformIfConfig;
+use super::{PlatformIfConfig, VNET_HEADER_LEN};
 use delegate::delegate;
+use futures::future::poll_fn;
 use futures::{AsyncRead, AsyncWrite};
-use log::debug;
+use log::{debug, warn};
+use netconfig::ipnet::IpNet;
+#[cfg(feature = "netconfig")]
 use netconfig::sys::InterfaceExt;
 use std::io;
-use std::io::{Read, Write};
+use std::io::{ErrorKind, Read, Write};
+use std::mem;
+use std::net::{IpAddr, Ipv6Addr};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
 use std::pin::Pin;
-use std::task::{Context, Poll};
-use tunio_core::config::IfConfig;
+use std::task::{ready, Context, Poll};
+use tunio_core::address::Address;
+use tunio_core::coalesce::ReadCoalescing;
+use tunio_core::config::{IfConfig, Layer, NameOutcome};
+use tunio_core::control::{ControlChannel, ControlSender};
+use tunio_core::diagnostics::Diagnostics;
+use tunio_core::egress::{EgressPriority, EgressScheduler};
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::mtu::MtuWatch;
+use tunio_core::name;
+use tunio_core::offload::Offload;
+use tunio_core::packet::is_ipv4;
+use tunio_core::pause::PauseHandle;
 use tunio_core::queue::syncfd::SyncFdQueue;
 #[cfg(feature = "tokio")]
-use tunio_core::queue::tokiofd::TokioFdQueue;
+use tunio_core::queue::tokiofd::{AsyncFd, TokioFdQueue};
 use tunio_core::queue::FdQueueT;
+use tunio_core::ready::{Readiness, ReadyHold, ReadyNotify};
+use tunio_core::sampling::{Anomaly, SampledLog};
+use tunio_core::shaper::RateLimiter;
+use tunio_core::spec::{Change, InterfaceSpec, LinkFlag, LinkFlags};
+use tunio_core::stats::{QueueStats, StatsCounters};
 use tunio_core::traits::{AsyncQueueT, InterfaceT, SyncQueueT};
+use tunio_core::transaction::Transaction;
 use tunio_core::Error;
 
 pub struct LinuxInterface<Q> {
     name: String,
-    pub(crate) queue: Q,
+    index: u32,
+    pub(crate) name_outcome: NameOutcome,
+    layer: Layer,
+    vnet_header: bool,
+    poll_budget: Option<usize>,
+    read_coalescing: Option<ReadCoalescing>,
+    ipv6_only: bool,
+    peer6: Option<Ipv6Addr>,
+    diagnostics: Diagnostics,
+    events: EventEmitter,
+    mtu: MtuMonitor,
+    pause: PauseHandle,
+    stats: StatsCounters,
+    anomalies: SampledLog,
+    shaper: RateLimiter,
+    egress: EgressScheduler,
+    control: ControlChannel,
+    vhost: Option<Vhost>,
+    readiness: Readiness,
+    /// Locks of the requested name and of the suffixed one, if it differs.
+    _instance_locks: Vec<InstanceLock>,
+    pub(crate) queue: Option<Q>,
+    /// Descriptor, that is left without a queue by a failed session restart, so that
+    /// [`AsFd`] stays valid.
+    detached: Option<OwnedFd>,
 }
 
 impl<Q> LinuxInterface<Q> {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Kernel interface index. It does not change during the interface lifetime, including
+    /// session restarts.
+    pub fn ifindex(&self) -> u32 {
+        self.index
+    }
+
+    /// Whether the interface was created, adopted or created with a suffixed name. See
+    /// [`IfConfig::name_conflict`].
+    pub fn name_outcome(&self) -> NameOutcome {
+        self.name_outcome
+    }
+
+    /// Stops taking packets from the device, until [`resume`](Self::resume) is called.
+    /// Pending and subsequent reads wait, while the kernel queue applies backpressure.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns a handle, that pauses and resumes this interface from another task or thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Limits the rate of packets, written to the interface, to `bytes_per_sec` with bursts
+    /// of up to `burst` bytes. Writes wait for the bucket to be replenished.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: u64, burst: u64) {
+        self.shaper.set(bytes_per_sec, burst);
+    }
+
+    pub fn clear_rate_limit(&mut self) {
+        self.shaper.clear();
+    }
+
+    /// Lets packets, selected by `policy`, bypass up to `backlog` bulk packets, queued while
+    /// async writes are pending. Has no effect on sync interfaces.
+    pub fn set_egress_priority(&mut self, policy: EgressPriority, backlog: usize) {
+        self.egress.set_policy(policy, backlog);
+    }
+
+    /// Handle, that queues control packets, like keepalives. They are sent ahead of all other
+    /// packets on the next read, write or flush, bypassing the egress backlog and the rate
+    /// limit.
+    pub fn control_sender(&self) -> ControlSender {
+        self.control.sender()
+    }
+
+    /// Future, that resolves, once the interface is up, and all holds of
+    /// [`hold_ready`](Self::hold_ready) are dropped.
+    pub fn ready_notify(&self) -> ReadyNotify {
+        self.readiness.wait()
+    }
+
+    /// Keeps the interface from being ready, while addresses and routes are applied.
+    pub fn hold_ready(&self) -> ReadyHold {
+        self.readiness.hold()
+    }
+
+    /// Packet and ECN counters of this interface.
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+
+    /// Whether packets start with a `virtio_net_hdr`. See [`PlatformIfConfig::vnet_header`].
+    pub fn vnet_header(&self) -> bool {
+        self.vnet_header
+    }
+
+    /// vhost-net instance, that drives the device, if it is attached.
+    pub fn vhost(&self) -> Option<&Vhost> {
+        self.vhost.as_ref()
+    }
+
+    /// Counters of data path anomalies, including the ones, that were not logged.
+    pub fn anomalies(&self) -> &SampledLog {
+        &self.anomalies
+    }
+
+    /// What was done while creating the interface, for bug reports. See
+    /// [`IfConfig::detailed_diagnostics`].
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// MTU of the interface, updated from link notifications, when it is changed by this or
+    /// another process. [`EventKind::MtuChanged`] is emitted on every change.
+    pub fn current_mtu(&self) -> u32 {
+        self.mtu.watch().get()
+    }
+
+    /// Returns a watch, that follows MTU changes of this interface, for hooks, such as
+    /// [`Fragmenter::tracking`](tunio_core::hooks::Fragmenter::tracking).
+    pub fn mtu_watch(&self) -> MtuWatch {
+        self.mtu.watch().clone()
+    }
+
+    /// Writes a per-interface sysctl under `/proc/sys/net`. Sysctls are reset by the kernel,
+    /// when the interface is removed. Needs `CAP_NET_ADMIN` in the network namespace, that
+    /// owns `/proc/sys/net`.
+    pub fn set_sysctl(&self, sysctl: Sysctl) -> Result<(), Error> {
+        sysctl::set_sysctl(&self.name, sysctl)
+    }
+
+    /// Lets the TAP interface receive frames, that are addressed to other hosts, for bridging
+    /// and monitoring. Fails with [`Error::LayerUnsupported`] on TUN interfaces.
+    pub fn set_promiscuous(&self, enabled: bool) -> Result<(), Error> {
+        self.require_l2()?;
+        set_flag(&self.name, libc::IFF_PROMISC, enabled)
+    }
+
+    /// Adds a link-layer multicast address to the filter of the TAP interface, so that frames,
+    /// sent to it, are received. Memberships are counted by the kernel: every join needs a
+    /// [`leave_multicast`](Self::leave_multicast).
+    pub fn join_multicast(&self, mac: [u8; 6]) -> Result<(), Error> {
+        self.require_l2()?;
+        set_multicast(&self.name, mac, true)
+    }
+
+    pub fn leave_multicast(&self, mac: [u8; 6]) -> Result<(), Error> {
+        self.require_l2()?;
+        set_multicast(&self.name, mac, false)
+    }
+
+    /// Sets the address of the other end of the point-to-point link, which replaces the
+    /// previous peer of the same family. IPv4 peers are set with `SIOCSIFDSTADDR`, after an
+    /// IPv4 address is added. IPv6 has no destination address, so an on-link host route to
+    /// the peer is added instead.
+    pub fn set_peer(&mut self, addr: IpAddr) -> Result<(), Error> {
+        match addr {
+            IpAddr::V4(addr) => set_dstaddr(&self.name, addr),
+            IpAddr::V6(addr) => {
+                set_onlink_route(self.index, IpAddr::V6(addr).into(), true)?;
+                match self.peer6.replace(addr) {
+                    Some(old) if old != addr => {
+                        set_onlink_route(self.index, IpAddr::V6(old).into(), false)
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Adds an address with its label, flags and lifetimes. An existing address is updated with
+    /// them, which refreshes lifetimes of temporary addresses.
+    pub fn add_address(&self, address: &Address) -> Result<(), Error> {
+        netlink::add_address(self.index, address)
+    }
+
+    pub fn remove_address(&self, net: IpNet) -> Result<(), Error> {
+        netlink::remove_address(self.index, net)
+    }
+
+    /// Addresses of the interface with their labels and flags, including IPv6 link-local
+    /// ones.
+    pub fn addresses(&self) -> Result<Vec<Address>, Error> {
+        netlink::addresses(self.index)
+    }
+
+    /// Lets the interface receive frames of all multicast groups, not only the joined ones.
+    pub fn set_allmulti(&self, enabled: bool) -> Result<(), Error> {
+        set_flag(&self.name, libc::IFF_ALLMULTI, enabled)
+    }
+
+    /// Enables or disables ARP on the interface, with `IFF_NOARP`. Point-to-point tunnels
+    /// usually disable it. TUN interfaces are created without ARP by the kernel.
+    pub fn set_arp(&self, enabled: bool) -> Result<(), Error> {
+        set_flag(&self.name, libc::IFF_NOARP, !enabled)
+    }
+
+    fn require_l2(&self) -> Result<(), Error> {
+        match self.layer {
+            Layer::L2 => Ok(()),
+            layer => Err(Error::LayerUnsupported(layer)),
+        }
+    }
+
+    pub(crate) fn inner_queue_mut(&mut self) -> io::Result<&mut Q> {
+        match &mut self.queue {
+            Some(queue) => Ok(queue),
+            None => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+
+    /// IPv4 packets are not written to IPv6-only interfaces.
+    fn drops(&self, packet: &[u8]) -> bool {
+        self.ipv6_only && is_ipv4(packet, self.layer)
+    }
+
+    fn write_failed(&self, err: io::Error) -> io::Error {
+        if err.kind() != io::ErrorKind::WouldBlock {
+            self.events
+                .emit(&self.name, EventKind::WriteFailed(err.kind()));
+        }
+        err
+    }
+
+    fn packet_read(&self, buf: &[u8], n: usize, truncated: bool) -> usize {
+        self.stats.record_rx(&buf[..n]);
+        if truncated {
+            self.anomalies
+                .record(Anomaly::Truncated, || format!("{n} bytes read"));
+        }
+        n
+    }
+
+    fn packet_written(&mut self, buf: &[u8], n: usize) -> usize {
+        self.stats.record_tx(&buf[..n]);
+        self.shaper.consume(n);
+        n
+    }
+}
+
+impl<Q> Drop for LinuxInterface<Q> {
+    fn drop(&mut self) {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+    }
 }
 
 impl<Q: FdQueueT> InterfaceT for LinuxInterface<Q> {
     type PlatformDriver = Driver;
     type PlatformIfConfig = PlatformIfConfig;
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(name = %params.name, layer = ?params.layer))
+    )]
     fn new(
-        _driver: &mut Self::PlatformDriver,
+        driver: &mut Self::PlatformDriver,
         params: IfConfig<Self::PlatformIfConfig>,
     ) -> Result<Self, Error> {
-        let Device { device, name } = create_device(&params.name, params.layer, Q::BLOCKING)?;
-        let queue = Q::new(device.into());
+        let mut diagnostics = Diagnostics::new("linux");
+        let name = name::normalize::<PlatformIfConfig>(&params.name, params.name_overflow)?;
+        // Before the name is resolved, so that the second instance does not adopt the device
+        let mut instance_locks = Vec::new();
+        if params.instance_lock {
+            instance_locks.push(InstanceLock::acquire(&name)?);
+        }
+        let (name, name_outcome) =
+            name::resolve(&name, params.name_conflict, Self::max_name_len(), |name| {
+                nix::net::if_::if_nametoindex(name).is_ok()
+            })?;
+        // Created name is locked too, so that an instance, that requests it, fails as well
+        if params.instance_lock && name_outcome == NameOutcome::Suffixed {
+            instance_locks.push(InstanceLock::acquire(&name)?);
+        }
+        diagnostics.step(
+            format!("resolve name \"{}\"", params.name),
+            format!("{name} ({name_outcome:?})"),
+        );
+        let Device { device, name } = create_device(
+            &name,
+            params.layer,
+            Q::BLOCKING,
+            params.platform.vnet_header,
+            &mut diagnostics,
+        )
+        .map_err(|err| {
+            // Device is attached to another descriptor, or it is not a TUN/TAP device of
+            // this layer
+            match is_name_taken(err.raw_os_error(), name_outcome) {
+                true => Error::NameTaken(name),
+                false => err,
+            }
+        })?;
 
         if params.name != name {
             debug!(
@@ -46,37 +360,524 @@ impl<Q: FdQueueT> InterfaceT for LinuxInterface<Q> {
             );
         }
 
-        Ok(Self { name, queue })
+        Self::with_device(
+            driver,
+            params,
+            device.into(),
+            name,
+            name_outcome,
+            diagnostics,
+            instance_locks,
+        )
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name)))]
     fn up(&mut self) -> Result<(), Error> {
-        Ok(self.handle().set_up(true)?)
+        #[cfg(feature = "netconfig")]
+        self.handle().set_up(true)?;
+        #[cfg(not(feature = "netconfig"))]
+        set_up(&self.name, true)?;
+        self.events.emit(&self.name, EventKind::SessionStarted);
+        self.readiness.set_session(true);
+        Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name)))]
     fn down(&mut self) -> Result<(), Error> {
-        Ok(self.handle().set_up(false)?)
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+        self.readiness.set_session(false);
+        #[cfg(feature = "netconfig")]
+        self.handle().set_up(false)?;
+        #[cfg(not(feature = "netconfig"))]
+        set_up(&self.name, false)?;
+        Ok(())
+    }
+
+    /// Adds the address with its label, flags and lifetimes, like
+    /// [`add_address`](Self::add_address).
+    fn add_address_async(&self, address: Address) -> Offload<()> {
+        let index = self.index;
+        Offload::new(move || netlink::add_address(index, &address))
+    }
+
+    fn remove_address_async(&self, net: IpNet) -> Offload<()> {
+        let index = self.index;
+        Offload::new(move || netlink::remove_address(index, net))
+    }
+
+    fn add_route_async(&self, net: IpNet) -> Offload<()> {
+        let index = self.index;
+        Offload::new(move || set_onlink_route(index, net, true))
+    }
+
+    fn remove_route_async(&self, net: IpNet) -> Offload<()> {
+        let index = self.index;
+        Offload::new(move || set_onlink_route(index, net, false))
     }
 
     fn handle(&self) -> netconfig::Interface {
-        netconfig::Interface::try_from_name(self.name()).unwrap()
+        netconfig::Interface::from_index_unchecked(self.index)
+    }
+
+    fn index(&self) -> Result<u32, Error> {
+        Ok(self.index)
+    }
+}
+
+/// Adopted interfaces may have IPv4 addresses, new ones have none.
+fn remove_ipv4_addresses(index: u32) -> Result<(), Error> {
+    let handle = netconfig::Interface::from_index_unchecked(index);
+    for network in handle.addresses()? {
+        if network.addr().is_ipv4() {
+            handle.remove_address(network)?;
+        }
+    }
+    Ok(())
+}
+
+fn is_name_taken(code: Option<i32>, outcome: NameOutcome) -> bool {
+    match code {
+        Some(libc::EBUSY) => true,
+        Some(libc::EINVAL) => outcome == NameOutcome::Adopted,
+        _ => false,
+    }
+}
+
+impl<Q: FdQueueT> LinuxInterface<Q> {
+    /// Creates an interface from a device descriptor, attached by another process, like the
+    /// privileged helper. Name, layer and vnet-header mode of `params` are replaced by the ones
+    /// of the device, and [`name_outcome`](Self::name_outcome) is [`NameOutcome::Adopted`].
+    pub fn from_fd(
+        driver: &mut Driver,
+        mut params: IfConfig<PlatformIfConfig>,
+        device: OwnedFd,
+    ) -> Result<Self, Error> {
+        let mut diagnostics = Diagnostics::new("linux");
+        let (name, layer, vnet_header) = device_info(device.as_raw_fd())?;
+        diagnostics.step(
+            format!("TUNGETIFF fd {}", device.as_raw_fd()),
+            format!("{name} {layer:?}"),
+        );
+        set_blocking(device.as_raw_fd(), Q::BLOCKING)?;
+        let instance_locks = match params.instance_lock {
+            true => vec![InstanceLock::acquire(&name)?],
+            false => Vec::new(),
+        };
+        params.name = name.clone();
+        params.layer = layer;
+        params.platform.vnet_header = vnet_header;
+        Self::with_device(
+            driver,
+            params,
+            device,
+            name,
+            NameOutcome::Adopted,
+            diagnostics,
+            instance_locks,
+        )
+    }
+
+    fn with_device(
+        driver: &mut Driver,
+        params: IfConfig<PlatformIfConfig>,
+        device: OwnedFd,
+        name: String,
+        name_outcome: NameOutcome,
+        mut diagnostics: Diagnostics,
+        instance_locks: Vec<InstanceLock>,
+    ) -> Result<Self, Error> {
+        if params.detailed_diagnostics {
+            collect_details(device.as_raw_fd(), &mut diagnostics);
+        }
+        let mut queue = Q::new(device)?;
+        queue.set_poll_budget(params.poll_budget);
+        queue.set_read_coalescing(params.read_coalescing);
+        queue.set_drop_ipv4(params.ipv6_only.then_some(params.layer));
+
+        let index = nix::net::if_::if_nametoindex(name.as_str()).map_err(io::Error::from)?;
+        diagnostics.step(format!("if_nametoindex \"{name}\""), index);
+        if let Some(tag) = &params.tag {
+            let alt_name = tag_name(tag, &name)?;
+            add_alt_name(index, &alt_name)?;
+            diagnostics.step(format!("add alternative name \"{alt_name}\""), "ok");
+        }
+        if params.ipv6_only {
+            remove_ipv4_addresses(index)?;
+            diagnostics.step("remove IPv4 addresses", "ok");
+        }
+
+        let mtu = MtuMonitor::start(&name, index, driver.events.clone())?;
+        diagnostics.step("start MTU monitor", format!("MTU {}", mtu.watch().get()));
+        driver.events.emit(&name, EventKind::Created);
+        let anomalies = SampledLog::new(name.clone(), params.anomaly_sampling);
+        let readiness = Readiness::default();
+        if params.platform.sd_notify {
+            let name = name.clone();
+            readiness.on_ready(move || {
+                if let Err(err) = sd_notify::notify_ready() {
+                    warn!("Failed to notify systemd, that {name} is ready: {err}");
+                }
+            });
+        }
+
+        Ok(Self {
+            name,
+            index,
+            name_outcome,
+            layer: params.layer,
+            vnet_header: params.platform.vnet_header,
+            poll_budget: params.poll_budget,
+            read_coalescing: params.read_coalescing,
+            ipv6_only: params.ipv6_only,
+            peer6: None,
+            diagnostics,
+            events: driver.events.clone(),
+            mtu,
+            pause: PauseHandle::default(),
+            stats: StatsCounters::new(params.layer),
+            anomalies,
+            shaper: RateLimiter::default(),
+            egress: EgressScheduler::new(params.layer),
+            control: ControlChannel::default(),
+            vhost: None,
+            readiness,
+            _instance_locks: instance_locks,
+            queue: Some(queue),
+            detached: None,
+        })
+    }
+
+    /// Consumes the interface, returning the device descriptor. Interface stays alive while
+    /// the descriptor is open.
+    pub fn into_fd(mut self) -> Result<OwnedFd, Error> {
+        match self.queue.take() {
+            Some(queue) => Ok(queue.into_fd()),
+            None => Err(io::Error::from(ErrorKind::NotConnected).into()),
+        }
+    }
+
+    /// Makes the interface outlive its descriptors, until it is made non-persistent again and
+    /// the last descriptor is closed.
+    pub fn set_persistent(&mut self, persistent: bool) -> Result<(), Error> {
+        let queue = self.inner_queue_mut()?;
+        set_persist(queue.as_raw_fd(), persistent)
+    }
+
+    fn device_fd(&self) -> BorrowedFd<'_> {
+        match (&self.queue, &self.detached) {
+            (Some(queue), _) => queue.as_fd(),
+            (None, Some(device)) => device.as_fd(),
+            // Queue is only taken by `into_fd`, that consumes the interface, and by restarts,
+            // that leave a detached descriptor, when they fail
+            (None, None) => unreachable!("interface has neither a queue nor a descriptor"),
+        }
+    }
+
+    /// Reopens the device descriptor, keeping the interface with all its addresses and routes.
+    ///
+    /// Devices without multiple queues take one descriptor at a time, so the interface is
+    /// made persistent, while the old descriptor is closed and the new one is attached. If
+    /// the new descriptor cannot be attached, the interface, that would outlive the process
+    /// otherwise, is removed, and all subsequent reads and writes fail with `NotConnected`.
+    /// Interface, that another process attached to in the meantime, is left to it. vhost-net
+    /// is detached, as it drives the old descriptor.
+    pub fn restart_session(&mut self) -> Result<(), Error> {
+        let device = open_device(Q::BLOCKING)?;
+
+        self.vhost = None;
+        if let Some(queue) = self.queue.take() {
+            if let Err(err) = set_persist(queue.as_raw_fd(), true) {
+                self.queue = Some(queue);
+                return Err(err);
+            }
+            drop(queue);
+            self.events.emit(&self.name, EventKind::ShutdownRequested);
+        }
+        // Descriptor of a failed restart stays attached, if its queue was not created, and
+        // would keep the new one from attaching
+        if let Some(detached) = self.detached.take() {
+            if device_info(detached.as_raw_fd()).is_ok() {
+                if let Err(err) = set_persist(detached.as_raw_fd(), true) {
+                    self.detached = Some(detached);
+                    return Err(err);
+                }
+            }
+            drop(detached);
+        }
+
+        if let Err(err) = attach_device(&device, &self.name, self.layer, self.vnet_header) {
+            if err.raw_os_error() != Some(libc::EBUSY) {
+                if let Err(err) = netlink::delete_link(self.index) {
+                    warn!("Persistent interface {} is not removed: {err}", self.name);
+                }
+            }
+            self.detached = Some(device.into());
+            return Err(err);
+        }
+
+        // Attached descriptor is kept, until the queue is created, so that the interface
+        // stays alive and is not persistent, if that fails
+        let queue = set_persist(device.as_raw_fd(), false)
+            .and_then(|()| Ok(Q::new(device.try_clone()?.into())?));
+        let mut queue = match queue {
+            Ok(queue) => queue,
+            Err(err) => {
+                self.detached = Some(device.into());
+                return Err(err);
+            }
+        };
+        queue.set_poll_budget(self.poll_budget);
+        queue.set_read_coalescing(self.read_coalescing);
+        queue.set_drop_ipv4(self.ipv6_only.then_some(self.layer));
+        self.queue = Some(queue);
+        self.detached = None;
+
+        self.events.emit(&self.name, EventKind::SessionStarted);
+        Ok(())
+    }
+
+    /// Attaches the device to `/dev/vhost-net`, whose kernel worker moves packets between the
+    /// device and the rings of `config` from then on. Interface must be in vnet-header mode.
+    /// Its own reads and writes would compete with the worker, so they are not used, while
+    /// vhost-net is attached. Header is 10 bytes long while attached, if neither
+    /// `VIRTIO_F_VERSION_1` nor `VIRTIO_NET_F_MRG_RXBUF` is acknowledged. Replaces the previous
+    /// attachment.
+    ///
+    /// # Safety
+    /// Memory regions of `config` and the rings must stay mapped, readable and writable, until
+    /// vhost-net is detached, and must not be accessed in ways, that the virtio protocol does
+    /// not allow: the worker reads and writes them concurrently.
+    #[allow(unsafe_code)]
+    pub unsafe fn attach_vhost(&mut self, config: &VhostConfig) -> Result<&Vhost, Error> {
+        if !self.vnet_header {
+            return Err(Error::InvalidConfigValue {
+                name: "vnet_header".to_string(),
+                value: "false".to_string(),
+                reason: "vhost-net needs packets with a virtio_net_hdr".to_string(),
+            });
+        }
+
+        self.vhost = None;
+        let vhost = Vhost::attach(self.inner_queue_mut()?.as_fd(), config)?;
+        Ok(self.vhost.insert(vhost))
+    }
+
+    /// Stops vhost-net from driving the device, so that packets are read and written through
+    /// the interface again. Packets, that are in the rings, are not delivered.
+    pub fn detach_vhost(&mut self) -> Result<(), Error> {
+        if self.vhost.take().is_some() {
+            // Header size follows the features of vhost-net while it is attached
+            let queue = self.inner_queue_mut()?;
+            set_vnet_header_len(queue.as_raw_fd(), VNET_HEADER_LEN)?;
+        }
+        Ok(())
+    }
+
+    /// Live state of the interface in the form of [`apply`](Self::apply). DNS is not read.
+    pub fn live_spec(&self) -> Result<InterfaceSpec, Error> {
+        let flags = queue::flags(&self.name)?;
+        Ok(InterfaceSpec {
+            up: Some(flags & libc::IFF_UP != 0),
+            mtu: Some(netlink::link_mtu(self.index)?),
+            addresses: self.addresses()?,
+            routes: netlink::onlink_routes(self.index)?,
+            dns: None,
+            flags: LinkFlags {
+                promiscuous: Some(flags & libc::IFF_PROMISC != 0),
+                allmulti: Some(flags & libc::IFF_ALLMULTI != 0),
+                arp: Some(flags & libc::IFF_NOARP == 0),
+            },
+        })
+    }
+
+    /// Reconciles the interface to `spec`, applying only the changes to its
+    /// [live state](Self::live_spec), and returns them. Managed routes are the ones without a
+    /// gateway and of the `boot` protocol, like routes, added with `ip route`.
+    ///
+    /// DNS is configured by resolvers, like systemd-resolved, not by interfaces, so a spec
+    /// with DNS servers fails with [`Unsupported`](io::ErrorKind::Unsupported), before
+    /// anything is changed. Changes are applied as a [`Transaction`]: if one fails, the ones
+    /// before it are rolled back.
+    pub fn apply(&mut self, spec: InterfaceSpec) -> Result<Vec<Change>, Error> {
+        if spec.dns.is_some() {
+            return Err(io::Error::from(ErrorKind::Unsupported).into());
+        }
+        let live = self.live_spec()?;
+        let changes = spec.diff(&live);
+        Transaction::new(live).run(changes, |change| self.apply_change(change))
+    }
+
+    /// Creates the interface and reconciles it to `spec` in one step. If a change fails, the
+    /// interface is dropped, which removes a new interface, while the changes to an adopted
+    /// one are rolled back.
+    pub fn new_with_spec(
+        driver: &mut Driver,
+        params: IfConfig<PlatformIfConfig>,
+        spec: InterfaceSpec,
+    ) -> Result<Self, Error> {
+        let mut interface = <Self as InterfaceT>::new(driver, params)?;
+        interface.apply(spec)?;
+        Ok(interface)
+    }
+
+    fn apply_change(&mut self, change: &Change) -> Result<(), Error> {
+        debug!("Applying {change:?} to {}", self.name);
+        match change {
+            Change::SetUp(true) => self.up(),
+            Change::SetUp(false) => self.down(),
+            Change::SetMtu(mtu) => Ok(self.handle().set_mtu(*mtu)?),
+            Change::AddAddress(address) => self.add_address(address),
+            Change::RemoveAddress(net) => self.remove_address(*net),
+            Change::AddRoute(net) => set_onlink_route(self.index, *net, true),
+            Change::RemoveRoute(net) => set_onlink_route(self.index, *net, false),
+            Change::SetDns(_) => Err(io::Error::from(ErrorKind::Unsupported).into()),
+            Change::SetFlag(LinkFlag::Promiscuous, enabled) => self.set_promiscuous(*enabled),
+            Change::SetFlag(LinkFlag::AllMulti, enabled) => self.set_allmulti(*enabled),
+            Change::SetFlag(LinkFlag::Arp, enabled) => self.set_arp(*enabled),
+        }
+    }
+
+    /// Runs `f` with the device descriptor, while the interface is paused, for ioctls, that
+    /// must not race the data path, like offload changes.
+    ///
+    /// Interface is borrowed exclusively, so no read or write is in flight, while `f` runs.
+    /// Async interfaces should use [`with_paused_async`](Self::with_paused_async), that
+    /// flushes pending writes first. Interface stays paused, if it was paused before.
+    pub fn with_paused<R>(
+        &mut self,
+        f: impl FnOnce(BorrowedFd<'_>) -> io::Result<R>,
+    ) -> Result<R, Error> {
+        let was_paused = self.pause.is_paused();
+        self.pause.pause();
+        let result = self.inner_queue_mut().and_then(|queue| f(queue.as_fd()));
+        if !was_paused {
+            self.pause.resume();
+        }
+        Ok(result?)
+    }
+}
+
+impl<Q: AsyncQueueT + FdQueueT> LinuxInterface<Q> {
+    /// Writes packets, queued by the egress scheduler, and then runs `f` like
+    /// [`with_paused`](Self::with_paused).
+    pub async fn with_paused_async<R>(
+        &mut self,
+        f: impl FnOnce(BorrowedFd<'_>) -> io::Result<R>,
+    ) -> Result<R, Error> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_flush(cx)).await?;
+        self.with_paused(f)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl LinuxInterface<TokioFdQueue> {
+    /// Device, registered with the tokio reactor, for custom read loops, that wait for
+    /// readiness and read batches with [`AsyncFd::try_io`], while keeping the device setup of
+    /// this interface. Packets, read or written through it, bypass hooks, statistics, pausing
+    /// and rate limits of the interface.
+    ///
+    /// Fails with `NotConnected`, if the device is detached after a failed
+    /// [`restart_session`](Self::restart_session).
+    pub fn async_fd(&self) -> io::Result<&AsyncFd<SyncFdQueue>> {
+        match &self.queue {
+            Some(queue) => Ok(queue.async_fd()),
+            None => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+
+    pub fn async_fd_mut(&mut self) -> io::Result<&mut AsyncFd<SyncFdQueue>> {
+        Ok(self.inner_queue_mut()?.async_fd_mut())
+    }
+}
+
+/// Device descriptor, for advanced users, who want to drive readiness themselves. See [`FdQueueT`]
+/// for the safety contract.
+///
+/// After a failed [`restart_session`](LinuxInterface::restart_session), it is the descriptor,
+/// that could not be attached.
+impl<Q: FdQueueT> AsRawFd for LinuxInterface<Q> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.device_fd().as_raw_fd()
+    }
+}
+
+impl<Q: FdQueueT> AsFd for LinuxInterface<Q> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.device_fd()
     }
 }
 
 pub type Interface = LinuxInterface<SyncFdQueue>;
-impl SyncQueueT for Interface {}
+impl<Q: SyncQueueT> SyncQueueT for LinuxInterface<Q> {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.send_control()?;
+        self.pause.wait_resumed();
+        let (n, truncated) = self.inner_queue_mut()?.recv(buf)?;
+        Ok((self.packet_read(buf, n, truncated), truncated))
+    }
 
-impl<Q: SyncQueueT> Read for LinuxInterface<Q> {
-    delegate! {
-        to self.queue {
-            fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error>;
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.send_control()?;
+        if self.drops(packet) {
+            return Ok(());
         }
+        self.shaper.wait_ready(packet.len());
+        self.inner_queue_mut()?
+            .send(packet)
+            .map_err(|e| self.write_failed(e))?;
+        self.packet_written(packet, packet.len());
+        Ok(())
+    }
+
+    type PacketRef<'a>
+        = Q::PacketRef<'a>
+    where
+        Q: 'a;
+
+    fn recv_ref(&mut self) -> io::Result<Self::PacketRef<'_>> {
+        self.pause.wait_resumed();
+        // Fields are borrowed separately, as the packet borrows the queue
+        let queue = self.queue.as_mut().ok_or(ErrorKind::NotConnected)?;
+        let packet = queue.recv_ref()?;
+        self.stats.record_rx(&packet);
+        Ok(packet)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        SyncQueueT::drain(self.inner_queue_mut()?)
+    }
+}
+
+impl<Q: SyncQueueT> LinuxInterface<Q> {
+    fn send_control(&mut self) -> io::Result<()> {
+        let Self {
+            control,
+            queue,
+            stats,
+            ..
+        } = self;
+        let queue = queue.as_mut().ok_or(ErrorKind::NotConnected)?;
+        control.send_all(|packet| {
+            queue.send(packet)?;
+            stats.record_tx(packet);
+            Ok(())
+        })
+    }
+}
+
+impl<Q: SyncQueueT> Read for LinuxInterface<Q> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
     }
 }
 
 impl<Q: SyncQueueT> Write for LinuxInterface<Q> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
     delegate! {
-        to self.queue {
-            fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+        to self.inner_queue_mut()? {
             fn flush(&mut self) -> io::Result<()>;
         }
     }
@@ -84,23 +885,212 @@ impl<Q: SyncQueueT> Write for LinuxInterface<Q> {
 
 #[cfg(feature = "tokio")]
 pub type TokioInterface = LinuxInterface<TokioFdQueue>;
-#[cfg(feature = "tokio")]
-impl AsyncQueueT for TokioInterface {}
+impl<Q: AsyncQueueT> AsyncQueueT for LinuxInterface<Q> {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        ready!(self.pause.poll_resumed(cx));
+        let self_mut = self.get_mut();
+        // Reads are not held up by control packets, that wait for ring space
+        if let Poll::Ready(Err(e)) = self_mut.poll_send_control(cx) {
+            return Poll::Ready(Err(e));
+        }
+        match self_mut.inner_queue_mut() {
+            Ok(queue) => Pin::new(queue)
+                .poll_recv(cx, buf)
+                .map_ok(|(n, truncated)| (self_mut.packet_read(buf, n, truncated), truncated)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
 
-impl<Q: AsyncQueueT + Unpin> AsyncRead for LinuxInterface<Q> {
-    delegate! {
-        to Pin::new(&mut self.queue) {
-            fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_send_control(cx))?;
+        if self_mut.drops(packet) {
+            return Poll::Ready(Ok(()));
+        }
+        if !self_mut.egress.is_enabled() {
+            return self_mut.poll_send_shaped(cx, packet).map_ok(drop);
+        }
+
+        let mut egress = mem::take(&mut self_mut.egress);
+        let result = egress.poll_write(cx, packet, |cx, packet| {
+            self_mut.poll_send_shaped(cx, packet)
+        });
+        self_mut.egress = egress;
+        result.map_ok(drop)
+    }
+
+    /// Paused interfaces are not ready.
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.pause.poll_resumed(cx));
+        match self.get_mut().inner_queue_mut() {
+            Ok(queue) => Pin::new(queue).poll_recv_ready(cx),
+            Err(e) => Poll::Ready(Err(e)),
         }
     }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        AsyncQueueT::drain(self.inner_queue_mut()?)
+    }
 }
 
-impl<Q: AsyncQueueT + Unpin> AsyncWrite for LinuxInterface<Q> {
-    delegate! {
-        to Pin::new(&mut self.queue) {
-            fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>;
-            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
-            fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+impl<Q: AsyncQueueT> LinuxInterface<Q> {
+    fn poll_send_shaped(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(self.shaper.poll_ready(cx, buf.len()));
+        match self.inner_queue_mut() {
+            Ok(queue) => Pin::new(queue)
+                .poll_send(cx, buf)
+                .map_ok(|()| self.packet_written(buf, buf.len()))
+                .map_err(|e| self.write_failed(e)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    /// Sends queued control packets, that are not shaped.
+    fn poll_send_control(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Self {
+            control,
+            queue,
+            stats,
+            ..
+        } = self;
+        let queue = match queue {
+            Some(queue) => queue,
+            None => return Poll::Ready(Err(ErrorKind::NotConnected.into())),
+        };
+        control.poll_send_all(cx, |cx, packet| {
+            ready!(Pin::new(&mut *queue).poll_send(cx, packet))?;
+            stats.record_tx(packet);
+            Poll::Ready(Ok(()))
+        })
+    }
+
+    fn poll_drain_egress(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_send_control(cx))?;
+        let mut egress = mem::take(&mut self.egress);
+        let result = egress.poll_drain(cx, |cx, packet| self.poll_send_shaped(cx, packet));
+        self.egress = egress;
+        result
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncRead for LinuxInterface<Q> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncWrite for LinuxInterface<Q> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        match self_mut.inner_queue_mut() {
+            Ok(queue) => Pin::new(queue).poll_flush(cx),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        match self_mut.inner_queue_mut() {
+            Ok(queue) => Pin::new(queue).poll_close(cx),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tunio_core::traits::{DriverT, InterfaceT};
+
+    /// Creates a TUN interface, or returns `None`, where the test runs without
+    /// `CAP_NET_ADMIN`.
+    fn new_interface(name: &str) -> Option<Interface> {
+        let mut driver = Driver::new().unwrap();
+        let config = Interface::config_builder()
+            .name(name.to_string())
+            .build()
+            .unwrap();
+        match Interface::new(&mut driver, config) {
+            Ok(interface) => Some(interface),
+            Err(err) => {
+                eprintln!("skipped, interface is not created: {err}");
+                None
+            }
         }
     }
+
+    #[test]
+    fn restart_keeps_interface() {
+        let Some(mut interface) = new_interface("tunio-rst0") else {
+            return;
+        };
+        let index = interface.ifindex();
+        interface.restart_session().unwrap();
+        assert_eq!(nix::net::if_::if_nametoindex("tunio-rst0").unwrap(), index);
+
+        // Interface is not left persistent
+        drop(interface);
+        assert!(nix::net::if_::if_nametoindex("tunio-rst0").is_err());
+    }
+
+    #[test]
+    fn failed_restart_removes_interface_and_detaches() {
+        let Some(mut interface) = new_interface("tunio-rst1") else {
+            return;
+        };
+        // Device of another layer is not attached
+        interface.layer = Layer::L2;
+        interface.restart_session().unwrap_err();
+        assert!(nix::net::if_::if_nametoindex("tunio-rst1").is_err());
+
+        assert!(interface.as_raw_fd() >= 0);
+        let err = interface.recv(&mut [0; 64]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotConnected);
+        let err = interface.set_persistent(true).unwrap_err();
+        assert_eq!(
+            err.io_error().map(io::Error::kind),
+            Some(ErrorKind::NotConnected)
+        );
+    }
+
+    #[test]
+    fn restart_is_retried_after_failed_queue_creation() {
+        let Some(mut interface) = new_interface("tunio-rst2") else {
+            return;
+        };
+        // Restart, whose queue was not created, leaves the attached descriptor
+        let queue = interface.queue.take().unwrap();
+        interface.detached = Some(queue.into_fd());
+
+        let index = interface.ifindex();
+        interface.restart_session().unwrap();
+        assert_eq!(nix::net::if_::if_nametoindex("tunio-rst2").unwrap(), index);
+        assert!(interface.detached.is_none());
+        interface.set_persistent(false).unwrap();
+
+        drop(interface);
+        assert!(nix::net::if_::if_nametoindex("tunio-rst2").is_err());
+    }
 }
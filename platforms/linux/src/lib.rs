@@ -5,27 +5,65 @@
 //! Supported features:
 //! - TUN/TAP modes
 //! - Sync and async mode
+//! - vnet-header mode with optional vhost-net attachment
 //!
 //! Low-level documentation for this driver can be found [here](https://www.kernel.org/doc/Documentation/networking/tuntap.txt).
+#![deny(unsafe_code)]
 
+mod enumerate;
+#[cfg(feature = "helper")]
+pub mod helper;
+mod instance;
 mod interface;
+mod mtu;
+mod netlink;
+pub mod profile;
 mod queue;
+pub mod sd_notify;
+mod socket;
+mod sys;
+pub mod sysctl;
+pub mod vhost;
 
 use derive_builder::Builder;
+use tunio_core::device::DeviceInfo;
+use tunio_core::events::{EventEmitter, EventReceiver};
 use tunio_core::traits::{DriverT, PlatformIfConfigT};
 use tunio_core::Error;
 
+pub use instance::InstanceLock;
 #[cfg(feature = "tokio")]
 pub use interface::TokioInterface;
 pub use interface::{Interface, LinuxInterface};
+pub use profile::syscall_profile;
+pub use socket::{bind_to_device, bind_to_interface};
 
-pub struct Driver {}
+pub struct Driver {
+    pub(crate) events: EventEmitter,
+}
+
+/// Size of the `virtio_net_hdr`, that starts every packet in vnet-header mode.
+pub const VNET_HEADER_LEN: usize = 12;
 
 #[derive(Builder, Clone)]
-pub struct PlatformIfConfig {}
+pub struct PlatformIfConfig {
+    /// Creates the device with `IFF_VNET_HDR`: every packet, that is read or written, starts
+    /// with a `virtio_net_hdr` of [`VNET_HEADER_LEN`] bytes, carrying checksum and
+    /// segmentation offload metadata. Stats and hooks see packets with the header. Needed to
+    /// [attach vhost-net](LinuxInterface::attach_vhost).
+    #[builder(default = "false")]
+    pub vnet_header: bool,
+    /// Sends `READY=1` to systemd, once the interface is
+    /// [ready](LinuxInterface::ready_notify), so that units of `Type=notify` are started only
+    /// when the tunnel is usable.
+    #[builder(default = "false")]
+    pub sd_notify: bool,
+}
 
 impl PlatformIfConfigT for PlatformIfConfig {
     type Builder = PlatformIfConfigBuilder;
+
+    const MAX_NAME_LEN: usize = libc::IFNAMSIZ - 1;
 }
 
 impl Default for PlatformIfConfig {
@@ -38,6 +76,20 @@ impl DriverT for Driver {
     type PlatformIfConfig = PlatformIfConfig;
 
     fn new() -> Result<Self, Error> {
-        Ok(Self {})
+        Ok(Self {
+            events: EventEmitter::default(),
+        })
+    }
+
+    fn subscribe(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+
+    fn enumerate(&self) -> Result<Vec<DeviceInfo>, Error> {
+        enumerate::enumerate()
+    }
+
+    fn delete(&self, device: &DeviceInfo) -> Result<(), Error> {
+        netlink::delete_link(device.index)
     }
 }
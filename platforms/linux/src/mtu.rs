@@ -0,0 +1,107 @@
+//! MTU changes of an interface, made by the application or by other processes, reported by
+//! link notifications.
+use crate::netlink::{link_mtu, LinkEvents};
+use log::warn;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::os::unix::io::{AsFd, AsRawFd};
+use std::os::unix::net::UnixStream;
+use std::thread::{self, JoinHandle};
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::mtu::MtuWatch;
+use tunio_core::Error;
+
+/// Thread, that keeps an [`MtuWatch`] in sync with the interface. It is stopped, when the
+/// monitor is dropped.
+pub(crate) struct MtuMonitor {
+    watch: MtuWatch,
+    stop: Option<UnixStream>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MtuMonitor {
+    pub(crate) fn start(name: &str, index: u32, events: EventEmitter) -> Result<Self, Error> {
+        // Subscribed before the MTU is read, so that changes in between are not missed
+        let link_events = LinkEvents::open()?;
+        let watch = MtuWatch::new(link_mtu(index)?);
+        let (stop, stopped) = UnixStream::pair()?;
+
+        let thread = thread::Builder::new()
+            .name(format!("tunio-mtu-{name}"))
+            .spawn({
+                let watch = watch.clone();
+                let name = name.to_string();
+                move || run(&link_events, &stopped, index, &name, &watch, &events)
+            })?;
+
+        Ok(Self {
+            watch,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    pub(crate) fn watch(&self) -> &MtuWatch {
+        &self.watch
+    }
+}
+
+impl Drop for MtuMonitor {
+    fn drop(&mut self) {
+        // Closed end of the pair wakes the thread up
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    link_events: &LinkEvents,
+    stopped: &UnixStream,
+    index: u32,
+    name: &str,
+    watch: &MtuWatch,
+    events: &EventEmitter,
+) {
+    loop {
+        let mut fds = [
+            PollFd::new(link_events.as_fd().as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(stopped.as_raw_fd(), PollFlags::POLLIN),
+        ];
+        match poll(&mut fds, -1) {
+            Ok(_) | Err(Errno::EINTR) => {}
+            Err(err) => {
+                warn!("MTU changes of {name} are not tracked anymore: {err}");
+                return;
+            }
+        }
+        if fds[1]
+            .revents()
+            .map_or(false, |revents| !revents.is_empty())
+        {
+            return;
+        }
+        if !fds[0]
+            .revents()
+            .map_or(false, |revents| !revents.is_empty())
+        {
+            continue;
+        }
+
+        let mtu = match link_events.recv_mtu(index) {
+            Ok(mtu) => mtu,
+            // Some notifications are lost, so the MTU is read again
+            Err(err) if err.raw_os_error() == Some(libc::ENOBUFS) => link_mtu(index).ok(),
+            Err(err) => {
+                warn!("MTU changes of {name} are not tracked anymore: {err}");
+                return;
+            }
+        };
+        if let Some(mtu) = mtu {
+            if watch.set(mtu) {
+                events.emit(name, EventKind::MtuChanged { mtu });
+            }
+        }
+    }
+}
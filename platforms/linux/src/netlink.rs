@@ -0,0 +1,670 @@
+//! Link requests over rtnetlink, that netconfig does not provide: alternative interface names
+//! (`IFLA_ALT_IFNAME`, Linux 5.5+), which carry application tags, link removal, link
+//! notifications, that report MTU changes, on-link routes and addresses with labels and
+//! flags.
+//!
+//! Tag of an interface is stored as the alternative name `<tag>.<name>`. Alternative names
+//! are unique like primary ones, so the interface name keeps them apart, and they survive,
+//! as long as the interface exists.
+use crate::sys;
+use netconfig::ipnet::IpNet;
+use nix::sys::socket::{
+    self, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType,
+};
+use std::io;
+use std::mem::size_of;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::time::Duration;
+use tunio_core::address::{Address, AddressFlags};
+use tunio_core::{Error, ResultExt};
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_GETLINK: u16 = 18;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const RTM_GETADDR: u16 = 22;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+const RTM_GETROUTE: u16 = 26;
+const RTM_NEWLINKPROP: u16 = 108;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_DONE: u16 = 3;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+const NLM_F_DUMP: u16 = 0x300;
+const NLM_F_REPLACE: u16 = 0x100;
+const NLM_F_EXCL: u16 = 0x200;
+const NLM_F_CREATE: u16 = 0x400;
+const NLA_F_NESTED: u16 = 0x8000;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+const RTMGRP_LINK: u32 = 0x1;
+const IFLA_MTU: u16 = 4;
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_LABEL: u16 = 3;
+const IFA_CACHEINFO: u16 = 6;
+const IFA_FLAGS: u16 = 8;
+/// Same bit as `IFA_F_TEMPORARY`, which is used by IPv6 addresses.
+const IFA_F_SECONDARY: u32 = 0x01;
+const IFA_F_TEMPORARY: u32 = 0x01;
+const IFA_F_NODAD: u32 = 0x02;
+const IFA_F_DEPRECATED: u32 = 0x20;
+const IFA_F_NOPREFIXROUTE: u32 = 0x200;
+const RTA_DST: u16 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_TABLE: u16 = 15;
+const IFLA_PROP_LIST: u16 = 52;
+const IFLA_ALT_IFNAME: u16 = 53;
+/// Maximum length of an alternative name with the terminating zero.
+const ALTIFNAMSIZ: usize = 128;
+
+const NLMSG_HDRLEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const IFADDRMSG_LEN: usize = 8;
+const RTMSG_LEN: usize = 12;
+const INFINITY_LIFE_TIME: u32 = u32::MAX;
+const RT_TABLE_MAIN: u8 = 254;
+const RTPROT_BOOT: u8 = 3;
+const RT_SCOPE_LINK: u8 = 253;
+const RTN_UNICAST: u8 = 1;
+const RTA_HDRLEN: usize = 4;
+
+/// Alternative name, that carries `tag` for the interface `name`.
+pub(crate) fn tag_name(tag: &str, name: &str) -> Result<String, Error> {
+    let alt_name = format!("{tag}.{name}");
+    let valid = !tag.is_empty()
+        && alt_name.len() < ALTIFNAMSIZ
+        && !tag.contains(|c: char| c == '/' || c == ':' || c.is_whitespace());
+    match valid {
+        true => Ok(alt_name),
+        false => Err(Error::InvalidConfigValue {
+            name: "tag".to_string(),
+            value: tag.to_string(),
+            reason: format!(
+                "must be non-empty, without '/', ':' or whitespace, and at most {} bytes long \
+                 together with the interface name",
+                ALTIFNAMSIZ - 1
+            ),
+        }),
+    }
+}
+
+/// Returns the tag of the interface `name`, if one of its alternative names carries it.
+pub(crate) fn find_tag(alt_names: &[String], name: &str) -> Option<String> {
+    alt_names.iter().find_map(|alt_name| {
+        let tag = alt_name.strip_suffix(name)?.strip_suffix('.')?;
+        (!tag.is_empty()).then(|| tag.to_string())
+    })
+}
+
+/// Adds an alternative name. Name, that the interface already has, is kept.
+pub(crate) fn add_alt_name(index: u32, alt_name: &str) -> Result<(), Error> {
+    let mut nested = vec![];
+    push_attr(&mut nested, IFLA_ALT_IFNAME, &nul_terminated(alt_name));
+    let mut attrs = vec![];
+    push_attr(&mut attrs, IFLA_PROP_LIST | NLA_F_NESTED, &nested);
+
+    let socket = Netlink::open()?;
+    let flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_EXCL;
+    socket.send(RTM_NEWLINKPROP, flags, index, &attrs)?;
+    match socket.recv_ack() {
+        Err(err) if err.raw_os_error() == Some(libc::EEXIST) => Ok(()),
+        result => result.context(|| format!("RTM_NEWLINKPROP({index}, {alt_name})")),
+    }
+}
+
+/// Lists alternative names of the interface.
+pub(crate) fn alt_names(index: u32) -> Result<Vec<String>, Error> {
+    let socket = Netlink::open()?;
+    socket.send(RTM_GETLINK, NLM_F_REQUEST, index, &[])?;
+    let message = socket.recv()?;
+    let link_attrs = match parse_link(parse_message(&message)?) {
+        Some((_, link_attrs)) => link_attrs,
+        None => return Err(unexpected_reply().into()),
+    };
+
+    let mut names = vec![];
+    for (kind, value) in attrs(link_attrs) {
+        if kind == IFLA_PROP_LIST {
+            names.extend(
+                attrs(value)
+                    .filter(|(kind, _)| *kind == IFLA_ALT_IFNAME)
+                    .map(|(_, name)| {
+                        let name = name.split(|b| *b == 0).next().unwrap_or_default();
+                        String::from_utf8_lossy(name).into_owned()
+                    }),
+            );
+        }
+    }
+    Ok(names)
+}
+
+/// Returns the MTU of the interface.
+pub(crate) fn link_mtu(index: u32) -> Result<u32, Error> {
+    let socket = Netlink::open()?;
+    socket.send(RTM_GETLINK, NLM_F_REQUEST, index, &[])?;
+    let message = socket.recv()?;
+    match parse_link(parse_message(&message)?).and_then(|(_, link_attrs)| mtu(link_attrs)) {
+        Some(mtu) => Ok(mtu),
+        None => Err(unexpected_reply().into()),
+    }
+}
+
+/// Socket, subscribed to link notifications of all interfaces.
+pub(crate) struct LinkEvents(Netlink);
+
+impl LinkEvents {
+    pub(crate) fn open() -> io::Result<Self> {
+        let socket = Netlink::open()?;
+        socket::bind(socket.0.as_raw_fd(), &NetlinkAddr::new(0, RTMGRP_LINK))
+            .map_err(io::Error::from)?;
+        Ok(Self(socket))
+    }
+
+    /// Receives a batch of notifications, returning the last MTU of the interface `index`,
+    /// that they carry. Fails with `ENOBUFS`, if notifications were lost, because the socket
+    /// buffer overflowed.
+    pub(crate) fn recv_mtu(&self, index: u32) -> io::Result<Option<u32>> {
+        Ok(last_mtu(&self.0.recv()?, index))
+    }
+}
+
+impl AsFd for LinkEvents {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0 .0.as_fd()
+    }
+}
+
+/// Removes the interface, like `ip link del`, even if its device is attached elsewhere.
+pub(crate) fn delete_link(index: u32) -> Result<(), Error> {
+    let socket = Netlink::open()?;
+    socket.send(RTM_DELLINK, NLM_F_REQUEST | NLM_F_ACK, index, &[])?;
+    socket
+        .recv_ack()
+        .context(|| format!("RTM_DELLINK({index})"))
+}
+
+/// Adds or removes a route to `dst` through the interface, without a gateway, like
+/// `ip route add <dst> dev <name>`. Existing and missing routes are not errors.
+pub(crate) fn set_onlink_route(index: u32, dst: IpNet, add: bool) -> Result<(), Error> {
+    let (header, attrs) = route_request(index, dst);
+    let socket = Netlink::open()?;
+    let (kind, flags) = match add {
+        true => (RTM_NEWROUTE, NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE),
+        false => (RTM_DELROUTE, NLM_F_REQUEST | NLM_F_ACK),
+    };
+    socket.send_message(kind, flags, &header, &attrs)?;
+    match socket.recv_ack() {
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EEXIST | libc::ESRCH)) => Ok(()),
+        result => result.context(|| {
+            let kind = if add { "RTM_NEWROUTE" } else { "RTM_DELROUTE" };
+            format!("{kind}({index}, {dst})")
+        }),
+    }
+}
+
+/// Returns `rtmsg` and attributes of an on-link route request.
+fn route_request(index: u32, dst: IpNet) -> (Vec<u8>, Vec<u8>) {
+    let (family, dst_bytes) = match dst.network() {
+        IpAddr::V4(addr) => (libc::AF_INET, addr.octets().to_vec()),
+        IpAddr::V6(addr) => (libc::AF_INET6, addr.octets().to_vec()),
+    };
+    // rtmsg: family, destination and source prefixes, TOS, table, protocol, scope, type and
+    // flags
+    let mut header = vec![family as u8, dst.prefix_len(), 0, 0];
+    header.extend_from_slice(&[RT_TABLE_MAIN, RTPROT_BOOT, RT_SCOPE_LINK, RTN_UNICAST]);
+    header.extend_from_slice(&[0; 4]);
+    let mut attrs = vec![];
+    push_attr(&mut attrs, RTA_DST, &dst_bytes);
+    push_attr(&mut attrs, RTA_OIF, &index.to_ne_bytes());
+    (header, attrs)
+}
+
+/// Routes of [`set_onlink_route`] through the interface. Routes of other protocols, like the
+/// ones of address prefixes, that the kernel adds, are skipped.
+pub(crate) fn onlink_routes(index: u32) -> Result<Vec<IpNet>, Error> {
+    let socket = Netlink::open()?;
+    let header = [libc::AF_UNSPEC as u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut routes = vec![];
+    for message in socket.dump(RTM_GETROUTE, &header)? {
+        match parse_route(parse_message(&message)?) {
+            Some((route_index, route)) if route_index == index => routes.push(route),
+            _ => {}
+        }
+    }
+    Ok(routes)
+}
+
+/// Returns the output interface and the destination of an on-link route, that is added like
+/// by [`set_onlink_route`].
+fn parse_route((kind, payload): (u16, &[u8])) -> Option<(u32, IpNet)> {
+    if kind != RTM_NEWROUTE || payload.len() < RTMSG_LEN {
+        return None;
+    }
+    let (family, prefix_len) = (libc::c_int::from(payload[0]), payload[1]);
+    let mut table = u32::from(payload[4]);
+    if payload[5..8] != [RTPROT_BOOT, RT_SCOPE_LINK, RTN_UNICAST] {
+        return None;
+    }
+    let (mut index, mut dst) = (None, None);
+    for (kind, value) in attrs(&payload[RTMSG_LEN..]) {
+        match (kind, value) {
+            (RTA_DST, _) => dst = Some(value),
+            (RTA_OIF, &[a, b, c, d]) => index = Some(u32::from_ne_bytes([a, b, c, d])),
+            (RTA_TABLE, &[a, b, c, d]) => table = u32::from_ne_bytes([a, b, c, d]),
+            (RTA_GATEWAY, _) => return None,
+            _ => {}
+        }
+    }
+    if table != u32::from(RT_TABLE_MAIN) {
+        return None;
+    }
+    // Default routes have no destination
+    let addr = match (family, dst) {
+        (libc::AF_INET, Some(&[a, b, c, d])) => IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+        (libc::AF_INET, None) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        (libc::AF_INET6, Some(value)) => {
+            IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(value).ok()?))
+        }
+        (libc::AF_INET6, None) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        _ => return None,
+    };
+    Some((index?, IpNet::new(addr, prefix_len).ok()?))
+}
+
+/// Adds an address with its label and flags, like `ip address replace`: an existing address
+/// is updated.
+pub(crate) fn add_address(index: u32, address: &Address) -> Result<(), Error> {
+    let (header, attrs) = address_request(index, address);
+    let socket = Netlink::open()?;
+    let flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_REPLACE;
+    socket.send_message(RTM_NEWADDR, flags, &header, &attrs)?;
+    socket
+        .recv_ack()
+        .context(|| format!("RTM_NEWADDR({index}, {})", address.net))
+}
+
+pub(crate) fn remove_address(index: u32, net: IpNet) -> Result<(), Error> {
+    let (header, attrs) = address_request(index, &Address::new(net));
+    let socket = Netlink::open()?;
+    socket.send_message(RTM_DELADDR, NLM_F_REQUEST | NLM_F_ACK, &header, &attrs)?;
+    socket
+        .recv_ack()
+        .context(|| format!("RTM_DELADDR({index}, {net})"))
+}
+
+/// Lists addresses of the interface with their labels and flags.
+pub(crate) fn addresses(index: u32) -> Result<Vec<Address>, Error> {
+    let socket = Netlink::open()?;
+    let header = [libc::AF_UNSPEC as u8, 0, 0, 0, 0, 0, 0, 0];
+    let mut addresses = vec![];
+    for message in socket.dump(RTM_GETADDR, &header)? {
+        match parse_address(parse_message(&message)?) {
+            Some((address_index, address)) if address_index == index => addresses.push(address),
+            _ => {}
+        }
+    }
+    Ok(addresses)
+}
+
+/// Returns `ifaddrmsg` and attributes of an address request.
+fn address_request(index: u32, address: &Address) -> (Vec<u8>, Vec<u8>) {
+    let (family, local) = match address.net.addr() {
+        IpAddr::V4(addr) => (libc::AF_INET, addr.octets().to_vec()),
+        IpAddr::V6(addr) => (libc::AF_INET6, addr.octets().to_vec()),
+    };
+    let mut flags = 0;
+    if address.flags.no_prefix_route {
+        flags |= IFA_F_NOPREFIXROUTE;
+    }
+    if address.net.addr().is_ipv6() {
+        if address.flags.no_dad {
+            flags |= IFA_F_NODAD;
+        }
+        if address.flags.temporary {
+            flags |= IFA_F_TEMPORARY;
+        }
+    }
+    // ifaddrmsg: family, prefix length, flags, that fit into 8 bits, scope and index
+    let mut header = vec![family as u8, address.net.prefix_len(), flags as u8, 0];
+    header.extend_from_slice(&index.to_ne_bytes());
+
+    let mut attrs = vec![];
+    push_attr(&mut attrs, IFA_LOCAL, &local);
+    push_attr(&mut attrs, IFA_ADDRESS, &local);
+    if let (Some(label), IpAddr::V4(_)) = (&address.label, address.net.addr()) {
+        push_attr(&mut attrs, IFA_LABEL, &nul_terminated(label));
+    }
+    push_attr(&mut attrs, IFA_FLAGS, &flags.to_ne_bytes());
+    if address.preferred_lifetime.is_some() || address.valid_lifetime.is_some() {
+        // Kernel rejects preferred lifetimes, that are longer than valid ones
+        let valid = address
+            .valid_lifetime
+            .map_or(INFINITY_LIFE_TIME, lifetime_secs);
+        let preferred = address.preferred_lifetime.map_or(valid, lifetime_secs);
+        // ifa_cacheinfo: preferred and valid lifetimes, creation and update timestamps
+        let mut cacheinfo = [0u8; 16];
+        cacheinfo[..4].copy_from_slice(&preferred.to_ne_bytes());
+        cacheinfo[4..8].copy_from_slice(&valid.to_ne_bytes());
+        push_attr(&mut attrs, IFA_CACHEINFO, &cacheinfo);
+    }
+    (header, attrs)
+}
+
+/// Lifetime in seconds, below the infinite one.
+fn lifetime_secs(lifetime: Duration) -> u32 {
+    lifetime.as_secs().min(u64::from(INFINITY_LIFE_TIME - 1)) as u32
+}
+
+fn lifetime(secs: u32) -> Option<Duration> {
+    (secs != INFINITY_LIFE_TIME).then(|| Duration::from_secs(secs.into()))
+}
+
+/// Returns the index and the address of an address message. Local address is taken over the
+/// peer one of point-to-point interfaces.
+fn parse_address((kind, payload): (u16, &[u8])) -> Option<(u32, Address)> {
+    if kind != RTM_NEWADDR || payload.len() < IFADDRMSG_LEN {
+        return None;
+    }
+    let family = libc::c_int::from(payload[0]);
+    let prefix_len = payload[1];
+    let index = u32::from_ne_bytes([payload[4], payload[5], payload[6], payload[7]]);
+    let (mut local, mut address, mut label) = (None, None, None);
+    let (mut preferred_lifetime, mut valid_lifetime) = (None, None);
+    let mut flags = u32::from(payload[2]);
+    for (kind, value) in attrs(&payload[IFADDRMSG_LEN..]) {
+        match (kind, value) {
+            (IFA_LOCAL, _) => local = Some(value),
+            (IFA_ADDRESS, _) => address = Some(value),
+            (IFA_LABEL, _) => {
+                let name = value.split(|b| *b == 0).next().unwrap_or_default();
+                label = Some(String::from_utf8_lossy(name).into_owned());
+            }
+            (IFA_FLAGS, &[a, b, c, d]) => flags = u32::from_ne_bytes([a, b, c, d]),
+            (IFA_CACHEINFO, _) if value.len() >= 8 => {
+                let secs = |i: usize| {
+                    u32::from_ne_bytes([value[i], value[i + 1], value[i + 2], value[i + 3]])
+                };
+                preferred_lifetime = lifetime(secs(0));
+                valid_lifetime = lifetime(secs(4));
+            }
+            _ => {}
+        }
+    }
+
+    let addr = match (family, local.or(address)?) {
+        (libc::AF_INET, &[a, b, c, d]) => IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+        (libc::AF_INET6, bytes) => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => return None,
+    };
+    let address = Address {
+        net: IpNet::new(addr, prefix_len).ok()?,
+        label,
+        flags: AddressFlags {
+            no_prefix_route: flags & IFA_F_NOPREFIXROUTE != 0,
+            secondary: addr.is_ipv4() && flags & IFA_F_SECONDARY != 0,
+            no_dad: flags & IFA_F_NODAD != 0,
+            temporary: addr.is_ipv6() && flags & IFA_F_TEMPORARY != 0,
+            deprecated: flags & IFA_F_DEPRECATED != 0,
+        },
+        preferred_lifetime,
+        valid_lifetime,
+    };
+    Some((index, address))
+}
+
+struct Netlink(OwnedFd);
+
+impl Netlink {
+    fn open() -> io::Result<Self> {
+        sys::socket(
+            AddressFamily::Netlink,
+            SockType::Raw,
+            SockFlag::SOCK_CLOEXEC,
+            Some(SockProtocol::NetlinkRoute),
+        )
+        .map(Self)
+    }
+
+    /// Sends a link request with `ifinfomsg` for the interface `index`.
+    fn send(&self, kind: u16, flags: u16, index: u32, attrs: &[u8]) -> io::Result<()> {
+        // ifinfomsg: family, padding and type, index, flags and change mask
+        let mut header = Vec::with_capacity(IFINFOMSG_LEN);
+        header.extend_from_slice(&[libc::AF_UNSPEC as u8, 0, 0, 0]);
+        header.extend_from_slice(&(index as i32).to_ne_bytes());
+        header.extend_from_slice(&[0; 8]);
+        self.send_message(kind, flags, &header, attrs)
+    }
+
+    /// Sends a request with a family-specific header, that is followed by attributes.
+    fn send_message(&self, kind: u16, flags: u16, header: &[u8], attrs: &[u8]) -> io::Result<()> {
+        let len = NLMSG_HDRLEN + header.len() + attrs.len();
+        let mut message = Vec::with_capacity(len);
+        message.extend_from_slice(&(len as u32).to_ne_bytes());
+        message.extend_from_slice(&kind.to_ne_bytes());
+        message.extend_from_slice(&flags.to_ne_bytes());
+        // Sequence number and port ID, assigned by the kernel
+        message.extend_from_slice(&[0; 8]);
+        message.extend_from_slice(header);
+        message.extend_from_slice(attrs);
+
+        socket::send(self.0.as_raw_fd(), &message, MsgFlags::empty()).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    fn recv(&self) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; 32 * 1024];
+        let n = socket::recv(self.0.as_raw_fd(), &mut buf, MsgFlags::empty())
+            .map_err(io::Error::from)?;
+        buf.truncate(n);
+        Ok(buf)
+    }
+
+    /// Sends a dump request and collects the messages of all reply batches.
+    fn dump(&self, kind: u16, header: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+        self.send_message(kind, NLM_F_REQUEST | NLM_F_DUMP, header, &[])?;
+        let mut messages = vec![];
+        loop {
+            let batch = self.recv()?;
+            let mut rest = &batch[..];
+            while !rest.is_empty() {
+                let (kind, payload) = parse_message(rest)?;
+                if kind == NLMSG_DONE {
+                    return Ok(messages);
+                }
+                let len = NLMSG_HDRLEN + payload.len();
+                messages.push(rest[..len].to_vec());
+                rest = &rest[align(len).min(rest.len())..];
+            }
+        }
+    }
+
+    fn recv_ack(&self) -> io::Result<()> {
+        match parse_message(&self.recv()?)? {
+            (NLMSG_ERROR, _) => Ok(()),
+            _ => Err(unexpected_reply()),
+        }
+    }
+}
+
+fn unexpected_reply() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "unexpected netlink reply")
+}
+
+/// Returns type and payload of the first message. `NLMSG_ERROR` replies with a non-zero code
+/// are returned as errors, and acknowledgements have code zero.
+fn parse_message(message: &[u8]) -> io::Result<(u16, &[u8])> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "truncated netlink reply");
+    if message.len() < NLMSG_HDRLEN {
+        return Err(invalid());
+    }
+    let len = u32::from_ne_bytes([message[0], message[1], message[2], message[3]]) as usize;
+    let kind = u16::from_ne_bytes([message[4], message[5]]);
+    if len < NLMSG_HDRLEN || len > message.len() {
+        return Err(invalid());
+    }
+    let payload = &message[NLMSG_HDRLEN..len];
+    if kind == NLMSG_ERROR && payload.len() >= 4 {
+        let code = i32::from_ne_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        if code != 0 {
+            return Err(io::Error::from_raw_os_error(-code));
+        }
+    }
+    Ok((kind, payload))
+}
+
+/// Returns the index and attributes of a link message.
+fn parse_link((kind, payload): (u16, &[u8])) -> Option<(u32, &[u8])> {
+    if kind != RTM_NEWLINK || payload.len() < IFINFOMSG_LEN {
+        return None;
+    }
+    let index = i32::from_ne_bytes([payload[4], payload[5], payload[6], payload[7]]);
+    Some((index as u32, &payload[IFINFOMSG_LEN..]))
+}
+
+fn mtu(link_attrs: &[u8]) -> Option<u32> {
+    attrs(link_attrs).find_map(|(kind, value)| match (kind, value) {
+        (IFLA_MTU, &[a, b, c, d]) => Some(u32::from_ne_bytes([a, b, c, d])),
+        _ => None,
+    })
+}
+
+/// Returns the MTU of the interface `index` from the last of its link messages in a batch.
+fn last_mtu(mut batch: &[u8], index: u32) -> Option<u32> {
+    let mut last = None;
+    while let Ok(message @ (_, payload)) = parse_message(batch) {
+        if let Some(mtu) = parse_link(message)
+            .filter(|(link, _)| *link == index)
+            .and_then(|(_, link_attrs)| mtu(link_attrs))
+        {
+            last = Some(mtu);
+        }
+        let len = NLMSG_HDRLEN + payload.len();
+        batch = &batch[align(len).min(batch.len())..];
+    }
+    last
+}
+
+/// Iterates over netlink attributes, clearing flags of their types.
+fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < RTA_HDRLEN {
+            return None;
+        }
+        let len = usize::from(u16::from_ne_bytes([buf[0], buf[1]]));
+        let kind = u16::from_ne_bytes([buf[2], buf[3]]) & NLA_TYPE_MASK;
+        if len < RTA_HDRLEN || len > buf.len() {
+            return None;
+        }
+        let value = &buf[RTA_HDRLEN..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((kind, value))
+    })
+}
+
+fn push_attr(buf: &mut Vec<u8>, kind: u16, value: &[u8]) {
+    let len = RTA_HDRLEN + value.len();
+    buf.extend_from_slice(&(len as u16).to_ne_bytes());
+    buf.extend_from_slice(&kind.to_ne_bytes());
+    buf.extend_from_slice(value);
+    buf.resize(buf.len() + align(len) - len, 0);
+}
+
+fn nul_terminated(s: &str) -> Vec<u8> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+fn align(len: usize) -> usize {
+    (len + size_of::<u32>() - 1) & !(size_of::<u32>() - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link_message(index: i32, mtu: u32) -> Vec<u8> {
+        let mut attrs = vec![];
+        push_attr(&mut attrs, IFLA_MTU, &mtu.to_ne_bytes());
+        let len = NLMSG_HDRLEN + IFINFOMSG_LEN + attrs.len();
+        let mut message = vec![];
+        message.extend_from_slice(&(len as u32).to_ne_bytes());
+        message.extend_from_slice(&RTM_NEWLINK.to_ne_bytes());
+        message.extend_from_slice(&[0; 10]);
+        message.extend_from_slice(&[0; 4]);
+        message.extend_from_slice(&index.to_ne_bytes());
+        message.extend_from_slice(&[0; 8]);
+        message.extend_from_slice(&attrs);
+        message
+    }
+
+    #[test]
+    fn last_mtu_of_the_link_is_taken() {
+        let batch = [
+            link_message(3, 1400),
+            link_message(4, 9000),
+            link_message(3, 1280),
+        ]
+        .concat();
+        assert_eq!(last_mtu(&batch, 3), Some(1280));
+        assert_eq!(last_mtu(&batch, 4), Some(9000));
+        assert_eq!(last_mtu(&batch, 5), None);
+        assert_eq!(last_mtu(&batch[..10], 3), None);
+    }
+
+    #[test]
+    fn address_request_roundtrip() {
+        let address = Address::new("10.1.0.2/24".parse().unwrap())
+            .with_label("tun0:1")
+            .with_flags(AddressFlags {
+                no_prefix_route: true,
+                ..Default::default()
+            });
+        let (header, attrs) = address_request(7, &address);
+        let payload = [header, attrs].concat();
+        assert_eq!(parse_address((RTM_NEWADDR, &payload)), Some((7, address)));
+
+        // Labels are not sent for IPv6
+        let address = Address::new("fd00::2/64".parse().unwrap()).with_label("tun0:1");
+        let (header, attrs) = address_request(7, &address);
+        let (_, parsed) = parse_address((RTM_NEWADDR, &[header, attrs].concat())).unwrap();
+        assert_eq!(parsed.label, None);
+    }
+
+    #[test]
+    fn route_request_roundtrip() {
+        let dst = "10.8.0.0/16".parse().unwrap();
+        let (header, attrs) = route_request(7, dst);
+        let mut payload = [header, attrs].concat();
+        assert_eq!(parse_route((RTM_NEWROUTE, &payload)), Some((7, dst)));
+
+        // Routes of address prefixes are added by the kernel protocol
+        payload[5] = 2;
+        assert_eq!(parse_route((RTM_NEWROUTE, &payload)), None);
+    }
+
+    #[test]
+    fn lifetimes_roundtrip() {
+        let address = Address::new("fd00::3/64".parse().unwrap())
+            .with_flags(AddressFlags {
+                no_dad: true,
+                temporary: true,
+                ..Default::default()
+            })
+            .with_lifetimes(Duration::from_secs(3600), Duration::from_secs(7200));
+        let (header, attrs) = address_request(7, &address);
+        let payload = [header, attrs].concat();
+        assert_eq!(parse_address((RTM_NEWADDR, &payload)), Some((7, address)));
+
+        // Missing preferred lifetime follows the valid one
+        let mut address = Address::new("fd00::4/64".parse().unwrap());
+        address.valid_lifetime = Some(Duration::from_secs(60));
+        let (header, attrs) = address_request(7, &address);
+        let (_, parsed) = parse_address((RTM_NEWADDR, &[header, attrs].concat())).unwrap();
+        assert_eq!(parsed.preferred_lifetime, Some(Duration::from_secs(60)));
+    }
+}
@@ -0,0 +1,256 @@
+//! System calls and ioctls, used by this backend, as data, so that sandboxed applications can
+//! generate seccomp filters programmatically.
+//!
+//! The profile is kept together with the implementation and covers calls, made by tunio and
+//! netconfig. Calls of the async runtime and of the standard library, like memory allocation,
+//! are not included.
+use std::mem::size_of;
+
+/// Not exported by libc, same on all architectures.
+const SIOCGIFINDEX: u64 = 0x8933;
+
+/// Stage of the interface lifetime, when a call is made. Filters, that are installed after
+/// interfaces are created and configured, only need [`Stage::Io`] calls.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stage {
+    /// Creating, configuring and restarting interfaces.
+    Setup,
+    /// Reading and writing packets, pausing queues and closing devices.
+    Io,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Syscall {
+    pub name: &'static str,
+    /// Number on the target architecture.
+    pub number: libc::c_long,
+    pub stage: Stage,
+}
+
+/// ioctl request, made on the device descriptor or on a configuration socket.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Ioctl {
+    pub name: &'static str,
+    pub request: u64,
+    pub stage: Stage,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SyscallProfile {
+    pub syscalls: Vec<Syscall>,
+    pub ioctls: Vec<Ioctl>,
+}
+
+impl SyscallProfile {
+    /// Returns calls of a single stage.
+    pub fn stage(&self, stage: Stage) -> Self {
+        Self {
+            syscalls: self
+                .syscalls
+                .iter()
+                .filter(|syscall| syscall.stage == stage)
+                .copied()
+                .collect(),
+            ioctls: self
+                .ioctls
+                .iter()
+                .filter(|ioctl| ioctl.stage == stage)
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+macro_rules! syscall {
+    ($number:ident, $stage:ident) => {
+        Syscall {
+            // Constants are named SYS_<name>
+            name: &stringify!($number)[4..],
+            number: libc::$number,
+            stage: Stage::$stage,
+        }
+    };
+}
+
+/// Returns system calls and ioctls, needed by the Linux backend with enabled features.
+// Types of request codes differ between targets
+#[allow(clippy::unnecessary_cast)]
+pub fn syscall_profile() -> SyscallProfile {
+    let mut syscalls = vec![
+        // Device node and /proc/self/status for capability checks
+        syscall!(SYS_openat, Setup),
+        syscall!(SYS_statx, Setup),
+        syscall!(SYS_read, Setup),
+        syscall!(SYS_ioctl, Setup),
+        // Configuration sockets for ioctls
+        syscall!(SYS_socket, Setup),
+        syscall!(SYS_setsockopt, Setup),
+        // Per-interface sysctls in /proc/sys/net
+        syscall!(SYS_write, Setup),
+        syscall!(SYS_read, Io),
+        syscall!(SYS_write, Io),
+        syscall!(SYS_close, Io),
+        // Paused queues wait on a condition variable
+        syscall!(SYS_futex, Io),
+        // Kick and call eventfds of vhost-net
+        syscall!(SYS_eventfd2, Setup),
+    ];
+    // Descriptors, passed by a helper, are switched between blocking modes
+    #[cfg(target_pointer_width = "64")]
+    syscalls.push(syscall!(SYS_fcntl, Setup));
+    #[cfg(target_pointer_width = "32")]
+    syscalls.push(syscall!(SYS_fcntl64, Setup));
+    // Netlink requests of tags and enumeration
+    syscalls.extend([syscall!(SYS_sendto, Setup), syscall!(SYS_recvfrom, Setup)]);
+    // Netlink sockets of netconfig
+    #[cfg(feature = "netconfig")]
+    syscalls.push(syscall!(SYS_connect, Setup));
+    #[cfg(feature = "helper")]
+    syscalls.extend([
+        syscall!(SYS_getsockopt, Setup),
+        syscall!(SYS_sendmsg, Setup),
+        syscall!(SYS_recvmsg, Setup),
+    ]);
+    #[cfg(feature = "tokio")]
+    syscalls.push(syscall!(SYS_epoll_ctl, Setup));
+    // Instance locks: lock files in the runtime directory, that keep the process ID
+    syscalls.extend([
+        syscall!(SYS_mkdirat, Setup),
+        syscall!(SYS_flock, Setup),
+        syscall!(SYS_ftruncate, Setup),
+        syscall!(SYS_lseek, Setup),
+    ]);
+    // Architectures without mkdir only have mkdirat
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm"))]
+    syscalls.push(syscall!(SYS_mkdir, Setup));
+    // Threads of the blocking pool, that async configuration calls run on
+    syscalls.extend([
+        syscall!(SYS_clone, Setup),
+        syscall!(SYS_clone3, Setup),
+        syscall!(SYS_futex, Setup),
+    ]);
+    // MTU monitor: its thread is started with a stop pair and a subscribed netlink socket,
+    // waits for link notifications and reads the MTU again, when some of them are lost
+    syscalls.extend([
+        syscall!(SYS_socketpair, Setup),
+        syscall!(SYS_bind, Setup),
+        syscall!(SYS_socketpair, Io),
+        syscall!(SYS_clone, Io),
+        syscall!(SYS_clone3, Io),
+        syscall!(SYS_bind, Io),
+        syscall!(SYS_ppoll, Io),
+        syscall!(SYS_recvfrom, Io),
+        syscall!(SYS_socket, Io),
+        syscall!(SYS_sendto, Io),
+    ]);
+    // Architectures without poll only have ppoll
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "arm"))]
+    syscalls.push(syscall!(SYS_poll, Io));
+
+    // Request codes are 32-bit. musl declares them as int, so they are converted through u32
+    // to keep the high direction bit from extending into the upper half.
+    let tun = |nr: u8, size: usize| nix::request_code_write!(b'T', nr, size) as u32 as u64;
+    let tun_read = |nr: u8, size: usize| nix::request_code_read!(b'T', nr, size) as u32 as u64;
+    let vhost = |nr: u8, size: usize| nix::request_code_write!(0xAF, nr, size) as u32 as u64;
+    let int = size_of::<libc::c_int>();
+    let uint = size_of::<libc::c_uint>();
+    let ioctl = |name, request: u64| Ioctl {
+        name,
+        request,
+        stage: Stage::Setup,
+    };
+    let ioctls = vec![
+        ioctl("TUNSETIFF", tun(202, int)),
+        ioctl("TUNSETPERSIST", tun(203, int)),
+        ioctl("TUNGETIFF", tun_read(210, uint)),
+        ioctl("TUNSETVNETHDRSZ", tun(216, int)),
+        // Only with detailed diagnostics
+        ioctl("TUNGETFEATURES", tun_read(207, uint)),
+        ioctl("SIOCGIFINDEX", SIOCGIFINDEX),
+        ioctl("SIOCGIFFLAGS", libc::SIOCGIFFLAGS as u32 as u64),
+        ioctl("SIOCSIFFLAGS", libc::SIOCSIFFLAGS as u32 as u64),
+        ioctl("SIOCGIFMTU", libc::SIOCGIFMTU as u32 as u64),
+        ioctl("SIOCSIFMTU", libc::SIOCSIFMTU as u32 as u64),
+        ioctl("SIOCSIFDSTADDR", libc::SIOCSIFDSTADDR as u32 as u64),
+        ioctl("SIOCADDMULTI", libc::SIOCADDMULTI as u32 as u64),
+        ioctl("SIOCDELMULTI", libc::SIOCDELMULTI as u32 as u64),
+        // vhost-net, with the sizes of u64, vhost_memory, vhost_vring_state,
+        // vhost_vring_addr and vhost_vring_file
+        ioctl(
+            "VHOST_GET_FEATURES",
+            nix::request_code_read!(0xAF, 0x00, 8) as u32 as u64,
+        ),
+        ioctl("VHOST_SET_FEATURES", vhost(0x00, 8)),
+        ioctl(
+            "VHOST_SET_OWNER",
+            nix::request_code_none!(0xAF, 0x01) as u32 as u64,
+        ),
+        ioctl("VHOST_SET_MEM_TABLE", vhost(0x03, 8)),
+        ioctl("VHOST_SET_VRING_NUM", vhost(0x10, 8)),
+        ioctl("VHOST_SET_VRING_ADDR", vhost(0x11, 40)),
+        ioctl("VHOST_SET_VRING_BASE", vhost(0x12, 8)),
+        ioctl("VHOST_SET_VRING_KICK", vhost(0x20, 8)),
+        ioctl("VHOST_SET_VRING_CALL", vhost(0x21, 8)),
+        ioctl("VHOST_NET_SET_BACKEND", vhost(0x30, 8)),
+    ];
+
+    SyscallProfile { syscalls, ioctls }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls, that modules of this backend make after the standard library, by stage.
+    const CALLS: &[(&str, Stage, &[&str])] = &[
+        ("queue", Stage::Setup, &["openat", "read", "ioctl"]),
+        (
+            "netlink",
+            Stage::Setup,
+            &["socket", "bind", "sendto", "recvfrom"],
+        ),
+        (
+            "mtu",
+            Stage::Setup,
+            &["socketpair", "socket", "bind", "clone3"],
+        ),
+        (
+            "mtu",
+            Stage::Io,
+            &["ppoll", "recvfrom", "socket", "sendto", "close", "futex"],
+        ),
+        ("interface", Stage::Io, &["read", "write", "futex"]),
+        (
+            "instance",
+            Stage::Setup,
+            &["mkdirat", "openat", "flock", "ftruncate", "lseek", "write"],
+        ),
+    ];
+
+    #[test]
+    fn calls_of_backend_are_listed() {
+        let profile = syscall_profile();
+        for (module, stage, names) in CALLS {
+            let listed = profile.stage(*stage);
+            for name in *names {
+                assert!(
+                    listed.syscalls.iter().any(|syscall| syscall.name == *name),
+                    "{name} of {module} is not listed for {stage:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn calls_are_listed_once_per_stage() {
+        let syscalls = syscall_profile().syscalls;
+        for (i, syscall) in syscalls.iter().enumerate() {
+            assert!(
+                !syscalls[..i].contains(syscall),
+                "{} is listed twice for {:?}",
+                syscall.name,
+                syscall.stage
+            );
+        }
+    }
+}
@@ -1,46 +1,321 @@
+use crate::sys::{self, IfReq};
 use crate::Error;
-use libc::{IFF_NO_PI, IFF_TAP, IFF_TUN};
-use netconfig::sys::posix::ifreq::ifreq;
+use crate::VNET_HEADER_LEN;
+use libc::{IFF_NO_PI, IFF_TAP, IFF_TUN, IFF_VNET_HDR};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
 use std::os::unix::fs::OpenOptionsExt;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
 use tunio_core::config::Layer;
+use tunio_core::diagnostics::Diagnostics;
 
-mod ioctls {
-    nix::ioctl_write_int!(tunsetiff, b'T', 202);
-    nix::ioctl_write_int!(tunsetpersist, b'T', 203);
-    nix::ioctl_write_int!(tunsetowner, b'T', 204);
-    nix::ioctl_write_int!(tunsetgroup, b'T', 206);
-}
+const DEVICE_NODE: &str = "/dev/net/tun";
+/// Bit of `CAP_NET_ADMIN` in capability sets, see `capabilities(7)`.
+const CAP_NET_ADMIN: u32 = 12;
 
 pub(crate) struct Device {
     pub device: fs::File,
     pub name: String,
 }
 
-pub(crate) fn create_device(name: &str, layer: Layer, blocking: bool) -> Result<Device, Error> {
+pub(crate) fn create_device(
+    name: &str,
+    layer: Layer,
+    blocking: bool,
+    vnet_header: bool,
+    diagnostics: &mut Diagnostics,
+) -> Result<Device, Error> {
+    let tun_device = open_device(blocking)?;
+    let mode = match blocking {
+        true => "blocking",
+        false => "non-blocking",
+    };
+    diagnostics.step(format!("open {DEVICE_NODE}"), mode);
+    let requested = name;
+    let name = attach_device(&tun_device, name, layer, vnet_header)?;
+    let vnet = match vnet_header {
+        true => " IFF_VNET_HDR",
+        false => "",
+    };
+    diagnostics.step(
+        format!("TUNSETIFF \"{requested}\" {layer:?}{vnet}"),
+        format!("attached to {name}"),
+    );
+
+    Ok(Device {
+        device: tun_device,
+        name,
+    })
+}
+
+pub(crate) fn open_device(blocking: bool) -> Result<fs::File, Error> {
     let mut open_opts = fs::OpenOptions::new();
     open_opts.read(true).write(true);
     if !blocking {
         open_opts.custom_flags(libc::O_NONBLOCK);
     }
-    let tun_device = open_opts.open("/dev/net/tun")?;
+    open_opts.open(DEVICE_NODE).map_err(|err| {
+        let path = DEVICE_NODE.to_string();
+        match err.raw_os_error() {
+            // Node exists without the driver, if the module is not loaded
+            Some(libc::ENOENT | libc::ENODEV | libc::ENXIO) => Error::DeviceNodeMissing {
+                path,
+                hint: "load the driver with `modprobe tun`, or pass the device into the container"
+                    .to_string(),
+            },
+            Some(libc::EACCES | libc::EPERM) => Error::DeviceNodeInaccessible {
+                path,
+                hint: "allow read and write access to the node, usually with mode 0666".to_string(),
+            },
+            _ => Error::call(format!("open({DEVICE_NODE})"), err),
+        }
+    })
+}
+
+/// Attaches opened device to the interface, creating it if necessary. Returns actual interface name.
+///
+/// In vnet-header mode, packets start with a header of [`VNET_HEADER_LEN`] bytes.
+pub(crate) fn attach_device(
+    tun_device: &fs::File,
+    name: &str,
+    layer: Layer,
+    vnet_header: bool,
+) -> Result<String, Error> {
+    let mut req = IfReq::new(name)?;
+    req.set_flags(match vnet_header {
+        // IFF_VNET_HDR fits into the short flags of ifreq
+        true => layer_flags(layer) | IFF_VNET_HDR as libc::c_short,
+        false => layer_flags(layer),
+    });
+
+    sys::tun_set_iff(tun_device.as_raw_fd(), &mut req).map_err(|err| {
+        // Attaching to a persistent device of the same owner needs no capability
+        match err == nix::errno::Errno::EPERM && has_capability(CAP_NET_ADMIN) == Some(false) {
+            true => Error::MissingCapability {
+                capability: "CAP_NET_ADMIN".to_string(),
+                hint: "run as root, grant it with `setcap cap_net_admin+ep` to the executable, or use a privileged helper".to_string(),
+            },
+            false => Error::call(format!("TUNSETIFF({name}, {layer:?})"), err),
+        }
+    })?;
+    if vnet_header {
+        set_vnet_header_len(tun_device.as_raw_fd(), VNET_HEADER_LEN)?;
+    }
+
+    // Name can change due to formatting
+    req.name()
+}
 
-    let mut init_flags = match layer {
+/// Records features of the driver and the kernel release. Failures are recorded instead of
+/// values, as diagnostics must not fail creation.
+pub(crate) fn collect_details(device: RawFd, diagnostics: &mut Diagnostics) {
+    let features = sys::tun_get_features(device).map(|features| format!("{features:#06x}"));
+    diagnostics.property("tun features", or_error(features));
+    let release =
+        fs::read_to_string("/proc/sys/kernel/osrelease").map(|release| release.trim().to_string());
+    diagnostics.property("kernel", or_error(release));
+}
+
+fn or_error<T: std::fmt::Display, E: std::fmt::Display>(result: Result<T, E>) -> String {
+    match result {
+        Ok(value) => value.to_string(),
+        Err(err) => format!("error: {err}"),
+    }
+}
+
+/// Returns name and layer of the interface, the device is attached to, and whether it is in
+/// vnet-header mode.
+pub(crate) fn device_info(fd: RawFd) -> Result<(String, Layer, bool), Error> {
+    let mut req = IfReq::new("")?;
+    sys::tun_get_iff(fd, &mut req).map_err(io::Error::from)?;
+    Ok((
+        req.name()?,
+        flags_layer(req.flags()),
+        flags_vnet_header(req.flags()),
+    ))
+}
+
+/// Interface flags of `ifreq` for a new device. Flags are a `short` in `ifreq`, while libc
+/// declares them as `int`.
+fn layer_flags(layer: Layer) -> libc::c_short {
+    let flags = match layer {
         Layer::L2 => IFF_TAP,
         Layer::L3 => IFF_TUN,
-    };
-    init_flags |= IFF_NO_PI;
+    } | IFF_NO_PI;
+    // All TUN flags fit into 16 bits
+    flags as libc::c_short
+}
 
-    let mut req = ifreq::new(name);
-    req.ifr_ifru.ifru_flags = init_flags as _;
+/// Layer of a device by its `ifreq` flags. Flags are reinterpreted as unsigned, so that the
+/// high bit does not extend into `int` bits.
+fn flags_layer(flags: libc::c_short) -> Layer {
+    match libc::c_int::from(flags as u16) & IFF_TAP {
+        0 => Layer::L3,
+        _ => Layer::L2,
+    }
+}
 
-    unsafe { ioctls::tunsetiff(tun_device.as_raw_fd(), &req as *const _ as _) }.unwrap();
+fn flags_vnet_header(flags: libc::c_short) -> bool {
+    libc::c_int::from(flags as u16) & IFF_VNET_HDR != 0
+}
 
-    // Name can change due to formatting
-    Ok(Device {
-        device: tun_device,
-        name: String::try_from(req.ifr_ifrn)
-            .map_err(|e| Error::InterfaceNameError(format!("{e:?}")))?,
-    })
+/// Sets the size of the header, that packets of a device in vnet-header mode start with.
+pub(crate) fn set_vnet_header_len(fd: RawFd, len: usize) -> Result<(), Error> {
+    // Header sizes are small constants
+    let len = len as libc::c_int;
+    sys::tun_set_vnet_header_len(fd, len).map_err(io::Error::from)?;
+    Ok(())
+}
+
+pub(crate) fn set_blocking(fd: RawFd, blocking: bool) -> Result<(), Error> {
+    let flags = fcntl(fd, FcntlArg::F_GETFL).map_err(io::Error::from)?;
+    let mut flags = OFlag::from_bits_truncate(flags);
+    flags.set(OFlag::O_NONBLOCK, !blocking);
+    fcntl(fd, FcntlArg::F_SETFL(flags)).map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// Returns `true`, if the capability is in the effective set of the process, or `None`, if
+/// capabilities are unknown.
+fn has_capability(capability: u32) -> Option<bool> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let effective = status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))?;
+    let effective = u64::from_str_radix(effective.trim(), 16).ok()?;
+    Some(effective & 1 << capability != 0)
+}
+
+/// Socket for interface ioctls.
+fn config_socket() -> io::Result<OwnedFd> {
+    use nix::sys::socket::{AddressFamily, SockFlag, SockType};
+
+    sys::socket(
+        AddressFamily::Inet,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+}
+
+/// Short `ifreq` flags of the interface, like `IFF_UP`.
+pub(crate) fn flags(name: &str) -> Result<libc::c_int, Error> {
+    let socket = config_socket()?;
+    let mut req = IfReq::new(name)?;
+    sys::get_if_flags(socket.as_raw_fd(), &mut req).map_err(io::Error::from)?;
+    Ok(libc::c_int::from(req.flags() as u16))
+}
+
+/// Sets or clears one of the short `ifreq` flags of the interface, keeping the others.
+pub(crate) fn set_flag(name: &str, flag: libc::c_int, enabled: bool) -> Result<(), Error> {
+    let socket = config_socket()?;
+    let mut req = IfReq::new(name)?;
+    sys::get_if_flags(socket.as_raw_fd(), &mut req).map_err(io::Error::from)?;
+    let flag = flag as libc::c_short;
+    req.set_flags(match enabled {
+        true => req.flags() | flag,
+        false => req.flags() & !flag,
+    });
+    sys::set_if_flags(socket.as_raw_fd(), &req).map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// Brings the interface up or down with ioctls on a configuration socket, without netconfig.
+#[cfg(not(feature = "netconfig"))]
+pub(crate) fn set_up(name: &str, up: bool) -> Result<(), Error> {
+    set_flag(name, libc::IFF_UP, up)
+}
+
+/// Sets the IPv4 peer of the point-to-point interface.
+pub(crate) fn set_dstaddr(name: &str, addr: Ipv4Addr) -> Result<(), Error> {
+    let socket = config_socket()?;
+    let mut req = IfReq::new(name)?;
+    req.set_dstaddr(addr);
+    sys::set_if_dstaddr(socket.as_raw_fd(), &req).map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// Joins or leaves a link-layer multicast group of the interface.
+pub(crate) fn set_multicast(name: &str, mac: [u8; 6], join: bool) -> Result<(), Error> {
+    let socket = config_socket()?;
+    let mut req = IfReq::new(name)?;
+    req.set_hwaddr(mac);
+    match join {
+        true => sys::add_multicast(socket.as_raw_fd(), &req),
+        false => sys::del_multicast(socket.as_raw_fd(), &req),
+    }
+    .map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// Persistent interface is not destroyed, when the last descriptor, attached to it, is closed.
+pub(crate) fn set_persist(fd: RawFd, persist: bool) -> Result<(), Error> {
+    sys::tun_set_persist(fd, persist).map_err(io::Error::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netconfig::sys::posix::ifreq::ifreq;
+    use std::mem::size_of;
+
+    #[test]
+    fn request_codes_match_kernel() {
+        // Direction bits differ on these architectures, see asm/ioctl.h
+        let write = match cfg!(any(
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "powerpc",
+            target_arch = "powerpc64",
+            target_arch = "sparc64"
+        )) {
+            true => 0x8000_0000,
+            false => 0x4000_0000,
+        };
+        let read = match write {
+            0x8000_0000 => 0x4000_0000,
+            _ => 0x8000_0000,
+        };
+        // Sizes are the ones of int and unsigned int on every target
+        let tunsetiff = nix::request_code_write!(b'T', 202, size_of::<libc::c_int>());
+        let tungetiff = nix::request_code_read!(b'T', 210, size_of::<libc::c_uint>());
+        let tunsetvnethdrsz = nix::request_code_write!(b'T', 216, size_of::<libc::c_int>());
+        assert_eq!(tunsetiff as u32, write | 0x0004_54ca);
+        assert_eq!(tungetiff as u32, read | 0x0004_54d2);
+        assert_eq!(tunsetvnethdrsz as u32, write | 0x0004_54d8);
+    }
+
+    #[test]
+    fn ifreq_covers_kernel_layout() {
+        // Kernel copies its own ifreq, whose union is the largest on 64-bit targets
+        let kernel_size = match cfg!(target_pointer_width = "64") {
+            true => 40,
+            false => 32,
+        };
+        assert!(size_of::<ifreq>() >= kernel_size);
+    }
+
+    #[test]
+    fn flags_roundtrip() {
+        assert_eq!(flags_layer(layer_flags(Layer::L2)), Layer::L2);
+        assert_eq!(flags_layer(layer_flags(Layer::L3)), Layer::L3);
+        assert_eq!(layer_flags(Layer::L3) as u16, 0x1001);
+    }
+
+    #[test]
+    fn vnet_header_flag_is_independent_of_layer() {
+        let vnet_header = IFF_VNET_HDR as libc::c_short;
+        assert!(flags_vnet_header(layer_flags(Layer::L2) | vnet_header));
+        assert!(!flags_vnet_header(layer_flags(Layer::L2)));
+        assert_eq!(flags_layer(layer_flags(Layer::L3) | vnet_header), Layer::L3);
+    }
+
+    #[test]
+    fn flags_high_bit_does_not_change_layer() {
+        let flags = layer_flags(Layer::L3) | libc::c_short::MIN;
+        assert_eq!(flags_layer(flags), Layer::L3);
+    }
 }
@@ -0,0 +1,44 @@
+//! Service notifications of systemd, sent to the socket in `NOTIFY_SOCKET`, without linking
+//! libsystemd.
+use super::sys;
+use nix::sys::socket::{sendto, AddressFamily, MsgFlags, SockFlag, SockType, UnixAddr};
+use std::env;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use tunio_core::{Error, ResultExt};
+
+/// Sends `state`, like `READY=1`, to systemd. Returns `false`, if the process is not run by
+/// systemd with `Type=notify`.
+pub fn notify(state: &str) -> Result<bool, Error> {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) if !path.is_empty() => path,
+        _ => return Ok(false),
+    };
+    // Leading `@` stands for the abstract namespace
+    let addr = match path.as_bytes() {
+        [b'@', name @ ..] => UnixAddr::new_abstract(name),
+        _ => UnixAddr::new(path.as_os_str()),
+    }
+    .map_err(io::Error::from)?;
+
+    let socket = sys::socket(
+        AddressFamily::Unix,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )?;
+    sendto(
+        socket.as_raw_fd(),
+        state.as_bytes(),
+        &addr,
+        MsgFlags::empty(),
+    )
+    .context(|| format!("sendto(NOTIFY_SOCKET={path:?})"))?;
+    Ok(true)
+}
+
+/// Notifies systemd, that the service is ready.
+pub fn notify_ready() -> Result<bool, Error> {
+    notify("READY=1")
+}
@@ -0,0 +1,25 @@
+use nix::sys::socket::{setsockopt, sockopt};
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::io::RawFd;
+use tunio_core::socket::SocketFamily;
+use tunio_core::Error;
+
+/// Binds socket to the interface with `SO_BINDTODEVICE`, so its traffic is not routed through
+/// the tunnel. Requires `CAP_NET_RAW`.
+///
+/// `SO_BINDTODEVICE` applies to both address families, `_family` is accepted for parity with
+/// other platforms.
+pub fn bind_to_interface(socket: RawFd, index: u32, _family: SocketFamily) -> Result<(), Error> {
+    #[cfg(feature = "netconfig")]
+    let name = netconfig::Interface::from_index_unchecked(index).name()?;
+    #[cfg(not(feature = "netconfig"))]
+    let name = crate::sys::index_to_name(index)?;
+    bind_to_device(socket, &name)
+}
+
+/// Binds socket to the interface with `SO_BINDTODEVICE` by interface name.
+pub fn bind_to_device(socket: RawFd, name: &str) -> Result<(), Error> {
+    setsockopt(socket, sockopt::BindToDevice, &OsString::from(name)).map_err(io::Error::from)?;
+    Ok(())
+}
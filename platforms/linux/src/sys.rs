@@ -0,0 +1,367 @@
+//! Calls, that take raw pointers or create descriptors: `ifreq` ioctls, sockets and
+//! descriptors, received over Unix sockets. Wrappers keep the invariants of the calls, so
+//! that the rest of the crate is safe code.
+#![allow(unsafe_code)]
+
+use netconfig::sys::posix::ifreq::ifreq;
+use netconfig::sys::posix::InterfaceName;
+use nix::sys::socket::{self, AddressFamily, SockFlag, SockType};
+use std::io;
+use std::net::Ipv4Addr;
+use std::os::unix::io::{FromRawFd, OwnedFd, RawFd};
+use tunio_core::Error;
+
+mod ioctls {
+    // Kernel declares TUNSETIFF with the size of int, although it takes a pointer to ifreq.
+    // Request code must not depend on the pointer width of the target. Kernel writes the
+    // actual name back, so the request is passed as mutable.
+    nix::ioctl_readwrite_bad!(
+        tunsetiff,
+        nix::request_code_write!(b'T', 202, std::mem::size_of::<libc::c_int>()),
+        netconfig::sys::posix::ifreq::ifreq
+    );
+    nix::ioctl_write_int!(tunsetpersist, b'T', 203);
+    nix::ioctl_read!(tungetfeatures, b'T', 207, libc::c_uint);
+    nix::ioctl_write_ptr!(tunsetvnethdrsz, b'T', 216, libc::c_int);
+    nix::ioctl_read_bad!(
+        tungetiff,
+        nix::request_code_read!(b'T', 210, std::mem::size_of::<libc::c_uint>()),
+        netconfig::sys::posix::ifreq::ifreq
+    );
+    // Request types differ between libc implementations, macros convert them
+    nix::ioctl_read_bad!(
+        siocgifflags,
+        libc::SIOCGIFFLAGS,
+        netconfig::sys::posix::ifreq::ifreq
+    );
+    nix::ioctl_write_ptr_bad!(
+        siocsifflags,
+        libc::SIOCSIFFLAGS,
+        netconfig::sys::posix::ifreq::ifreq
+    );
+    nix::ioctl_write_ptr_bad!(
+        siocsifdstaddr,
+        libc::SIOCSIFDSTADDR,
+        netconfig::sys::posix::ifreq::ifreq
+    );
+    nix::ioctl_write_ptr_bad!(
+        siocaddmulti,
+        libc::SIOCADDMULTI,
+        netconfig::sys::posix::ifreq::ifreq
+    );
+    nix::ioctl_write_ptr_bad!(
+        siocdelmulti,
+        libc::SIOCDELMULTI,
+        netconfig::sys::posix::ifreq::ifreq
+    );
+
+    pub(super) mod vhost {
+        use super::super::{VringAddr, VringFile, VringState};
+
+        const VHOST_VIRTIO: u8 = 0xAF;
+
+        nix::ioctl_read!(vhost_get_features, VHOST_VIRTIO, 0x00, u64);
+        nix::ioctl_write_ptr!(vhost_set_features, VHOST_VIRTIO, 0x00, u64);
+        nix::ioctl_none!(vhost_set_owner, VHOST_VIRTIO, 0x01);
+        // Argument is a vhost_memory header, that is followed by its regions
+        nix::ioctl_write_ptr!(vhost_set_mem_table, VHOST_VIRTIO, 0x03, u64);
+        nix::ioctl_write_ptr!(vhost_set_vring_num, VHOST_VIRTIO, 0x10, VringState);
+        nix::ioctl_write_ptr!(vhost_set_vring_addr, VHOST_VIRTIO, 0x11, VringAddr);
+        nix::ioctl_write_ptr!(vhost_set_vring_base, VHOST_VIRTIO, 0x12, VringState);
+        nix::ioctl_write_ptr!(vhost_set_vring_kick, VHOST_VIRTIO, 0x20, VringFile);
+        nix::ioctl_write_ptr!(vhost_set_vring_call, VHOST_VIRTIO, 0x21, VringFile);
+        nix::ioctl_write_ptr!(vhost_net_set_backend, VHOST_VIRTIO, 0x30, VringFile);
+    }
+}
+
+/// `vhost_vring_state`, that sets size or first index of a ring.
+#[repr(C)]
+pub(crate) struct VringState {
+    pub index: u32,
+    pub num: u32,
+}
+
+/// `vhost_vring_file`, that passes a descriptor for a ring.
+#[repr(C)]
+pub(crate) struct VringFile {
+    pub index: u32,
+    pub fd: RawFd,
+}
+
+/// `vhost_vring_addr`, that places a ring in memory of the process.
+#[repr(C)]
+pub(crate) struct VringAddr {
+    pub index: u32,
+    pub flags: u32,
+    pub desc_user_addr: u64,
+    pub used_user_addr: u64,
+    pub avail_user_addr: u64,
+    pub log_guest_addr: u64,
+}
+
+/// Request of interface ioctls.
+///
+/// Requests are zeroed on creation, so every member of the `ifr_ifru` union is initialized,
+/// whichever one was written last, and all of them are plain integers, so any bit pattern is
+/// valid to read.
+pub(crate) struct IfReq(ifreq);
+
+impl IfReq {
+    /// Fails, if `name` does not fit into `IFNAMSIZ` bytes with the terminating zero.
+    pub fn new(name: &str) -> Result<Self, Error> {
+        let name = InterfaceName::try_from(name)
+            .map_err(|e| Error::InterfaceNameError(format!("{e:?}")))?;
+        Ok(Self(ifreq {
+            ifr_ifrn: name,
+            ..Default::default()
+        }))
+    }
+
+    pub fn name(&self) -> Result<String, Error> {
+        String::try_from(self.0.ifr_ifrn).map_err(|e| Error::InterfaceNameError(format!("{e:?}")))
+    }
+
+    /// Interface flags. They are a `short` in `ifreq`, while libc declares them as `int`.
+    pub fn flags(&self) -> libc::c_short {
+        // SAFETY: union is initialized, and any value of it is a valid short, see above
+        unsafe { self.0.ifr_ifru.ifru_flags }
+    }
+
+    pub fn set_flags(&mut self, flags: libc::c_short) {
+        self.0.ifr_ifru.ifru_flags = flags;
+    }
+
+    /// Sets the destination address of point-to-point interfaces, as `sockaddr_in`: port and
+    /// address at the start of the data.
+    pub fn set_dstaddr(&mut self, addr: Ipv4Addr) {
+        let mut sockaddr = libc::sockaddr {
+            sa_family: libc::AF_INET as libc::sa_family_t,
+            sa_data: [0; 14],
+        };
+        for (data, byte) in sockaddr.sa_data[2..].iter_mut().zip(addr.octets()) {
+            *data = byte as libc::c_char;
+        }
+        self.0.ifr_ifru.ifru_dstaddr = sockaddr;
+    }
+
+    /// Sets a link-layer address, as multicast ioctls take it: unspecified family with the
+    /// address at the start of the data.
+    pub fn set_hwaddr(&mut self, mac: [u8; 6]) {
+        let mut addr = libc::sockaddr {
+            sa_family: libc::AF_UNSPEC as libc::sa_family_t,
+            sa_data: [0; 14],
+        };
+        for (data, byte) in addr.sa_data.iter_mut().zip(mac) {
+            *data = byte as libc::c_char;
+        }
+        self.0.ifr_ifru.ifru_hwaddr = addr;
+    }
+}
+
+/// Attaches a TUN/TAP device to the interface of `req`, creating it if necessary. Name of the
+/// request is replaced with the actual one.
+pub(crate) fn tun_set_iff(device: RawFd, req: &mut IfReq) -> nix::Result<()> {
+    // SAFETY: request lives through the call, and kernel writes at most an ifreq into it
+    unsafe { ioctls::tunsetiff(device, &mut req.0) }.map(drop)
+}
+
+/// Fills name and flags of the interface, the device is attached to.
+pub(crate) fn tun_get_iff(device: RawFd, req: &mut IfReq) -> nix::Result<()> {
+    // SAFETY: request lives through the call, and kernel writes at most an ifreq into it
+    unsafe { ioctls::tungetiff(device, &mut req.0) }.map(drop)
+}
+
+/// Returns `IFF_*` flags, that the driver supports.
+pub(crate) fn tun_get_features(device: RawFd) -> nix::Result<libc::c_uint> {
+    let mut features = 0;
+    // SAFETY: kernel writes a single unsigned int into the argument
+    unsafe { ioctls::tungetfeatures(device, &mut features) }?;
+    Ok(features)
+}
+
+pub(crate) fn tun_set_persist(device: RawFd, persist: bool) -> nix::Result<()> {
+    // SAFETY: argument is passed by value, no memory is shared with the kernel
+    unsafe { ioctls::tunsetpersist(device, persist as _) }.map(drop)
+}
+
+pub(crate) fn tun_set_vnet_header_len(device: RawFd, len: libc::c_int) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it
+    unsafe { ioctls::tunsetvnethdrsz(device, &len) }.map(drop)
+}
+
+pub(crate) fn vhost_set_owner(device: RawFd) -> nix::Result<()> {
+    // SAFETY: request has no argument
+    unsafe { ioctls::vhost::vhost_set_owner(device) }.map(drop)
+}
+
+pub(crate) fn vhost_get_features(device: RawFd) -> nix::Result<u64> {
+    let mut features = 0;
+    // SAFETY: argument lives through the call, and kernel writes a u64 into it
+    unsafe { ioctls::vhost::vhost_get_features(device, &mut features) }?;
+    Ok(features)
+}
+
+pub(crate) fn vhost_set_features(device: RawFd, features: u64) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it
+    unsafe { ioctls::vhost::vhost_set_features(device, &features) }.map(drop)
+}
+
+/// Sets the memory table of vhost, `table` being a `vhost_memory` as u64 words.
+///
+/// # Safety
+/// Regions of the table must stay mapped, while the worker runs, which accesses them.
+pub(crate) unsafe fn vhost_set_mem_table(device: RawFd, table: &[u64]) -> nix::Result<()> {
+    // SAFETY: table lives through the call, and kernel reads as many regions, as its header
+    // counts, that the caller put after it
+    unsafe { ioctls::vhost::vhost_set_mem_table(device, table.as_ptr()) }.map(drop)
+}
+
+pub(crate) fn vhost_set_vring_num(device: RawFd, state: &VringState) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it
+    unsafe { ioctls::vhost::vhost_set_vring_num(device, state) }.map(drop)
+}
+
+pub(crate) fn vhost_set_vring_base(device: RawFd, state: &VringState) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it
+    unsafe { ioctls::vhost::vhost_set_vring_base(device, state) }.map(drop)
+}
+
+/// Places a ring of vhost in memory of the process.
+///
+/// # Safety
+/// Ring must stay inside the memory table, while the worker runs, which accesses it.
+pub(crate) unsafe fn vhost_set_vring_addr(device: RawFd, addr: &VringAddr) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it; the caller keeps
+    // the ring, it points to
+    unsafe { ioctls::vhost::vhost_set_vring_addr(device, addr) }.map(drop)
+}
+
+pub(crate) fn vhost_set_vring_kick(device: RawFd, file: &VringFile) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it
+    unsafe { ioctls::vhost::vhost_set_vring_kick(device, file) }.map(drop)
+}
+
+pub(crate) fn vhost_set_vring_call(device: RawFd, file: &VringFile) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it
+    unsafe { ioctls::vhost::vhost_set_vring_call(device, file) }.map(drop)
+}
+
+pub(crate) fn vhost_net_set_backend(device: RawFd, file: &VringFile) -> nix::Result<()> {
+    // SAFETY: argument lives through the call, and kernel only reads it
+    unsafe { ioctls::vhost::vhost_net_set_backend(device, file) }.map(drop)
+}
+
+/// Creates a close-on-exec eventfd, owning its descriptor.
+pub(crate) fn eventfd() -> io::Result<OwnedFd> {
+    let fd = nix::sys::eventfd::eventfd(0, nix::sys::eventfd::EfdFlags::EFD_CLOEXEC)
+        .map_err(io::Error::from)?;
+    // SAFETY: descriptor is new and is not owned by anything else
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Fills flags of the interface, named in `req`.
+pub(crate) fn get_if_flags(socket: RawFd, req: &mut IfReq) -> nix::Result<()> {
+    // SAFETY: request lives through the call, and kernel writes at most an ifreq into it
+    unsafe { ioctls::siocgifflags(socket, &mut req.0) }.map(drop)
+}
+
+/// Sets flags of the interface, named in `req`.
+pub(crate) fn set_if_flags(socket: RawFd, req: &IfReq) -> nix::Result<()> {
+    // SAFETY: request lives through the call, and kernel only reads it
+    unsafe { ioctls::siocsifflags(socket, &req.0) }.map(drop)
+}
+
+/// Sets the destination address of the point-to-point interface, named in `req`.
+pub(crate) fn set_if_dstaddr(socket: RawFd, req: &IfReq) -> nix::Result<()> {
+    // SAFETY: request lives through the call, and kernel only reads it
+    unsafe { ioctls::siocsifdstaddr(socket, &req.0) }.map(drop)
+}
+
+/// Adds the link-layer multicast address of `req` to the filter of the interface, named in it.
+pub(crate) fn add_multicast(socket: RawFd, req: &IfReq) -> nix::Result<()> {
+    // SAFETY: request lives through the call, and kernel only reads it
+    unsafe { ioctls::siocaddmulti(socket, &req.0) }.map(drop)
+}
+
+pub(crate) fn del_multicast(socket: RawFd, req: &IfReq) -> nix::Result<()> {
+    // SAFETY: request lives through the call, and kernel only reads it
+    unsafe { ioctls::siocdelmulti(socket, &req.0) }.map(drop)
+}
+
+/// Creates a socket, owning its descriptor.
+pub(crate) fn socket(
+    domain: AddressFamily,
+    ty: SockType,
+    flags: SockFlag,
+    protocol: Option<socket::SockProtocol>,
+) -> io::Result<OwnedFd> {
+    let fd = socket::socket(domain, ty, flags, protocol).map_err(io::Error::from)?;
+    // SAFETY: descriptor is new and is not owned by anything else
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Takes ownership of descriptors, passed with `SCM_RIGHTS` in a received message.
+#[cfg(feature = "helper")]
+pub(crate) fn received_fds<S>(message: &socket::RecvMsg<'_, S>) -> Vec<OwnedFd> {
+    use socket::ControlMessageOwned;
+
+    message
+        .cmsgs()
+        .filter_map(|cmsg| match cmsg {
+            ControlMessageOwned::ScmRights(fds) => Some(fds),
+            _ => None,
+        })
+        .flatten()
+        // SAFETY: kernel installs passed descriptors into this process as new ones, and
+        // message is borrowed, so they cannot be taken twice
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+        .collect()
+}
+
+#[cfg(not(feature = "netconfig"))]
+pub(crate) fn index_to_name(index: u32) -> io::Result<String> {
+    let mut buf = [0 as libc::c_char; libc::IF_NAMESIZE];
+    // SAFETY: buffer has room for IF_NAMESIZE bytes, that the call writes at most
+    if unsafe { libc::if_indextoname(index, buf.as_mut_ptr()) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: on success, buffer holds a zero-terminated name
+    let name = unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) };
+    Ok(name.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ifreq_keeps_name() {
+        let req = IfReq::new("tun0").unwrap();
+        assert_eq!(req.name().unwrap(), "tun0");
+        assert_eq!(req.flags(), 0);
+    }
+
+    #[test]
+    fn ifreq_rejects_long_name() {
+        assert!(IfReq::new("interface-name-17").is_err());
+    }
+
+    #[test]
+    fn ifreq_flags_roundtrip() {
+        let mut req = IfReq::new("tap0").unwrap();
+        req.set_flags(libc::c_short::MIN | 2);
+        assert_eq!(req.flags(), libc::c_short::MIN | 2);
+        assert_eq!(req.name().unwrap(), "tap0");
+    }
+
+    #[test]
+    fn ifreq_hwaddr_layout() {
+        let mut req = IfReq::new("tap0").unwrap();
+        req.set_hwaddr([0x01, 0x00, 0x5e, 0x00, 0x00, 0xfb]);
+        // SAFETY: the address was written last
+        let addr = unsafe { req.0.ifr_ifru.ifru_hwaddr };
+        assert_eq!(addr.sa_family, libc::AF_UNSPEC as libc::sa_family_t);
+        assert_eq!(addr.sa_data[2] as u8, 0x5e);
+        assert_eq!(addr.sa_data[6], 0);
+    }
+}
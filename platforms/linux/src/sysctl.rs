@@ -0,0 +1,86 @@
+//! Per-interface sysctls, that tunnels commonly need, written to `/proc/sys/net`.
+use std::fs;
+use tunio_core::Error;
+
+/// Reverse path filtering of IPv4 packets, received on the interface.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RpFilter {
+    Off,
+    /// Packets are dropped, unless the route back to the source goes through the interface.
+    Strict,
+    /// Packets are dropped, unless the source is reachable through any interface.
+    Loose,
+}
+
+/// Acceptance of IPv6 router advertisements on the interface.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AcceptRa {
+    Off,
+    /// Accepted, unless forwarding is enabled.
+    On,
+    /// Accepted, even if forwarding is enabled.
+    Always,
+}
+
+/// Sysctl of a single interface, together with its value.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Sysctl {
+    RpFilter(RpFilter),
+    AcceptRa(AcceptRa),
+    Ipv4Forwarding(bool),
+    Ipv6Forwarding(bool),
+    DisableIpv6(bool),
+}
+
+impl Sysctl {
+    /// Path of the sysctl of `interface`. Dots in interface names are kept, as they are in
+    /// `/proc`, unlike in `sysctl` keys.
+    pub fn path(&self, interface: &str) -> String {
+        let (family, key) = match self {
+            Self::RpFilter(_) => ("ipv4", "rp_filter"),
+            Self::AcceptRa(_) => ("ipv6", "accept_ra"),
+            Self::Ipv4Forwarding(_) => ("ipv4", "forwarding"),
+            Self::Ipv6Forwarding(_) => ("ipv6", "forwarding"),
+            Self::DisableIpv6(_) => ("ipv6", "disable_ipv6"),
+        };
+        format!("/proc/sys/net/{family}/conf/{interface}/{key}")
+    }
+
+    pub fn value(&self) -> u8 {
+        match self {
+            Self::RpFilter(filter) => *filter as u8,
+            Self::AcceptRa(accept) => *accept as u8,
+            Self::Ipv4Forwarding(enabled)
+            | Self::Ipv6Forwarding(enabled)
+            | Self::DisableIpv6(enabled) => *enabled as u8,
+        }
+    }
+}
+
+pub(crate) fn set_sysctl(interface: &str, sysctl: Sysctl) -> Result<(), Error> {
+    fs::write(sysctl.path(interface), format!("{}\n", sysctl.value()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sysctls_map_to_proc() {
+        let rp_filter = Sysctl::RpFilter(RpFilter::Loose);
+        assert_eq!(
+            rp_filter.path("tun.0"),
+            "/proc/sys/net/ipv4/conf/tun.0/rp_filter"
+        );
+        assert_eq!(rp_filter.value(), 2);
+
+        let accept_ra = Sysctl::AcceptRa(AcceptRa::Off);
+        assert_eq!(
+            accept_ra.path("tun0"),
+            "/proc/sys/net/ipv6/conf/tun0/accept_ra"
+        );
+        assert_eq!(accept_ra.value(), 0);
+        assert_eq!(Sysctl::Ipv6Forwarding(true).value(), 1);
+    }
+}
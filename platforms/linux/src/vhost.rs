@@ -0,0 +1,289 @@
+//! Attachment of devices in vnet-header mode to `/dev/vhost-net`, so that a kernel worker
+//! moves packets between the device and virtio rings in memory of the process.
+//!
+//! Rings and the buffers, they point to, are owned by the application, usually a VMM, that
+//! shares them with a guest. The worker waits for a kick eventfd, after buffers are made
+//! available, and signals a call eventfd, after it has used them.
+use super::queue::set_vnet_header_len;
+use super::sys::{self, VringAddr, VringFile, VringState};
+use super::VNET_HEADER_LEN;
+use std::fs;
+use std::io;
+use std::mem::size_of;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use tunio_core::Error;
+
+const DEVICE_NODE: &str = "/dev/vhost-net";
+
+/// Mergeable receive buffers: every received packet starts with the count of buffers it spans.
+pub const VIRTIO_NET_F_MRG_RXBUF: u64 = 1 << 15;
+/// virtio 1.0 device. Its header always has the buffer count.
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+/// vhost adds the header itself, for devices without vnet-header mode.
+const VHOST_NET_F_VIRTIO_NET_HDR: u64 = 1 << 27;
+
+/// Size of `virtio_net_hdr` without the buffer count. With it, header is [`VNET_HEADER_LEN`]
+/// bytes.
+const LEGACY_HEADER_LEN: usize = 10;
+
+/// Virtqueue of vhost-net, in the order of the device.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Ring {
+    /// Packets, read from the interface, are written into its buffers.
+    Rx = 0,
+    /// Packets in its buffers are written to the interface.
+    Tx = 1,
+}
+
+/// Region of process memory, to which the worker translates guest addresses of descriptors.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MemoryRegion {
+    pub guest_addr: u64,
+    pub len: u64,
+    /// Address of the region in the process.
+    pub user_addr: u64,
+}
+
+/// Virtqueue in the split layout of the virtio specification. Addresses are the ones of the
+/// process, not of the guest.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct VringConfig {
+    /// Number of descriptors, a power of two.
+    pub size: u16,
+    pub desc_addr: u64,
+    pub avail_addr: u64,
+    pub used_addr: u64,
+    /// Index of the next available descriptor, which is 0 for a new ring.
+    pub base: u16,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VhostConfig {
+    pub memory: Vec<MemoryRegion>,
+    pub rx: VringConfig,
+    pub tx: VringConfig,
+    /// virtio features, accepted by the driver. Features, that vhost-net does not offer, are
+    /// not acknowledged.
+    pub features: u64,
+}
+
+/// Header of `vhost_memory`, that is followed by its regions.
+#[repr(C)]
+struct MemoryTable {
+    nregions: u32,
+    padding: u32,
+}
+
+/// vhost-net instance, that drives a device. The device is detached, when it is dropped.
+pub struct Vhost {
+    // Eventfds are closed after the instance, which stops the worker
+    device: fs::File,
+    features: u64,
+    kick: [OwnedFd; 2],
+    call: [OwnedFd; 2],
+}
+
+impl Vhost {
+    /// # Safety
+    /// See [`LinuxInterface::attach_vhost`](crate::LinuxInterface::attach_vhost).
+    #[allow(unsafe_code)]
+    pub(crate) unsafe fn attach(tun: BorrowedFd<'_>, config: &VhostConfig) -> Result<Self, Error> {
+        if config.features & VHOST_NET_F_VIRTIO_NET_HDR != 0 {
+            return Err(Error::InvalidConfigValue {
+                name: "features".to_string(),
+                value: format!("{:#x}", config.features),
+                reason: "VHOST_NET_F_VIRTIO_NET_HDR conflicts with the header of the device"
+                    .to_string(),
+            });
+        }
+
+        let device = open_device()?;
+        let fd = device.as_raw_fd();
+        sys::vhost_set_owner(fd).map_err(io::Error::from)?;
+        let offered = sys::vhost_get_features(fd).map_err(io::Error::from)?;
+        let features = config.features & offered;
+        sys::vhost_set_features(fd, features).map_err(io::Error::from)?;
+        // Device writes the header, whose size vhost derives from the features
+        set_vnet_header_len(tun.as_raw_fd(), header_len(features))?;
+
+        let table = memory_table(&config.memory);
+        // SAFETY: the caller keeps the regions mapped, while vhost-net is attached
+        unsafe { sys::vhost_set_mem_table(fd, &table) }.map_err(io::Error::from)?;
+
+        let kick = [sys::eventfd()?, sys::eventfd()?];
+        let call = [sys::eventfd()?, sys::eventfd()?];
+        for (ring, vring) in [(Ring::Rx, &config.rx), (Ring::Tx, &config.tx)] {
+            let index = ring as u32;
+            let num = VringState {
+                index,
+                num: vring.size.into(),
+            };
+            sys::vhost_set_vring_num(fd, &num).map_err(io::Error::from)?;
+            let base = VringState {
+                index,
+                num: vring.base.into(),
+            };
+            sys::vhost_set_vring_base(fd, &base).map_err(io::Error::from)?;
+            let addr = VringAddr {
+                index,
+                flags: 0,
+                desc_user_addr: vring.desc_addr,
+                used_user_addr: vring.used_addr,
+                avail_user_addr: vring.avail_addr,
+                log_guest_addr: 0,
+            };
+            // SAFETY: the caller keeps the rings in the regions, while vhost-net is attached
+            unsafe { sys::vhost_set_vring_addr(fd, &addr) }.map_err(io::Error::from)?;
+
+            let file = |fd: &OwnedFd| VringFile {
+                index,
+                fd: fd.as_raw_fd(),
+            };
+            sys::vhost_set_vring_kick(fd, &file(&kick[ring as usize])).map_err(io::Error::from)?;
+            sys::vhost_set_vring_call(fd, &file(&call[ring as usize])).map_err(io::Error::from)?;
+            let backend = VringFile {
+                index,
+                fd: tun.as_raw_fd(),
+            };
+            sys::vhost_net_set_backend(fd, &backend).map_err(io::Error::from)?;
+        }
+
+        Ok(Self {
+            device,
+            features,
+            kick,
+            call,
+        })
+    }
+
+    /// Features, acknowledged to vhost-net.
+    pub fn features(&self) -> u64 {
+        self.features
+    }
+
+    /// Eventfd, that the application writes to, after it has made buffers of the ring
+    /// available.
+    pub fn kick_fd(&self, ring: Ring) -> BorrowedFd<'_> {
+        self.kick[ring as usize].as_fd()
+    }
+
+    /// Eventfd, that the worker signals, after it has used buffers of the ring.
+    pub fn call_fd(&self, ring: Ring) -> BorrowedFd<'_> {
+        self.call[ring as usize].as_fd()
+    }
+}
+
+impl AsRawFd for Vhost {
+    fn as_raw_fd(&self) -> RawFd {
+        self.device.as_raw_fd()
+    }
+}
+
+fn open_device() -> Result<fs::File, Error> {
+    let path = DEVICE_NODE.to_string();
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(DEVICE_NODE)
+        .map_err(|err| match err.raw_os_error() {
+            Some(libc::ENOENT | libc::ENODEV | libc::ENXIO) => Error::DeviceNodeMissing {
+                path,
+                hint: "load the driver with `modprobe vhost_net`, or pass the device into the container"
+                    .to_string(),
+            },
+            Some(libc::EACCES | libc::EPERM) => Error::DeviceNodeInaccessible {
+                path,
+                hint: "allow read and write access to the node, usually with mode 0666".to_string(),
+            },
+            _ => err.into(),
+        })
+}
+
+/// Size of the header, that vhost-net expects from the device with given features.
+fn header_len(features: u64) -> usize {
+    match features & (VIRTIO_NET_F_MRG_RXBUF | VIRTIO_F_VERSION_1) {
+        0 => LEGACY_HEADER_LEN,
+        _ => VNET_HEADER_LEN,
+    }
+}
+
+/// `vhost_memory` with its regions, as u64 words, so that it is aligned like the kernel struct.
+fn memory_table(regions: &[MemoryRegion]) -> Vec<u64> {
+    // Header is a u32 count, followed by padding
+    let mut header = [0; size_of::<MemoryTable>()];
+    header[..4].copy_from_slice(&(regions.len() as u32).to_ne_bytes());
+    let mut table = vec![u64::from_ne_bytes(header)];
+    for region in regions {
+        // guest_phys_addr, memory_size, userspace_addr and flags_padding
+        table.extend([region.guest_addr, region.len, region.user_addr, 0]);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_codes_match_kernel() {
+        // Direction bits differ on these architectures, see asm/ioctl.h
+        let (none, write, read) = match cfg!(any(
+            target_arch = "mips",
+            target_arch = "mips64",
+            target_arch = "powerpc",
+            target_arch = "powerpc64",
+            target_arch = "sparc64"
+        )) {
+            true => (0x2000_0000, 0x8000_0000, 0x4000_0000),
+            false => (0, 0x4000_0000, 0x8000_0000),
+        };
+        let get_features = nix::request_code_read!(0xAF, 0x00, size_of::<u64>());
+        let set_owner = nix::request_code_none!(0xAF, 0x01);
+        let set_mem_table = nix::request_code_write!(0xAF, 0x03, size_of::<MemoryTable>());
+        let set_vring_addr = nix::request_code_write!(0xAF, 0x11, size_of::<VringAddr>());
+        let net_set_backend = nix::request_code_write!(0xAF, 0x30, size_of::<VringFile>());
+        assert_eq!(get_features as u32, read | 0x0008_af00);
+        assert_eq!(set_owner as u32, none | 0x0000_af01);
+        assert_eq!(set_mem_table as u32, write | 0x0008_af03);
+        assert_eq!(set_vring_addr as u32, write | 0x0028_af11);
+        assert_eq!(net_set_backend as u32, write | 0x0008_af30);
+    }
+
+    #[test]
+    fn structs_match_kernel_layout() {
+        assert_eq!(size_of::<VringState>(), 8);
+        assert_eq!(size_of::<VringFile>(), 8);
+        assert_eq!(size_of::<VringAddr>(), 40);
+        assert_eq!(size_of::<MemoryTable>(), 8);
+    }
+
+    #[test]
+    fn header_len_follows_features() {
+        assert_eq!(header_len(0), 10);
+        assert_eq!(header_len(VIRTIO_NET_F_MRG_RXBUF), 12);
+        assert_eq!(header_len(VIRTIO_F_VERSION_1), 12);
+        assert_eq!(header_len(VIRTIO_F_VERSION_1 | VIRTIO_NET_F_MRG_RXBUF), 12);
+    }
+
+    #[test]
+    fn memory_table_has_count_and_regions() {
+        let regions = [
+            MemoryRegion {
+                guest_addr: 0,
+                len: 0x1000,
+                user_addr: 0x7f00_0000_0000,
+            },
+            MemoryRegion {
+                guest_addr: 0x10_0000,
+                len: 0x2000,
+                user_addr: 0x7f00_0001_0000,
+            },
+        ];
+        let table = memory_table(&regions);
+        assert_eq!(table.len(), 1 + 2 * 4);
+        assert_eq!(table[0].to_ne_bytes()[..4], 2u32.to_ne_bytes());
+        assert_eq!(table[1..5], [0, 0x1000, 0x7f00_0000_0000, 0]);
+        assert_eq!(table[5..9], [0x10_0000, 0x2000, 0x7f00_0001_0000, 0]);
+        assert_eq!(memory_table(&[]), [0]);
+    }
+}
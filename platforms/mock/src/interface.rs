@@ -0,0 +1,627 @@
+use super::link;
+use super::pipe::Pipe;
+use super::queue::{AsyncPipeQueue, PipeQueueT, SyncPipeQueue};
+use super::Driver;
+use super::PlatformIfConfig;
+use delegate::delegate;
+use futures::{AsyncRead, AsyncWrite};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tunio_core::config::{IfConfig, Layer, NameOutcome};
+use tunio_core::egress::{EgressPriority, EgressScheduler};
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::mtu::MtuWatch;
+use tunio_core::packet::is_ipv4;
+use tunio_core::pause::PauseHandle;
+use tunio_core::shaper::RateLimiter;
+use tunio_core::stats::{BufferUsage, QueueStats, StatsCounters};
+use tunio_core::traits::{AsyncQueueT, InterfaceT, SyncQueueT};
+use tunio_core::Error;
+
+/// MTU of new mock interfaces, until [`set_mtu`](MockInterface::set_mtu) is called.
+pub const DEFAULT_MTU: u32 = 1500;
+
+pub struct MockInterface<Q> {
+    name: String,
+    up: bool,
+    layer: Layer,
+    ipv6_only: bool,
+    events: EventEmitter,
+    mtu: MtuWatch,
+    pause: PauseHandle,
+    stats: StatsCounters,
+    shaper: RateLimiter,
+    egress: EgressScheduler,
+    pub(crate) queue: Q,
+}
+
+impl<Q: PipeQueueT> MockInterface<Q> {
+    /// Creates two interfaces, connected to each other: packets, written to one of them,
+    /// can be read from another.
+    pub fn new_pair(
+        driver: &mut Driver,
+        params_a: IfConfig<PlatformIfConfig>,
+        params_b: IfConfig<PlatformIfConfig>,
+    ) -> Result<(Self, Self), Error> {
+        let (input_a, output_a) = new_link(&params_a.platform)?;
+        let (input_b, output_b) = new_link(&params_b.platform)?;
+
+        let a = Self::with_queue(driver, params_a, Q::new(output_a, input_b));
+        let b = Self::with_queue(driver, params_b, Q::new(output_b, input_a));
+        Ok((a, b))
+    }
+
+    /// Packets, buffered for reading on this interface, and their total length. Buffer is
+    /// bounded by [`capacity`](PlatformIfConfig::capacity) and
+    /// [`capacity_bytes`](PlatformIfConfig::capacity_bytes).
+    pub fn buffered(&self) -> BufferUsage {
+        self.queue.buffered()
+    }
+
+    pub(crate) fn with_queue(
+        driver: &Driver,
+        params: IfConfig<PlatformIfConfig>,
+        mut queue: Q,
+    ) -> Self {
+        queue.set_poll_budget(params.poll_budget);
+        // Like on Linux, IPv4 packets are dropped in both directions
+        if params.ipv6_only {
+            queue.drop_ipv4(params.layer);
+        }
+        driver.events.emit(&params.name, EventKind::Created);
+
+        Self {
+            name: params.name,
+            up: false,
+            layer: params.layer,
+            ipv6_only: params.ipv6_only,
+            events: driver.events.clone(),
+            mtu: MtuWatch::new(DEFAULT_MTU),
+            pause: PauseHandle::default(),
+            stats: StatsCounters::new(params.layer),
+            shaper: RateLimiter::default(),
+            egress: EgressScheduler::new(params.layer),
+            queue,
+        }
+    }
+}
+
+impl<Q> MockInterface<Q> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Mock interfaces are not visible to the OS, so their names never conflict.
+    pub fn name_outcome(&self) -> NameOutcome {
+        NameOutcome::Created
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+
+    /// Limits the rate of packets, written to the interface, to `bytes_per_sec` with bursts
+    /// of up to `burst` bytes. Writes wait for the bucket to be replenished.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: u64, burst: u64) {
+        self.shaper.set(bytes_per_sec, burst);
+    }
+
+    pub fn clear_rate_limit(&mut self) {
+        self.shaper.clear();
+    }
+
+    /// Lets packets, selected by `policy`, bypass up to `backlog` bulk packets, queued while
+    /// async writes are pending. Has no effect on sync interfaces.
+    pub fn set_egress_priority(&mut self, policy: EgressPriority, backlog: usize) {
+        self.egress.set_policy(policy, backlog);
+    }
+
+    /// Packet and ECN counters of this interface.
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+
+    pub fn current_mtu(&self) -> u32 {
+        self.mtu.get()
+    }
+
+    /// Returns a watch, that follows MTU changes of this interface, for hooks, such as
+    /// [`Fragmenter::tracking`](tunio_core::hooks::Fragmenter::tracking).
+    pub fn mtu_watch(&self) -> MtuWatch {
+        self.mtu.clone()
+    }
+
+    /// Simulates an MTU change of the system: watches are updated, and
+    /// [`EventKind::MtuChanged`] is emitted, if the MTU differs from the current one. Pipe
+    /// does not limit packet sizes.
+    pub fn set_mtu(&self, mtu: u32) {
+        if self.mtu.set(mtu) {
+            self.events.emit(&self.name, EventKind::MtuChanged { mtu });
+        }
+    }
+
+    /// Stops taking packets from the pipe, until [`resume`](Self::resume) is called.
+    /// Peer writes block, once the pipe is full.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns a handle, that pauses and resumes this interface from another task or thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// IPv4 packets are not written to IPv6-only interfaces.
+    fn drops(&self, packet: &[u8]) -> bool {
+        self.ipv6_only && is_ipv4(packet, self.layer)
+    }
+
+    fn write_failed(&self, err: io::Error) -> io::Error {
+        self.events
+            .emit(&self.name, EventKind::WriteFailed(err.kind()));
+        err
+    }
+
+    fn packet_read(&self, buf: &[u8], n: usize) -> usize {
+        self.stats.record_rx(&buf[..n]);
+        n
+    }
+
+    fn packet_written(&mut self, buf: &[u8], n: usize) -> usize {
+        self.stats.record_tx(&buf[..n]);
+        self.shaper.consume(n);
+        n
+    }
+}
+
+impl<Q> Drop for MockInterface<Q> {
+    fn drop(&mut self) {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+    }
+}
+
+impl<Q: PipeQueueT> InterfaceT for MockInterface<Q> {
+    type PlatformDriver = Driver;
+    type PlatformIfConfig = PlatformIfConfig;
+
+    /// Creates a loopback interface: every written packet can be read back from it.
+    fn new(
+        driver: &mut Self::PlatformDriver,
+        params: IfConfig<Self::PlatformIfConfig>,
+    ) -> Result<Self, Error> {
+        let (input, output) = new_link(&params.platform)?;
+
+        Ok(Self::with_queue(driver, params, Q::new(output, input)))
+    }
+
+    fn up(&mut self) -> Result<(), Error> {
+        self.up = true;
+        self.events.emit(&self.name, EventKind::SessionStarted);
+        Ok(())
+    }
+
+    fn down(&mut self) -> Result<(), Error> {
+        self.up = false;
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+        Ok(())
+    }
+
+    /// Mock interfaces are not registered in OS, so returned handle does not point to any
+    /// existing interface.
+    fn handle(&self) -> netconfig::Interface {
+        netconfig::Interface::from_index_unchecked(0)
+    }
+}
+
+pub(crate) fn new_pipe(config: &PlatformIfConfig) -> Result<Arc<Pipe>, Error> {
+    let capacity = validate_capacity("capacity", config.capacity)?;
+    let capacity_bytes = config
+        .capacity_bytes
+        .map(|bytes| validate_capacity("capacity_bytes", bytes))
+        .transpose()?;
+    Ok(Arc::new(Pipe::new(capacity, capacity_bytes)))
+}
+
+/// Pipes, that packets for an interface are written to and are read from. They are the same
+/// pipe, unless the link is emulated.
+pub(crate) fn new_link(config: &PlatformIfConfig) -> Result<(Arc<Pipe>, Arc<Pipe>), Error> {
+    let output = new_pipe(config)?;
+    match &config.link {
+        Some(link) => {
+            let input = new_pipe(config)?;
+            link::spawn(link.clone(), input.clone(), output.clone())?;
+            Ok((input, output))
+        }
+        None => Ok((output.clone(), output)),
+    }
+}
+
+fn validate_capacity(name: &str, capacity: usize) -> Result<usize, Error> {
+    match capacity {
+        0 => Err(Error::InvalidConfigValue {
+            name: name.to_string(),
+            value: capacity.to_string(),
+            reason: "must be greater than 0".to_string(),
+        }),
+        _ => Ok(capacity),
+    }
+}
+
+pub type Interface = MockInterface<SyncPipeQueue>;
+impl<Q: SyncQueueT> SyncQueueT for MockInterface<Q> {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.pause.wait_resumed();
+        let (n, truncated) = self.queue.recv(buf)?;
+        Ok((self.packet_read(buf, n), truncated))
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        if self.drops(packet) {
+            return Ok(());
+        }
+        self.shaper.wait_ready(packet.len());
+        self.queue.send(packet).map_err(|e| self.write_failed(e))?;
+        self.packet_written(packet, packet.len());
+        Ok(())
+    }
+
+    type PacketRef<'a>
+        = Q::PacketRef<'a>
+    where
+        Q: 'a;
+
+    fn recv_ref(&mut self) -> io::Result<Self::PacketRef<'_>> {
+        self.pause.wait_resumed();
+        let packet = self.queue.recv_ref()?;
+        self.stats.record_rx(&packet);
+        Ok(packet)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        SyncQueueT::drain(&mut self.queue)
+    }
+}
+
+impl<Q: SyncQueueT> Read for MockInterface<Q> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
+    }
+}
+
+impl<Q: SyncQueueT> Write for MockInterface<Q> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
+    delegate! {
+        to self.queue {
+            fn flush(&mut self) -> io::Result<()>;
+        }
+    }
+}
+
+pub type AsyncInterface = MockInterface<AsyncPipeQueue>;
+impl<Q: AsyncQueueT> AsyncQueueT for MockInterface<Q> {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        ready!(self.pause.poll_resumed(cx));
+        let self_mut = self.get_mut();
+        Pin::new(&mut self_mut.queue)
+            .poll_recv(cx, buf)
+            .map_ok(|(n, truncated)| (self_mut.packet_read(buf, n), truncated))
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        if self_mut.drops(packet) {
+            return Poll::Ready(Ok(()));
+        }
+        if !self_mut.egress.is_enabled() {
+            return self_mut.poll_send_shaped(cx, packet).map_ok(drop);
+        }
+
+        let mut egress = mem::take(&mut self_mut.egress);
+        let result = egress.poll_write(cx, packet, |cx, packet| {
+            self_mut.poll_send_shaped(cx, packet)
+        });
+        self_mut.egress = egress;
+        result.map_ok(drop)
+    }
+
+    /// Paused interfaces are not ready.
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.pause.poll_resumed(cx));
+        Pin::new(&mut self.get_mut().queue).poll_recv_ready(cx)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        AsyncQueueT::drain(&mut self.queue)
+    }
+}
+
+impl<Q: AsyncQueueT> MockInterface<Q> {
+    fn poll_send_shaped(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(self.shaper.poll_ready(cx, buf.len()));
+        Pin::new(&mut self.queue)
+            .poll_send(cx, buf)
+            .map_ok(|()| self.packet_written(buf, buf.len()))
+            .map_err(|e| self.write_failed(e))
+    }
+
+    fn poll_drain_egress(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut egress = mem::take(&mut self.egress);
+        let result = egress.poll_drain(cx, |cx, packet| self.poll_send_shaped(cx, packet));
+        self.egress = egress;
+        result
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncRead for MockInterface<Q> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncWrite for MockInterface<Q> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        Pin::new(&mut self_mut.queue).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        Pin::new(&mut self_mut.queue).poll_close(cx)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::future::poll_fn;
+    use futures::{AsyncReadExt, AsyncWriteExt, FutureExt};
+    use std::thread;
+    use tunio_core::traits::{AsyncQueueExt, DriverT};
+
+    const PACKETS: u64 = 10_000;
+
+    fn config(name: &str, capacity: usize) -> IfConfig<PlatformIfConfig> {
+        MockInterface::<SyncPipeQueue>::config_builder()
+            .name(name.to_string())
+            .platform(|mut builder| builder.capacity(capacity).build())
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    fn pair<Q: PipeQueueT>(capacity: usize) -> (MockInterface<Q>, MockInterface<Q>) {
+        let mut driver = Driver::new().unwrap();
+        MockInterface::new_pair(&mut driver, config("a", capacity), config("b", capacity)).unwrap()
+    }
+
+    #[test]
+    fn sync_packets_keep_driver_order() {
+        // Small pipe makes the writer block and hand off to the reader often
+        let (mut a, mut b): (Interface, Interface) = pair(2);
+        let writer = thread::spawn(move || {
+            for seq in 0..PACKETS {
+                a.send(&seq.to_be_bytes()).unwrap();
+            }
+        });
+
+        let mut buf = [0u8; 8];
+        for seq in 0..PACKETS {
+            assert_eq!(b.recv(&mut buf).unwrap(), (8, false));
+            assert_eq!(u64::from_be_bytes(buf), seq);
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn async_packets_keep_driver_order() {
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair(2);
+        let writer = async move {
+            for seq in 0..PACKETS {
+                a.write_all(&seq.to_be_bytes()).await.unwrap();
+            }
+        };
+        let reader = async move {
+            let mut buf = [0u8; 8];
+            for seq in 0..PACKETS {
+                assert_eq!(b.read(&mut buf).await.unwrap(), 8);
+                assert_eq!(u64::from_be_bytes(buf), seq);
+            }
+        };
+        // Writer and reader wake each other through the pipe wakers on a single thread
+        block_on(futures::future::join(writer, reader));
+    }
+
+    #[test]
+    fn async_packets_keep_order_across_threads() {
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair(1);
+        let writer = thread::spawn(move || {
+            block_on(async {
+                for seq in 0..PACKETS {
+                    a.write_all(&seq.to_be_bytes()).await.unwrap();
+                }
+            })
+        });
+
+        block_on(async {
+            let mut buf = [0u8; 8];
+            for seq in 0..PACKETS {
+                assert_eq!(b.read(&mut buf).await.unwrap(), 8);
+                assert_eq!(u64::from_be_bytes(buf), seq);
+            }
+        });
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn cancelled_reads_keep_order() {
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair(16);
+        let mut buf = [0u8; 8];
+        for seq in (0..PACKETS).step_by(2) {
+            // Read future is polled once and dropped, like a losing branch of select!
+            assert!(b.read(&mut buf).now_or_never().is_none());
+
+            block_on(a.write_all(&seq.to_be_bytes())).unwrap();
+            block_on(a.write_all(&(seq + 1).to_be_bytes())).unwrap();
+            for expected in [seq, seq + 1] {
+                assert_eq!(block_on(b.read(&mut buf)).unwrap(), 8);
+                assert_eq!(u64::from_be_bytes(buf), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn dropped_peer_is_not_end_of_stream() {
+        let (mut a, mut b): (Interface, Interface) = pair(4);
+        a.send(&[1]).unwrap();
+        drop(a);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(b.recv(&mut buf).unwrap(), (1, false));
+        let err = b.recv(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn closed_peer_is_end_of_stream() {
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair(4);
+        block_on(async {
+            a.write_all(&[1]).await.unwrap();
+            a.close().await.unwrap();
+            drop(a);
+
+            let mut buf = [0u8; 8];
+            assert_eq!(b.read(&mut buf).await.unwrap(), 1);
+            assert_eq!(b.read(&mut buf).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn recv_many_takes_ready_packets() {
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair(8);
+        block_on(async {
+            for seq in 0..5u8 {
+                a.write_all(&[seq; 3]).await.unwrap();
+            }
+
+            let mut out = Vec::new();
+            assert_eq!(b.recv_many(&mut out, 3).await.unwrap(), 3);
+            assert_eq!(b.recv_many(&mut out, 3).await.unwrap(), 2);
+            let received: Vec<_> = out.iter().map(|(p, t)| (p.to_vec(), *t)).collect();
+            let expected: Vec<_> = (0..5u8).map(|seq| (vec![seq; 3], false)).collect();
+            assert_eq!(received, expected);
+
+            assert!(b.recv_many(&mut out, 3).now_or_never().is_none());
+            assert_eq!(b.recv_many(&mut out, 0).await.unwrap(), 0);
+        });
+    }
+
+    #[test]
+    fn readiness_does_not_take_packets() {
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair(4);
+        let ready = |b: &mut AsyncInterface| {
+            block_on(poll_fn(|cx| {
+                Poll::Ready(Pin::new(&mut *b).poll_recv_ready(cx).is_ready())
+            }))
+        };
+        assert!(!ready(&mut b));
+
+        block_on(a.write_all(&[1])).unwrap();
+        assert!(ready(&mut b));
+        assert!(ready(&mut b));
+        let mut buf = [0u8; 4];
+        assert_eq!(block_on(b.read(&mut buf)).unwrap(), 1);
+        assert!(!ready(&mut b));
+
+        b.pause();
+        block_on(a.write_all(&[2])).unwrap();
+        assert!(!ready(&mut b));
+    }
+
+    #[test]
+    fn ipv6_only_drops_ipv4() {
+        let mut driver = Driver::new().unwrap();
+        let mut params = config("a", 4);
+        params.ipv6_only = true;
+        let (mut a, mut b): (Interface, Interface) =
+            MockInterface::new_pair(&mut driver, params, config("b", 4)).unwrap();
+
+        for packet in [[0x45, 1], [0x60, 2]] {
+            a.send(&packet).unwrap();
+            b.send(&packet).unwrap();
+        }
+        assert_eq!(a.buffered().packets, 1);
+        assert_eq!(b.buffered().packets, 1);
+        let mut buf = [0u8; 2];
+        assert_eq!(a.recv(&mut buf).unwrap(), (2, false));
+        assert_eq!(buf, [0x60, 2]);
+    }
+
+    #[test]
+    fn mtu_changes_reach_watches() {
+        let mut driver = Driver::new().unwrap();
+        let mut events = driver.subscribe();
+        let (a, _b): (Interface, Interface) =
+            MockInterface::new_pair(&mut driver, config("a", 1), config("b", 1)).unwrap();
+        let watch = a.mtu_watch();
+        assert_eq!(watch.get(), DEFAULT_MTU);
+
+        a.set_mtu(1280);
+        a.set_mtu(1280);
+        assert_eq!((a.current_mtu(), watch.get()), (1280, 1280));
+        let kinds: Vec<_> = std::iter::from_fn(|| events.try_next().ok().flatten())
+            .map(|event| event.kind)
+            .collect();
+        assert_eq!(kinds[2..], [EventKind::MtuChanged { mtu: 1280 }]);
+    }
+
+    #[test]
+    fn generated_packets_roundtrip() {
+        use std::net::Ipv6Addr;
+        use tunio_core::test_util::{roundtrip, Packets, RoundtripError};
+
+        let source = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1).into();
+        let destination = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2).into();
+        let packets: Vec<_> = Packets::new(1, source, destination).take(500).collect();
+        let (mut a, mut b): (AsyncInterface, AsyncInterface) = pair(4);
+        let result = block_on(roundtrip(&mut a, &mut b, &packets)).unwrap();
+        assert_eq!((result.received, result.unexpected), (500, 0));
+
+        // Packet, that overtakes the expected one, means, that the expected one is lost
+        block_on(a.write_all(&packets[1])).unwrap();
+        let result = block_on(roundtrip(&mut a, &mut b, &packets[..2]));
+        assert!(matches!(result, Err(RoundtripError::Missing { index: 0 })));
+    }
+}
@@ -0,0 +1,99 @@
+//! # In-memory mock backend for tunio.
+//!
+//! Interfaces, created by this driver, are not backed by any OS device. Packets are passed
+//! between interfaces through bounded in-memory pipes, so this backend works on every platform
+//! and without elevated privileges. It is useful for testing and benchmarking code, written
+//! against tunio traits.
+//!
+//! Supported features:
+//! - Loopback interfaces (created with [`InterfaceT::new`](tunio_core::traits::InterfaceT::new))
+//! - Connected interface pairs (created with [`MockInterface::new_pair`])
+//! - Replay of pcap and pcapng captures, with recording of written packets (created with
+//!   [`MockInterface::new_replay`])
+//! - Emulated delay, jitter, loss, duplication and bandwidth of links (configured with
+//!   [`PlatformIfConfig::link`])
+//! - Sync and async mode
+
+mod interface;
+pub mod link;
+pub mod pcap;
+mod pipe;
+mod queue;
+pub mod replay;
+
+use derive_builder::Builder;
+use tunio_core::config::Violation;
+use tunio_core::events::{EventEmitter, EventReceiver};
+use tunio_core::traits::{DriverT, PlatformIfConfigT};
+use tunio_core::Error;
+
+pub use interface::{AsyncInterface, Interface, MockInterface, DEFAULT_MTU};
+pub use link::{Delay, LinkEmulation};
+pub use queue::{AsyncPipeQueue, PipeQueueT, SyncPipeQueue};
+
+pub struct Driver {
+    pub(crate) events: EventEmitter,
+}
+
+/// It is generally better to use [`PlatformIfConfigBuilder`] to create a new PlatformIfConfig instance.
+#[derive(Builder, Clone)]
+pub struct PlatformIfConfig {
+    /// Maximum number of packets, buffered for reading on this interface.
+    #[builder(default = "1024")]
+    pub capacity: usize,
+    /// Maximum total length of packets, buffered for reading on this interface. A packet is
+    /// always accepted into an empty buffer, so packets, longer than the limit, still pass.
+    #[builder(default = "None")]
+    pub capacity_bytes: Option<usize>,
+    /// Emulated conditions of the link, that delivers packets to this interface. Packets are
+    /// delivered immediately and without losses, if it is not set.
+    #[builder(default = "None")]
+    pub link: Option<LinkEmulation>,
+}
+
+impl PlatformIfConfigT for PlatformIfConfig {
+    type Builder = PlatformIfConfigBuilder;
+
+    /// Mock interfaces are not visible to the OS, so any name is accepted.
+    const MAX_NAME_LEN: usize = usize::MAX;
+
+    fn check_name(_name: &str) -> Option<String> {
+        None
+    }
+
+    fn validate(&self, violations: &mut Vec<Violation>) {
+        if self.capacity == 0 {
+            violations.push(Violation::new("capacity", 0, "must be greater than 0"));
+        }
+        if self.capacity_bytes == Some(0) {
+            violations.push(Violation::new(
+                "capacity_bytes",
+                0,
+                "must be greater than 0",
+            ));
+        }
+        if let Some(link) = &self.link {
+            link.validate(violations);
+        }
+    }
+}
+
+impl Default for PlatformIfConfig {
+    fn default() -> Self {
+        PlatformIfConfigBuilder::default().build().unwrap()
+    }
+}
+
+impl DriverT for Driver {
+    type PlatformIfConfig = PlatformIfConfig;
+
+    fn new() -> Result<Self, Error> {
+        Ok(Self {
+            events: EventEmitter::default(),
+        })
+    }
+
+    fn subscribe(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+}
@@ -0,0 +1,323 @@
+//! Emulation of link conditions, such as delay, loss and limited bandwidth, between a writer
+//! and a reader of a pipe, for testing congestion control without netem.
+use super::pipe::{Pipe, Received};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tunio_core::config::Violation;
+
+/// Distribution of one-way delays of packets.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Delay {
+    Fixed(Duration),
+    /// Delay is uniformly distributed between `min` and `max`.
+    Uniform {
+        min: Duration,
+        max: Duration,
+    },
+    /// Delay is normally distributed, negative delays are cut to zero.
+    Normal {
+        mean: Duration,
+        jitter: Duration,
+    },
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Delay::Fixed(Duration::ZERO)
+    }
+}
+
+/// Conditions of the link, that delivers packets to an interface.
+///
+/// Packets are delayed, lost and duplicated after they are written, and the writer does not
+/// wait for them to be delivered. Packets in flight are still delivered, when the writer is
+/// dropped, and are not counted in [`buffered`](crate::MockInterface::buffered).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LinkEmulation {
+    pub delay: Delay,
+    /// Probability from 0 to 1, that a packet is lost.
+    pub loss: f64,
+    /// Probability from 0 to 1, that a packet is delivered twice.
+    pub duplication: f64,
+    /// Bandwidth in bytes per second. Packets wait, while previous ones are transmitted.
+    pub rate: Option<u64>,
+    /// Bytes, waiting to be transmitted, above which new packets are dropped, like by a
+    /// tail-drop router queue. Only used with `rate`.
+    pub queue_limit: Option<usize>,
+    /// Packets with varying delays may overtake each other. Otherwise a packet is delayed,
+    /// until the previous one is delivered.
+    pub reorder: bool,
+    /// Seed of random losses, duplicates and delays, so that runs are reproducible.
+    pub seed: u64,
+}
+
+impl LinkEmulation {
+    pub(crate) fn validate(&self, violations: &mut Vec<Violation>) {
+        for (name, probability) in [("loss", self.loss), ("duplication", self.duplication)] {
+            if !(0.0..=1.0).contains(&probability) {
+                violations.push(Violation::new(name, probability, "must be from 0 to 1"));
+            }
+        }
+        if let Delay::Uniform { min, max } = self.delay {
+            if min > max {
+                violations.push(Violation::new(
+                    "delay",
+                    format!("{min:?}..{max:?}"),
+                    "minimum must not exceed maximum",
+                ));
+            }
+        }
+        if self.rate == Some(0) {
+            violations.push(Violation::new("rate", 0, "must be greater than 0"));
+        }
+    }
+}
+
+/// Moves packets from `input` to `output` on a separate thread, applying `link` to them.
+///
+/// Shutdown and disconnection of `input` are passed to `output` after the packets in flight
+/// are delivered, and disconnection of `output` is passed back to `input`.
+pub(crate) fn spawn(link: LinkEmulation, input: Arc<Pipe>, output: Arc<Pipe>) -> io::Result<()> {
+    thread::Builder::new()
+        .name("tunio-link".to_string())
+        .spawn(move || Emulator::new(link).run(&input, &output))
+        .map(drop)
+}
+
+struct Emulator {
+    rng: SplitMix64,
+    link: LinkEmulation,
+    /// Packets in flight, ordered by their delivery time.
+    in_flight: VecDeque<(Instant, Vec<u8>)>,
+    /// When the transmission of the last accepted packet ends.
+    link_free: Instant,
+}
+
+impl Emulator {
+    fn new(link: LinkEmulation) -> Self {
+        Self {
+            rng: SplitMix64(link.seed),
+            link,
+            in_flight: VecDeque::new(),
+            link_free: Instant::now(),
+        }
+    }
+
+    fn run(mut self, input: &Pipe, output: &Pipe) {
+        loop {
+            if self.deliver(output, Instant::now()).is_err() {
+                input.disconnect();
+                return;
+            }
+            let deadline = self.in_flight.front().map(|(due, _)| *due);
+            match input.recv_packet_until(deadline) {
+                Ok(Received::Packet(packet)) => self.schedule(packet, Instant::now()),
+                Ok(Received::Closed) => break,
+                Ok(Received::TimedOut) => {}
+                Err(_) => {
+                    self.flush(output);
+                    output.disconnect();
+                    return;
+                }
+            }
+        }
+        self.flush(output);
+        output.close();
+    }
+
+    /// Sends packets, that are due at `now`. Fails, when the reader is gone.
+    fn deliver(&mut self, output: &Pipe, now: Instant) -> io::Result<()> {
+        while self.in_flight.front().map_or(false, |(due, _)| *due <= now) {
+            let (_, packet) = self.in_flight.pop_front().unwrap();
+            output.send(&packet)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, output: &Pipe) {
+        while let Some((due, _)) = self.in_flight.back() {
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+            if self.deliver(output, Instant::now()).is_err() {
+                return;
+            }
+        }
+    }
+
+    fn schedule(&mut self, packet: Vec<u8>, now: Instant) {
+        if self.rng.chance(self.link.loss) {
+            return;
+        }
+        if self.rng.chance(self.link.duplication) {
+            self.transmit(packet.clone(), now);
+        }
+        self.transmit(packet, now);
+    }
+
+    fn transmit(&mut self, packet: Vec<u8>, now: Instant) {
+        let mut sent = now;
+        if let Some(rate) = self.link.rate {
+            let start = self.link_free.max(now);
+            let backlog = (start - now).as_secs_f64() * rate as f64;
+            if let Some(limit) = self.link.queue_limit {
+                if backlog as usize + packet.len() > limit {
+                    return;
+                }
+            }
+            self.link_free = start + Duration::from_secs_f64(packet.len() as f64 / rate as f64);
+            sent = self.link_free;
+        }
+
+        let mut due = sent + self.delay();
+        if !self.link.reorder {
+            if let Some((last, _)) = self.in_flight.back() {
+                due = due.max(*last);
+            }
+        }
+        let index = self.in_flight.partition_point(|(other, _)| *other <= due);
+        self.in_flight.insert(index, (due, packet));
+    }
+
+    fn delay(&mut self) -> Duration {
+        match self.link.delay {
+            Delay::Fixed(delay) => delay,
+            Delay::Uniform { min, max } => min + (max - min).mul_f64(self.rng.next_f64()),
+            Delay::Normal { mean, jitter } => {
+                let delay = mean.as_secs_f64() + jitter.as_secs_f64() * self.rng.next_normal();
+                Duration::from_secs_f64(delay.max(0.0))
+            }
+        }
+    }
+}
+
+/// Small deterministic generator, good enough for emulated randomness.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniformly distributed in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal, with the Box-Muller transform.
+    fn next_normal(&mut self) -> f64 {
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+
+    fn link(link: LinkEmulation) -> (Arc<Pipe>, Arc<Pipe>) {
+        let input = Arc::new(Pipe::new(1024, None));
+        let output = Arc::new(Pipe::new(1024, None));
+        spawn(link, input.clone(), output.clone()).unwrap();
+        (input, output)
+    }
+
+    fn received(output: &Pipe) -> Vec<Vec<u8>> {
+        let mut packets = vec![];
+        loop {
+            match output.recv_packet().unwrap() {
+                Some(packet) => packets.push(packet),
+                None => return packets,
+            }
+        }
+    }
+
+    #[test]
+    fn packets_are_delayed_in_order() {
+        let (input, output) = link(LinkEmulation {
+            delay: Delay::Uniform {
+                min: Duration::from_millis(5),
+                max: Duration::from_millis(20),
+            },
+            ..Default::default()
+        });
+        let start = Instant::now();
+        for i in 0..10u8 {
+            input.send(&[i]).unwrap();
+        }
+        input.close();
+
+        let packets = received(&output);
+        assert!(start.elapsed() >= Duration::from_millis(5));
+        assert_eq!(packets, (0..10u8).map(|i| vec![i]).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_packets_do_not_close_the_link() {
+        let (input, output) = link(LinkEmulation::default());
+        input.send(&[]).unwrap();
+        input.send(&[1]).unwrap();
+        input.close();
+
+        assert_eq!(received(&output), [vec![], vec![1]]);
+    }
+
+    #[test]
+    fn losses_and_duplicates_follow_probabilities() {
+        let (input, output) = link(LinkEmulation {
+            loss: 0.2,
+            duplication: 0.1,
+            seed: 7,
+            ..Default::default()
+        });
+        for i in 0..1000u16 {
+            input.send(&i.to_be_bytes()).unwrap();
+        }
+        input.close();
+
+        let packets = received(&output);
+        // About 800 packets pass, and a tenth of them are duplicated
+        assert!((820..940).contains(&packets.len()), "{}", packets.len());
+    }
+
+    #[test]
+    fn bandwidth_is_limited_and_queue_overflow_dropped() {
+        let (input, output) = link(LinkEmulation {
+            rate: Some(1_000_000),
+            queue_limit: Some(5_000),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        for _ in 0..20 {
+            input.send(&[0; 1000]).unwrap();
+        }
+        input.close();
+
+        // 1 ms per packet, and the queue holds about 5 of them
+        let packets = received(&output);
+        assert!((5..=7).contains(&packets.len()), "{}", packets.len());
+        assert!(start.elapsed() >= Duration::from_millis(5));
+    }
+
+    #[test]
+    fn invalid_probabilities_are_rejected() {
+        let mut violations = vec![];
+        LinkEmulation {
+            loss: 1.5,
+            duplication: f64::NAN,
+            ..Default::default()
+        }
+        .validate(&mut violations);
+        assert_eq!(violations.len(), 2);
+    }
+}
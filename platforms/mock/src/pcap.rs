@@ -0,0 +1,465 @@
+//! Reader of pcap and pcapng captures and writer of pcap ones, for replaying captured traffic
+//! through mock interfaces.
+use std::io::{self, ErrorKind, Read, Write};
+use std::time::Duration;
+use tunio_core::config::Layer;
+
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101;
+pub const LINKTYPE_IPV4: u32 = 228;
+pub const LINKTYPE_IPV6: u32 = 229;
+
+/// Largest packet, that is written whole. Same as the default of tcpdump.
+const SNAPLEN: u32 = 262_144;
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_OPT_END: u16 = 0;
+const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+/// Blocks are read into memory, so their length is limited.
+const PCAPNG_MAX_BLOCK: usize = 16 * 1024 * 1024;
+
+/// Layer of packets with the link type, if mock interfaces can carry them.
+pub fn linktype_layer(linktype: u32) -> Option<Layer> {
+    match linktype {
+        LINKTYPE_ETHERNET => Some(Layer::L2),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(Layer::L3),
+        _ => None,
+    }
+}
+
+/// Packet of a capture.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PcapPacket {
+    /// Time since the Unix epoch. Packets without a timestamp, like simple packets of pcapng,
+    /// get the timestamp of the previous packet.
+    pub timestamp: Duration,
+    pub linktype: u32,
+    pub data: Vec<u8>,
+}
+
+/// Resolution of pcapng timestamps: ticks per second as a power of 10 or 2.
+#[derive(Clone, Copy)]
+enum Resolution {
+    Decimal(u8),
+    Binary(u8),
+}
+
+impl Resolution {
+    fn duration(self, ticks: u64) -> Duration {
+        let per_sec: u128 = match self {
+            Resolution::Decimal(exp) => 10u128.pow(exp.min(19).into()),
+            Resolution::Binary(exp) => 1u128 << exp.min(63),
+        };
+        let ticks = u128::from(ticks);
+        let nanos = (ticks % per_sec) * 1_000_000_000 / per_sec;
+        Duration::new((ticks / per_sec) as u64, nanos as u32)
+    }
+}
+
+struct Interface {
+    linktype: u32,
+    resolution: Resolution,
+}
+
+enum Format {
+    Pcap { linktype: u32, nanos: bool },
+    Pcapng { interfaces: Vec<Interface> },
+}
+
+/// Reader of classic pcap files with microsecond or nanosecond timestamps, and of pcapng
+/// files with any number of sections and interfaces. Byte order of the capture is detected.
+pub struct PcapReader<R> {
+    reader: R,
+    format: Format,
+    big_endian: bool,
+    last_timestamp: Duration,
+}
+
+impl<R: Read> PcapReader<R> {
+    /// Reads the file header. Fails with `InvalidData`, if the capture is neither pcap nor
+    /// pcapng.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let mut this = Self {
+            reader,
+            format: Format::Pcapng { interfaces: vec![] },
+            big_endian: false,
+            last_timestamp: Duration::ZERO,
+        };
+        match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAPNG_SECTION_HEADER, _) => {
+                let mut len = [0u8; 4];
+                this.reader.read_exact(&mut len)?;
+                this.read_section_header(len)?;
+            }
+            (PCAP_MAGIC_MICROS, _) => this.read_pcap_header(false)?,
+            (PCAP_MAGIC_NANOS, _) => this.read_pcap_header(true)?,
+            (_, magic) if magic == PCAP_MAGIC_MICROS || magic == PCAP_MAGIC_NANOS => {
+                this.big_endian = true;
+                this.read_pcap_header(magic == PCAP_MAGIC_NANOS)?;
+            }
+            _ => return Err(invalid("not a pcap or pcapng capture")),
+        }
+        Ok(this)
+    }
+
+    /// Returns the next packet, or `None` at the end of the capture. Blocks of pcapng, that
+    /// carry no packets, are skipped.
+    pub fn next_packet(&mut self) -> io::Result<Option<PcapPacket>> {
+        match self.format {
+            Format::Pcap { linktype, nanos } => self.next_pcap_packet(linktype, nanos),
+            Format::Pcapng { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn read_pcap_header(&mut self, nanos: bool) -> io::Result<()> {
+        // Version, time zone, accuracy and snapshot length are not needed
+        let mut header = [0u8; 20];
+        self.reader.read_exact(&mut header)?;
+        let linktype = self.u32_at(&header, 16);
+        self.format = Format::Pcap { linktype, nanos };
+        Ok(())
+    }
+
+    fn next_pcap_packet(&mut self, linktype: u32, nanos: bool) -> io::Result<Option<PcapPacket>> {
+        let mut header = [0u8; 16];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let secs = self.u32_at(&header, 0);
+        let frac = self.u32_at(&header, 4);
+        let len = self.u32_at(&header, 8) as usize;
+        if len > SNAPLEN as usize {
+            return Err(invalid("packet is longer than the snapshot length"));
+        }
+        let mut data = vec![0u8; len];
+        self.reader.read_exact(&mut data)?;
+
+        let subsec_nanos = match nanos {
+            true => frac,
+            false => frac.saturating_mul(1000),
+        };
+        let timestamp =
+            Duration::from_secs(secs.into()) + Duration::from_nanos(subsec_nanos.into());
+        self.last_timestamp = timestamp;
+        Ok(Some(PcapPacket {
+            timestamp,
+            linktype,
+            data,
+        }))
+    }
+
+    /// Reads the rest of a section header block, after its type and length, and starts a new
+    /// section. Length is decoded, once the byte order of the section is known.
+    fn read_section_header(&mut self, len: [u8; 4]) -> io::Result<()> {
+        let mut magic = [0u8; 4];
+        self.reader.read_exact(&mut magic)?;
+        self.big_endian = match magic {
+            _ if magic == PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes() => false,
+            _ if magic == PCAPNG_BYTE_ORDER_MAGIC.to_be_bytes() => true,
+            _ => return Err(invalid("pcapng section has an unknown byte order")),
+        };
+        // Type, length and byte order magic are read, the rest is skipped
+        self.skip_block(self.u32_at(&len, 0) as usize, 12)?;
+        self.format = Format::Pcapng { interfaces: vec![] };
+        Ok(())
+    }
+
+    fn next_pcapng_packet(&mut self) -> io::Result<Option<PcapPacket>> {
+        loop {
+            let mut head = [0u8; 8];
+            if !read_exact_or_eof(&mut self.reader, &mut head)? {
+                return Ok(None);
+            }
+            let kind = self.u32_at(&head, 0);
+            if kind == PCAPNG_SECTION_HEADER {
+                self.read_section_header([head[4], head[5], head[6], head[7]])?;
+                continue;
+            }
+
+            let len = self.u32_at(&head, 4) as usize;
+            if !(12..=PCAPNG_MAX_BLOCK).contains(&len) || len % 4 != 0 {
+                return Err(invalid("pcapng block has an invalid length"));
+            }
+            // Body without the trailing length
+            let mut body = vec![0u8; len - 12];
+            self.reader.read_exact(&mut body)?;
+            self.reader.read_exact(&mut [0u8; 4])?;
+
+            match kind {
+                PCAPNG_INTERFACE_DESCRIPTION => self.add_interface(&body)?,
+                PCAPNG_ENHANCED_PACKET => return self.enhanced_packet(&body).map(Some),
+                PCAPNG_SIMPLE_PACKET => return self.simple_packet(body).map(Some),
+                _ => {}
+            }
+        }
+    }
+
+    fn add_interface(&mut self, body: &[u8]) -> io::Result<()> {
+        if body.len() < 8 {
+            return Err(invalid("pcapng interface block is truncated"));
+        }
+        let linktype = u32::from(self.u16_at(body, 0));
+        let mut resolution = Resolution::Decimal(6);
+        let mut options = &body[8..];
+        while options.len() >= 4 {
+            let code = self.u16_at(options, 0);
+            let len = usize::from(self.u16_at(options, 2));
+            let value = options.get(4..4 + len).unwrap_or_default();
+            match (code, value) {
+                (PCAPNG_OPT_END, _) => break,
+                (PCAPNG_OPT_IF_TSRESOL, &[exp]) => {
+                    resolution = match exp & 0x80 {
+                        0 => Resolution::Decimal(exp),
+                        _ => Resolution::Binary(exp & 0x7f),
+                    };
+                }
+                _ => {}
+            }
+            options = options.get(4 + pad4(len)..).unwrap_or_default();
+        }
+        if let Format::Pcapng { interfaces } = &mut self.format {
+            interfaces.push(Interface {
+                linktype,
+                resolution,
+            });
+        }
+        Ok(())
+    }
+
+    fn interface(&self, id: usize) -> io::Result<&Interface> {
+        match &self.format {
+            Format::Pcapng { interfaces } => interfaces.get(id),
+            Format::Pcap { .. } => None,
+        }
+        .ok_or_else(|| invalid("pcapng packet refers to an unknown interface"))
+    }
+
+    fn enhanced_packet(&mut self, body: &[u8]) -> io::Result<PcapPacket> {
+        if body.len() < 20 {
+            return Err(invalid("pcapng packet block is truncated"));
+        }
+        let interface = self.interface(self.u32_at(body, 0) as usize)?;
+        let ticks = u64::from(self.u32_at(body, 4)) << 32 | u64::from(self.u32_at(body, 8));
+        let (linktype, timestamp) = (interface.linktype, interface.resolution.duration(ticks));
+        let len = self.u32_at(body, 12) as usize;
+        let data = match body.get(20..20 + len) {
+            Some(data) => data.to_vec(),
+            None => return Err(invalid("pcapng packet block is truncated")),
+        };
+        self.last_timestamp = timestamp;
+        Ok(PcapPacket {
+            timestamp,
+            linktype,
+            data,
+        })
+    }
+
+    /// Simple packets belong to the first interface and have no timestamp. Their data is
+    /// limited by the block, as snapshot length is not tracked.
+    fn simple_packet(&mut self, mut body: Vec<u8>) -> io::Result<PcapPacket> {
+        if body.len() < 4 {
+            return Err(invalid("pcapng packet block is truncated"));
+        }
+        let linktype = self.interface(0)?.linktype;
+        let len = (self.u32_at(&body, 0) as usize).min(body.len() - 4);
+        body.drain(..4);
+        body.truncate(len);
+        Ok(PcapPacket {
+            timestamp: self.last_timestamp,
+            linktype,
+            data: body,
+        })
+    }
+
+    /// Skips the rest of a block of `len` bytes, `read` of which are already read.
+    fn skip_block(&mut self, len: usize, read: usize) -> io::Result<()> {
+        if len < read + 4 || len > PCAPNG_MAX_BLOCK || len % 4 != 0 {
+            return Err(invalid("pcapng block has an invalid length"));
+        }
+        let skipped = io::copy(
+            &mut (&mut self.reader).take((len - read) as u64),
+            &mut io::sink(),
+        )?;
+        match skipped == (len - read) as u64 {
+            true => Ok(()),
+            false => Err(ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    fn u32_at(&self, buf: &[u8], at: usize) -> u32 {
+        let bytes = [buf[at], buf[at + 1], buf[at + 2], buf[at + 3]];
+        match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    }
+
+    fn u16_at(&self, buf: &[u8], at: usize) -> u16 {
+        let bytes = [buf[at], buf[at + 1]];
+        match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        }
+    }
+}
+
+/// Writer of classic pcap files with microsecond timestamps, as read by every tool.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the file header with the link type of `layer`: Ethernet or raw IP.
+    pub fn new(mut writer: W, layer: Layer) -> io::Result<Self> {
+        let linktype = match layer {
+            Layer::L2 => LINKTYPE_ETHERNET,
+            Layer::L3 => LINKTYPE_RAW,
+        };
+        writer.write_all(&PCAP_MAGIC_MICROS.to_le_bytes())?;
+        // Version 2.4, UTC timestamps with unknown accuracy
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        writer.write_all(&[0; 8])?;
+        writer.write_all(&SNAPLEN.to_le_bytes())?;
+        writer.write_all(&linktype.to_le_bytes())?;
+        Ok(Self { writer })
+    }
+
+    /// Writes a packet with a timestamp since the Unix epoch. Packets, longer than the
+    /// snapshot length, are truncated.
+    pub fn write_packet(&mut self, timestamp: Duration, packet: &[u8]) -> io::Result<()> {
+        let captured = &packet[..packet.len().min(SNAPLEN as usize)];
+        self.writer
+            .write_all(&(timestamp.as_secs() as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&timestamp.subsec_micros().to_le_bytes())?;
+        self.writer
+            .write_all(&(captured.len() as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&(packet.len() as u32).to_le_bytes())?;
+        self.writer.write_all(captured)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Fills `buf`, returning `false`, if the reader is at its end. End in the middle of `buf`
+/// is an error.
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+fn pad4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pcap_roundtrip() {
+        let mut writer = PcapWriter::new(vec![], Layer::L3).unwrap();
+        writer
+            .write_packet(Duration::new(10, 5_000), &[0x45, 1])
+            .unwrap();
+        writer
+            .write_packet(Duration::new(11, 0), &[0x60, 2, 3])
+            .unwrap();
+
+        let capture = writer.into_inner();
+        let mut reader = PcapReader::new(&capture[..]).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.timestamp, Duration::new(10, 5_000));
+        assert_eq!(packet.linktype, LINKTYPE_RAW);
+        assert_eq!(packet.data, [0x45, 1]);
+        assert_eq!(reader.next_packet().unwrap().unwrap().data, [0x60, 2, 3]);
+        assert_eq!(reader.next_packet().unwrap(), None);
+    }
+
+    fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+        let len = (12 + body.len()) as u32;
+        [
+            &kind.to_le_bytes()[..],
+            &len.to_le_bytes(),
+            body,
+            &len.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn pcapng_packets_are_read() {
+        let section = [
+            &PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes()[..],
+            &[1, 0, 0, 0],
+            &(-1i64).to_le_bytes(),
+        ]
+        .concat();
+        // Ethernet interface with millisecond timestamps
+        let interface = [
+            &1u16.to_le_bytes()[..],
+            &[0; 2],
+            &SNAPLEN.to_le_bytes(),
+            &PCAPNG_OPT_IF_TSRESOL.to_le_bytes(),
+            &1u16.to_le_bytes(),
+            &[3, 0, 0, 0],
+            &[0; 4],
+        ]
+        .concat();
+        let packet = [
+            &0u32.to_le_bytes()[..],
+            &0u32.to_le_bytes(),
+            &1500u32.to_le_bytes(),
+            &3u32.to_le_bytes(),
+            &3u32.to_le_bytes(),
+            &[7, 8, 9, 0],
+        ]
+        .concat();
+        let simple = [&2u32.to_le_bytes()[..], &[5, 6, 0, 0]].concat();
+        let capture = [
+            block(PCAPNG_SECTION_HEADER, &section),
+            block(PCAPNG_INTERFACE_DESCRIPTION, &interface),
+            block(PCAPNG_ENHANCED_PACKET, &packet),
+            block(PCAPNG_SIMPLE_PACKET, &simple),
+        ]
+        .concat();
+
+        let mut reader = PcapReader::new(&capture[..]).unwrap();
+        let packet = reader.next_packet().unwrap().unwrap();
+        assert_eq!(packet.timestamp, Duration::from_millis(1500));
+        assert_eq!(packet.linktype, LINKTYPE_ETHERNET);
+        assert_eq!(packet.data, [7, 8, 9]);
+        let simple = reader.next_packet().unwrap().unwrap();
+        assert_eq!(simple.timestamp, Duration::from_millis(1500));
+        assert_eq!(simple.data, [5, 6]);
+        assert_eq!(reader.next_packet().unwrap(), None);
+    }
+}
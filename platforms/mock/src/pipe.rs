@@ -0,0 +1,371 @@
+use std::collections::VecDeque;
+use std::io;
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+use tunio_core::config::Layer;
+use tunio_core::packet::is_ipv4;
+use tunio_core::stats::BufferUsage;
+use tunio_core::sync::{Condvar, Mutex, MutexGuard};
+use tunio_core::traits::copy_packet;
+
+/// Why a pipe no longer accepts packets.
+#[derive(Clone, Copy)]
+enum Closed {
+    /// Queue was closed explicitly, reads return end of stream.
+    Shutdown,
+    /// Queue was dropped, reads fail with `BrokenPipe`.
+    Disconnected,
+}
+
+impl Closed {
+    fn read_result(self) -> io::Result<(usize, bool)> {
+        match self {
+            Closed::Shutdown => Ok((0, false)),
+            Closed::Disconnected => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "peer queue is disconnected",
+            )),
+        }
+    }
+}
+
+/// Outcome of [`Pipe::recv_packet_until`].
+#[derive(Debug, PartialEq)]
+pub enum Received {
+    Packet(Vec<u8>),
+    /// Pipe is shut down, and all buffered packets are received.
+    Closed,
+    /// No packet is received before the deadline.
+    TimedOut,
+}
+
+struct PipeState {
+    packets: VecDeque<Vec<u8>>,
+    capacity: usize,
+    capacity_bytes: Option<usize>,
+    /// Total length of buffered packets.
+    bytes: usize,
+    closed: Option<Closed>,
+    /// Layer of IPv4 packets, that are dropped instead of being buffered.
+    drop_ipv4: Option<Layer>,
+
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl PipeState {
+    fn drops(&self, buf: &[u8]) -> bool {
+        self.drop_ipv4.map_or(false, |layer| is_ipv4(buf, layer))
+    }
+
+    fn has_room(&self, len: usize) -> bool {
+        let bytes_fit = match self.capacity_bytes {
+            // Oversized packets would never fit otherwise
+            Some(capacity_bytes) => self.packets.is_empty() || self.bytes + len <= capacity_bytes,
+            None => true,
+        };
+        self.packets.len() < self.capacity && bytes_fit
+    }
+}
+
+/// Single-direction packet pipe, bounded by the number of packets and, optionally, by their
+/// total length.
+pub struct Pipe {
+    state: Mutex<PipeState>,
+    readable: Condvar,
+    writable: Condvar,
+}
+
+impl Pipe {
+    pub fn new(capacity: usize, capacity_bytes: Option<usize>) -> Self {
+        Self {
+            state: Mutex::new(PipeState {
+                packets: VecDeque::with_capacity(capacity),
+                capacity,
+                capacity_bytes,
+                bytes: 0,
+                closed: None,
+                drop_ipv4: None,
+                read_waker: None,
+                write_waker: None,
+            }),
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Makes the pipe accept and drop IPv4 packets of `layer`, for IPv6-only interfaces.
+    pub fn drop_ipv4(&self, layer: Layer) {
+        self.lock().drop_ipv4 = Some(layer);
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        let state = self.lock();
+        BufferUsage {
+            packets: state.packets.len(),
+            bytes: state.bytes,
+        }
+    }
+
+    fn pop(&self, state: &mut PipeState) -> Option<Vec<u8>> {
+        let packet = state.packets.pop_front()?;
+        state.bytes -= packet.len();
+
+        self.writable.notify_one();
+        if let Some(waker) = state.write_waker.take() {
+            waker.wake();
+        }
+        Some(packet)
+    }
+
+    fn pop_into(&self, state: &mut PipeState, buf: &mut [u8]) -> Option<(usize, bool)> {
+        let packet = self.pop(state)?;
+
+        // Just like a real TUN device, the remainder of a packet, that does not fit into
+        // the buffer, is discarded.
+        Some(copy_packet(&packet, buf))
+    }
+
+    fn push(&self, state: &mut PipeState, buf: &[u8]) {
+        state.packets.push_back(buf.to_vec());
+        state.bytes += buf.len();
+
+        self.readable.notify_one();
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        let mut state = self.lock();
+        loop {
+            if let Some(received) = self.pop_into(&mut state, buf) {
+                return Ok(received);
+            }
+            if let Some(closed) = state.closed {
+                return closed.read_result();
+            }
+            state = self.readable.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Receives a packet without copying it. Returns `None`, when the pipe is shut down, so
+    /// that empty packets are told apart from the end of stream.
+    pub fn recv_packet(&self) -> io::Result<Option<Vec<u8>>> {
+        match self.recv_packet_until(None)? {
+            Received::Packet(packet) => Ok(Some(packet)),
+            Received::Closed | Received::TimedOut => Ok(None),
+        }
+    }
+
+    /// Like [`recv_packet`](Self::recv_packet), but gives up at the deadline.
+    pub fn recv_packet_until(&self, deadline: Option<Instant>) -> io::Result<Received> {
+        let mut state = self.lock();
+        loop {
+            if let Some(packet) = self.pop(&mut state) {
+                return Ok(Received::Packet(packet));
+            }
+            if let Some(closed) = state.closed {
+                return closed.read_result().map(|_| Received::Closed);
+            }
+            state = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if !timeout.is_zero() => self
+                        .readable
+                        .wait_timeout(state, timeout)
+                        .map_or_else(|e| e.into_inner().0, |(state, _)| state),
+                    _ => return Ok(Received::TimedOut),
+                },
+                None => self.readable.wait(state).unwrap_or_else(|e| e.into_inner()),
+            };
+        }
+    }
+
+    pub fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        let mut state = self.lock();
+        if let Some(received) = self.pop_into(&mut state, buf) {
+            return Poll::Ready(Ok(received));
+        }
+        if let Some(closed) = state.closed {
+            return Poll::Ready(closed.read_result());
+        }
+        state.read_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Ready, when a packet is buffered or the pipe is closed. Packet is not taken.
+    pub fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.lock();
+        if !state.packets.is_empty() || state.closed.is_some() {
+            return Poll::Ready(());
+        }
+        state.read_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Discards buffered packets, returning their number.
+    pub fn drain(&self) -> usize {
+        let mut state = self.lock();
+        let mut drained = 0;
+        while self.pop(&mut state).is_some() {
+            drained += 1;
+        }
+        drained
+    }
+
+    pub fn send(&self, buf: &[u8]) -> io::Result<()> {
+        let mut state = self.lock();
+        loop {
+            if state.closed.is_some() {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            if state.drops(buf) {
+                return Ok(());
+            }
+            if state.has_room(buf.len()) {
+                self.push(&mut state, buf);
+                return Ok(());
+            }
+            state = self.writable.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    pub fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<()>> {
+        let mut state = self.lock();
+        if state.closed.is_some() {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if state.drops(buf) {
+            return Poll::Ready(Ok(()));
+        }
+        if state.has_room(buf.len()) {
+            self.push(&mut state, buf);
+            return Poll::Ready(Ok(()));
+        }
+        state.write_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Shuts the pipe down: packets, that are already buffered, are still received, and then
+    /// reads return end of stream.
+    pub fn close(&self) {
+        self.close_with(Closed::Shutdown);
+    }
+
+    /// Like [`close`](Self::close), but reads fail with `BrokenPipe` instead of end of stream.
+    /// Pipes are disconnected, when their queue is dropped without closing it.
+    pub fn disconnect(&self) {
+        self.close_with(Closed::Disconnected);
+    }
+
+    fn close_with(&self, reason: Closed) {
+        let mut state = self.lock();
+        // Reason of the first close is kept: a closed queue is dropped later
+        state.closed.get_or_insert(reason);
+
+        self.readable.notify_all();
+        self.writable.notify_all();
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = state.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod tests {
+    use super::*;
+    use futures::future::poll_fn;
+    use loom::sync::Arc;
+    use loom::thread;
+
+    #[test]
+    fn send_wakes_pending_reader() {
+        loom::model(|| {
+            let pipe = Arc::new(Pipe::new(1, None));
+            let reader = {
+                let pipe = pipe.clone();
+                thread::spawn(move || {
+                    let mut buf = [0u8; 4];
+                    loom::future::block_on(poll_fn(|cx| pipe.poll_recv(cx, &mut buf))).unwrap()
+                })
+            };
+            pipe.send(&[1, 2]).unwrap();
+            assert_eq!(reader.join().unwrap(), (2, false));
+        });
+    }
+
+    #[test]
+    fn recv_wakes_pending_writer() {
+        loom::model(|| {
+            let pipe = Arc::new(Pipe::new(1, None));
+            pipe.send(&[1]).unwrap();
+            let writer = {
+                let pipe = pipe.clone();
+                thread::spawn(move || {
+                    loom::future::block_on(poll_fn(|cx| pipe.poll_send(cx, &[2]))).unwrap()
+                })
+            };
+            assert_eq!(pipe.recv_packet().unwrap().unwrap(), [1]);
+            writer.join().unwrap();
+            assert_eq!(pipe.recv_packet().unwrap().unwrap(), [2]);
+        });
+    }
+
+    #[test]
+    fn close_wakes_blocked_reader() {
+        loom::model(|| {
+            let pipe = Arc::new(Pipe::new(1, None));
+            let reader = {
+                let pipe = pipe.clone();
+                thread::spawn(move || pipe.recv(&mut [0u8; 4]).unwrap())
+            };
+            pipe.close();
+            assert_eq!(reader.join().unwrap(), (0, false));
+        });
+    }
+
+    #[test]
+    fn close_wakes_pending_writer() {
+        loom::model(|| {
+            let pipe = Arc::new(Pipe::new(1, None));
+            pipe.send(&[1]).unwrap();
+            let writer = {
+                let pipe = pipe.clone();
+                thread::spawn(move || {
+                    loom::future::block_on(poll_fn(|cx| pipe.poll_send(cx, &[2])))
+                })
+            };
+            pipe.close();
+            let err = writer.join().unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        });
+    }
+
+    #[test]
+    fn packets_sent_before_close_are_received() {
+        loom::model(|| {
+            let pipe = Arc::new(Pipe::new(2, None));
+            let writer = {
+                let pipe = pipe.clone();
+                thread::spawn(move || {
+                    pipe.send(&[1]).unwrap();
+                    pipe.close();
+                })
+            };
+            assert_eq!(pipe.recv_packet().unwrap().unwrap(), [1]);
+            assert_eq!(pipe.recv_packet().unwrap(), None);
+            writer.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,185 @@
+use super::pipe::Pipe;
+use futures::{AsyncRead, AsyncWrite};
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tunio_core::budget::PollBudget;
+use tunio_core::config::Layer;
+use tunio_core::stats::BufferUsage;
+use tunio_core::traits::{AsyncQueueT, SyncQueueT};
+
+pub trait PipeQueueT {
+    fn new(rx: Arc<Pipe>, tx: Arc<Pipe>) -> Self;
+
+    /// Sets the number of packets, read in a row before yielding. Only used by async queues.
+    fn set_poll_budget(&mut self, _limit: Option<usize>) {}
+
+    /// Packets, buffered for reading.
+    fn buffered(&self) -> BufferUsage;
+
+    /// Drops IPv4 packets of `layer`, that the peer writes to this queue.
+    fn drop_ipv4(&self, layer: Layer);
+}
+
+pub struct SyncPipeQueue {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+}
+
+impl SyncQueueT for SyncPipeQueue {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.rx.recv(buf)
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.tx.send(packet)
+    }
+
+    type PacketRef<'a> = Vec<u8>;
+
+    /// Returns an empty packet at the end of stream.
+    fn recv_ref(&mut self) -> io::Result<Vec<u8>> {
+        Ok(self.rx.recv_packet()?.unwrap_or_default())
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        Ok(self.rx.drain())
+    }
+}
+
+impl PipeQueueT for SyncPipeQueue {
+    fn new(rx: Arc<Pipe>, tx: Arc<Pipe>) -> Self {
+        Self { rx, tx }
+    }
+
+    fn buffered(&self) -> BufferUsage {
+        self.rx.usage()
+    }
+
+    fn drop_ipv4(&self, layer: Layer) {
+        self.rx.drop_ipv4(layer);
+    }
+}
+
+impl Drop for SyncPipeQueue {
+    fn drop(&mut self) {
+        self.rx.disconnect();
+        self.tx.disconnect();
+    }
+}
+
+impl Read for SyncPipeQueue {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
+    }
+}
+
+impl Write for SyncPipeQueue {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct AsyncPipeQueue {
+    rx: Arc<Pipe>,
+    tx: Arc<Pipe>,
+    budget: PollBudget,
+}
+
+impl PipeQueueT for AsyncPipeQueue {
+    fn new(rx: Arc<Pipe>, tx: Arc<Pipe>) -> Self {
+        Self {
+            rx,
+            tx,
+            budget: PollBudget::default(),
+        }
+    }
+
+    fn set_poll_budget(&mut self, limit: Option<usize>) {
+        self.budget.set_limit(limit);
+    }
+
+    fn buffered(&self) -> BufferUsage {
+        self.rx.usage()
+    }
+
+    fn drop_ipv4(&self, layer: Layer) {
+        self.rx.drop_ipv4(layer);
+    }
+}
+
+impl Drop for AsyncPipeQueue {
+    fn drop(&mut self) {
+        self.rx.disconnect();
+        self.tx.disconnect();
+    }
+}
+
+impl AsyncQueueT for AsyncPipeQueue {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.budget.poll_proceed(cx));
+
+        let result = self_mut.rx.poll_recv(cx, buf);
+        if result.is_pending() {
+            self_mut.budget.reset();
+        }
+        result
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        self.tx.poll_send(cx, packet)
+    }
+
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.rx.poll_recv_ready(cx).map(Ok)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        Ok(self.rx.drain())
+    }
+}
+
+impl AsyncRead for AsyncPipeQueue {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl AsyncWrite for AsyncPipeQueue {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Closing the queue closes both directions, just like dropping it
+        self.rx.close();
+        self.tx.close();
+        Poll::Ready(Ok(()))
+    }
+}
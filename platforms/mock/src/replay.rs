@@ -0,0 +1,319 @@
+//! Interfaces, that replay captured packets as received ones and record written packets, for
+//! testing protocol logic against real-world traffic.
+use super::interface::{new_link, new_pipe, MockInterface};
+use super::pcap::{linktype_layer, PcapReader, PcapWriter};
+use super::pipe::Pipe;
+use super::queue::PipeQueueT;
+use super::{Driver, PlatformIfConfig};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tunio_core::config::{IfConfig, Layer};
+use tunio_core::Error;
+
+/// Pacing of replayed packets.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ReplayTiming {
+    /// Packets are received as fast as they are read, so that results do not depend on the
+    /// speed of the machine. Replay waits, while the read buffer is full.
+    Immediate,
+    /// Gaps between packets are kept, as they were captured.
+    Original,
+    /// Gaps are multiplied by the factor: replay is faster below 1 and slower above it.
+    Scaled(f64),
+}
+
+/// Capture, that is replayed by [`MockInterface::new_replay`], and an optional recording of
+/// written packets.
+pub struct Replay {
+    input: PcapReader<Box<dyn Read + Send>>,
+    output: Option<Box<dyn Write + Send>>,
+    timing: ReplayTiming,
+}
+
+impl Replay {
+    /// Replays a pcap or pcapng capture from `input`. Fails with `InvalidData`, if it is
+    /// neither.
+    pub fn new(input: impl Read + Send + 'static) -> io::Result<Self> {
+        Ok(Self {
+            input: PcapReader::new(Box::new(input) as Box<dyn Read + Send>)?,
+            output: None,
+            timing: ReplayTiming::Immediate,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+
+    pub fn timing(mut self, timing: ReplayTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Records packets, written to the interface, as a pcap capture with the link type of
+    /// the interface. Written packets are discarded otherwise.
+    pub fn record(mut self, output: impl Write + Send + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    pub fn record_to(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(self.record(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Packets, that were replayed and recorded.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct ReplayStats {
+    pub replayed: u64,
+    pub recorded: u64,
+}
+
+/// Threads, that replay and record packets of an interface.
+pub struct ReplayHandle {
+    player: JoinHandle<io::Result<u64>>,
+    recorder: JoinHandle<io::Result<u64>>,
+}
+
+impl ReplayHandle {
+    /// Waits for the replay and the recording to finish, and returns the first error of them.
+    ///
+    /// Replay finishes at the end of the capture, or when the interface is dropped. Recording
+    /// finishes, when the interface is dropped or closed, so this must be called after that.
+    pub fn join(self) -> io::Result<ReplayStats> {
+        let replayed = self.player.join().unwrap_or_else(|_| Err(panicked()));
+        let recorded = self.recorder.join().unwrap_or_else(|_| Err(panicked()));
+        Ok(ReplayStats {
+            replayed: replayed?,
+            recorded: recorded?,
+        })
+    }
+}
+
+impl<Q: PipeQueueT> MockInterface<Q> {
+    /// Creates an interface, that receives packets of a capture, and records packets, written
+    /// to it, if the replay is configured so.
+    ///
+    /// Reads return end of stream after the last packet. If a packet of the capture has a link
+    /// type, that does not match the layer of the interface, or the capture is broken, reads
+    /// fail with `BrokenPipe`, and [`ReplayHandle::join`] returns the error.
+    pub fn new_replay(
+        driver: &mut Driver,
+        params: IfConfig<PlatformIfConfig>,
+        replay: Replay,
+    ) -> Result<(Self, ReplayHandle), Error> {
+        let (feed, rx) = new_link(&params.platform)?;
+        let tx = new_pipe(&params.platform)?;
+        let layer = params.layer;
+
+        let Replay {
+            input,
+            output,
+            timing,
+        } = replay;
+        let player = {
+            thread::Builder::new()
+                .name(format!("tunio-replay-{}", params.name))
+                .spawn(move || play(input, &feed, layer, timing))?
+        };
+        let recorder = {
+            let tx = tx.clone();
+            let output = output
+                .map(|output| PcapWriter::new(output, layer))
+                .transpose()?;
+            thread::Builder::new()
+                .name(format!("tunio-record-{}", params.name))
+                .spawn(move || record(output, &tx))?
+        };
+
+        let interface = Self::with_queue(driver, params, Q::new(rx, tx));
+        Ok((interface, ReplayHandle { player, recorder }))
+    }
+}
+
+/// Sends packets of the capture to the pipe, until the capture ends or the pipe is
+/// disconnected. Returns the number of sent packets.
+fn play(
+    mut input: PcapReader<Box<dyn Read + Send>>,
+    rx: &Pipe,
+    layer: Layer,
+    timing: ReplayTiming,
+) -> io::Result<u64> {
+    let start = Instant::now();
+    let mut first_timestamp = None;
+    let mut replayed = 0;
+    loop {
+        let packet = match input.next_packet() {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break,
+            Err(err) => {
+                rx.disconnect();
+                return Err(err);
+            }
+        };
+        if linktype_layer(packet.linktype) != Some(layer) {
+            rx.disconnect();
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "capture has link type {}, interface is {layer:?}",
+                    packet.linktype
+                ),
+            ));
+        }
+
+        let offset = packet
+            .timestamp
+            .saturating_sub(*first_timestamp.get_or_insert(packet.timestamp));
+        let due = match timing {
+            ReplayTiming::Immediate => None,
+            ReplayTiming::Original => Some(offset),
+            ReplayTiming::Scaled(factor) => Some(offset.mul_f64(factor.max(0.0))),
+        };
+        if let Some(due) = due {
+            thread::sleep(due.saturating_sub(start.elapsed()));
+        }
+
+        match rx.send(&packet.data) {
+            Ok(()) => replayed += 1,
+            // Interface is dropped
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => return Ok(replayed),
+            Err(err) => return Err(err),
+        }
+    }
+    rx.close();
+    Ok(replayed)
+}
+
+/// Writes packets of the pipe, until it is closed or disconnected. Returns the number of
+/// written packets.
+fn record(mut output: Option<PcapWriter<Box<dyn Write + Send>>>, tx: &Pipe) -> io::Result<u64> {
+    let mut recorded = 0;
+    loop {
+        let packet = match tx.recv_packet() {
+            Ok(Some(packet)) => packet,
+            Ok(None) => break,
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => break,
+            Err(err) => return Err(err),
+        };
+        if let Some(output) = &mut output {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            output.write_packet(timestamp, &packet)?;
+            recorded += 1;
+        }
+    }
+    if let Some(output) = &mut output {
+        output.flush()?;
+    }
+    Ok(recorded)
+}
+
+fn panicked() -> io::Error {
+    io::Error::new(ErrorKind::Other, "replay thread panicked")
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::{Interface, SyncPipeQueue};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tunio_core::traits::{DriverT, InterfaceT, SyncQueueT};
+
+    /// Output, that stays readable after the recorder drops its writer.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capture(layer: Layer, packets: &[&[u8]]) -> Vec<u8> {
+        let mut writer = PcapWriter::new(vec![], layer).unwrap();
+        for (i, packet) in packets.iter().enumerate() {
+            let timestamp = Duration::from_millis(i as u64 * 10);
+            writer.write_packet(timestamp, packet).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn config() -> IfConfig<PlatformIfConfig> {
+        MockInterface::<SyncPipeQueue>::config_builder()
+            .name("replay".to_string())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn captured_packets_are_replayed_and_written_ones_recorded() {
+        let input = capture(Layer::L3, &[&[0x45, 1], &[0x60, 2]]);
+        let output = Shared::default();
+        let replay = Replay::new(io::Cursor::new(input))
+            .unwrap()
+            .timing(ReplayTiming::Scaled(0.1))
+            .record(output.clone());
+        let mut driver = Driver::new().unwrap();
+        let (mut interface, handle): (Interface, _) =
+            MockInterface::new_replay(&mut driver, config(), replay).unwrap();
+
+        assert_eq!(interface.recv_ref().unwrap(), [0x45, 1]);
+        interface.send(&[0x45, 3]).unwrap();
+        assert_eq!(interface.recv_ref().unwrap(), [0x60, 2]);
+        assert_eq!(interface.recv(&mut [0u8; 4]).unwrap(), (0, false));
+        drop(interface);
+
+        let stats = handle.join().unwrap();
+        assert_eq!(
+            stats,
+            ReplayStats {
+                replayed: 2,
+                recorded: 1
+            }
+        );
+        let recorded = output.0.lock().unwrap().clone();
+        let mut reader = PcapReader::new(&recorded[..]).unwrap();
+        assert_eq!(reader.next_packet().unwrap().unwrap().data, [0x45, 3]);
+        assert_eq!(reader.next_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn empty_written_packets_are_recorded() {
+        let replay = Replay::new(io::Cursor::new(capture(Layer::L3, &[])))
+            .unwrap()
+            .record(Shared::default());
+        let mut driver = Driver::new().unwrap();
+        let (mut interface, handle): (Interface, _) =
+            MockInterface::new_replay(&mut driver, config(), replay).unwrap();
+
+        interface.send(&[]).unwrap();
+        interface.send(&[0x45, 3]).unwrap();
+        drop(interface);
+        assert_eq!(handle.join().unwrap().recorded, 2);
+    }
+
+    #[test]
+    fn link_type_must_match_layer() {
+        let input = capture(Layer::L2, &[&[0; 14]]);
+        let replay = Replay::new(io::Cursor::new(input)).unwrap();
+        let mut driver = Driver::new().unwrap();
+        let (mut interface, handle): (Interface, _) =
+            MockInterface::new_replay(&mut driver, config(), replay).unwrap();
+
+        let err = interface.recv(&mut [0u8; 16]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        drop(interface);
+        assert_eq!(handle.join().unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,213 @@
+use super::queue::{AsyncNullQueue, NullQueueT, SyncNullQueue};
+use super::Driver;
+use super::PlatformIfConfig;
+use futures::{AsyncRead, AsyncWrite};
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tunio_core::config::{IfConfig, NameOutcome};
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::stats::{QueueStats, StatsCounters};
+use tunio_core::traits::{AsyncQueueT, InterfaceT, SyncQueueT};
+use tunio_core::Error;
+
+pub struct NullInterface<Q> {
+    name: String,
+    up: bool,
+    events: EventEmitter,
+    stats: StatsCounters,
+    queue: Q,
+}
+
+impl<Q> NullInterface<Q> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Null interfaces are not visible to the OS, so their names never conflict.
+    pub fn name_outcome(&self) -> NameOutcome {
+        NameOutcome::Created
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+
+    /// Counters of discarded packets.
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+}
+
+impl<Q> Drop for NullInterface<Q> {
+    fn drop(&mut self) {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+    }
+}
+
+impl<Q: NullQueueT> InterfaceT for NullInterface<Q> {
+    type PlatformDriver = Driver;
+    type PlatformIfConfig = PlatformIfConfig;
+
+    fn new(
+        driver: &mut Self::PlatformDriver,
+        params: IfConfig<Self::PlatformIfConfig>,
+    ) -> Result<Self, Error> {
+        driver.events.emit(&params.name, EventKind::Created);
+
+        Ok(Self {
+            name: params.name,
+            up: false,
+            events: driver.events.clone(),
+            stats: StatsCounters::new(params.layer),
+            queue: Q::new(),
+        })
+    }
+
+    fn up(&mut self) -> Result<(), Error> {
+        self.up = true;
+        self.events.emit(&self.name, EventKind::SessionStarted);
+        Ok(())
+    }
+
+    fn down(&mut self) -> Result<(), Error> {
+        self.up = false;
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+        Ok(())
+    }
+
+    /// Null interfaces are not registered in OS, so returned handle does not point to any
+    /// existing interface.
+    fn handle(&self) -> netconfig::Interface {
+        netconfig::Interface::from_index_unchecked(0)
+    }
+}
+
+pub type Interface = NullInterface<SyncNullQueue>;
+impl<Q: SyncQueueT> SyncQueueT for NullInterface<Q> {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.queue.recv(buf)
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.queue.send(packet)?;
+        self.stats.record_tx(packet);
+        Ok(())
+    }
+
+    type PacketRef<'a>
+        = Q::PacketRef<'a>
+    where
+        Q: 'a;
+
+    fn recv_ref(&mut self) -> io::Result<Self::PacketRef<'_>> {
+        self.queue.recv_ref()
+    }
+}
+
+impl<Q: SyncQueueT> Read for NullInterface<Q> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
+    }
+}
+
+impl<Q: SyncQueueT> Write for NullInterface<Q> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.queue.flush()
+    }
+}
+
+pub type AsyncInterface = NullInterface<AsyncNullQueue>;
+impl<Q: AsyncQueueT> AsyncQueueT for NullInterface<Q> {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        Pin::new(&mut self.get_mut().queue).poll_recv(cx, buf)
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        Pin::new(&mut self_mut.queue)
+            .poll_send(cx, packet)
+            .map_ok(|()| self_mut.stats.record_tx(packet))
+    }
+
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().queue).poll_recv_ready(cx)
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncRead for NullInterface<Q> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncWrite for NullInterface<Q> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().queue).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().queue).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::{AsyncReadExt, AsyncWriteExt, FutureExt};
+    use tunio_core::traits::DriverT;
+
+    fn config() -> IfConfig<PlatformIfConfig> {
+        Interface::config_builder()
+            .name("null0".to_string())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn writes_are_discarded_and_reads_are_empty() {
+        let mut driver = Driver::new().unwrap();
+        let mut interface = Interface::new_up(&mut driver, config()).unwrap();
+        interface.send(&[0x60, 1]).unwrap();
+        assert_eq!(interface.stats().tx.packets, 1);
+        let err = interface.recv(&mut [0u8; 4]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        interface.down().unwrap();
+    }
+
+    #[test]
+    fn async_reads_end_on_close() {
+        let mut driver = Driver::new().unwrap();
+        let mut interface = AsyncInterface::new_up(&mut driver, config()).unwrap();
+        let mut buf = [0u8; 4];
+        assert!(interface.read(&mut buf).now_or_never().is_none());
+        block_on(interface.write_all(&[0x60, 1])).unwrap();
+        block_on(interface.close()).unwrap();
+        assert_eq!(block_on(interface.read(&mut buf)).unwrap(), 0);
+    }
+}
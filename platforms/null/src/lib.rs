@@ -0,0 +1,53 @@
+//! # Null backend for tunio.
+//!
+//! Interfaces, created by this driver, accept writes and discard them, and never produce any
+//! packets. This backend needs no OS driver and no privileges, so code, written against tunio
+//! traits, can run construction, configuration and teardown paths in CI on any platform.
+//!
+//! Supported features:
+//! - Any number of interfaces with any names
+//! - Sync and async mode, reads never return packets
+
+mod interface;
+mod queue;
+
+use derive_builder::Builder;
+use tunio_core::events::{EventEmitter, EventReceiver};
+use tunio_core::traits::{DriverT, PlatformIfConfigT};
+use tunio_core::Error;
+
+pub use interface::{AsyncInterface, Interface, NullInterface};
+pub use queue::{AsyncNullQueue, NullQueueT, SyncNullQueue};
+
+pub struct Driver {
+    pub(crate) events: EventEmitter,
+}
+
+/// Null interfaces have no platform settings.
+#[derive(Builder, Clone, Default)]
+pub struct PlatformIfConfig {}
+
+impl PlatformIfConfigT for PlatformIfConfig {
+    type Builder = PlatformIfConfigBuilder;
+
+    /// Null interfaces are not visible to the OS, so any name is accepted.
+    const MAX_NAME_LEN: usize = usize::MAX;
+
+    fn check_name(_name: &str) -> Option<String> {
+        None
+    }
+}
+
+impl DriverT for Driver {
+    type PlatformIfConfig = PlatformIfConfig;
+
+    fn new() -> Result<Self, Error> {
+        Ok(Self {
+            events: EventEmitter::default(),
+        })
+    }
+
+    fn subscribe(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+}
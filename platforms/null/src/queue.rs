@@ -0,0 +1,125 @@
+use futures::{AsyncRead, AsyncWrite};
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tunio_core::traits::{AsyncQueueT, SyncQueueT};
+
+pub trait NullQueueT {
+    fn new() -> Self;
+}
+
+/// Queue, that discards written packets. Reads fail with `WouldBlock`, like reads of an idle
+/// non-blocking device, so that they never hang a test.
+pub struct SyncNullQueue;
+
+impl NullQueueT for SyncNullQueue {
+    fn new() -> Self {
+        Self
+    }
+}
+
+impl SyncQueueT for SyncNullQueue {
+    fn recv(&mut self, _buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    fn send(&mut self, _packet: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    type PacketRef<'a> = Vec<u8>;
+
+    fn recv_ref(&mut self) -> io::Result<Vec<u8>> {
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+}
+
+impl Read for SyncNullQueue {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
+    }
+}
+
+impl Write for SyncNullQueue {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Queue, that discards written packets. Reads stay pending, until the queue is closed, and
+/// then return end of stream.
+#[derive(Default)]
+pub struct AsyncNullQueue {
+    closed: bool,
+}
+
+impl NullQueueT for AsyncNullQueue {
+    fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl AsyncQueueT for AsyncNullQueue {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        match self.closed {
+            true => Poll::Ready(Ok((0, false))),
+            // Nothing ever arrives, so there is nothing to wake the task for
+            false => Poll::Pending,
+        }
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        match self.closed {
+            true => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+            false => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_recv_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.closed {
+            true => Poll::Ready(Ok(())),
+            false => Poll::Pending,
+        }
+    }
+}
+
+impl AsyncRead for AsyncNullQueue {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
+impl AsyncWrite for AsyncNullQueue {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
@@ -4,19 +4,35 @@ use delegate::delegate;
 use futures::{AsyncRead, AsyncWrite};
 use netconfig::sys::InterfaceHandleExt;
 use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
 use std::pin::Pin;
-use std::task::{Context, Poll};
-use tunio_core::config::IfConfig;
+use std::task::{ready, Context, Poll};
+use tunio_core::config::{IfConfig, Layer, NameConflict, NameOutcome};
+use tunio_core::diagnostics::Diagnostics;
+use tunio_core::egress::{EgressPriority, EgressScheduler};
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::name;
+use tunio_core::pause::PauseHandle;
 use tunio_core::queue::syncfd::SyncFdQueue;
 #[cfg(feature = "tokio")]
 use tunio_core::queue::tokiofd::TokioFdQueue;
 use tunio_core::queue::FdQueueT;
+use tunio_core::shaper::RateLimiter;
+use tunio_core::stats::{QueueStats, StatsCounters};
 use tunio_core::traits::{AsyncQueueT, InterfaceT, SyncQueueT};
 use tunio_core::Error;
 
 pub struct UtunInterface<Q> {
     name: String,
-    queue: Q,
+    name_outcome: NameOutcome,
+    diagnostics: Diagnostics,
+    events: EventEmitter,
+    pause: PauseHandle,
+    stats: StatsCounters,
+    shaper: RateLimiter,
+    egress: EgressScheduler,
+    queue: Option<Q>,
 }
 
 impl<Q: FdQueueT> InterfaceT for UtunInterface<Q> {
@@ -24,14 +40,70 @@ impl<Q: FdQueueT> InterfaceT for UtunInterface<Q> {
     type PlatformIfConfig = PlatformIfConfig;
 
     fn new_up(
-        _driver: &mut Self::PlatformDriver,
+        driver: &mut Self::PlatformDriver,
         params: IfConfig<Self::PlatformIfConfig>,
     ) -> Result<Self, Error> {
-        let queue = Q::new(create_device(&params.name, Q::BLOCKING)?);
+        if let Some(tag) = params.tag {
+            return Err(Error::InvalidConfigValue {
+                name: "tag".to_string(),
+                value: tag,
+                reason: "utun interfaces cannot be tagged".to_string(),
+            });
+        }
+        if params.ipv6_only {
+            return Err(Error::InvalidConfigValue {
+                name: "ipv6_only".to_string(),
+                value: "true".to_string(),
+                reason: "utun interfaces cannot disable IPv4".to_string(),
+            });
+        }
+        if params.instance_lock {
+            return Err(Error::InvalidConfigValue {
+                name: "instance_lock".to_string(),
+                value: "true".to_string(),
+                reason: "utun interfaces cannot be locked".to_string(),
+            });
+        }
+        // utun devices only live while their descriptor is open, so they cannot be adopted
+        let policy = match params.name_conflict {
+            NameConflict::Adopt => NameConflict::Fail,
+            policy => policy,
+        };
+        let name = name::normalize::<PlatformIfConfig>(&params.name, params.name_overflow)?;
+        let (name, name_outcome) = name::resolve(&name, policy, Self::max_name_len(), |name| {
+            nix::net::if_::if_nametoindex(name).is_ok()
+        })?;
+        let mut diagnostics = Diagnostics::new("utun");
+        diagnostics.step(
+            format!("resolve name \"{}\"", params.name),
+            format!("{name} ({name_outcome:?})"),
+        );
+        let device = create_device(&name, Q::BLOCKING)?;
+        diagnostics.step(
+            format!("connect utun control \"{name}\""),
+            format!("fd {}", device.as_raw_fd()),
+        );
+        if params.detailed_diagnostics {
+            let release = nix::sys::utsname::uname()
+                .map(|uname| uname.release().to_string_lossy().into_owned())
+                .unwrap_or_else(|err| format!("error: {err}"));
+            diagnostics.property("kernel", release);
+        }
+        let mut queue = Q::new(device)?;
+        queue.set_poll_budget(params.poll_budget);
+        queue.set_read_coalescing(params.read_coalescing);
+        driver.events.emit(&name, EventKind::Created);
 
         Ok(Self {
-            name: params.name,
-            queue,
+            name,
+            name_outcome,
+            diagnostics,
+            events: driver.events.clone(),
+            pause: PauseHandle::default(),
+            stats: StatsCounters::new(Layer::L3),
+            shaper: RateLimiter::default(),
+            egress: EgressScheduler::new(Layer::L3),
+            queue: Some(queue),
         })
     }
 
@@ -45,12 +117,15 @@ impl<Q: FdQueueT> InterfaceT for UtunInterface<Q> {
     }
 
     fn up(&mut self) -> Result<(), Error> {
-        Ok(self.handle().set_flags(
+        self.handle().set_flags(
             (libc::IFF_POINTOPOINT | libc::IFF_MULTICAST | libc::IFF_UP | libc::IFF_RUNNING) as _,
-        )?)
+        )?;
+        self.events.emit(&self.name, EventKind::SessionStarted);
+        Ok(())
     }
 
     fn down(&mut self) -> Result<(), Error> {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
         Ok(self
             .handle()
             .set_flags((libc::IFF_POINTOPOINT | libc::IFF_MULTICAST) as _)?)
@@ -65,24 +140,179 @@ impl<Q> UtunInterface<Q> {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Whether the interface was created with the requested or a suffixed name. See
+    /// [`IfConfig::name_conflict`].
+    pub fn name_outcome(&self) -> NameOutcome {
+        self.name_outcome
+    }
+
+    /// What was done while creating the interface, for bug reports. See
+    /// [`IfConfig::detailed_diagnostics`].
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Limits the rate of packets, written to the interface, to `bytes_per_sec` with bursts
+    /// of up to `burst` bytes. Writes wait for the bucket to be replenished.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: u64, burst: u64) {
+        self.shaper.set(bytes_per_sec, burst);
+    }
+
+    pub fn clear_rate_limit(&mut self) {
+        self.shaper.clear();
+    }
+
+    /// Lets packets, selected by `policy`, bypass up to `backlog` bulk packets, queued while
+    /// async writes are pending. Has no effect on sync interfaces.
+    pub fn set_egress_priority(&mut self, policy: EgressPriority, backlog: usize) {
+        self.egress.set_policy(policy, backlog);
+    }
+
+    /// Packet and ECN counters of this interface.
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+
+    /// Stops taking packets from the device, until [`resume`](Self::resume) is called.
+    /// Pending and subsequent reads wait, while the kernel queue applies backpressure.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns a handle, that pauses and resumes this interface from another task or thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// utun devices have no link-layer multicast filter, so this fails with
+    /// [`Unsupported`](io::ErrorKind::Unsupported).
+    pub fn set_allmulti(&self, _enabled: bool) -> Result<(), Error> {
+        Err(io::Error::from(io::ErrorKind::Unsupported).into())
+    }
+
+    /// utun devices are point-to-point and never use ARP: disabling it does nothing, and
+    /// enabling it fails with [`Unsupported`](io::ErrorKind::Unsupported).
+    pub fn set_arp(&self, enabled: bool) -> Result<(), Error> {
+        match enabled {
+            true => Err(io::Error::from(io::ErrorKind::Unsupported).into()),
+            false => Ok(()),
+        }
+    }
+
+    fn write_failed(&self, err: io::Error) -> io::Error {
+        if err.kind() != io::ErrorKind::WouldBlock {
+            self.events
+                .emit(&self.name, EventKind::WriteFailed(err.kind()));
+        }
+        err
+    }
+
+    fn packet_read(&self, buf: &[u8], n: usize) -> usize {
+        self.stats.record_rx(&buf[..n]);
+        n
+    }
+
+    fn packet_written(&mut self, buf: &[u8], n: usize) -> usize {
+        self.stats.record_tx(&buf[..n]);
+        self.shaper.consume(n);
+        n
+    }
+}
+
+impl<Q: FdQueueT> UtunInterface<Q> {
+    /// Consumes the interface, returning the device descriptor. Interface stays alive while
+    /// the descriptor is open.
+    pub fn into_fd(mut self) -> OwnedFd {
+        self.queue.take().expect(QUEUE_TAKEN).into_fd()
+    }
+}
+
+/// Queue is only taken by [`into_fd`](UtunInterface::into_fd), that consumes the interface.
+const QUEUE_TAKEN: &str = "queue is taken by into_fd";
+
+impl<Q> UtunInterface<Q> {
+    fn queue(&self) -> &Q {
+        self.queue.as_ref().expect(QUEUE_TAKEN)
+    }
+
+    fn queue_mut(&mut self) -> &mut Q {
+        self.queue.as_mut().expect(QUEUE_TAKEN)
+    }
+}
+
+/// Device descriptor, for advanced users, who want to drive readiness themselves. See [`FdQueueT`]
+/// for the safety contract.
+impl<Q: FdQueueT> AsRawFd for UtunInterface<Q> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.queue().as_raw_fd()
+    }
+}
+
+impl<Q: FdQueueT> AsFd for UtunInterface<Q> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.queue().as_fd()
+    }
+}
+
+impl<Q> Drop for UtunInterface<Q> {
+    fn drop(&mut self) {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+    }
 }
 
 pub type Interface = UtunInterface<SyncFdQueue>;
 
-impl SyncQueueT for Interface {}
+impl<Q: SyncQueueT> SyncQueueT for UtunInterface<Q> {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.pause.wait_resumed();
+        let (n, truncated) = self.queue_mut().recv(buf)?;
+        Ok((self.packet_read(buf, n), truncated))
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.shaper.wait_ready(packet.len());
+        let result = self.queue_mut().send(packet);
+        result.map_err(|e| self.write_failed(e))?;
+        self.packet_written(packet, packet.len());
+        Ok(())
+    }
+
+    type PacketRef<'a>
+        = Q::PacketRef<'a>
+    where
+        Q: 'a;
+
+    fn recv_ref(&mut self) -> io::Result<Self::PacketRef<'_>> {
+        self.pause.wait_resumed();
+        let stats = &self.stats;
+        let packet = self.queue.as_mut().expect(QUEUE_TAKEN).recv_ref()?;
+        stats.record_rx(&packet);
+        Ok(packet)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        SyncQueueT::drain(self.queue_mut())
+    }
+}
 
 impl<Q: SyncQueueT> Read for UtunInterface<Q> {
-    delegate! {
-        to self.queue {
-            fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error>;
-        }
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
     }
 }
 
 impl<Q: SyncQueueT> Write for UtunInterface<Q> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
     delegate! {
-        to self.queue {
-            fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
+        to self.queue_mut() {
             fn flush(&mut self) -> io::Result<()>;
         }
     }
@@ -90,23 +320,94 @@ impl<Q: SyncQueueT> Write for UtunInterface<Q> {
 
 #[cfg(feature = "tokio")]
 pub type TokioInterface = UtunInterface<TokioFdQueue>;
-#[cfg(feature = "tokio")]
-impl AsyncQueueT for TokioInterface {}
 
-impl<Q: AsyncQueueT> AsyncRead for UtunInterface<Q> {
-    delegate! {
-        to Pin::new(&mut self.queue) {
-            fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+impl<Q: AsyncQueueT> AsyncQueueT for UtunInterface<Q> {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        ready!(self.pause.poll_resumed(cx));
+        let self_mut = self.get_mut();
+        Pin::new(self_mut.queue_mut())
+            .poll_recv(cx, buf)
+            .map_ok(|(n, truncated)| (self_mut.packet_read(buf, n), truncated))
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        if !self_mut.egress.is_enabled() {
+            return self_mut.poll_send_shaped(cx, packet).map_ok(drop);
         }
+
+        let mut egress = mem::take(&mut self_mut.egress);
+        let result = egress.poll_write(cx, packet, |cx, packet| {
+            self_mut.poll_send_shaped(cx, packet)
+        });
+        self_mut.egress = egress;
+        result.map_ok(drop)
+    }
+
+    /// Paused interfaces are not ready.
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.pause.poll_resumed(cx));
+        Pin::new(self.get_mut().queue_mut()).poll_recv_ready(cx)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        AsyncQueueT::drain(self.queue_mut())
+    }
+}
+
+impl<Q: AsyncQueueT> UtunInterface<Q> {
+    fn poll_send_shaped(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(self.shaper.poll_ready(cx, buf.len()));
+        Pin::new(self.queue_mut())
+            .poll_send(cx, buf)
+            .map_ok(|()| self.packet_written(buf, buf.len()))
+            .map_err(|e| self.write_failed(e))
+    }
+
+    fn poll_drain_egress(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut egress = mem::take(&mut self.egress);
+        let result = egress.poll_drain(cx, |cx, packet| self.poll_send_shaped(cx, packet));
+        self.egress = egress;
+        result
+    }
+}
+
+impl<Q: AsyncQueueT> AsyncRead for UtunInterface<Q> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
     }
 }
 
 impl<Q: AsyncQueueT> AsyncWrite for UtunInterface<Q> {
-    delegate! {
-        to Pin::new(&mut self.queue) {
-            fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>>;
-            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
-            fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
-        }
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        Pin::new(self_mut.queue_mut()).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        Pin::new(self_mut.queue_mut()).poll_close(cx)
     }
 }
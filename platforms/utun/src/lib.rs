@@ -1,21 +1,49 @@
+#![deny(unsafe_code)]
+
 use derive_builder::Builder;
+use tunio_core::config::Layer;
+use tunio_core::device::DeviceInfo;
+use tunio_core::events::{EventEmitter, EventReceiver};
 use tunio_core::traits::{DriverT, PlatformIfConfigT};
 use tunio_core::Error;
 
 mod interface;
 mod queue;
+mod socket;
+mod sys;
 
 pub use interface::Interface;
 #[cfg(feature = "tokio")]
 pub use interface::TokioInterface;
+pub use socket::bind_to_interface;
 
-pub struct Driver {}
+pub struct Driver {
+    pub(crate) events: EventEmitter,
+}
 
 impl DriverT for Driver {
     type PlatformIfConfig = PlatformIfConfig;
 
     fn new() -> Result<Self, Error> {
-        Ok(Driver {})
+        Ok(Driver {
+            events: EventEmitter::default(),
+        })
+    }
+
+    fn subscribe(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+
+    /// Lists `utun` interfaces. Ownership is not exposed by the OS.
+    fn enumerate(&self) -> Result<Vec<DeviceInfo>, Error> {
+        let mut devices = vec![];
+        for interface in netconfig::list_interfaces()? {
+            let name = interface.name()?;
+            if name.starts_with("utun") {
+                devices.push(DeviceInfo::new(name, interface.index()?, Layer::L3, "utun"));
+            }
+        }
+        Ok(devices)
     }
 }
 
@@ -24,6 +52,9 @@ pub struct PlatformIfConfig {}
 
 impl PlatformIfConfigT for PlatformIfConfig {
     type Builder = PlatformIfConfigBuilder;
+
+    const MAX_NAME_LEN: usize = libc::IFNAMSIZ - 1;
+    const LAYERS: &'static [Layer] = &[Layer::L3];
 }
 
 impl Default for PlatformIfConfig {
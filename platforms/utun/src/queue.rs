@@ -1,9 +1,10 @@
+use crate::sys;
 use crate::Error;
 use libc::{PF_SYSTEM, SYSPROTO_CONTROL};
 use nix::sys::socket::SysControlAddr;
-use socket2::{Domain, Protocol, SockAddr, Socket, Type};
-use std::mem;
-use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::os::unix::io::{AsRawFd, OwnedFd};
 
 const UTUN_CONTROL_NAME: &str = "com.apple.net.utun_control";
 
@@ -18,24 +19,16 @@ pub(crate) fn create_device(name: &str, blocking: bool) -> Result<OwnedFd, Error
         Domain::from(PF_SYSTEM),
         Type::DGRAM,
         Some(Protocol::from(SYSPROTO_CONTROL)),
-    )
-    .unwrap();
+    )?;
 
-    let sa = SysControlAddr::from_name(tun_device.as_raw_fd(), UTUN_CONTROL_NAME, id).unwrap();
+    let sa = SysControlAddr::from_name(tun_device.as_raw_fd(), UTUN_CONTROL_NAME, id)
+        .map_err(io::Error::from)?;
 
-    let (_, sa) = unsafe {
-        SockAddr::init(|sa_storage, len| {
-            let sockaddr = sa_storage as *mut libc::sockaddr_ctl;
-            *sockaddr = *sa.as_ref();
-            *len = mem::size_of::<libc::sockaddr_ctl>() as _;
-            Ok(())
-        })
-    }
-    .unwrap();
+    let sa = sys::control_addr(&sa)?;
     if !blocking {
         tun_device.set_nonblocking(true)?;
     }
-    tun_device.connect(&sa).unwrap();
+    tun_device.connect(&sa)?;
 
-    Ok(unsafe { OwnedFd::from_raw_fd(tun_device.into_raw_fd()) })
+    Ok(sys::into_owned_fd(tun_device))
 }
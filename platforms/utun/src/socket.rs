@@ -0,0 +1,15 @@
+use crate::sys;
+use std::os::unix::io::RawFd;
+use tunio_core::socket::SocketFamily;
+use tunio_core::Error;
+
+/// Binds socket to the interface with `IP_BOUND_IF`/`IPV6_BOUND_IF`, so its traffic is not
+/// routed through the tunnel.
+pub fn bind_to_interface(socket: RawFd, index: u32, family: SocketFamily) -> Result<(), Error> {
+    let (level, option) = match family {
+        SocketFamily::Ipv4 => (libc::IPPROTO_IP, libc::IP_BOUND_IF),
+        SocketFamily::Ipv6 => (libc::IPPROTO_IPV6, libc::IPV6_BOUND_IF),
+    };
+    sys::set_int_option(socket, level, option, index as libc::c_int)?;
+    Ok(())
+}
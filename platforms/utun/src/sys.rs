@@ -0,0 +1,53 @@
+//! Safe wrappers of the calls, that are not covered by `socket2` and `nix`. This is the only
+//! module of the crate, that contains `unsafe`.
+#![allow(unsafe_code)]
+
+use nix::sys::socket::SysControlAddr;
+use socket2::{SockAddr, Socket};
+use std::io;
+use std::mem;
+use std::os::unix::io::{FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+/// Converts kernel control address to the address type of `socket2`.
+pub fn control_addr(addr: &SysControlAddr) -> io::Result<SockAddr> {
+    // SAFETY: storage is a zeroed `sockaddr_storage`, that is larger and more aligned, than
+    // `sockaddr_ctl`, and the length, that is reported, is the one of the written address
+    let (_, sa) = unsafe {
+        SockAddr::init(|storage, len| {
+            let sockaddr = storage as *mut libc::sockaddr_ctl;
+            *sockaddr = *addr.as_ref();
+            *len = mem::size_of::<libc::sockaddr_ctl>() as _;
+            Ok(())
+        })
+    }?;
+    Ok(sa)
+}
+
+/// Moves descriptor of `socket` into an `OwnedFd`, as `socket2` 0.4 has no such conversion.
+pub fn into_owned_fd(socket: Socket) -> OwnedFd {
+    // SAFETY: descriptor is released by `socket`, so it is owned by nothing else
+    unsafe { OwnedFd::from_raw_fd(socket.into_raw_fd()) }
+}
+
+/// Sets socket option, which value is an `int`.
+pub fn set_int_option(
+    socket: RawFd,
+    level: libc::c_int,
+    option: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    // SAFETY: value points to an `int`, that lives through the call, and its size is passed
+    let result = unsafe {
+        libc::setsockopt(
+            socket,
+            level,
+            option,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
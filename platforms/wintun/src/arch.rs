@@ -0,0 +1,84 @@
+//! Architecture check of the Wintun DLL, which is shipped by applications for every target
+//! separately. Loading a DLL of another architecture fails with an unhelpful
+//! `ERROR_BAD_EXE_FORMAT`, so the DLL, that would be loaded, is inspected beforehand.
+use std::env;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use tunio_core::Error;
+
+const IMAGE_FILE_MACHINE_I386: u16 = 0x014c;
+const IMAGE_FILE_MACHINE_ARMNT: u16 = 0x01c4;
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+/// Offset of the PE header offset in the DOS header.
+const PE_OFFSET_FIELD: u64 = 0x3c;
+
+/// Name of the Wintun build directory for the architecture of this process.
+pub(crate) const PROCESS_ARCH: &str = if cfg!(target_arch = "x86_64") {
+    "amd64"
+} else if cfg!(target_arch = "aarch64") {
+    "arm64"
+} else if cfg!(target_arch = "x86") {
+    "x86"
+} else {
+    "arm"
+};
+
+/// Fails with [`Error::LibraryArchMismatch`], if the DLL, that is found first in the
+/// application directory or in the current directory, is built for another architecture.
+///
+/// Files, that cannot be read or parsed, are left to the loader.
+pub(crate) fn check_library_arch(library_name: &str) -> Result<(), Error> {
+    let file_name = format!("{library_name}.dll");
+    let app_dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let mut candidates = app_dir
+        .into_iter()
+        .chain(env::current_dir().ok())
+        .map(|dir| dir.join(&file_name));
+
+    let path = match candidates.find(|path| path.is_file()) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    match dll_arch(&path) {
+        Some(found) if found != PROCESS_ARCH => Err(Error::LibraryArchMismatch {
+            path: path.display().to_string(),
+            found: found.to_string(),
+            expected: PROCESS_ARCH.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Reads machine type from the PE header of a DLL.
+fn dll_arch(path: &Path) -> Option<&'static str> {
+    let mut file = File::open(path).ok()?;
+    let mut dos_magic = [0u8; 2];
+    file.read_exact(&mut dos_magic).ok()?;
+    if &dos_magic != b"MZ" {
+        return None;
+    }
+
+    let mut offset = [0u8; 4];
+    file.seek(SeekFrom::Start(PE_OFFSET_FIELD)).ok()?;
+    file.read_exact(&mut offset).ok()?;
+    // Signature is followed by the machine type of COFF header
+    let mut header = [0u8; 6];
+    file.seek(SeekFrom::Start(u64::from(u32::from_le_bytes(offset))))
+        .ok()?;
+    file.read_exact(&mut header).ok()?;
+    if &header[..4] != b"PE\0\0" {
+        return None;
+    }
+
+    match u16::from_le_bytes([header[4], header[5]]) {
+        IMAGE_FILE_MACHINE_I386 => Some("x86"),
+        IMAGE_FILE_MACHINE_ARMNT => Some("arm"),
+        IMAGE_FILE_MACHINE_AMD64 => Some("amd64"),
+        IMAGE_FILE_MACHINE_ARM64 => Some("arm64"),
+        _ => None,
+    }
+}
@@ -2,45 +2,134 @@ use super::async_queue::AsyncQueue;
 use super::interface::CommonInterface;
 use futures::{AsyncRead, AsyncWrite};
 use std::io::{self};
+use std::mem;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
+use std::time::Instant;
+use tunio_core::traits::AsyncQueueT;
 
 pub type AsyncInterface = CommonInterface<AsyncQueue>;
 
-impl AsyncRead for AsyncInterface {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
+impl AsyncQueueT for AsyncInterface {
+    fn poll_recv(
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &mut [u8],
-    ) -> Poll<io::Result<usize>> {
+    ) -> Poll<io::Result<(usize, bool)>> {
+        ready!(self.pause.poll_resumed(cx));
+        let self_mut = self.get_mut();
+        // Reads are not held up by control packets, that wait for ring space
+        if let Poll::Ready(Err(e)) = self_mut.poll_send_control(cx) {
+            return Poll::Ready(Err(e));
+        }
+        match self_mut.inner_queue_mut() {
+            Ok(queue) => Pin::new(queue)
+                .poll_recv(cx, buf)
+                .map_ok(|(n, truncated)| (self_mut.packet_read(buf, n, truncated), truncated)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn poll_send(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_send_control(cx))?;
+        if !self_mut.egress.is_enabled() {
+            return self_mut.poll_send_shaped(cx, packet).map_ok(drop);
+        }
+
+        let mut egress = mem::take(&mut self_mut.egress);
+        let result = egress.poll_write(cx, packet, |cx, packet| {
+            self_mut.poll_send_shaped(cx, packet)
+        });
+        self_mut.egress = egress;
+        result.map_ok(drop)
+    }
+
+    /// Paused interfaces are not ready.
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.pause.poll_resumed(cx));
+        match self.get_mut().inner_queue_mut() {
+            Ok(queue) => Pin::new(queue).poll_recv_ready(cx),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        AsyncQueueT::drain(self.inner_queue_mut()?)
+    }
+
+    fn poll_recv_timestamped(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, Instant)>> {
+        ready!(self.pause.poll_resumed(cx));
+        let self_mut = self.get_mut();
+        match self_mut.inner_queue_mut() {
+            Ok(queue) => Pin::new(queue)
+                .poll_recv_timestamped(cx, buf)
+                .map_ok(|(n, timestamp)| (self_mut.packet_read(buf, n, false), timestamp)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl AsyncInterface {
+    fn poll_send_shaped(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        ready!(self.shaper.poll_ready(cx, buf.len()));
         match self.inner_queue_mut() {
-            Ok(queue) => Pin::new(queue).poll_read(cx, buf),
+            Ok(queue) => Pin::new(queue)
+                .poll_send(cx, buf)
+                .map_ok(|()| self.packet_written(buf, buf.len())),
             Err(e) => Poll::Ready(Err(e)),
         }
     }
+
+    fn poll_drain_egress(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_send_control(cx))?;
+        let mut egress = mem::take(&mut self.egress);
+        let result = egress.poll_drain(cx, |cx, packet| self.poll_send_shaped(cx, packet));
+        self.egress = egress;
+        result
+    }
+}
+
+impl AsyncRead for AsyncInterface {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
 }
 
 impl AsyncWrite for AsyncInterface {
     fn poll_write(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        match self.inner_queue_mut() {
-            Ok(queue) => Pin::new(queue).poll_write(cx, buf),
-            Err(e) => Poll::Ready(Err(e)),
-        }
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
     }
 
-    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        match self.inner_queue_mut() {
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        match self_mut.inner_queue_mut() {
             Ok(queue) => Pin::new(queue).poll_flush(cx),
             Err(e) => Poll::Ready(Err(e)),
         }
     }
 
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        match self.inner_queue_mut() {
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let self_mut = self.get_mut();
+        ready!(self_mut.poll_drain_egress(cx))?;
+        match self_mut.inner_queue_mut() {
             Ok(queue) => Pin::new(queue).poll_close(cx),
             Err(e) => Poll::Ready(Err(e)),
         }
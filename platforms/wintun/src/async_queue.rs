@@ -1,137 +1,479 @@
-use super::event::SafeEvent;
-use super::wrappers::Session;
+use super::power::PowerState;
+use super::thread::ReaderThreadGuard;
+use super::wrappers::{wait_any, ActiveWait, ReaderStop, Session};
+use super::{CloseMode, PlatformIfConfig, ThreadPriority};
 use crate::queue::SessionQueueT;
 use futures::{AsyncRead, AsyncWrite};
+use log::{error, warn};
+use std::any::Any;
 use std::future::Future;
-use std::io::{self, Read, Write};
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::pin::Pin;
 use std::sync::Arc;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+use tunio_core::budget::PollBudget;
+use tunio_core::coalesce::Coalescer;
+use tunio_core::config::IfConfig;
+use tunio_core::events::EventKind;
+use tunio_core::traits::AsyncQueueT;
+use tunio_core::Error;
 use windows::Win32::Foundation::WIN32_ERROR;
 use windows::{
     Win32::Foundation::HANDLE, Win32::Foundation::WAIT_ABANDONED_0,
-    Win32::Foundation::WAIT_OBJECT_0, Win32::System::Threading::WaitForMultipleObjects,
-    Win32::System::WindowsProgramming::INFINITE,
+    Win32::Foundation::WAIT_OBJECT_0,
 };
 
 enum WaitingStopReason {
     Shutdown,
-    Ready,
+    /// Ring has packets to read, that arrived no later than the contained instant.
+    Ready(Option<Instant>),
+    Failed(String),
 }
 
 enum ReadState {
-    Waiting(Option<async_task::Task<WaitingStopReason>>),
+    Waiting(async_task::Task<WaitingStopReason>),
     Idle,
+    /// Queue is closed, and packets, left in the ring, are read without waiting for more.
+    Draining,
     Closed,
+    /// Reader has failed and the session was not restarted. This state is terminal.
+    Failed(String),
 }
 
+/// Async queue of a Wintun session.
+///
+/// Packets are copied directly from the Wintun ring, which is a lock-free ring, shared with
+/// the driver, into the buffer of the read, so there is no intermediate channel and no
+/// allocation on the receive path. A blocking pool thread only waits for the read event, when
+/// the ring is empty. Ring size is set by [`capacity`](PlatformIfConfig::capacity).
+///
+/// Packets are written directly into the send ring on the polling thread too. Only a write,
+/// that finds the ring full, is copied and handed to a blocking pool thread, which retries it
+/// with backoff, so the task does not spin. Next write and flush wait for it, so packets stay
+/// in order.
 pub struct AsyncQueue {
     session: Session,
+    /// Write, that found the ring full and is retried on a pool thread.
+    pending_send: Option<async_task::Task<io::Result<()>>>,
 
     read_state: ReadState,
-    shutdown_event: Arc<SafeEvent>,
+    reader: Arc<ReaderStop>,
+    budget: PollBudget,
+    coalescer: Coalescer,
+    /// Time, when the reader was notified about new packets. Used as the receive timestamp of
+    /// the first packet, read after the notification.
+    ready_at: Option<Instant>,
+    /// Time of the last packet, read from the ring, for spinning within a burst.
+    last_packet_at: Option<Instant>,
+    read_spin: Duration,
+    restart_on_failure: bool,
+    close_mode: CloseMode,
+    closing: bool,
+
+    reader_name: Arc<str>,
+    reader_priority: ThreadPriority,
+    reader_affinity: Option<usize>,
 }
 
 impl SessionQueueT for AsyncQueue {
-    fn new(session: Session) -> Self {
-        Self {
+    fn new(mut session: Session, config: &IfConfig<PlatformIfConfig>) -> Result<Self, Error> {
+        // Session stops the reader, before it ends, so that it is not left waiting on a closed
+        // read event
+        let reader = Arc::new(ReaderStop::new().map_err(io::Error::from)?);
+        session.set_reader(reader.clone());
+
+        Ok(Self {
             session,
+            pending_send: None,
 
             read_state: ReadState::Idle,
 
-            // Manual reset, because we use this event once and it must fire on all threads
-            shutdown_event: Arc::new(SafeEvent::new(true, false)),
-        }
+            reader,
+            budget: PollBudget::new(config.poll_budget),
+            coalescer: Coalescer::new(config.read_coalescing),
+            ready_at: None,
+            last_packet_at: None,
+            read_spin: config.platform.read_spin,
+            restart_on_failure: config.platform.restart_on_failure,
+            close_mode: config.platform.close_mode,
+            closing: false,
+
+            reader_name: config.name.as_str().into(),
+            reader_priority: config.platform.reader_priority,
+            reader_affinity: config.platform.reader_affinity,
+        })
+    }
+
+    fn into_session(self) -> Session {
+        let mut session = self.session;
+        session.stop_reader();
+        session
+    }
+
+    fn session(&self) -> &Session {
+        &self.session
     }
 }
 
-impl Drop for AsyncQueue {
-    fn drop(&mut self) {
-        self.shutdown_event.set_event();
+impl AsyncQueue {
+    fn in_burst(&self) -> bool {
+        self.last_packet_at
+            .map_or(false, |last| last.elapsed() < self.read_spin)
+    }
+
+    /// State of reads after the queue is closed.
+    fn closed_state(&self) -> ReadState {
+        match self.close_mode {
+            CloseMode::Drain => ReadState::Draining,
+            CloseMode::Abort => ReadState::Closed,
+        }
+    }
+
+    /// Waits for the write, that was handed to a pool thread, and returns its result.
+    fn poll_pending_send(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let task = match &mut self.pending_send {
+            Some(task) => task,
+            None => return Poll::Ready(Ok(())),
+        };
+        let result = ready!(Pin::new(task).poll(cx));
+        self.pending_send = None;
+        if let Err(e) = &result {
+            self.session.emit(EventKind::WriteFailed(e.kind()));
+        }
+        Poll::Ready(result)
+    }
+
+    fn recover(&mut self, reason: String) -> ReadState {
+        if !self.restart_on_failure {
+            error!("Wintun reader failed: {reason}");
+            return ReadState::Failed(reason);
+        }
+
+        warn!("Wintun reader failed: {reason}, restarting session");
+        match self.session.restart() {
+            Ok(()) => ReadState::Idle,
+            Err(e) => {
+                error!("Failed to restart session: {e}");
+                ReadState::Failed(format!("{reason}, session restart failed: {e}"))
+            }
+        }
     }
 }
 
-fn wait_for_read(read_event: HANDLE, shutdown_event: Arc<SafeEvent>) -> WaitingStopReason {
+fn wait_for_read(
+    read_event: HANDLE,
+    shutdown_event: HANDLE,
+    power_state: Option<Arc<PowerState>>,
+) -> WaitingStopReason {
     const WAIT_OBJECT_1: WIN32_ERROR = WIN32_ERROR(WAIT_OBJECT_0.0 + 1);
+    const WAIT_OBJECT_2: WIN32_ERROR = WIN32_ERROR(WAIT_OBJECT_0.0 + 2);
     const WAIT_ABANDONED_1: WIN32_ERROR = WIN32_ERROR(WAIT_ABANDONED_0.0 + 1);
 
-    match unsafe { WaitForMultipleObjects(&[shutdown_event.handle(), read_event], false, INFINITE) }
-    {
+    #[cfg(feature = "tracing")]
+    let wait_started = std::time::Instant::now();
+
+    let result = match &power_state {
+        Some(power_state) => wait_any(&[shutdown_event, read_event, power_state.resume_event()]),
+        None => wait_any(&[shutdown_event, read_event]),
+    };
+
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        wait_us = wait_started.elapsed().as_micros() as u64,
+        result = result.0,
+        "ring wait finished"
+    );
+
+    match result {
         // Shutdown
         WAIT_OBJECT_0 | WAIT_ABANDONED_0 => WaitingStopReason::Shutdown,
         // Ready for read
-        WAIT_OBJECT_1 => WaitingStopReason::Ready,
+        WAIT_OBJECT_1 => WaitingStopReason::Ready(Some(Instant::now())),
+        // Resumed from sleep, session is restarted on the next read
+        WAIT_OBJECT_2 => WaitingStopReason::Ready(None),
         // Read event deleted
-        WAIT_ABANDONED_1 => {
-            panic!("Read event deleted unexpectedly");
-        }
+        WAIT_ABANDONED_1 => WaitingStopReason::Failed("read event deleted unexpectedly".into()),
 
-        e => {
-            panic!("Unexpected event result: {e:?}");
-        }
+        e => WaitingStopReason::Failed(format!("unexpected event result: {e:?}")),
     }
 }
 
-impl AsyncRead for AsyncQueue {
-    fn poll_read(
-        mut self: Pin<&mut Self>,
+/// Runs [`wait_for_read`], converting a panic into a failure, so it never silently stops reads.
+/// Panic message is kept in the error, that subsequent reads return. Wait is over, when this
+/// returns, even if it panicked.
+fn supervised_wait_for_read(
+    read_event: HANDLE,
+    wait: ActiveWait,
+    power_state: Option<Arc<PowerState>>,
+    reader_name: Arc<str>,
+    reader_priority: ThreadPriority,
+    reader_affinity: Option<usize>,
+) -> WaitingStopReason {
+    // Thread settings are restored inside, so a panic while changing them is caught too
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let _thread = ReaderThreadGuard::enter(&reader_name, reader_priority, reader_affinity);
+        wait_for_read(read_event, wait.shutdown_event(), power_state)
+    }))
+    .unwrap_or_else(|payload| WaitingStopReason::Failed(panic_message(payload.as_ref())))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(s), _) => format!("reader panicked: {s}"),
+        (_, Some(s)) => format!("reader panicked: {s}"),
+        _ => "reader panicked".to_string(),
+    }
+}
+
+impl AsyncQueue {
+    /// Polls the wait task, until the ring has packets or the reader stops.
+    ///
+    /// Wait task is kept across polls, so a dropped read future does not lose the wakeup, and
+    /// each poll registers the most recent waker.
+    fn poll_wait(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let task = match &mut self.read_state {
+            ReadState::Waiting(task) => task,
+            _ => return Poll::Ready(()),
+        };
+
+        self.read_state = match ready!(Pin::new(task).poll(cx)) {
+            // Packets may have arrived, while the shutdown was signaled, so the outcome does
+            // not depend on which event the wait saw first
+            WaitingStopReason::Shutdown => {
+                self.session.emit(EventKind::ReaderExited);
+                self.closed_state()
+            }
+            WaitingStopReason::Ready(_) if self.closing => self.closed_state(),
+            WaitingStopReason::Ready(ready_at) => {
+                self.ready_at = ready_at;
+                ReadState::Idle
+            }
+            WaitingStopReason::Failed(reason) => {
+                self.session.emit(EventKind::ReaderExited);
+                self.recover(reason)
+            }
+        };
+        Poll::Ready(())
+    }
+
+    /// Polls for a packet, returning its length, whether it was truncated, and its receive
+    /// timestamp.
+    fn poll_recv_packet(
+        &mut self,
         cx: &mut Context<'_>,
         buf: &mut [u8],
-    ) -> Poll<io::Result<usize>> {
+    ) -> Poll<io::Result<(usize, bool, Instant)>> {
+        ready!(self.budget.poll_proceed(cx));
+
         loop {
             match &mut self.read_state {
-                ReadState::Waiting(task) => {
-                    let mut task = task.take().unwrap();
-
-                    self.read_state = match Pin::new(&mut task).poll(cx) {
-                        Poll::Ready(WaitingStopReason::Shutdown) => ReadState::Closed,
-                        Poll::Ready(WaitingStopReason::Ready) => ReadState::Idle,
-                        Poll::Pending => ReadState::Waiting(Some(task)),
-                    };
-
-                    if let ReadState::Waiting(..) = self.read_state {
+                ReadState::Waiting(..) => {
+                    if self.poll_wait(cx).is_pending() {
+                        self.budget.reset();
                         return Poll::Pending;
                     }
                 }
-                ReadState::Idle => match self.session.read(buf) {
-                    Ok(n) => return Poll::Ready(Ok(n)),
-                    Err(e) => {
-                        if e.kind() == io::ErrorKind::WouldBlock {
-                            let read_event = self.session.read_event();
-                            let inner_shutdown_event = self.shutdown_event.clone();
-
-                            self.read_state =
-                                ReadState::Waiting(Some(blocking::unblock(move || {
-                                    wait_for_read(read_event, inner_shutdown_event)
-                                })));
-                        } else {
-                            return Poll::Ready(Err(e));
+                ReadState::Idle => {
+                    ready!(self.coalescer.poll_ready(cx));
+
+                    match self.session.recv(buf) {
+                        Ok((n, truncated)) => {
+                            #[cfg(feature = "tracing")]
+                            tracing::trace!(len = n, "packet read");
+                            self.coalescer.packet_read();
+                            let now = Instant::now();
+                            self.last_packet_at = Some(now);
+                            let timestamp = self.ready_at.take().unwrap_or(now);
+                            return Poll::Ready(Ok((n, truncated, timestamp)));
+                        }
+                        Err(e) => {
+                            if e.kind() == io::ErrorKind::WouldBlock && self.in_burst() {
+                                // Next packet of a burst usually arrives sooner, than the
+                                // wait task is scheduled. Task yields, so that the executor
+                                // runs other tasks, until it is polled again.
+                                self.budget.reset();
+                                cx.waker().wake_by_ref();
+                                return Poll::Pending;
+                            } else if e.kind() == io::ErrorKind::WouldBlock {
+                                #[cfg(feature = "tracing")]
+                                tracing::trace!("ring is empty, waiting for read event");
+                                self.coalescer.batch_finished();
+
+                                let read_event = self.session.read_event();
+                                let wait = self.reader.begin_wait();
+                                let power_state = self.session.power_state();
+                                let reader_name = self.reader_name.clone();
+                                let reader_priority = self.reader_priority;
+                                let reader_affinity = self.reader_affinity;
+
+                                self.read_state =
+                                    ReadState::Waiting(blocking::unblock(move || {
+                                        supervised_wait_for_read(
+                                            read_event,
+                                            wait,
+                                            power_state,
+                                            reader_name,
+                                            reader_priority,
+                                            reader_affinity,
+                                        )
+                                    }));
+                            } else {
+                                return Poll::Ready(Err(e));
+                            }
                         }
                     }
+                }
+                ReadState::Draining => match self.session.recv(buf) {
+                    Ok((n, truncated)) => return Poll::Ready(Ok((n, truncated, Instant::now()))),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        self.read_state = ReadState::Closed;
+                    }
+                    Err(e) => return Poll::Ready(Err(e)),
                 },
-                ReadState::Closed => return Poll::Ready(Ok(0)),
+                ReadState::Closed => return Poll::Ready(Ok((0, false, Instant::now()))),
+                ReadState::Failed(reason) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        format!("wintun reader failed: {reason}"),
+                    )))
+                }
             }
         }
     }
 }
 
+impl AsyncQueueT for AsyncQueue {
+    fn poll_recv(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, bool)>> {
+        self.get_mut()
+            .poll_recv_packet(cx, buf)
+            .map_ok(|(n, truncated, _)| (n, truncated))
+    }
+
+    /// Packets are copied into the ring on the polling thread. Wintun does not signal, when
+    /// a full ring has space again, so a packet, that does not fit, is sent by a pool thread
+    /// and is reported as sent. Its error is returned by the next send or flush.
+    fn poll_send(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        packet: &[u8],
+    ) -> Poll<io::Result<()>> {
+        ready!(self.poll_pending_send(cx))?;
+
+        let result = self.session.write(packet);
+        if matches!(&result, Err(e) if e.kind() == io::ErrorKind::WouldBlock) {
+            #[cfg(feature = "tracing")]
+            tracing::trace!(len = packet.len(), "ring is full, writing on pool thread");
+            let sender = self.session.sender();
+            let packet = packet.to_vec();
+            self.pending_send = Some(blocking::unblock(move || sender.send(&packet)));
+            return Poll::Ready(Ok(()));
+        }
+
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(n) => tracing::trace!(len = n, "packet written"),
+            Err(e) => tracing::debug!(error = %e, "write failed"),
+        }
+
+        Poll::Ready(result.map(drop))
+    }
+
+    /// Ring is only checked by the next read: if it is found empty, the read waits and
+    /// readiness is polled on the wait task from then on.
+    fn poll_recv_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_wait(cx).map(Ok)
+    }
+
+    /// Packets are released from the ring, while a pending wait of the reader is kept.
+    fn drain(&mut self) -> io::Result<usize> {
+        self.session.drain()
+    }
+
+    fn poll_recv_timestamped(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, Instant)>> {
+        self.get_mut()
+            .poll_recv_packet(cx, buf)
+            .map_ok(|(n, _, timestamp)| (n, timestamp))
+    }
+}
+
+impl AsyncRead for AsyncQueue {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.poll_recv(cx, buf).map_ok(|(n, _)| n)
+    }
+}
+
 impl AsyncWrite for AsyncQueue {
-    // Write to wintun is already nonblocking
     fn poll_write(
-        mut self: Pin<&mut Self>,
-        _cx: &mut Context<'_>,
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Poll::Ready(self.session.write(buf))
+        self.poll_send(cx, buf).map_ok(|()| buf.len())
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        // Not implemented by driver
-        Poll::Ready(Ok(()))
+    /// Waits for a write, that a pool thread retries. Ring is not flushed otherwise, as the
+    /// driver takes packets on its own.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending_send(cx)
     }
 
+    /// Stops the reader: a pending wait ends, and reads return end of stream, after packets in
+    /// the ring are read, as [`close_mode`](PlatformIfConfig::close_mode) sets. Writes are not
+    /// affected.
     fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        // Not implemented by driver
+        let self_mut = self.get_mut();
+        if !self_mut.closing {
+            self_mut.closing = true;
+            self_mut.reader.request_shutdown();
+            if matches!(self_mut.read_state, ReadState::Idle) {
+                self_mut.read_state = self_mut.closed_state();
+            }
+        }
         Poll::Ready(Ok(()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caught(f: impl FnOnce()) -> String {
+        let payload = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+        panic_message(payload.as_ref())
+    }
+
+    #[test]
+    fn panic_message_is_kept() {
+        assert_eq!(caught(|| panic!("ring lost")), "reader panicked: ring lost");
+        let code = 5;
+        assert_eq!(
+            caught(|| panic!("wait failed: {code}")),
+            "reader panicked: wait failed: 5"
+        );
+    }
+
+    #[test]
+    fn unknown_payload_is_reported() {
+        assert_eq!(
+            caught(|| panic::resume_unwind(Box::new(5))),
+            "reader panicked"
+        );
+    }
+}
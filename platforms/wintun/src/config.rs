@@ -1,5 +1,10 @@
+use crate::wrappers::adapter::MAX_NAME;
+use crate::wrappers::Session;
 use derive_builder::Builder;
+use std::time::Duration;
+use tunio_core::config::{Layer, Violation};
 use tunio_core::traits::PlatformIfConfigT;
+use tunio_core::Error;
 
 /// It is generally better to use [`PlatformIfConfigBuilder`] to create a new PlatformIfConfig instance.
 #[derive(Builder, Clone)]
@@ -7,6 +12,9 @@ pub struct PlatformIfConfig {
     /// Wintun ring capacity. Must be power of 2 between 128KiB and 64MiB
     #[builder(default = "2 * 1024 * 1024")]
     pub capacity: u32,
+    /// Tunnel type, that Windows shows as the device name of the adapter, like `WireGuard`.
+    /// Name in Network Connections is the interface name and may be changed later with
+    /// `Interface::set_friendly_name`.
     #[builder(default = "String::new()")]
     pub description: String,
     /// GUID of this network interface. It is recommended to set it manually,
@@ -14,6 +22,97 @@ pub struct PlatformIfConfig {
     /// pollute Windows registry.
     #[builder(default = "windows::core::GUID::new().unwrap().to_u128()")]
     pub guid: u128,
+    /// Restart Wintun session, if the async reader fails unexpectedly or panics. Otherwise, the
+    /// failure is terminal, and all subsequent reads return
+    /// [`BrokenPipe`](std::io::ErrorKind::BrokenPipe) with its reason, never end of stream.
+    #[builder(default = "false")]
+    pub restart_on_failure: bool,
+    /// What async reads return, after the queue is closed, while the ring still has packets.
+    #[builder(default = "CloseMode::default()")]
+    pub close_mode: CloseMode,
+    /// Priority of the thread, waiting for incoming packets. Higher priorities reduce tail
+    /// latency at the expense of other threads in the system.
+    #[builder(default = "ThreadPriority::default()")]
+    pub reader_priority: ThreadPriority,
+    /// Affinity mask of the thread, waiting for incoming packets: bit `n` allows it to run
+    /// on logical processor `n`. `None` leaves scheduling to the system.
+    #[builder(default = "None")]
+    pub reader_affinity: Option<usize>,
+    /// While packets keep arriving within this interval, the async reader yields to the
+    /// executor and polls the ring again instead of waiting for the read event, so a burst is
+    /// received without a thread pool wakeup per packet. Executor thread is not blocked, but
+    /// stays busy, while there are no other tasks. Zero disables spinning.
+    #[builder(default = "Duration::from_micros(50)")]
+    pub read_spin: Duration,
+    /// IPv6 configuration, that Windows does on the adapter by itself, before the application
+    /// configures the tunnel. Applied on creation and kept by the system afterwards.
+    #[builder(default = "Ipv6Setup::default()")]
+    pub ipv6_setup: Ipv6Setup,
+    /// Category of the network behind the adapter, which decides the Windows Firewall
+    /// profile of the tunnel. Windows identifies the network a few seconds after the
+    /// interface is up, so it is set in the background then. `None` keeps the category,
+    /// that Windows chooses.
+    #[builder(default = "None")]
+    pub network_category: Option<NetworkCategory>,
+    /// Serialization of adapter creation and removal with other adapters. Creating adapters
+    /// concurrently, from several threads or processes, sometimes fails on races in the
+    /// registry.
+    #[builder(default = "AdapterLock::default()")]
+    pub adapter_lock: AdapterLock,
+    /// Turns off the "Network location" prompt, that Windows shows for new networks. The
+    /// setting is system-wide and is kept after the adapter is removed.
+    #[builder(default = "false")]
+    pub suppress_location_prompt: bool,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum CloseMode {
+    /// Packets, that are already in the ring, are read, and then reads return end of stream.
+    #[default]
+    Drain,
+    /// Reads return end of stream at once, and packets in the ring are left to the driver.
+    Abort,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum AdapterLock {
+    /// Adapters are created without a lock, for applications, that create them from a single
+    /// thread, or serialize creation by themselves.
+    Off,
+    /// Adapters are created and removed one at a time in this process.
+    #[default]
+    Process,
+    /// Like `Process`, and also one at a time with other processes, that use tunio, with a
+    /// named mutex in the global namespace.
+    System,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NetworkCategory {
+    Public,
+    Private,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Ipv6Setup {
+    /// Link-local address, router discovery and SLAAC, as configured in Windows.
+    #[default]
+    System,
+    /// Router advertisements are ignored: no autoconfigured addresses and routes, and no
+    /// router solicitations. Link-local address is kept.
+    NoRouterDiscovery,
+    /// IPv6 binding of the adapter is removed, so it has no IPv6 addresses at all.
+    Unbound,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum ThreadPriority {
+    #[default]
+    Normal,
+    AboveNormal,
+    Highest,
+    /// `THREAD_PRIORITY_TIME_CRITICAL`
+    TimeCritical,
 }
 
 impl Default for PlatformIfConfig {
@@ -24,4 +123,33 @@ impl Default for PlatformIfConfig {
 
 impl PlatformIfConfigT for PlatformIfConfig {
     type Builder = PlatformIfConfigBuilder;
+
+    const MAX_NAME_LEN: usize = MAX_NAME;
+    const LAYERS: &'static [Layer] = &[Layer::L3];
+
+    /// Aliases are limited in UTF-16 code units.
+    fn name_len(name: &str) -> usize {
+        name.encode_utf16().count()
+    }
+
+    /// Aliases may contain any characters, like spaces in `Ethernet 2`, except zero ones.
+    fn check_name(name: &str) -> Option<String> {
+        name.contains('\0')
+            .then(|| "must not contain zero characters".to_string())
+    }
+
+    fn validate(&self, violations: &mut Vec<Violation>) {
+        if let Err(Error::InvalidConfigValue {
+            name,
+            value,
+            reason,
+        }) = Session::validate_capacity(self.capacity)
+        {
+            violations.push(Violation {
+                name,
+                value,
+                reason,
+            });
+        }
+    }
 }
@@ -1,35 +1,53 @@
-use super::logger::wintun_logger;
+use super::arch::check_library_arch;
+use super::version::{library_version, running_driver_version, WintunVersion};
+use super::wrappers::library;
 use super::PlatformIfConfig;
 use std::sync::Arc;
+use tunio_core::device::DeviceInfo;
+use tunio_core::events::{EventEmitter, EventReceiver};
 use tunio_core::traits::DriverT;
 use tunio_core::Error;
 
+const LIBRARY_NAME: &str = "wintun";
+
 pub struct Driver {
     pub wintun: Arc<wintun_sys::wintun>,
+    pub(crate) events: EventEmitter,
 }
 
 impl DriverT for Driver {
     type PlatformIfConfig = PlatformIfConfig;
 
     fn new() -> Result<Self, Error> {
-        let library_name = "wintun".to_string();
-        let wintun = Arc::new(
-            unsafe { wintun_sys::wintun::new(library_name) }.map_err(|e| {
-                Error::LibraryNotLoaded {
-                    reason: format!("{e:?}"),
-                }
-            })?,
-        );
-
-        unsafe {
-            wintun.WintunSetLogger(Some(wintun_logger));
-        }
+        let library_name = LIBRARY_NAME.to_string();
+        check_library_arch(&library_name)?;
+        let wintun = Arc::new(library::load(&library_name)?);
 
-        Ok(Self { wintun })
+        Ok(Self {
+            wintun,
+            events: EventEmitter::default(),
+        })
+    }
+
+    fn subscribe(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+
+    fn enumerate(&self) -> Result<Vec<DeviceInfo>, Error> {
+        super::enumerate::enumerate(&self.wintun)
     }
 }
 
 impl Driver {
+    /// Returns versions of the running driver and of the loaded DLL, so that applications can
+    /// warn about outdated drivers. Driver version changes, once the first adapter is created.
+    pub fn version(&self) -> WintunVersion {
+        WintunVersion {
+            driver: running_driver_version(&self.wintun),
+            library: library_version(LIBRARY_NAME),
+        }
+    }
+
     pub(crate) fn wintun(&self) -> &Arc<wintun_sys::wintun> {
         &self.wintun
     }
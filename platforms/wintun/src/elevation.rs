@@ -0,0 +1,31 @@
+use crate::wrappers::library::running_driver_version;
+use crate::wrappers::ProcessToken;
+use log::debug;
+use tunio_core::Error;
+
+const SE_LOAD_DRIVER_NAME: &str = "SeLoadDriverPrivilege";
+
+/// Fails with [`Error::NeedsElevation`], if the process is not elevated, as creating and
+/// opening adapters fails with `ERROR_ACCESS_DENIED` otherwise. If the Wintun driver is not
+/// loaded yet, `SeLoadDriverPrivilege` is required as well to install it.
+///
+/// Checks, that cannot be performed, are passed, leaving the decision to Wintun.
+pub(crate) fn check_elevation(wintun: &wintun_sys::wintun) -> Result<(), Error> {
+    let token = match ProcessToken::open() {
+        Some(token) => token,
+        None => return Ok(()),
+    };
+    // Version is zero, when the driver is not loaded
+    let driver_install = running_driver_version(wintun) == 0;
+
+    let elevated = token.is_elevated().unwrap_or(true);
+    let can_load_driver =
+        !driver_install || token.has_privilege(SE_LOAD_DRIVER_NAME).unwrap_or(true);
+    match elevated && can_load_driver {
+        true => Ok(()),
+        false => {
+            debug!("Process is not elevated, driver install is needed: {driver_install}");
+            Err(Error::NeedsElevation { driver_install })
+        }
+    }
+}
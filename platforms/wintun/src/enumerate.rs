@@ -0,0 +1,33 @@
+//! Listing of Wintun adapters. Wintun has no enumeration API, so virtual interfaces of the
+//! system are listed with `GetIfTable2` and checked by opening them as adapters.
+use crate::tag::tag;
+use crate::wrappers::adapter::is_wintun_adapter;
+use crate::wrappers::ip_helper::IfTable;
+use std::sync::Arc;
+use tunio_core::config::Layer;
+use tunio_core::device::DeviceInfo;
+use tunio_core::Error;
+use widestring::U16CStr;
+
+/// Interface type of Wintun adapters, see `ipifcons.h`.
+const IF_TYPE_PROP_VIRTUAL: u32 = 53;
+
+pub(crate) fn enumerate(wintun: &Arc<wintun_sys::wintun>) -> Result<Vec<DeviceInfo>, Error> {
+    let table = IfTable::get()?;
+    let devices = table
+        .rows()
+        .iter()
+        .filter(|row| row.Type == IF_TYPE_PROP_VIRTUAL)
+        .filter_map(|row| {
+            let alias = U16CStr::from_slice_truncate(&row.Alias)
+                .ok()?
+                .to_string_lossy();
+            is_wintun_adapter(&alias, wintun).then(|| {
+                let mut device = DeviceInfo::new(alias, row.InterfaceIndex, Layer::L3, "wintun");
+                device.tag = tag(&row.InterfaceGuid);
+                device
+            })
+        })
+        .collect();
+    Ok(devices)
+}
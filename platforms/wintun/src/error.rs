@@ -0,0 +1,34 @@
+//! Windows errors with their texts, captured when they happen, as bare codes in logs of end
+//! users are hard to support.
+use crate::wrappers::format_message;
+use std::io;
+use tunio_core::Error;
+
+/// Error of the last failed Win32 call of this thread.
+pub(crate) fn last_error(call: impl Into<String>) -> Error {
+    win32_error(call, io::Error::last_os_error())
+}
+
+/// Error of a failed call with the text of its code. Codes may be Win32 error codes, HRESULTs
+/// or NTSTATUS codes.
+pub(crate) fn win32_error(call: impl Into<String>, source: impl Into<io::Error>) -> Error {
+    let source = source.into();
+    let code = source.raw_os_error().unwrap_or_default() as u32;
+    Error::Win32 {
+        call: call.into(),
+        code,
+        message: format_message(code).unwrap_or_else(|| source.to_string()),
+        source,
+    }
+}
+
+/// Attaches the failed call with the text of the error to `windows` results.
+pub(crate) trait Win32ResultExt<T> {
+    fn win32_context<C: Into<String>>(self, call: impl FnOnce() -> C) -> Result<T, Error>;
+}
+
+impl<T> Win32ResultExt<T> for windows::core::Result<T> {
+    fn win32_context<C: Into<String>>(self, call: impl FnOnce() -> C) -> Result<T, Error> {
+        self.map_err(|err| win32_error(call(), err))
+    }
+}
@@ -0,0 +1,37 @@
+//! Advisory locks of interface names, so that two instances of an application do not
+//! configure the same adapter. Locks are named mutexes, that exist, while a handle to them is
+//! open, so the system releases them, when the process exits.
+use crate::wrappers::NamedMutex;
+use std::fmt;
+use tunio_core::Error;
+
+/// Lock of an interface name, that is held, until it is dropped.
+pub struct InstanceLock(NamedMutex);
+
+impl InstanceLock {
+    /// Locks `name` with a mutex in the global namespace, so that instances in other
+    /// sessions, like services, are seen too. Aliases are case-insensitive, and so is the
+    /// lock. Fails with [`Error::InterfaceLocked`], if another handle of the mutex is open,
+    /// in this or another process.
+    pub fn acquire(name: &str) -> Result<Self, Error> {
+        // Backslashes separate namespaces in object names
+        let object = format!(
+            "Global\\tunio-instance-{}",
+            name.to_lowercase().replace('\\', "/")
+        );
+        // Mutex is only used for its name and is never owned by a thread
+        match NamedMutex::open(&object)? {
+            (_, true) => Err(Error::InterfaceLocked {
+                name: name.to_string(),
+                owner: None,
+            }),
+            (mutex, false) => Ok(Self(mutex)),
+        }
+    }
+}
+
+impl fmt::Debug for InstanceLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("InstanceLock").field(&self.0).finish()
+    }
+}
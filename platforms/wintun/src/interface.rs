@@ -1,22 +1,67 @@
+use super::elevation::check_elevation;
+use super::instance::InstanceLock;
+use super::lock::lock_adapters;
+use super::mtu::MtuNotifications;
 use super::queue::SessionQueueT;
-use super::wrappers::{Adapter, Session};
-use super::PlatformIfConfig;
+use super::tag::set_tag;
+use super::version::Version;
+use super::wrappers::adapter::{alias_exists, MAX_NAME};
+use super::wrappers::{ip_helper, suppress_location_prompt, Adapter, PacketReader, Session};
 use super::Queue;
+use super::{Ipv6Setup, NetworkCategory, PlatformIfConfig};
 use crate::Driver;
+use log::{debug, warn};
+use netconfig::ipnet::IpNet;
 use std::io;
 use std::io::{ErrorKind, Read, Write};
-use std::sync::Arc;
-use tunio_core::config::{IfConfig, Layer};
-use tunio_core::traits::InterfaceT;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{ready, Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+use tunio_core::address::Address;
+use tunio_core::config::{IfConfig, Layer, NameOutcome};
+use tunio_core::control::{ControlChannel, ControlSender};
+use tunio_core::diagnostics::Diagnostics;
+use tunio_core::egress::{EgressPriority, EgressScheduler};
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::mtu::MtuWatch;
+use tunio_core::name;
+use tunio_core::offload::Offload;
+use tunio_core::pause::PauseHandle;
+use tunio_core::ready::{Readiness, ReadyHold, ReadyNotify};
+use tunio_core::sampling::{Anomaly, SampledLog};
+use tunio_core::shaper::RateLimiter;
+use tunio_core::spec::{Change, InterfaceSpec, LinkFlag, LinkFlags};
+use tunio_core::stats::{QueueStats, StatsCounters};
+use tunio_core::traits::{AsyncQueueT, InterfaceT, SyncQueueT};
+use tunio_core::transaction::Transaction;
 use tunio_core::Error;
 use windows::core::GUID;
-use windows::Win32::NetworkManagement::IpHelper::ConvertInterfaceLuidToIndex;
-use windows::Win32::NetworkManagement::Ndis::NET_LUID_LH;
+
+/// How long the network category is retried, while Windows identifies the network.
+const CATEGORY_TIMEOUT: Duration = Duration::from_secs(30);
+const CATEGORY_RETRY_INTERVAL: Duration = Duration::from_millis(500);
 
 pub struct CommonInterface<Q: SessionQueueT> {
     wintun: Arc<wintun_sys::wintun>,
     adapter: Arc<Adapter>,
     config: IfConfig<PlatformIfConfig>,
+    name_outcome: NameOutcome,
+    peers: Vec<IpAddr>,
+    diagnostics: Diagnostics,
+    events: EventEmitter,
+    mtu: MtuNotifications,
+    pub(crate) pause: PauseHandle,
+    stats: StatsCounters,
+    anomalies: SampledLog,
+    pub(crate) shaper: RateLimiter,
+    pub(crate) egress: EgressScheduler,
+    control: ControlChannel,
+    readiness: Readiness,
+    /// Locks of the requested name and of the suffixed one, if it differs.
+    _instance_locks: Vec<InstanceLock>,
     pub(crate) queue: Option<Q>,
 }
 
@@ -24,63 +69,524 @@ impl<Q: SessionQueueT> InterfaceT for CommonInterface<Q> {
     type PlatformDriver = Driver;
     type PlatformIfConfig = PlatformIfConfig;
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(name = %params.name, guid = ?GUID::from_u128(params.platform.guid))
+        )
+    )]
     fn new(
         driver: &mut Self::PlatformDriver,
-        params: IfConfig<Self::PlatformIfConfig>,
+        mut params: IfConfig<Self::PlatformIfConfig>,
     ) -> Result<Self, Error> {
         let _ = Session::validate_capacity(params.platform.capacity);
         if params.layer == Layer::L2 {
             return Err(Error::LayerUnsupported(params.layer));
         }
 
+        let mut diagnostics = Diagnostics::new("wintun");
         let wintun = driver.wintun().clone();
+        check_elevation(&wintun)?;
+        diagnostics.step("check elevation", "ok");
+        // Before the adapter is created, so that its network is never prompted for
+        if params.platform.suppress_location_prompt {
+            suppress_location_prompt()?;
+            diagnostics.step("suppress network location prompt", "ok");
+        }
+
+        let name = name::normalize::<PlatformIfConfig>(&params.name, params.name_overflow)?;
+        // Before the name is resolved, so that the second instance does not adopt the adapter
+        let mut instance_locks = Vec::new();
+        if params.instance_lock {
+            instance_locks.push(InstanceLock::acquire(&name)?);
+        }
+        // Held, until the adapter is created, so that concurrent creations neither race in
+        // the registry, nor resolve the same name
+        let guard = lock_adapters(params.platform.adapter_lock)?;
+        let (name, name_outcome) =
+            name::resolve(&name, params.name_conflict, MAX_NAME, alias_exists)?;
+        diagnostics.step(
+            format!("resolve name \"{}\"", params.name),
+            format!("{name} ({name_outcome:?})"),
+        );
+        // Created name is locked too, so that an instance, that requests it, fails as well
+        if params.instance_lock && name_outcome == NameOutcome::Suffixed {
+            instance_locks.push(InstanceLock::acquire(&name)?);
+        }
+        let adapter = match name_outcome {
+            // Interface with this alias may be other than a Wintun adapter
+            NameOutcome::Adopted => {
+                Adapter::open(&name, wintun.clone()).map_err(|_| Error::NameTaken(name.clone()))?
+            }
+            NameOutcome::Created | NameOutcome::Suffixed => Adapter::new(
+                GUID::from_u128(params.platform.guid),
+                &name,
+                &params.platform.description,
+                wintun.clone(),
+                params.platform.adapter_lock,
+            )?,
+        };
+        drop(guard);
+        let guid = adapter.guid()?;
+        let action = match name_outcome {
+            NameOutcome::Adopted => "WintunOpenAdapter",
+            NameOutcome::Created | NameOutcome::Suffixed => "WintunCreateAdapter",
+        };
+        diagnostics.step(
+            format!("{action} \"{name}\""),
+            format!("GUID {guid:?}, LUID {:#x}", adapter.luid()),
+        );
+        if let Some(tag) = &params.tag {
+            set_tag(&guid, tag)?;
+            diagnostics.step(format!("set tag \"{tag}\""), "ok");
+        }
+        if params.ipv6_only {
+            adapter.disable_ipv4()?;
+            diagnostics.step("unbind IPv4", "ok");
+        }
+        match params.platform.ipv6_setup {
+            Ipv6Setup::System => {}
+            Ipv6Setup::NoRouterDiscovery => adapter.disable_router_discovery()?,
+            Ipv6Setup::Unbound => adapter.disable_ipv6()?,
+        }
+        if params.platform.ipv6_setup != Ipv6Setup::System {
+            diagnostics.step(format!("IPv6 setup {:?}", params.platform.ipv6_setup), "ok");
+        }
+        if params.detailed_diagnostics {
+            let version = driver.version();
+            let or_unknown =
+                |version: Option<Version>| version.map_or("unknown".to_string(), |v| v.to_string());
+            diagnostics.property("driver version", or_unknown(version.driver));
+            diagnostics.property("library version", or_unknown(version.library));
+            diagnostics.property(
+                "description",
+                adapter
+                    .description()
+                    .unwrap_or_else(|err| format!("error: {err}")),
+            );
+        }
+        let adapter = Arc::new(adapter);
+        params.name = name;
 
-        let adapter = Arc::new(Adapter::new(
-            GUID::from_u128(params.platform.guid),
-            &params.name,
-            &params.platform.description,
-            wintun.clone(),
-        )?);
+        let mtu =
+            MtuNotifications::register(params.name.clone(), adapter.luid(), driver.events.clone());
+        driver.events.emit(&params.name, EventKind::Created);
+        let anomalies = SampledLog::new(params.name.clone(), params.anomaly_sampling);
 
         Ok(Self {
             wintun,
             adapter,
             config: params,
+            name_outcome,
+            peers: Vec::new(),
+            diagnostics,
+            events: driver.events.clone(),
+            mtu,
+            pause: PauseHandle::default(),
+            stats: StatsCounters::new(Layer::L3),
+            anomalies,
+            shaper: RateLimiter::default(),
+            egress: EgressScheduler::new(Layer::L3),
+            control: ControlChannel::default(),
+            readiness: Readiness::default(),
+            _instance_locks: instance_locks,
             queue: None,
         })
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(name = %self.config.name, capacity = self.config.platform.capacity)
+        )
+    )]
     fn up(&mut self) -> Result<(), Error> {
         let session = Session::new(
             self.adapter.clone(),
             self.wintun.clone(),
             self.config.platform.capacity,
+            self.config.name.clone(),
+            self.events.clone(),
         )?;
-        self.queue = Some(Q::new(session));
+        self.queue = Some(Q::new(session, &self.config)?);
+        self.diagnostics.step(
+            "WintunStartSession",
+            format!("ring capacity {:#x}", self.config.platform.capacity),
+        );
 
+        if let Some(category) = self.config.platform.network_category {
+            spawn_category_setter(Arc::downgrade(&self.adapter), &self.config.name, category)?;
+        }
+        self.readiness.set_session(true);
         Ok(())
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(name = %self.config.name))
+    )]
     fn down(&mut self) -> Result<(), Error> {
+        self.events
+            .emit(&self.config.name, EventKind::ShutdownRequested);
+        self.readiness.set_session(false);
         let _ = self.queue.take();
         Ok(())
     }
 
+    fn add_route_async(&self, net: IpNet) -> Offload<()> {
+        let adapter = self.adapter.clone();
+        Offload::new(move || adapter.set_onlink_route(net, true))
+    }
+
+    fn remove_route_async(&self, net: IpNet) -> Offload<()> {
+        let adapter = self.adapter.clone();
+        Offload::new(move || adapter.set_onlink_route(net, false))
+    }
+
     fn handle(&self) -> netconfig::Interface {
-        let mut index = 0;
-        let luid = NET_LUID_LH {
-            Value: self.adapter.luid(),
-        };
+        netconfig::Interface::try_from_index(self.index().unwrap()).unwrap()
+    }
 
-        unsafe {
-            ConvertInterfaceLuidToIndex(&luid, &mut index).unwrap();
-        }
+    fn index(&self) -> Result<u32, Error> {
+        ip_helper::luid_to_index(self.luid())
+    }
+}
+
+/// Sets the network category, once Windows identifies the network. Adapter is not kept alive
+/// by the thread.
+fn spawn_category_setter(
+    adapter: Weak<Adapter>,
+    name: &str,
+    category: NetworkCategory,
+) -> io::Result<()> {
+    let name = name.to_string();
+    thread::Builder::new()
+        .name(format!("tunio-category-{name}"))
+        .spawn(move || {
+            let deadline = Instant::now() + CATEGORY_TIMEOUT;
+            while let Some(adapter) = adapter.upgrade() {
+                let err = match adapter.set_network_category(category) {
+                    Ok(()) => return,
+                    Err(Error::Io(err)) if err.kind() == ErrorKind::NotFound => err,
+                    Err(err) => {
+                        warn!("Failed to set network category of {name}: {err}");
+                        return;
+                    }
+                };
+                drop(adapter);
+                if Instant::now() >= deadline {
+                    warn!("Failed to set network category of {name}: {err}");
+                    return;
+                }
+                thread::sleep(CATEGORY_RETRY_INTERVAL);
+            }
+        })
+        .map(drop)
+}
 
-        netconfig::Interface::try_from_index(index).unwrap()
+impl<Q: SessionQueueT> Drop for CommonInterface<Q> {
+    fn drop(&mut self) {
+        self.events
+            .emit(&self.config.name, EventKind::ShutdownRequested);
     }
 }
 
 impl<Q: SessionQueueT> CommonInterface<Q> {
+    /// Locally unique identifier of the adapter, as used by IP Helper API.
+    pub fn luid(&self) -> u64 {
+        self.adapter.luid()
+    }
+
+    /// Whether the adapter was created, adopted or created with a suffixed name. See
+    /// [`IfConfig::name_conflict`].
+    pub fn name_outcome(&self) -> NameOutcome {
+        self.name_outcome
+    }
+
+    /// GUID of the adapter, as set in [`PlatformIfConfig::guid`]. Adopted adapters keep their
+    /// own GUID.
+    pub fn guid(&self) -> u128 {
+        self.config.platform.guid
+    }
+
+    /// What was done while creating the interface and starting its sessions, for bug
+    /// reports. See [`IfConfig::detailed_diagnostics`].
+    pub fn diagnostics(&self) -> &Diagnostics {
+        &self.diagnostics
+    }
+
+    /// Name of the adapter, shown in Network Connections. It differs from the configured
+    /// name, if the adapter was renamed by the user.
+    pub fn friendly_name(&self) -> Result<String, Error> {
+        self.adapter.alias()
+    }
+
+    /// Renames the adapter in Network Connections. Fails with [`Error::NameTaken`], if
+    /// another interface has this name.
+    pub fn set_friendly_name(&mut self, name: &str) -> Result<(), Error> {
+        if alias_exists(name) && self.friendly_name()? != name {
+            return Err(Error::NameTaken(name.to_string()));
+        }
+        self.adapter.set_alias(name)?;
+        self.config.name = name.to_string();
+        Ok(())
+    }
+
+    /// Device name of the adapter, derived from
+    /// [`PlatformIfConfig::description`].
+    pub fn description(&self) -> Result<String, Error> {
+        self.adapter.description()
+    }
+
+    /// Sets the category of the network behind the adapter, which decides the Windows
+    /// Firewall profile of the tunnel. Fails with `NotFound`, until Windows identifies the
+    /// network after the interface is up. See [`PlatformIfConfig::network_category`] to set it
+    /// automatically.
+    pub fn set_network_category(&self, category: NetworkCategory) -> Result<(), Error> {
+        self.adapter.set_network_category(category)
+    }
+
+    /// Sets the address of the other end of the tunnel, which replaces the previous peer of
+    /// the same family. Windows has no point-to-point destination address, so an on-link host
+    /// route to the peer is added instead.
+    pub fn set_peer(&mut self, addr: IpAddr) -> Result<(), Error> {
+        self.adapter.set_onlink_route(addr.into(), true)?;
+        if let Some(i) = self
+            .peers
+            .iter()
+            .position(|p| p.is_ipv4() == addr.is_ipv4())
+        {
+            let old = self.peers.swap_remove(i);
+            if old != addr {
+                self.adapter.set_onlink_route(old.into(), false)?;
+            }
+        }
+        self.peers.push(addr);
+        Ok(())
+    }
+
+    /// Live state of the interface in the form of [`apply`](Self::apply). Interface is up,
+    /// while the Wintun session is started. DNS is not read.
+    pub fn live_spec(&self) -> Result<InterfaceSpec, Error> {
+        let handle = self.handle();
+        Ok(InterfaceSpec {
+            up: Some(self.queue.is_some()),
+            mtu: Some(handle.mtu()?),
+            addresses: handle.addresses()?.into_iter().map(Address::new).collect(),
+            routes: self.adapter.onlink_routes()?,
+            dns: None,
+            flags: LinkFlags {
+                promiscuous: Some(false),
+                allmulti: Some(false),
+                arp: Some(false),
+            },
+        })
+    }
+
+    /// Reconciles the interface to `spec`, applying only the changes to its
+    /// [live state](Self::live_spec), and returns them. Managed routes are the ones without a
+    /// gateway, that are added like `route add`. Labels, flags and lifetimes of addresses
+    /// are ignored.
+    ///
+    /// A spec with DNS servers fails with [`Unsupported`](io::ErrorKind::Unsupported),
+    /// before anything is changed. Changes are applied as a [`Transaction`]: if one fails,
+    /// the ones before it are rolled back.
+    pub fn apply(&mut self, mut spec: InterfaceSpec) -> Result<Vec<Change>, Error> {
+        if spec.dns.is_some() {
+            return Err(io::Error::from(ErrorKind::Unsupported).into());
+        }
+        for address in &mut spec.addresses {
+            *address = Address::new(address.net);
+        }
+        let live = self.live_spec()?;
+        let changes = spec.diff(&live);
+        Transaction::new(live).run(changes, |change| self.apply_change(change))
+    }
+
+    /// Creates the adapter and reconciles it to `spec` in one step. If a change fails, the
+    /// interface is dropped, which removes a new adapter, while the changes to an adopted
+    /// one are rolled back.
+    pub fn new_with_spec(
+        driver: &mut Driver,
+        params: IfConfig<PlatformIfConfig>,
+        spec: InterfaceSpec,
+    ) -> Result<Self, Error> {
+        let mut interface = <Self as InterfaceT>::new(driver, params)?;
+        interface.apply(spec)?;
+        Ok(interface)
+    }
+
+    fn apply_change(&mut self, change: &Change) -> Result<(), Error> {
+        debug!("Applying {change:?} to {}", self.config.name);
+        match change {
+            Change::SetUp(true) => self.up(),
+            Change::SetUp(false) => self.down(),
+            Change::SetMtu(mtu) => Ok(self.handle().set_mtu(*mtu)?),
+            Change::AddAddress(address) => Ok(self.handle().add_address(address.net)?),
+            Change::RemoveAddress(net) => Ok(self.handle().remove_address(*net)?),
+            Change::AddRoute(net) => self.adapter.set_onlink_route(*net, true),
+            Change::RemoveRoute(net) => self.adapter.set_onlink_route(*net, false),
+            Change::SetDns(_)
+            | Change::SetFlag(LinkFlag::Promiscuous, _)
+            | Change::SetFlag(LinkFlag::AllMulti, _) => {
+                Err(io::Error::from(ErrorKind::Unsupported).into())
+            }
+            Change::SetFlag(LinkFlag::Arp, enabled) => self.set_arp(*enabled),
+        }
+    }
+
+    /// Wintun adapters have no link-layer multicast filter, so this fails with
+    /// [`Unsupported`](io::ErrorKind::Unsupported).
+    pub fn set_allmulti(&self, _enabled: bool) -> Result<(), Error> {
+        Err(io::Error::from(ErrorKind::Unsupported).into())
+    }
+
+    /// Wintun adapters carry IP packets and never use ARP: disabling it does nothing, and
+    /// enabling it fails with [`Unsupported`](io::ErrorKind::Unsupported).
+    pub fn set_arp(&self, enabled: bool) -> Result<(), Error> {
+        match enabled {
+            true => Err(io::Error::from(ErrorKind::Unsupported).into()),
+            false => Ok(()),
+        }
+    }
+
+    /// Stops taking packets from the ring, until [`resume`](Self::resume) is called. Pending
+    /// and subsequent reads wait, and Wintun drops incoming packets once the ring is full.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns a handle, that pauses and resumes this interface from another task or thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Limits the rate of packets, written to the interface, to `bytes_per_sec` with bursts
+    /// of up to `burst` bytes. Writes wait for the bucket to be replenished.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: u64, burst: u64) {
+        self.shaper.set(bytes_per_sec, burst);
+    }
+
+    pub fn clear_rate_limit(&mut self) {
+        self.shaper.clear();
+    }
+
+    /// Lets packets, selected by `policy`, bypass up to `backlog` bulk packets, queued while
+    /// async writes are pending. Has no effect on sync interfaces.
+    pub fn set_egress_priority(&mut self, policy: EgressPriority, backlog: usize) {
+        self.egress.set_policy(policy, backlog);
+    }
+
+    /// Handle, that queues control packets, like keepalives. They are sent ahead of all other
+    /// packets on the next read, write or flush, bypassing the egress backlog and the rate
+    /// limit.
+    pub fn control_sender(&self) -> ControlSender {
+        self.control.sender()
+    }
+
+    /// Future, that resolves, once the Wintun session is started, and all holds of
+    /// [`hold_ready`](Self::hold_ready) are dropped.
+    pub fn ready_notify(&self) -> ReadyNotify {
+        self.readiness.wait()
+    }
+
+    /// Keeps the interface from being ready, while addresses and routes are applied.
+    pub fn hold_ready(&self) -> ReadyHold {
+        self.readiness.hold()
+    }
+
+    /// Sends queued control packets, that are not shaped.
+    pub(crate) fn poll_send_control(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>>
+    where
+        Q: AsyncQueueT,
+    {
+        let Self {
+            control,
+            queue,
+            stats,
+            ..
+        } = self;
+        let queue = match queue {
+            Some(queue) => queue,
+            None => return Poll::Ready(Err(ErrorKind::BrokenPipe.into())),
+        };
+        control.poll_send_all(cx, |cx, packet| {
+            ready!(Pin::new(&mut *queue).poll_send(cx, packet))?;
+            stats.record_tx(packet);
+            Poll::Ready(Ok(()))
+        })
+    }
+
+    /// Packet and ECN counters of this interface.
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+
+    /// Counters of data path anomalies, including the ones, that were not logged.
+    pub fn anomalies(&self) -> &SampledLog {
+        &self.anomalies
+    }
+
+    /// IP MTU of the adapter, the lowest one of IPv4 and IPv6, updated from IP Helper
+    /// notifications. [`EventKind::MtuChanged`] is emitted on every change.
+    pub fn current_mtu(&self) -> u32 {
+        self.mtu.watch().get()
+    }
+
+    /// Returns a watch, that follows MTU changes of this adapter, for hooks, such as
+    /// [`Fragmenter::tracking`](tunio_core::hooks::Fragmenter::tracking).
+    pub fn mtu_watch(&self) -> MtuWatch {
+        self.mtu.watch().clone()
+    }
+
+    pub(crate) fn packet_read(&self, buf: &[u8], n: usize, truncated: bool) -> usize {
+        self.stats.record_rx(&buf[..n]);
+        if truncated {
+            self.anomalies
+                .record(Anomaly::Truncated, || format!("{n} bytes read"));
+        }
+        n
+    }
+
+    pub(crate) fn packet_written(&mut self, buf: &[u8], n: usize) -> usize {
+        self.stats.record_tx(&buf[..n]);
+        self.shaper.consume(n);
+        n
+    }
+
+    /// Current Wintun session, for advanced users, who want to drive readiness themselves.
+    /// See [`Session::handle`] for the safety contract. Returns `None`, if interface is down.
+    pub fn session(&self) -> Option<&Session> {
+        self.queue.as_ref().map(|queue| queue.session())
+    }
+
+    /// Consumes the interface, returning its current session. Internal reader tasks are
+    /// stopped, and adapter stays alive while the session exists.
+    pub fn into_session(mut self) -> Result<Session, Error> {
+        match self.queue.take() {
+            Some(queue) => Ok(queue.into_session()),
+            None => Err(io::Error::from(ErrorKind::BrokenPipe).into()),
+        }
+    }
+
+    /// Ends current Wintun session and starts a new one on the same adapter.
+    ///
+    /// Pending reads of the old session are cancelled. If the new session cannot be started,
+    /// interface stays down, and all subsequent reads and writes fail with `BrokenPipe`.
+    pub fn restart_session(&mut self) -> Result<(), Error> {
+        if self.queue.take().is_some() {
+            self.events
+                .emit(&self.config.name, EventKind::ShutdownRequested);
+        }
+        self.up()
+    }
+
     pub(crate) fn inner_queue_mut(&mut self) -> io::Result<&mut Q> {
         match &mut self.queue {
             Some(queue) => Ok(queue),
@@ -91,18 +597,68 @@ impl<Q: SessionQueueT> CommonInterface<Q> {
 
 pub type Interface = CommonInterface<Queue>;
 
+impl Interface {
+    fn send_control(&mut self) -> io::Result<()> {
+        let Self {
+            control,
+            queue,
+            stats,
+            ..
+        } = self;
+        let queue = queue.as_mut().ok_or(ErrorKind::BrokenPipe)?;
+        control.send_all(|packet| {
+            queue.send(packet)?;
+            stats.record_tx(packet);
+            Ok(())
+        })
+    }
+}
+
+impl SyncQueueT for Interface {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.send_control()?;
+        self.pause.wait_resumed();
+        let (n, truncated) = self.inner_queue_mut()?.recv(buf)?;
+        Ok((self.packet_read(buf, n, truncated), truncated))
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.send_control()?;
+        self.shaper.wait_ready(packet.len());
+        self.inner_queue_mut()?.send(packet)?;
+        self.packet_written(packet, packet.len());
+        Ok(())
+    }
+
+    type PacketRef<'a> = PacketReader<'a>;
+
+    fn recv_ref(&mut self) -> io::Result<PacketReader<'_>> {
+        self.pause.wait_resumed();
+        // Fields are borrowed separately, as the packet borrows the queue
+        let queue = self.queue.as_mut().ok_or(ErrorKind::BrokenPipe)?;
+        let packet = queue.recv_ref()?;
+        self.stats.record_rx(&packet);
+        Ok(packet)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        SyncQueueT::drain(self.inner_queue_mut()?)
+    }
+}
+
 impl Read for Interface {
-    delegate::delegate! {
-        to self.inner_queue_mut()? {
-            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
-        }
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
     }
 }
 
 impl Write for Interface {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
     delegate::delegate! {
         to self.inner_queue_mut()? {
-            fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
             fn flush(&mut self) -> io::Result<()>;
         }
     }
@@ -1,15 +1,35 @@
+#![deny(unsafe_code)]
+
+mod arch;
 mod config;
 mod driver;
-mod event;
+mod elevation;
+mod enumerate;
+mod error;
+mod instance;
 mod interface;
+mod lock;
 mod logger;
+mod mtu;
+mod power;
 mod queue;
+mod socket;
+mod tag;
+mod thread;
+mod version;
 mod wrappers;
 
-pub use config::{PlatformIfConfig, PlatformIfConfigBuilder};
+pub use config::{
+    AdapterLock, CloseMode, Ipv6Setup, NetworkCategory, PlatformIfConfig, PlatformIfConfigBuilder,
+    ThreadPriority,
+};
 pub use driver::Driver;
+pub use instance::InstanceLock;
 pub use interface::Interface;
 pub use queue::Queue;
+pub use socket::bind_to_interface;
+pub use version::{Version, WintunVersion};
+pub use wrappers::{PacketReader, Session};
 
 mod async_interface;
 mod async_queue;
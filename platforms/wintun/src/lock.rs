@@ -0,0 +1,53 @@
+//! Serialization of adapter creation and removal. Wintun writes the registry keys of new
+//! adapters without a lock of its own, so adapters, that are created concurrently, sometimes
+//! fail to install.
+use crate::config::AdapterLock;
+use crate::wrappers::NamedMutex;
+use std::sync::{Mutex, MutexGuard};
+use tunio_core::Error;
+
+static PROCESS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Held, while an adapter is created or removed.
+pub(crate) struct AdapterGuard {
+    // Fields are dropped in order, so the named mutex is released before the process one
+    _system: Option<SystemMutex>,
+    _process: Option<MutexGuard<'static, ()>>,
+}
+
+pub(crate) fn lock_adapters(mode: AdapterLock) -> Result<AdapterGuard, Error> {
+    let process = match mode {
+        AdapterLock::Off => None,
+        // Poisoned lock only means, that another creation panicked
+        AdapterLock::Process | AdapterLock::System => {
+            Some(PROCESS_LOCK.lock().unwrap_or_else(|e| e.into_inner()))
+        }
+    };
+    let system = match mode {
+        AdapterLock::System => Some(SystemMutex::acquire()?),
+        AdapterLock::Off | AdapterLock::Process => None,
+    };
+    Ok(AdapterGuard {
+        _system: system,
+        _process: process,
+    })
+}
+
+/// Named mutex, that is shared by all processes, using tunio. Mutexes are owned by threads,
+/// so the guard must be dropped on the thread, that acquired it.
+struct SystemMutex(NamedMutex);
+
+impl SystemMutex {
+    fn acquire() -> Result<Self, Error> {
+        let (mutex, _) = NamedMutex::open("Global\\tunio-adapter-lock")?;
+        // Abandoned mutex was held by a process, that exited, and is acquired all the same
+        mutex.acquire()?;
+        Ok(Self(mutex))
+    }
+}
+
+impl Drop for SystemMutex {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
@@ -0,0 +1,80 @@
+use crate::wrappers::ip_helper::{if_mtu, ip_interface_mtu, IpInterfaceChanges};
+use log::{debug, warn};
+use std::sync::Arc;
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::mtu::MtuWatch;
+use windows::Win32::Networking::WinSock::{AF_INET, AF_INET6};
+
+struct MtuState {
+    name: String,
+    luid: u64,
+    watch: MtuWatch,
+    events: EventEmitter,
+}
+
+impl MtuState {
+    fn on_change(&self) {
+        if let Some(mtu) = ip_mtu(self.luid) {
+            if self.watch.set(mtu) {
+                debug!("MTU of {} is changed to {mtu}", self.name);
+                self.events.emit(&self.name, EventKind::MtuChanged { mtu });
+            }
+        }
+    }
+}
+
+/// Subscription to IP interface changes, that keeps an [`MtuWatch`] in sync with the adapter.
+/// Unsubscribes on drop.
+pub(crate) struct MtuNotifications {
+    _registration: Option<IpInterfaceChanges>,
+    state: Arc<MtuState>,
+}
+
+impl MtuNotifications {
+    /// Subscribes to MTU changes of the adapter. Failure is not fatal: watch keeps the MTU,
+    /// that the adapter had at this point.
+    pub fn register(name: String, luid: u64, events: EventEmitter) -> Self {
+        let state = Arc::new(MtuState {
+            name,
+            luid,
+            watch: MtuWatch::new(ip_mtu(luid).or_else(|| if_mtu(luid)).unwrap_or(0)),
+            events,
+        });
+
+        let callback_state = state.clone();
+        // Notifications carry only the interface, so the MTU is read again
+        let result = IpInterfaceChanges::register(Box::new(move |changed| {
+            if changed == callback_state.luid {
+                callback_state.on_change();
+            }
+        }));
+        let registration = match result {
+            Ok(registration) => {
+                // MTU could change before the subscription
+                state.on_change();
+                Some(registration)
+            }
+            Err(e) => {
+                warn!("Failed to subscribe to MTU changes of {}: {e}", state.name);
+                None
+            }
+        };
+
+        Self {
+            _registration: registration,
+            state,
+        }
+    }
+
+    pub fn watch(&self) -> &MtuWatch {
+        &self.state.watch
+    }
+}
+
+/// IP MTU of the adapter, the lowest one of IPv4 and IPv6, that are enabled on it.
+fn ip_mtu(luid: u64) -> Option<u32> {
+    [AF_INET, AF_INET6]
+        .into_iter()
+        .filter_map(|family| ip_interface_mtu(luid, family))
+        .min()
+}
@@ -0,0 +1,93 @@
+use crate::wrappers::{SafeEvent, SuspendResumeNotification};
+use log::{debug, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tunio_core::events::{EventEmitter, EventKind};
+use windows::Win32::Foundation::HANDLE;
+
+const PBT_APMSUSPEND: u32 = 0x4;
+const PBT_APMRESUMESUSPEND: u32 = 0x7;
+const PBT_APMRESUMEAUTOMATIC: u32 = 0x12;
+
+pub(crate) struct PowerState {
+    name: String,
+    events: EventEmitter,
+
+    resumed: AtomicBool,
+    // Auto reset, because only one reader waits for it at a time
+    resume_event: SafeEvent,
+}
+
+impl PowerState {
+    /// Returns `true` once after each resume from sleep.
+    pub fn take_resumed(&self) -> bool {
+        self.resumed.swap(false, Ordering::AcqRel)
+    }
+
+    /// Event is signaled on resume from sleep, so pending ring waits can be interrupted.
+    pub fn resume_event(&self) -> HANDLE {
+        self.resume_event.handle()
+    }
+
+    fn on_suspend(&self) {
+        debug!("System is going to sleep, interface: {}", self.name);
+        self.events.emit(&self.name, EventKind::Suspended);
+    }
+
+    fn on_resume(&self) {
+        // Both automatic and user-triggered resume are reported, handle only the first one
+        if !self.resumed.swap(true, Ordering::AcqRel) {
+            debug!("System resumed from sleep, interface: {}", self.name);
+            self.events.emit(&self.name, EventKind::Resumed);
+            self.resume_event.set_event();
+        }
+    }
+}
+
+/// Subscription to system suspend/resume notifications. Unsubscribes on drop.
+pub(crate) struct PowerNotifications {
+    _registration: SuspendResumeNotification,
+    state: Arc<PowerState>,
+}
+
+impl PowerNotifications {
+    /// Subscribes to power notifications. Failure is not fatal: interface keeps working,
+    /// but the session will not be restarted after sleep automatically.
+    pub fn register(name: String, events: EventEmitter) -> Option<Self> {
+        let resume_event = match SafeEvent::new(false, false) {
+            Ok(event) => event,
+            Err(e) => {
+                warn!("Failed to create resume event: {e}");
+                return None;
+            }
+        };
+        let state = Arc::new(PowerState {
+            name,
+            events,
+            resumed: AtomicBool::new(false),
+            resume_event,
+        });
+
+        let callback_state = state.clone();
+        let registration =
+            SuspendResumeNotification::register(Box::new(move |event_type| match event_type {
+                PBT_APMSUSPEND => callback_state.on_suspend(),
+                PBT_APMRESUMEAUTOMATIC | PBT_APMRESUMESUSPEND => callback_state.on_resume(),
+                _ => {}
+            }));
+        match registration {
+            Ok(registration) => Some(Self {
+                _registration: registration,
+                state,
+            }),
+            Err(code) => {
+                warn!("Failed to subscribe to power notifications, error code: {code}");
+                None
+            }
+        }
+    }
+
+    pub fn state(&self) -> &Arc<PowerState> {
+        &self.state
+    }
+}
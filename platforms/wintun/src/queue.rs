@@ -1,35 +1,79 @@
-use super::wrappers::Session;
+use super::wrappers::{PacketReader, SendBackoff, Session};
+use super::PlatformIfConfig;
 use std::io::{self, Read, Write};
+use tunio_core::config::IfConfig;
 use tunio_core::traits::SyncQueueT;
+use tunio_core::Error;
 
-pub trait SessionQueueT {
-    fn new(session: Session) -> Self;
-}
+pub trait SessionQueueT: Sized {
+    fn new(session: Session, config: &IfConfig<PlatformIfConfig>) -> Result<Self, Error>;
+
+    /// Stops internal reader tasks and returns the underlying session.
+    fn into_session(self) -> Session;
 
-impl SyncQueueT for Queue {}
+    fn session(&self) -> &Session;
+}
 
 pub struct Queue {
     session: Session,
 }
 
 impl SessionQueueT for Queue {
-    fn new(session: Session) -> Self {
-        Self { session }
+    fn new(session: Session, _config: &IfConfig<PlatformIfConfig>) -> Result<Self, Error> {
+        Ok(Self { session })
+    }
+
+    fn into_session(self) -> Session {
+        self.session
+    }
+
+    fn session(&self) -> &Session {
+        &self.session
     }
 }
 
-impl Read for Queue {
-    delegate::delegate! {
-        to self.session {
-            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+impl SyncQueueT for Queue {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.session.recv(buf)
+    }
+
+    /// Blocks, while the ring is full, retrying after growing delays.
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        let mut backoff = SendBackoff::default();
+        loop {
+            match self.session.write(packet) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => backoff.wait(),
+                result => return result.map(drop),
+            }
         }
     }
+
+    /// Packet is borrowed from the ring. Like reads, fails with
+    /// [`WouldBlock`](io::ErrorKind::WouldBlock), when the ring is empty.
+    type PacketRef<'a> = PacketReader<'a>;
+
+    fn recv_ref(&mut self) -> io::Result<PacketReader<'_>> {
+        self.session.recv_ref()
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        self.session.drain()
+    }
+}
+
+impl Read for Queue {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
+    }
 }
 
 impl Write for Queue {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
     delegate::delegate! {
         to self.session {
-            fn write(&mut self, buf: &[u8]) -> io::Result<usize>;
             fn flush(&mut self) -> io::Result<()>;
         }
     }
@@ -0,0 +1,33 @@
+use crate::wrappers::winsock;
+use std::os::windows::io::RawSocket;
+use tunio_core::socket::SocketFamily;
+use tunio_core::Error;
+use windows::Win32::Networking::WinSock::{
+    IPPROTO_IP, IPPROTO_IPV6, IPV6_UNICAST_IF, IP_UNICAST_IF, SOCKET,
+};
+
+/// Binds socket to the interface with `IP_UNICAST_IF`/`IPV6_UNICAST_IF`, so its traffic is not
+/// routed through the tunnel.
+///
+/// For [`SocketFamily::Ipv6`] both options are set, so IPv4-mapped traffic of dual-stack
+/// sockets is bound too.
+pub fn bind_to_interface(socket: RawSocket, index: u32, family: SocketFamily) -> Result<(), Error> {
+    // RawSocket is u64 on all targets, while SOCKET is pointer-sized. Handles of 32-bit
+    // processes always fit into 32 bits.
+    let socket = SOCKET(socket as usize);
+
+    match family {
+        // IP_UNICAST_IF expects index in network byte order, unlike IPV6_UNICAST_IF
+        SocketFamily::Ipv4 => set_option(socket, IPPROTO_IP as _, IP_UNICAST_IF, index.to_be()),
+        SocketFamily::Ipv6 => {
+            set_option(socket, IPPROTO_IPV6.0, IPV6_UNICAST_IF, index)?;
+            // Fails on IPv6-only sockets
+            let _ = set_option(socket, IPPROTO_IP as _, IP_UNICAST_IF, index.to_be());
+            Ok(())
+        }
+    }
+}
+
+fn set_option(socket: SOCKET, level: i32, option: u32, value: u32) -> Result<(), Error> {
+    Ok(winsock::set_option(socket, level, option as _, value)?)
+}
@@ -0,0 +1,44 @@
+//! Application tags of adapters, stored as a value of the connection key of the adapter, which
+//! is removed together with the adapter.
+use crate::wrappers::RegKey;
+use tunio_core::Error;
+use widestring::U16CString;
+use windows::core::GUID;
+use windows::Win32::System::Registry::{KEY_QUERY_VALUE, KEY_SET_VALUE, REG_SAM_FLAGS};
+
+/// Network adapter class.
+const NETWORK_KEY: &str =
+    r"SYSTEM\CurrentControlSet\Control\Network\{4D36E972-E325-11CE-BFC1-08002BE10318}";
+const TAG_VALUE: &str = "TunioTag";
+
+pub(crate) fn set_tag(guid: &GUID, tag: &str) -> Result<(), Error> {
+    let key = connection_key(guid, KEY_SET_VALUE)?;
+    let value = U16CString::from_str(TAG_VALUE).map_err(|_| Error::InterfaceNameUnicodeError)?;
+    let tag = U16CString::from_str(tag).map_err(|_| Error::InvalidConfigValue {
+        name: "tag".to_string(),
+        value: tag.to_string(),
+        reason: "must not contain zero characters".to_string(),
+    })?;
+    key.set_string(&value, &tag)
+}
+
+/// Returns `None`, if the adapter has no tag.
+pub(crate) fn tag(guid: &GUID) -> Option<String> {
+    let key = connection_key(guid, KEY_QUERY_VALUE).ok()?;
+    let value = U16CString::from_str(TAG_VALUE).ok()?;
+    key.query_string(&value)
+}
+
+fn connection_key(guid: &GUID, access: REG_SAM_FLAGS) -> Result<RegKey, Error> {
+    let path = format!("{NETWORK_KEY}\\{}\\Connection", format_guid(guid));
+    RegKey::open_local_machine(&path, access)
+}
+
+/// Registry form of a GUID, like `{4D36E972-E325-11CE-BFC1-08002BE10318}`.
+fn format_guid(guid: &GUID) -> String {
+    let d = guid.data4;
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        guid.data1, guid.data2, guid.data3, d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7]
+    )
+}
@@ -0,0 +1,97 @@
+use crate::config::ThreadPriority;
+use crate::wrappers::CurrentThread;
+use log::warn;
+use widestring::U16CString;
+use windows::Win32::System::Threading::{
+    THREAD_PRIORITY, THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_HIGHEST, THREAD_PRIORITY_NORMAL,
+    THREAD_PRIORITY_TIME_CRITICAL,
+};
+
+/// Names the current thread and sets its priority and affinity for the duration of a ring wait.
+///
+/// Ring waits are executed on a shared thread pool, so previous settings are restored on drop.
+pub(crate) struct ReaderThreadGuard {
+    thread: CurrentThread,
+    previous_description: Option<U16CString>,
+    previous_priority: Option<THREAD_PRIORITY>,
+    previous_affinity: Option<usize>,
+}
+
+impl ReaderThreadGuard {
+    pub fn enter(name: &str, priority: ThreadPriority, affinity: Option<usize>) -> Self {
+        let thread = CurrentThread::get();
+
+        let previous_description = set_description(&thread, &format!("tunio-rx-{name}"));
+
+        let previous_priority = match priority {
+            ThreadPriority::Normal => None,
+            priority => {
+                let previous = thread.priority();
+                match thread.set_priority(priority.into()) {
+                    true => Some(previous),
+                    false => {
+                        warn!(
+                            "Failed to set reader thread priority: {}",
+                            std::io::Error::last_os_error()
+                        );
+                        None
+                    }
+                }
+            }
+        };
+
+        let previous_affinity = affinity.and_then(|mask| match thread.set_affinity(mask) {
+            0 => {
+                warn!(
+                    "Failed to set reader thread affinity {mask:#x}: {}",
+                    std::io::Error::last_os_error()
+                );
+                None
+            }
+            previous => Some(previous),
+        });
+
+        Self {
+            thread,
+            previous_description,
+            previous_priority,
+            previous_affinity,
+        }
+    }
+}
+
+impl Drop for ReaderThreadGuard {
+    fn drop(&mut self) {
+        if let Some(mask) = self.previous_affinity {
+            self.thread.set_affinity(mask);
+        }
+        if let Some(priority) = self.previous_priority {
+            self.thread.set_priority(priority);
+        }
+        if let Some(description) = &self.previous_description {
+            self.thread.set_description(description);
+        }
+    }
+}
+
+/// Sets thread description, returning the previous one. Thread descriptions are available
+/// since Windows 10 1607, failures are ignored.
+fn set_description(thread: &CurrentThread, description: &str) -> Option<U16CString> {
+    let previous = thread.description();
+
+    let description = U16CString::from_str_truncate(description);
+    thread.set_description(&description).then_some(())?;
+
+    previous
+}
+
+impl From<ThreadPriority> for THREAD_PRIORITY {
+    fn from(priority: ThreadPriority) -> Self {
+        match priority {
+            ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+            ThreadPriority::AboveNormal => THREAD_PRIORITY_ABOVE_NORMAL,
+            ThreadPriority::Highest => THREAD_PRIORITY_HIGHEST,
+            ThreadPriority::TimeCritical => THREAD_PRIORITY_TIME_CRITICAL,
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Versions of the running Wintun driver and of the loaded DLL.
+use crate::wrappers::library;
+use std::fmt;
+
+/// Four-part Windows version. Driver versions only have `major` and `minor` parts.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+    pub build: u16,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 || self.build != 0 {
+            write!(f, ".{}.{}", self.patch, self.build)?;
+        }
+        Ok(())
+    }
+}
+
+/// Versions, returned by [`Driver::version`](crate::Driver::version).
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct WintunVersion {
+    /// Version of the running driver. `None`, if the driver is not loaded yet: it is
+    /// installed with the first adapter.
+    pub driver: Option<Version>,
+    /// File version of the loaded `wintun.dll`. `None`, if it has no version resource.
+    pub library: Option<Version>,
+}
+
+pub(crate) fn running_driver_version(wintun: &wintun_sys::wintun) -> Option<Version> {
+    // Packed as major << 16 | minor, zero when the driver is not loaded
+    match library::running_driver_version(wintun) {
+        0 => None,
+        version => Some(Version {
+            major: (version >> 16) as u16,
+            minor: (version & 0xffff) as u16,
+            ..Default::default()
+        }),
+    }
+}
+
+pub(crate) fn library_version(library_name: &str) -> Option<Version> {
+    let (ms, ls) = library::file_version(library_name)?;
+    Some(Version {
+        major: (ms >> 16) as u16,
+        minor: (ms & 0xffff) as u16,
+        patch: (ls >> 16) as u16,
+        build: (ls & 0xffff) as u16,
+    })
+}
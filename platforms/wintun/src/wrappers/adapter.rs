@@ -1,26 +1,53 @@
+use super::nci::set_connection_name;
+use super::netcfg::{unbind_protocol, TCPIP, TCPIP6};
+use super::nlm::set_category;
 use super::HandleWrapper;
+use crate::config::{AdapterLock, NetworkCategory};
+use crate::error::{last_error, win32_error, Win32ResultExt};
+use crate::lock::lock_adapters;
 use log::error;
+use netconfig::ipnet::IpNet;
 use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::slice;
 use std::sync::Arc;
 use tunio_core::Error;
-use widestring::U16CString;
+use widestring::{U16CStr, U16CString};
 use windows::core::{GUID, PCWSTR};
+use windows::Win32::Foundation::{
+    ERROR_ACCESS_DENIED, ERROR_NOT_FOUND, ERROR_OBJECT_ALREADY_EXISTS,
+};
+use windows::Win32::NetworkManagement::IpHelper::{
+    ConvertInterfaceAliasToLuid, ConvertInterfaceLuidToAlias, ConvertInterfaceLuidToGuid,
+    CreateIpForwardEntry2, DeleteIpForwardEntry2, FreeMibTable, GetIfEntry2, GetIpForwardTable2,
+    GetIpInterfaceEntry, InitializeIpForwardEntry, SetIpInterfaceEntry, MIB_IF_ROW2,
+    MIB_IPFORWARD_ROW2, MIB_IPFORWARD_TABLE2, MIB_IPINTERFACE_ROW,
+};
 use windows::Win32::NetworkManagement::Ndis::NET_LUID_LH;
+use windows::Win32::Networking::WinSock::{
+    RouterDiscoveryDisabled, ADDRESS_FAMILY, AF_INET, AF_INET6, AF_UNSPEC, MIB_IPPROTO_NETMGMT,
+    SOCKADDR_INET,
+};
 use wintun_sys::WINTUN_ADAPTER_HANDLE;
 
-const MAX_NAME: usize = 255;
+pub(crate) const MAX_NAME: usize = 255;
 
 pub struct Adapter {
     wintun: Arc<wintun_sys::wintun>,
     handle: HandleWrapper<WINTUN_ADAPTER_HANDLE>,
+    /// Lock of the removal, when the adapter is closed. Opened adapters are not removed.
+    lock: AdapterLock,
 }
 
 impl Adapter {
+    /// Creates an adapter. Caller holds the lock of `lock` during creation, so that it also
+    /// covers name resolution.
     pub fn new(
         guid: GUID,
         name: &str,
         description: &str,
         wintun: Arc<wintun_sys::wintun>,
+        lock: AdapterLock,
     ) -> Result<Self, Error> {
         let [name_u16, description_u16] = [name, description].map(encode_name);
         let (name_u16, description_u16) = (name_u16?, description_u16?);
@@ -36,12 +63,38 @@ impl Adapter {
         if adapter_handle.is_null() {
             let err = io::Error::last_os_error();
             error!("Failed to create adapter: {err}");
-            return Err(Error::from(err));
+            if err.raw_os_error() == Some(ERROR_ACCESS_DENIED.0 as i32) {
+                return Err(Error::NeedsElevation {
+                    driver_install: false,
+                });
+            }
+            return Err(win32_error(format!("WintunCreateAdapter({name})"), err));
         }
 
         Ok(Self {
             wintun,
             handle: HandleWrapper(adapter_handle),
+            lock,
+        })
+    }
+
+    /// Opens an existing Wintun adapter by its name.
+    pub fn open(name: &str, wintun: Arc<wintun_sys::wintun>) -> Result<Self, Error> {
+        let name_u16 = encode_name(name)?;
+
+        let adapter_handle =
+            unsafe { wintun.WintunOpenAdapter(PCWSTR::from_raw(name_u16.as_ptr())) };
+
+        if adapter_handle.is_null() {
+            let err = last_error(format!("WintunOpenAdapter({name})"));
+            error!("Failed to open adapter: {err}");
+            return Err(err);
+        }
+
+        Ok(Self {
+            wintun,
+            handle: HandleWrapper(adapter_handle),
+            lock: AdapterLock::Off,
         })
     }
 
@@ -54,6 +107,132 @@ impl Adapter {
         }
     }
 
+    /// Name of the adapter, shown in Network Connections.
+    pub fn alias(&self) -> Result<String, Error> {
+        let luid = NET_LUID_LH { Value: self.luid() };
+        let mut alias = [0u16; MAX_NAME + 1];
+        unsafe { ConvertInterfaceLuidToAlias(&luid, &mut alias) }
+            .win32_context(|| "ConvertInterfaceLuidToAlias")?;
+        Ok(U16CStr::from_slice_truncate(&alias)
+            .map_err(|_| Error::InterfaceNameUnicodeError)?
+            .to_string_lossy())
+    }
+
+    /// GUID of the adapter, as known to the system. Adopted adapters keep their own GUID.
+    pub fn guid(&self) -> Result<GUID, Error> {
+        let luid = NET_LUID_LH { Value: self.luid() };
+        let mut guid = GUID::zeroed();
+        unsafe { ConvertInterfaceLuidToGuid(&luid, &mut guid) }
+            .win32_context(|| "ConvertInterfaceLuidToGuid")?;
+        Ok(guid)
+    }
+
+    /// Renames the adapter, as if it was renamed in Network Connections.
+    pub fn set_alias(&self, name: &str) -> Result<(), Error> {
+        let name = encode_name(name)?;
+        set_connection_name(&self.guid()?, &name)
+    }
+
+    /// Removes IPv4 binding of the adapter, leaving it IPv6-only.
+    pub fn disable_ipv4(&self) -> Result<(), Error> {
+        unbind_protocol(&self.guid()?, TCPIP)
+    }
+
+    /// Removes IPv6 binding of the adapter, so that it has no IPv6 addresses, including
+    /// the link-local one.
+    pub fn disable_ipv6(&self) -> Result<(), Error> {
+        unbind_protocol(&self.guid()?, TCPIP6)
+    }
+
+    /// Makes IPv6 ignore router advertisements on the adapter, like `accept_ra=0` on Linux:
+    /// no addresses are autoconfigured, and no routes are learned from them. Link-local
+    /// address is kept.
+    pub fn disable_router_discovery(&self) -> Result<(), Error> {
+        let mut row = MIB_IPINTERFACE_ROW {
+            Family: AF_INET6.0 as u16,
+            InterfaceLuid: NET_LUID_LH { Value: self.luid() },
+            ..Default::default()
+        };
+        unsafe { GetIpInterfaceEntry(&mut row) }.win32_context(|| "GetIpInterfaceEntry")?;
+        row.RouterDiscoveryBehavior = RouterDiscoveryDisabled;
+        unsafe { SetIpInterfaceEntry(&mut row) }.win32_context(|| "SetIpInterfaceEntry")?;
+        Ok(())
+    }
+
+    /// Adds or removes a route to `dst` through the adapter without a gateway. Host routes
+    /// make their destinations on-link peers. Existing and missing routes are not errors.
+    pub fn set_onlink_route(&self, dst: IpNet, add: bool) -> Result<(), Error> {
+        let mut row = MIB_IPFORWARD_ROW2::default();
+        unsafe { InitializeIpForwardEntry(&mut row) };
+        row.InterfaceLuid = NET_LUID_LH { Value: self.luid() };
+        let unspecified = match dst {
+            IpNet::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpNet::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        row.DestinationPrefix.Prefix = SocketAddr::new(dst.network(), 0).into();
+        row.DestinationPrefix.PrefixLength = dst.prefix_len();
+        row.NextHop = SocketAddr::new(unspecified, 0).into();
+
+        let result = match add {
+            true => unsafe { CreateIpForwardEntry2(&row) },
+            false => unsafe { DeleteIpForwardEntry2(&row) },
+        };
+        match result {
+            Err(err)
+                if err.code() == ERROR_OBJECT_ALREADY_EXISTS.to_hresult()
+                    || err.code() == ERROR_NOT_FOUND.to_hresult() =>
+            {
+                Ok(())
+            }
+            result => result.win32_context(|| match add {
+                true => "CreateIpForwardEntry2",
+                false => "DeleteIpForwardEntry2",
+            }),
+        }
+    }
+
+    /// Routes of [`set_onlink_route`](Self::set_onlink_route) through the adapter. Routes,
+    /// that Windows adds by itself, like the ones of address prefixes, are skipped.
+    pub fn onlink_routes(&self) -> Result<Vec<IpNet>, Error> {
+        let mut table: *mut MIB_IPFORWARD_TABLE2 = std::ptr::null_mut();
+        unsafe { GetIpForwardTable2(AF_UNSPEC.0 as u16, &mut table) }
+            .win32_context(|| "GetIpForwardTable2")?;
+
+        let rows =
+            unsafe { slice::from_raw_parts((*table).Table.as_ptr(), (*table).NumEntries as usize) };
+        let routes = rows
+            .iter()
+            .filter(|row| unsafe { row.InterfaceLuid.Value } == self.luid())
+            .filter(|row| row.Protocol == MIB_IPPROTO_NETMGMT)
+            .filter(|row| ip_addr(&row.NextHop).map_or(false, |hop| hop.is_unspecified()))
+            .filter_map(|row| {
+                let prefix = &row.DestinationPrefix;
+                IpNet::new(ip_addr(&prefix.Prefix)?, prefix.PrefixLength).ok()
+            })
+            .collect();
+
+        unsafe { FreeMibTable(table as _) };
+        Ok(routes)
+    }
+
+    /// Sets the category of the network behind the adapter. Fails with `NotFound`, until
+    /// Windows identifies the network.
+    pub fn set_network_category(&self, category: NetworkCategory) -> Result<(), Error> {
+        set_category(&self.guid()?, category)
+    }
+
+    /// Description of the adapter, derived from the tunnel type, that it was created with.
+    pub fn description(&self) -> Result<String, Error> {
+        let mut row = MIB_IF_ROW2 {
+            InterfaceLuid: NET_LUID_LH { Value: self.luid() },
+            ..Default::default()
+        };
+        unsafe { GetIfEntry2(&mut row) }.win32_context(|| "GetIfEntry2")?;
+        Ok(U16CStr::from_slice_truncate(&row.Description)
+            .map_err(|_| Error::InterfaceNameUnicodeError)?
+            .to_string_lossy())
+    }
+
     pub fn handle(&self) -> WINTUN_ADAPTER_HANDLE {
         self.handle.0
     }
@@ -61,10 +240,54 @@ impl Adapter {
 
 impl Drop for Adapter {
     fn drop(&mut self) {
+        // Closing a created adapter removes it. It is removed without the lock, if that fails
+        let _guard =
+            lock_adapters(self.lock).map_err(|err| error!("Failed to lock adapter removal: {err}"));
         unsafe { self.wintun.WintunCloseAdapter(self.handle.0) };
     }
 }
 
+fn ip_addr(addr: &SOCKADDR_INET) -> Option<IpAddr> {
+    // SAFETY: family tells, which member of the union is set
+    unsafe {
+        match ADDRESS_FAMILY(u32::from(addr.si_family)) {
+            AF_INET => Some(IpAddr::V4(
+                addr.Ipv4.sin_addr.S_un.S_addr.to_ne_bytes().into(),
+            )),
+            AF_INET6 => Some(IpAddr::V6(addr.Ipv6.sin6_addr.u.Byte.into())),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `true`, if the interface with alias `name` is a Wintun adapter. Unlike
+/// [`Adapter::open`], failures are expected and not logged.
+pub fn is_wintun_adapter(name: &str, wintun: &wintun_sys::wintun) -> bool {
+    let name = match encode_name(name) {
+        Ok(name) => name,
+        Err(_) => return false,
+    };
+    let handle = unsafe { wintun.WintunOpenAdapter(PCWSTR::from_raw(name.as_ptr())) };
+    if handle.is_null() {
+        return false;
+    }
+    // Closing an opened adapter does not remove it
+    unsafe { wintun.WintunCloseAdapter(handle) };
+    true
+}
+
+/// Returns `true`, if any network interface has `name` as its alias.
+pub fn alias_exists(name: &str) -> bool {
+    let mut luid = NET_LUID_LH::default();
+    match U16CString::from_str(name) {
+        Ok(name) => {
+            unsafe { ConvertInterfaceAliasToLuid(PCWSTR::from_raw(name.as_ptr()), &mut luid) }
+                .is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
 fn encode_name(string: &str) -> Result<U16CString, Error> {
     let result = U16CString::from_str(string).map_err(|_| Error::InterfaceNameUnicodeError)?;
     match result.len() {
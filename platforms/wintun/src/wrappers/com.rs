@@ -0,0 +1,19 @@
+use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED};
+
+/// COM initialization of the current thread. Threads, that initialized COM in another
+/// apartment, keep it.
+pub(crate) struct ComScope(bool);
+
+impl ComScope {
+    pub fn enter() -> Self {
+        Self(unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok())
+    }
+}
+
+impl Drop for ComScope {
+    fn drop(&mut self) {
+        if self.0 {
+            unsafe { CoUninitialize() };
+        }
+    }
+}
@@ -0,0 +1,36 @@
+#![allow(dead_code)]
+use windows::Win32::Foundation::{CloseHandle, HANDLE, WIN32_ERROR};
+use windows::Win32::System::Threading::{CreateEventA, SetEvent, WaitForMultipleObjects};
+use windows::Win32::System::WindowsProgramming::INFINITE;
+
+pub(crate) struct SafeEvent(HANDLE);
+
+impl SafeEvent {
+    pub fn new(manual_reset: bool, initial_state: bool) -> windows::core::Result<Self> {
+        unsafe { CreateEventA(None, manual_reset, initial_state, None) }.map(Self)
+    }
+
+    pub fn set_event(&self) {
+        unsafe {
+            SetEvent(self.handle());
+        }
+    }
+
+    pub fn handle(&self) -> HANDLE {
+        self.0
+    }
+}
+
+impl Drop for SafeEvent {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// Waits without a timeout, until any of `handles` is signaled. Returns `WAIT_OBJECT_0` or
+/// `WAIT_ABANDONED_0` plus the position of the handle, or `WAIT_FAILED`.
+pub(crate) fn wait_any(handles: &[HANDLE]) -> WIN32_ERROR {
+    // SAFETY: the slice is borrowed, while the call waits. Handles, that are closed meanwhile,
+    // only make it fail.
+    unsafe { WaitForMultipleObjects(handles, false, INFINITE) }
+}
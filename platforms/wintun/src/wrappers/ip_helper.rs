@@ -0,0 +1,121 @@
+//! IP Helper calls of interfaces, that are not bound to a Wintun adapter.
+use crate::error::Win32ResultExt;
+use std::ffi::c_void;
+use std::io;
+use std::slice;
+use tunio_core::Error;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::NetworkManagement::IpHelper::{
+    CancelMibChangeNotify2, ConvertInterfaceLuidToIndex, FreeMibTable, GetIfEntry2, GetIfTable2,
+    GetIpInterfaceEntry, NotifyIpInterfaceChange, MIB_IF_ROW2, MIB_IF_TABLE2, MIB_IPINTERFACE_ROW,
+    MIB_NOTIFICATION_TYPE,
+};
+use windows::Win32::NetworkManagement::Ndis::NET_LUID_LH;
+use windows::Win32::Networking::WinSock::{ADDRESS_FAMILY, AF_UNSPEC};
+
+/// Table of all interfaces of the system, that is freed on drop.
+pub(crate) struct IfTable(*mut MIB_IF_TABLE2);
+
+impl IfTable {
+    pub fn get() -> Result<Self, Error> {
+        let mut table: *mut MIB_IF_TABLE2 = std::ptr::null_mut();
+        // SAFETY: table is allocated by the call and owned by the result
+        unsafe { GetIfTable2(&mut table) }.map_err(io::Error::from)?;
+        Ok(Self(table))
+    }
+
+    pub fn rows(&self) -> &[MIB_IF_ROW2] {
+        // SAFETY: table holds `NumEntries` rows, that live until the table is freed
+        unsafe { slice::from_raw_parts((*self.0).Table.as_ptr(), (*self.0).NumEntries as usize) }
+    }
+}
+
+impl Drop for IfTable {
+    fn drop(&mut self) {
+        // SAFETY: table is owned and freed once
+        unsafe { FreeMibTable(self.0 as _) };
+    }
+}
+
+pub(crate) fn luid_to_index(luid: u64) -> Result<u32, Error> {
+    let mut index = 0;
+    let luid = NET_LUID_LH { Value: luid };
+    // SAFETY: call only writes the index
+    unsafe { ConvertInterfaceLuidToIndex(&luid, &mut index) }
+        .win32_context(|| "ConvertInterfaceLuidToIndex")?;
+    Ok(index)
+}
+
+/// IP MTU of the interface for `family`. `None`, if the family is disabled on it.
+pub(crate) fn ip_interface_mtu(luid: u64, family: ADDRESS_FAMILY) -> Option<u32> {
+    let mut row = MIB_IPINTERFACE_ROW {
+        Family: family.0 as u16,
+        InterfaceLuid: NET_LUID_LH { Value: luid },
+        ..Default::default()
+    };
+    // SAFETY: row is initialized with the keys of the entry, that is read
+    unsafe { GetIpInterfaceEntry(&mut row) }
+        .ok()
+        .map(|()| row.NlMtu)
+}
+
+/// Link MTU of the interface.
+pub(crate) fn if_mtu(luid: u64) -> Option<u32> {
+    let mut row = MIB_IF_ROW2 {
+        InterfaceLuid: NET_LUID_LH { Value: luid },
+        ..Default::default()
+    };
+    // SAFETY: row is initialized with the key of the entry, that is read
+    unsafe { GetIfEntry2(&mut row) }.ok().map(|()| row.Mtu)
+}
+
+type Callback = Box<dyn Fn(u64) + Send + Sync>;
+
+/// Callback, that is called with the LUID of each changed IP interface. Cancels on drop.
+pub(crate) struct IpInterfaceChanges {
+    registration: HANDLE,
+    // Must stay at the same address until the registration is cancelled
+    _callback: Box<Callback>,
+}
+
+impl IpInterfaceChanges {
+    pub fn register(callback: Callback) -> windows::core::Result<Self> {
+        let callback = Box::new(callback);
+        let mut registration = HANDLE::default();
+        // SAFETY: callback is boxed and is released only after the registration is cancelled
+        unsafe {
+            NotifyIpInterfaceChange(
+                AF_UNSPEC.0 as u16,
+                Some(ip_interface_callback),
+                Some(&*callback as *const Callback as *const c_void),
+                false,
+                &mut registration,
+            )
+        }?;
+        Ok(Self {
+            registration,
+            _callback: callback,
+        })
+    }
+}
+
+impl Drop for IpInterfaceChanges {
+    fn drop(&mut self) {
+        // Waits for running callbacks, so the callback can be safely released afterwards
+        // SAFETY: registration is cancelled once
+        let _ = unsafe { CancelMibChangeNotify2(self.registration) };
+    }
+}
+
+unsafe extern "system" fn ip_interface_callback(
+    context: *const c_void,
+    row: *const MIB_IPINTERFACE_ROW,
+    _notification_type: MIB_NOTIFICATION_TYPE,
+) {
+    // SAFETY: context is the boxed callback, that outlives the registration, and the row is
+    // valid through the call
+    let callback = &*(context as *const Callback);
+    if !row.is_null() {
+        callback((*row).InterfaceLuid.Value);
+    }
+}
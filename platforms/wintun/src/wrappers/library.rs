@@ -0,0 +1,95 @@
+//! Loading of `wintun.dll` and calls of the library, that are not bound to an adapter.
+use crate::logger::log_message;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use tunio_core::Error;
+use widestring::{U16CStr, U16CString};
+use windows::core::PCWSTR;
+use windows::Win32::Storage::FileSystem::{
+    GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW, VS_FIXEDFILEINFO,
+};
+use windows::Win32::System::LibraryLoader::{GetModuleFileNameW, GetModuleHandleW};
+use wintun_sys::WINTUN_LOGGER_LEVEL;
+
+/// Loads the library by its name without the extension and routes its log to the `log` crate.
+pub(crate) fn load(library_name: &str) -> Result<wintun_sys::wintun, Error> {
+    // SAFETY: loading runs no code of the library besides its initializer, and functions are
+    // resolved with the signatures of `wintun.h`
+    let wintun =
+        unsafe { wintun_sys::wintun::new(library_name) }.map_err(|e| Error::LibraryNotLoaded {
+            reason: format!("{e:?}"),
+        })?;
+
+    // SAFETY: logger is a function of this crate, so it outlives the library
+    unsafe {
+        wintun.WintunSetLogger(Some(wintun_logger));
+    }
+    Ok(wintun)
+}
+
+/// Version of the running driver, packed as `major << 16 | minor`. Zero, when the driver is
+/// not loaded.
+pub(crate) fn running_driver_version(wintun: &wintun_sys::wintun) -> u32 {
+    // SAFETY: call takes no arguments and only reads state of the driver
+    unsafe { wintun.WintunGetRunningDriverVersion() }
+}
+
+/// Most and least significant words of the file version of the loaded library. `None`, if it
+/// is not loaded or has no version resource.
+pub(crate) fn file_version(library_name: &str) -> Option<(u32, u32)> {
+    let name = U16CString::from_str(format!("{library_name}.dll")).ok()?;
+    // SAFETY: name is zero-terminated. Module handle is not reference-counted, and the library
+    // stays loaded, while the driver, that loaded it, exists.
+    let module = unsafe { GetModuleHandleW(PCWSTR::from_raw(name.as_ptr())) }.ok()?;
+
+    let mut path = vec![0u16; 32768];
+    // SAFETY: buffer is passed as a slice, so the call knows its length
+    let len = unsafe { GetModuleFileNameW(module, &mut path) } as usize;
+    if len == 0 || len == path.len() {
+        return None;
+    }
+    path.truncate(len);
+    path.push(0);
+    let path = PCWSTR::from_raw(path.as_ptr());
+
+    // SAFETY: path is zero-terminated and outlives the calls
+    let size = unsafe { GetFileVersionInfoSizeW(path, None) };
+    if size == 0 {
+        return None;
+    }
+    let mut info = vec![0u8; size as usize];
+    // SAFETY: buffer is `size` bytes long
+    unsafe { GetFileVersionInfoW(path, 0, size, info.as_mut_ptr() as *mut c_void) }
+        .as_bool()
+        .then_some(())?;
+
+    let root = U16CString::from_str("\\").ok()?;
+    let mut fixed: *mut c_void = ptr::null_mut();
+    let mut fixed_len = 0u32;
+    // SAFETY: info is a version resource, filled above. Returned pointer points into it.
+    unsafe {
+        VerQueryValueW(
+            info.as_ptr() as *const c_void,
+            PCWSTR::from_raw(root.as_ptr()),
+            &mut fixed,
+            &mut fixed_len,
+        )
+    }
+    .as_bool()
+    .then_some(())?;
+    if fixed.is_null() || (fixed_len as usize) < mem::size_of::<VS_FIXEDFILEINFO>() {
+        return None;
+    }
+
+    // SAFETY: pointer is checked to be non-null and to point to a whole structure, and info
+    // outlives the reference
+    let fixed = unsafe { &*(fixed as *const VS_FIXEDFILEINFO) };
+    Some((fixed.dwFileVersionMS, fixed.dwFileVersionLS))
+}
+
+unsafe extern "C" fn wintun_logger(level: WINTUN_LOGGER_LEVEL, _timestamp: u64, message: PCWSTR) {
+    // SAFETY: Wintun passes zero-terminated messages, that live through the call
+    let message = U16CStr::from_ptr_str(message.as_ptr());
+    log_message(level, &message.to_string_lossy());
+}
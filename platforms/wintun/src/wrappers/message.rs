@@ -0,0 +1,40 @@
+//! Texts of system error codes.
+use std::ffi::c_void;
+use windows::core::PWSTR;
+use windows::w;
+use windows::Win32::System::Diagnostics::Debug::{
+    FormatMessageW, FORMAT_MESSAGE_FROM_HMODULE, FORMAT_MESSAGE_FROM_SYSTEM,
+    FORMAT_MESSAGE_IGNORE_INSERTS,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+
+/// Text of the code in the language of the user, without the trailing line break.
+pub(crate) fn format_message(code: u32) -> Option<String> {
+    let mut flags = FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS;
+    let mut module = None;
+    // NTSTATUS errors have both severity bits set, and their texts are kept in ntdll
+    if code & 0xC000_0000 == 0xC000_0000 {
+        // SAFETY: name is a literal, and ntdll is never unloaded
+        if let Ok(ntdll) = unsafe { GetModuleHandleW(w!("ntdll.dll")) } {
+            flags |= FORMAT_MESSAGE_FROM_HMODULE;
+            module = Some(ntdll.0 as *const c_void);
+        }
+    }
+
+    let mut buf = [0u16; 512];
+    // SAFETY: buffer length is passed, and no inserts are formatted
+    let len = unsafe {
+        FormatMessageW(
+            flags,
+            module,
+            code,
+            0,
+            PWSTR(buf.as_mut_ptr()),
+            buf.len() as u32,
+            None,
+        )
+    };
+    let message = String::from_utf16_lossy(&buf[..len as usize]);
+    let message = message.trim_end();
+    (!message.is_empty()).then(|| message.to_string())
+}
@@ -1,7 +1,33 @@
+#![allow(unsafe_code)]
+
 pub(crate) mod adapter;
+mod com;
+pub(crate) mod event;
 pub(crate) mod handle;
+pub(crate) mod ip_helper;
+pub(crate) mod library;
+mod message;
+mod mutex;
+mod nci;
+mod netcfg;
+mod nlm;
+mod packet;
+mod power;
+mod registry;
 pub(crate) mod session;
+mod thread;
+mod token;
+pub(crate) mod winsock;
 
 pub(crate) use adapter::Adapter;
+pub(crate) use event::{wait_any, SafeEvent};
 pub(crate) use handle::HandleWrapper;
-pub(crate) use session::Session;
+pub(crate) use message::format_message;
+pub(crate) use mutex::NamedMutex;
+pub(crate) use nlm::suppress_location_prompt;
+pub(crate) use power::SuspendResumeNotification;
+pub(crate) use registry::RegKey;
+pub(crate) use session::{ActiveWait, ReaderStop, SendBackoff};
+pub use session::{PacketReader, Session};
+pub(crate) use thread::CurrentThread;
+pub(crate) use token::ProcessToken;
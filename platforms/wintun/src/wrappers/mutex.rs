@@ -0,0 +1,57 @@
+//! Named mutexes, that are shared by processes.
+use crate::error::{last_error, Win32ResultExt};
+use std::fmt;
+use tunio_core::Error;
+use widestring::U16CString;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, WAIT_ABANDONED, WAIT_OBJECT_0,
+};
+use windows::Win32::System::Threading::{CreateMutexW, ReleaseMutex, WaitForSingleObject};
+
+/// Handle of a named mutex, that is closed on drop. Mutex exists, while any handle to it is
+/// open.
+pub(crate) struct NamedMutex(HANDLE);
+
+impl NamedMutex {
+    /// Opens the mutex, creating it, if it does not exist. Returns, whether it existed.
+    pub fn open(name: &str) -> Result<(Self, bool), Error> {
+        let name_u16 = U16CString::from_str(name).map_err(|_| Error::InterfaceNameInvalid)?;
+        // SAFETY: name is zero-terminated, and the handle is owned by the result
+        let handle = unsafe { CreateMutexW(None, false, PCWSTR::from_raw(name_u16.as_ptr())) }
+            .win32_context(|| format!("CreateMutexW({name})"))?;
+        let mutex = Self(handle);
+        // SAFETY: reads the error of the call above, which is made by this thread
+        let existed = unsafe { GetLastError() } == ERROR_ALREADY_EXISTS;
+        Ok((mutex, existed))
+    }
+
+    /// Waits without a timeout, until the current thread owns the mutex. Abandoned mutex was
+    /// held by a thread, that exited, and is acquired all the same.
+    pub fn acquire(&self) -> Result<(), Error> {
+        // SAFETY: handle is open, while self exists
+        match unsafe { WaitForSingleObject(self.0, u32::MAX) } {
+            WAIT_OBJECT_0 | WAIT_ABANDONED => Ok(()),
+            _ => Err(last_error("WaitForSingleObject")),
+        }
+    }
+
+    /// Releases ownership of the mutex. Fails silently, if the current thread does not own it.
+    pub fn release(&self) {
+        // SAFETY: handle is open, while self exists
+        let _ = unsafe { ReleaseMutex(self.0) };
+    }
+}
+
+impl fmt::Debug for NamedMutex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NamedMutex").field(&self.0 .0).finish()
+    }
+}
+
+impl Drop for NamedMutex {
+    fn drop(&mut self) {
+        // SAFETY: handle is owned and closed once
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
@@ -0,0 +1,31 @@
+//! Connection names, as set by Network Connections. There is no documented API to rename an
+//! adapter, so `NciSetConnectionName` of `nci.dll` is used, like Wintun did itself before its
+//! rename function was removed.
+use std::io;
+use tunio_core::Error;
+use widestring::U16CString;
+use windows::core::{GUID, PCSTR, PCWSTR};
+use windows::Win32::System::LibraryLoader::{FreeLibrary, GetProcAddress, LoadLibraryW};
+
+type NciSetConnectionName = unsafe extern "system" fn(*const GUID, PCWSTR) -> u32;
+
+pub(crate) fn set_connection_name(guid: &GUID, name: &U16CString) -> Result<(), Error> {
+    let library = U16CString::from_str("nci.dll").map_err(|_| Error::InterfaceNameUnicodeError)?;
+    let module =
+        unsafe { LoadLibraryW(PCWSTR::from_raw(library.as_ptr())) }.map_err(io::Error::from)?;
+
+    let result = match unsafe { GetProcAddress(module, PCSTR(b"NciSetConnectionName\0".as_ptr())) }
+    {
+        Some(function) => {
+            let function: NciSetConnectionName = unsafe { std::mem::transmute(function) };
+            match unsafe { function(guid, PCWSTR::from_raw(name.as_ptr())) } {
+                0 => Ok(()),
+                code => Err(io::Error::from_raw_os_error(code as i32).into()),
+            }
+        }
+        None => Err(io::Error::last_os_error().into()),
+    };
+
+    unsafe { FreeLibrary(module) };
+    result
+}
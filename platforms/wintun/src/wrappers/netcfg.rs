@@ -0,0 +1,68 @@
+//! Protocol bindings of adapters, as shown in adapter properties. They are only changed with
+//! `INetCfg`, which has no replacement in newer APIs.
+use super::com::ComScope;
+use std::io;
+use tunio_core::Error;
+use windows::core::{Interface, GUID, HSTRING};
+use windows::w;
+use windows::Win32::NetworkManagement::NetManagement::{
+    INetCfg, INetCfgComponentBindings, INetCfgLock, NETCFG_E_ADAPTER_NOT_FOUND,
+};
+use windows::Win32::System::Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_INPROC_SERVER};
+
+const CLSID_CNETCFG: GUID = GUID::from_u128(0x5b035261_40f9_11d1_aaec_00805fc1270e);
+const GUID_DEVCLASS_NET: GUID = GUID::from_u128(0x4d36e972_e325_11ce_bfc1_08002be10318);
+const LOCK_TIMEOUT_MS: u32 = 5000;
+
+/// Component IDs of the protocols.
+pub(crate) const TCPIP: &str = "ms_tcpip";
+pub(crate) const TCPIP6: &str = "ms_tcpip6";
+
+/// Unbinds `protocol`, like [`TCPIP`], from the adapter, so that it gets no configuration of
+/// this protocol. Other adapters are not affected.
+pub(crate) fn unbind_protocol(adapter: &GUID, protocol: &str) -> Result<(), Error> {
+    let _com = ComScope::enter();
+    let net_cfg: INetCfg = unsafe { CoCreateInstance(&CLSID_CNETCFG, None, CLSCTX_INPROC_SERVER) }
+        .map_err(io::Error::from)?;
+
+    let lock: INetCfgLock = net_cfg.cast().map_err(io::Error::from)?;
+    let holder =
+        unsafe { lock.AcquireWriteLock(LOCK_TIMEOUT_MS, w!("tunio")) }.map_err(io::Error::from)?;
+    // Description of the holder is returned, if another process keeps the lock
+    if !holder.is_null() {
+        let holder_name = unsafe { holder.to_string() }.unwrap_or_default();
+        unsafe { CoTaskMemFree(Some(holder.0 as _)) };
+        return Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            format!("network configuration is locked by {holder_name}"),
+        )
+        .into());
+    }
+
+    let result = unsafe { net_cfg.Initialize(None) }.and_then(|()| {
+        let result = unbind(&net_cfg, adapter, protocol);
+        let _ = unsafe { net_cfg.Uninitialize() };
+        result
+    });
+    let _ = unsafe { lock.ReleaseWriteLock() };
+    Ok(result.map_err(io::Error::from)?)
+}
+
+fn unbind(net_cfg: &INetCfg, adapter: &GUID, protocol: &str) -> windows::core::Result<()> {
+    let protocol = unsafe { net_cfg.FindComponent(&HSTRING::from(protocol)) }?;
+    let bindings: INetCfgComponentBindings = protocol.cast()?;
+    let components = unsafe { net_cfg.EnumComponents(&GUID_DEVCLASS_NET) }?;
+    loop {
+        let mut component = [None];
+        unsafe { components.Next(&mut component, None) }?;
+        let component = match component {
+            [Some(component)] => component,
+            [None] => return Err(NETCFG_E_ADAPTER_NOT_FOUND.into()),
+        };
+        if unsafe { component.GetInstanceGuid() }? == *adapter {
+            // Unbound adapters are reported with S_FALSE
+            unsafe { bindings.UnbindFrom(&component) }?;
+            return unsafe { net_cfg.Apply() };
+        }
+    }
+}
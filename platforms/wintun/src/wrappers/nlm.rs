@@ -0,0 +1,77 @@
+//! Network categories of adapters, managed by Network List Manager. Windows Firewall applies
+//! the profile of the category to the network, that an adapter is connected to.
+use super::com::ComScope;
+use crate::config::NetworkCategory;
+use std::io;
+use tunio_core::Error;
+use widestring::U16CString;
+use windows::core::{GUID, PCWSTR};
+use windows::Win32::Foundation::ERROR_SUCCESS;
+use windows::Win32::Networking::NetworkListManager::{
+    INetworkListManager, NetworkListManager, NLM_NETWORK_CATEGORY_PRIVATE,
+    NLM_NETWORK_CATEGORY_PUBLIC,
+};
+use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_ALL};
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegCreateKeyExW, HKEY, HKEY_LOCAL_MACHINE, KEY_WRITE, REG_OPTION_NON_VOLATILE,
+};
+
+/// Existence of this key turns the prompt off for all new networks.
+const NEW_NETWORK_WINDOW_OFF_KEY: &str =
+    r"SYSTEM\CurrentControlSet\Control\Network\NewNetworkWindowOff";
+
+/// Sets the category of the network, that the adapter is connected to. Fails with
+/// `NotFound`, while Windows has not identified the network yet, which takes a few seconds
+/// after the adapter is up.
+pub(crate) fn set_category(adapter: &GUID, category: NetworkCategory) -> Result<(), Error> {
+    let _com = ComScope::enter();
+    let manager: INetworkListManager =
+        unsafe { CoCreateInstance(&NetworkListManager, None, CLSCTX_ALL) }
+            .map_err(io::Error::from)?;
+
+    let connections = unsafe { manager.GetNetworkConnections() }.map_err(io::Error::from)?;
+    loop {
+        let mut connection = [None];
+        unsafe { connections.Next(&mut connection, None) }.map_err(io::Error::from)?;
+        let connection = match connection {
+            [Some(connection)] => connection,
+            [None] => return Err(io::Error::from(io::ErrorKind::NotFound).into()),
+        };
+        if unsafe { connection.GetAdapterId() }.map_err(io::Error::from)? != *adapter {
+            continue;
+        }
+
+        let network = unsafe { connection.GetNetwork() }.map_err(io::Error::from)?;
+        let category = match category {
+            NetworkCategory::Public => NLM_NETWORK_CATEGORY_PUBLIC,
+            NetworkCategory::Private => NLM_NETWORK_CATEGORY_PRIVATE,
+        };
+        unsafe { network.SetCategory(category) }.map_err(io::Error::from)?;
+        return Ok(());
+    }
+}
+
+/// Turns the "Network location" prompt off. The setting is system-wide.
+pub(crate) fn suppress_location_prompt() -> Result<(), Error> {
+    let path = U16CString::from_str(NEW_NETWORK_WINDOW_OFF_KEY)
+        .map_err(|_| Error::InterfaceNameUnicodeError)?;
+    let mut key = HKEY::default();
+    let result = unsafe {
+        RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR::from_raw(path.as_ptr()),
+            0,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_WRITE,
+            None,
+            &mut key,
+            None,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return Err(io::Error::from_raw_os_error(result.0 as i32).into());
+    }
+    unsafe { RegCloseKey(key) };
+    Ok(())
+}
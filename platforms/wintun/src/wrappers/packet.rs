@@ -0,0 +1,83 @@
+//! Memory of packets in Wintun rings. Wintun returns raw pointers to packets, that stay valid
+//! until the packet is released or sent, so their slices are only created here.
+use std::ptr::NonNull;
+use std::slice;
+
+/// Packet in a receive or send ring.
+///
+/// Packet memory is valid for reads and writes of `len` bytes, and nothing else accesses it,
+/// until the packet is returned to Wintun, which its owner does after dropping the slices.
+pub(crate) struct RingPacket {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl RingPacket {
+    /// Returns `None` for a null pointer, which Wintun returns on failure.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and writes of `len` bytes and must not be accessed
+    /// otherwise, until the packet is dropped. Memory must be initialized, if it is read.
+    pub unsafe fn new(ptr: *mut u8, len: usize) -> Option<Self> {
+        NonNull::new(ptr).map(|ptr| Self { ptr, len })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: memory is valid for `len` bytes while the packet exists, see `new`
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// Copies `data` into the packet. Unlike the plain pointer copy, lengths are checked, so
+    /// that a shorter packet is never overrun.
+    pub fn fill(&mut self, data: &[u8]) {
+        // SAFETY: memory is valid and exclusive for `len` bytes, see `new`
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }.copy_from_slice(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn null_is_rejected() {
+        assert!(unsafe { RingPacket::new(std::ptr::null_mut(), 10) }.is_none());
+    }
+
+    #[test]
+    fn slice_covers_packet() {
+        let mut ring = vec![1u8, 2, 3, 4, 5];
+        let packet = unsafe { RingPacket::new(ring.as_mut_ptr().add(1), 3) }.unwrap();
+        assert_eq!(packet.as_slice(), [2, 3, 4]);
+    }
+
+    #[test]
+    fn fill_stays_in_packet() {
+        let mut ring = vec![0u8; 6];
+        {
+            let mut packet = unsafe { RingPacket::new(ring.as_mut_ptr().add(2), 2) }.unwrap();
+            packet.fill(&[7, 8]);
+        }
+        assert_eq!(ring, [0, 0, 7, 8, 0, 0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn fill_rejects_other_length() {
+        let mut ring = vec![0u8; 4];
+        let mut packet = unsafe { RingPacket::new(ring.as_mut_ptr(), 2) }.unwrap();
+        packet.fill(&[1, 2, 3]);
+    }
+
+    #[test]
+    fn empty_packet() {
+        let mut ring: Vec<u8> = Vec::with_capacity(1);
+        let packet = unsafe { RingPacket::new(ring.as_mut_ptr(), 0) }.unwrap();
+        assert!(packet.as_slice().is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! Registration of callbacks for system suspend and resume.
+use std::ffi::c_void;
+use std::ptr;
+use windows::Win32::Foundation::{ERROR_SUCCESS, HANDLE};
+use windows::Win32::System::Power::{
+    PowerRegisterSuspendResumeNotification, PowerUnregisterSuspendResumeNotification,
+    DEVICE_NOTIFY_CALLBACK, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS, HPOWERNOTIFY,
+};
+
+type Callback = Box<dyn Fn(u32) + Send + Sync>;
+
+/// Callback, that is called with the `PBT_*` type of each power event. Unregisters on drop.
+pub(crate) struct SuspendResumeNotification {
+    registration: *mut c_void,
+    // Both must stay at the same address until the registration is dropped
+    _params: Box<DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS>,
+    _callback: Box<Callback>,
+}
+
+// Registration is only used to unregister, and the callback is `Send` and `Sync` itself
+unsafe impl Send for SuspendResumeNotification {}
+unsafe impl Sync for SuspendResumeNotification {}
+
+impl SuspendResumeNotification {
+    /// Fails with the error code of the registration.
+    pub fn register(callback: Callback) -> Result<Self, u32> {
+        let callback = Box::new(callback);
+        let params = Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+            Callback: Some(power_callback),
+            Context: &*callback as *const Callback as *mut c_void,
+        });
+
+        let mut registration = ptr::null_mut();
+        // SAFETY: parameters and the callback are boxed and are released only after the
+        // registration in drop
+        let result = unsafe {
+            PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK.0,
+                HANDLE(&*params as *const _ as isize),
+                &mut registration,
+            )
+        };
+        match result {
+            0 => Ok(Self {
+                registration,
+                _params: params,
+                _callback: callback,
+            }),
+            code => Err(code),
+        }
+    }
+}
+
+impl Drop for SuspendResumeNotification {
+    fn drop(&mut self) {
+        // Waits for running callbacks, so the callback can be safely released afterwards
+        // SAFETY: registration is unregistered once
+        let _ = unsafe {
+            PowerUnregisterSuspendResumeNotification(HPOWERNOTIFY(self.registration as isize))
+        };
+    }
+}
+
+unsafe extern "system" fn power_callback(
+    context: *const c_void,
+    event_type: u32,
+    _setting: *const c_void,
+) -> u32 {
+    // SAFETY: context is the boxed callback, that outlives the registration
+    let callback = &*(context as *const Callback);
+    callback(event_type);
+    ERROR_SUCCESS.0
+}
@@ -0,0 +1,89 @@
+//! Keys of the registry, that close on drop.
+use std::io;
+use tunio_core::Error;
+use widestring::{U16CStr, U16CString};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::ERROR_SUCCESS;
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegOpenKeyExW, RegQueryValueExW, RegSetValueExW, HKEY, HKEY_LOCAL_MACHINE,
+    REG_SAM_FLAGS, REG_SZ,
+};
+
+pub(crate) struct RegKey(HKEY);
+
+impl RegKey {
+    /// Opens a subkey of `HKEY_LOCAL_MACHINE`.
+    pub fn open_local_machine(path: &str, access: REG_SAM_FLAGS) -> Result<Self, Error> {
+        let path = U16CString::from_str(path).map_err(|_| Error::InterfaceNameUnicodeError)?;
+        let mut key = HKEY::default();
+        // SAFETY: path is zero-terminated, and the opened key is owned by the result
+        let result = unsafe {
+            RegOpenKeyExW(
+                HKEY_LOCAL_MACHINE,
+                PCWSTR::from_raw(path.as_ptr()),
+                0,
+                access,
+                &mut key,
+            )
+        };
+        check(result.0)?;
+        Ok(Self(key))
+    }
+
+    /// Sets a `REG_SZ` value.
+    pub fn set_string(&self, name: &U16CStr, value: &U16CStr) -> Result<(), Error> {
+        let data: Vec<u8> = value
+            .as_slice_with_nul()
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        // SAFETY: name is zero-terminated, and data is passed as a slice
+        let result = unsafe {
+            RegSetValueExW(
+                self.0,
+                PCWSTR::from_raw(name.as_ptr()),
+                0,
+                REG_SZ,
+                Some(&data),
+            )
+        };
+        check(result.0)
+    }
+
+    /// Returns a string value of up to 255 characters, or `None`, if there is no such value.
+    pub fn query_string(&self, name: &U16CStr) -> Option<String> {
+        let mut data = [0u16; 256];
+        let mut len = (data.len() * 2) as u32;
+        // SAFETY: name is zero-terminated, and `len` is the size of data in bytes
+        let result = unsafe {
+            RegQueryValueExW(
+                self.0,
+                PCWSTR::from_raw(name.as_ptr()),
+                None,
+                None,
+                Some(data.as_mut_ptr() as *mut u8),
+                Some(&mut len),
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return None;
+        }
+        let data = &data[..(len as usize / 2).min(data.len())];
+        Some(U16CStr::from_slice_truncate(data).ok()?.to_string_lossy())
+    }
+}
+
+impl Drop for RegKey {
+    fn drop(&mut self) {
+        // SAFETY: key is owned and closed once
+        unsafe { RegCloseKey(self.0) };
+    }
+}
+
+fn check(code: u32) -> Result<(), Error> {
+    match code {
+        0 => Ok(()),
+        // Registry functions return error codes instead of setting the last error
+        code => Err(io::Error::from_raw_os_error(code as i32).into()),
+    }
+}
@@ -1,20 +1,32 @@
+use super::packet::RingPacket;
 use super::Adapter;
 use super::HandleWrapper;
-use bytes::BufMut;
-use log::error;
+use super::SafeEvent;
+use crate::error::last_error;
+use crate::power::{PowerNotifications, PowerState};
+use log::{error, warn};
 use std::io;
 use std::io::{Read, Write};
-use std::sync::Arc;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::traits::copy_packet;
 use tunio_core::Error;
 use windows::Win32::Foundation::{ERROR_BUFFER_OVERFLOW, ERROR_NO_MORE_ITEMS, HANDLE, WIN32_ERROR};
-use wintun_sys::{WINTUN_MAX_RING_CAPACITY, WINTUN_MIN_RING_CAPACITY, WINTUN_SESSION_HANDLE};
+use wintun_sys::{
+    WINTUN_MAX_IP_PACKET_SIZE, WINTUN_MAX_RING_CAPACITY, WINTUN_MIN_RING_CAPACITY,
+    WINTUN_SESSION_HANDLE,
+};
 
-struct PacketReader<'a> {
+/// Packet, borrowed from the receive ring. It is released to the driver on drop.
+pub struct PacketReader<'a> {
     handle: HandleWrapper<WINTUN_SESSION_HANDLE>,
     wintun: &'a wintun_sys::wintun,
 
-    ptr: *const u8,
-    len: usize,
+    packet: RingPacket,
 }
 
 impl<'a> PacketReader<'a> {
@@ -24,67 +36,372 @@ impl<'a> PacketReader<'a> {
     ) -> io::Result<Self> {
         let mut len: u32 = 0;
         let ptr = unsafe { wintun.WintunReceivePacket(handle.0, &mut len) };
-
-        if !ptr.is_null() {
-            Ok(Self {
+        // SAFETY: received packet is valid for its length until it is released on drop, and
+        // DWORD fits into usize on all Windows targets
+        match unsafe { RingPacket::new(ptr, len as usize) } {
+            Some(packet) => Ok(Self {
                 handle,
                 wintun,
-                ptr,
-                len: len as _,
-            })
-        } else {
-            Err(io::Error::last_os_error())
+                packet,
+            }),
+            None => Err(io::Error::last_os_error()),
         }
     }
 
     pub fn as_slice(&self) -> &[u8] {
-        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+        self.packet.as_slice()
+    }
+}
+
+impl Deref for PacketReader<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
     }
 }
 
 impl<'a> Drop for PacketReader<'a> {
     fn drop(&mut self) {
-        if !self.ptr.is_null() {
-            unsafe {
-                self.wintun
-                    .WintunReleaseReceivePacket(self.handle.0, self.ptr);
+        unsafe {
+            self.wintun
+                .WintunReleaseReceivePacket(self.handle.0, self.packet.as_ptr());
+        }
+    }
+}
+
+/// Send side of a session, that is shared with the writer thread of the async queue.
+///
+/// Handle is locked for each write, and it is cleared, when the session ends, so a write on
+/// another thread is finished before the session ends, and later ones fail with `BrokenPipe`.
+pub(crate) struct RingSender {
+    wintun: Arc<wintun_sys::wintun>,
+    handle: Mutex<HandleWrapper<WINTUN_SESSION_HANDLE>>,
+}
+
+impl RingSender {
+    fn new(wintun: Arc<wintun_sys::wintun>, handle: WINTUN_SESSION_HANDLE) -> Self {
+        Self {
+            wintun,
+            handle: Mutex::new(HandleWrapper(handle)),
+        }
+    }
+
+    /// Allocates a packet in the ring and copies `buf` into it directly. Fails with
+    /// [`WouldBlock`](io::ErrorKind::WouldBlock), when the ring is full.
+    pub fn try_send(&self, buf: &[u8]) -> io::Result<()> {
+        let len = packet_len(buf.len())?;
+        let handle = self.lock();
+        if handle.0.is_null() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let ptr = unsafe { self.wintun.WintunAllocateSendPacket(handle.0, len) };
+        // SAFETY: allocated packet is valid for its length until it is sent
+        match unsafe { RingPacket::new(ptr, buf.len()) } {
+            Some(mut packet) => {
+                packet.fill(buf);
+                // Deallocates packet
+                unsafe { self.wintun.WintunSendPacket(handle.0, packet.as_ptr()) };
+                Ok(())
+            }
+            None => {
+                let e = io::Error::last_os_error();
+                match error_eq(&e, ERROR_BUFFER_OVERFLOW) {
+                    true => Err(io::ErrorKind::WouldBlock.into()),
+                    false => Err(e),
+                }
             }
         }
     }
+
+    /// Sends `buf`, retrying with [`SendBackoff`], while the ring is full.
+    pub fn send(&self, buf: &[u8]) -> io::Result<()> {
+        let mut backoff = SendBackoff::default();
+        loop {
+            match self.try_send(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => backoff.wait(),
+                result => return result,
+            }
+        }
+    }
+
+    /// Waits for a write in progress and makes later ones fail.
+    fn close(&self) {
+        *self.lock() = HandleWrapper(ptr::null_mut());
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HandleWrapper<WINTUN_SESSION_HANDLE>> {
+        self.handle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Delays of writes, that wait for space in a full ring. Wintun does not signal, when the
+/// driver takes packets from the ring, so writes are retried after delays, that double up to
+/// [`MAX`](Self::MAX), instead of spinning.
+pub(crate) struct SendBackoff(Duration);
+
+impl SendBackoff {
+    const MIN: Duration = Duration::from_micros(20);
+    const MAX: Duration = Duration::from_millis(1);
+
+    pub fn wait(&mut self) {
+        thread::sleep(self.0);
+        self.0 = (self.0 * 2).min(Self::MAX);
+    }
 }
 
+impl Default for SendBackoff {
+    fn default() -> Self {
+        Self(Self::MIN)
+    }
+}
+
+/// Longest time, that ending a session waits for its reader to stop.
+const READER_STOP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Stops the async reader of a session, before the session ends.
+///
+/// Ending a session closes its read event, so no wait on it may still be running. Waits are
+/// registered with [`begin_wait`](Self::begin_wait), before they are scheduled, so that a wait,
+/// that has not started yet, is waited for too.
+pub(crate) struct ReaderStop {
+    shutdown: SafeEvent,
+    waiting: Mutex<bool>,
+    finished: Condvar,
+}
+
+impl ReaderStop {
+    pub fn new() -> windows::core::Result<Self> {
+        Ok(Self {
+            // Manual reset, because the event must also stop waits, that start after it is set
+            shutdown: SafeEvent::new(true, false)?,
+            waiting: Mutex::new(false),
+            finished: Condvar::new(),
+        })
+    }
+
+    /// Ends the active wait and the ones, that start later, without waiting for them.
+    pub fn request_shutdown(&self) {
+        self.shutdown.set_event();
+    }
+
+    pub fn begin_wait(self: &Arc<Self>) -> ActiveWait {
+        *self.lock() = true;
+        ActiveWait(self.clone())
+    }
+
+    /// Signals shutdown and waits for the active wait to finish. Returns `false`, if it did not
+    /// finish in `timeout`.
+    fn stop(&self, timeout: Duration) -> bool {
+        self.shutdown.set_event();
+        let (_waiting, result) = self
+            .finished
+            .wait_timeout_while(self.lock(), timeout, |waiting| *waiting)
+            .unwrap_or_else(|e| e.into_inner());
+        !result.timed_out()
+    }
+
+    /// Returns `true`, while a wait on the read event is registered.
+    fn is_waiting(&self) -> bool {
+        *self.lock()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, bool> {
+        self.waiting.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Wait of the reader on the read event. It is over, when this is dropped.
+pub(crate) struct ActiveWait(Arc<ReaderStop>);
+
+impl ActiveWait {
+    /// Event, that is signaled, when the wait must stop.
+    pub fn shutdown_event(&self) -> HANDLE {
+        self.0.shutdown.handle()
+    }
+}
+
+impl Drop for ActiveWait {
+    fn drop(&mut self) {
+        *self.0.lock() = false;
+        self.0.finished.notify_all();
+    }
+}
+
+/// Wintun session. Reads are nonblocking and fail with
+/// [`WouldBlock`](io::ErrorKind::WouldBlock), when the ring is empty; wait on
+/// [`read_event`](Session::read_event) to be notified about new packets.
 pub struct Session {
     handle: HandleWrapper<WINTUN_SESSION_HANDLE>,
     wintun: Arc<wintun_sys::wintun>,
+    adapter: Arc<Adapter>,
+    capacity: u32,
+    sender: Arc<RingSender>,
+
+    name: String,
+    events: EventEmitter,
+    power: Option<PowerNotifications>,
+    reader: Option<Arc<ReaderStop>>,
 }
 
 impl Session {
-    pub fn new(
+    pub(crate) fn new(
         adapter: Arc<Adapter>,
         wintun: Arc<wintun_sys::wintun>,
         capacity: u32,
+        name: String,
+        events: EventEmitter,
     ) -> Result<Self, Error> {
-        let _ = Self::validate_capacity(capacity)?;
-
-        let session_handle = unsafe { wintun.WintunStartSession(adapter.handle(), capacity) };
+        Self::validate_capacity(capacity)?;
 
-        if session_handle.is_null() {
-            let err = io::Error::last_os_error();
-            error!("Failed to create session: {err}");
-            return Err(err.into());
-        }
+        let session_handle = start_session(&wintun, &adapter, capacity)?;
+        events.emit(&name, EventKind::SessionStarted);
+        let power = PowerNotifications::register(name.clone(), events.clone());
 
         Ok(Self {
             handle: HandleWrapper(session_handle),
+            sender: Arc::new(RingSender::new(wintun.clone(), session_handle)),
             wintun,
+            adapter,
+            capacity,
+            name,
+            events,
+            power,
+            reader: None,
         })
     }
 
-    #[allow(dead_code)]
+    /// Ends current session and starts a new one on the same adapter. If a new session cannot
+    /// be started, all subsequent reads and writes fail with `BrokenPipe`.
+    pub fn restart(&mut self) -> Result<(), Error> {
+        // Reader restarts the session only between waits, so it is not stopped
+        self.end();
+
+        let handle = start_session(&self.wintun, &self.adapter, self.capacity)?;
+        self.handle = HandleWrapper(handle);
+        self.sender = Arc::new(RingSender::new(self.wintun.clone(), handle));
+        self.emit(EventKind::SessionStarted);
+        Ok(())
+    }
+
+    /// Makes the session stop `reader`, before it ends.
+    pub(crate) fn set_reader(&mut self, reader: Arc<ReaderStop>) {
+        self.reader = Some(reader);
+    }
+
+    /// Stops the reader, if it is set. If it does not stop in time, the session is leaked
+    /// instead of ending it, so that the read event stays valid for the reader. All
+    /// subsequent reads and writes fail with `BrokenPipe` then.
+    pub(crate) fn stop_reader(&mut self) {
+        let stopped = match self.reader.take() {
+            Some(reader) => reader.stop(READER_STOP_TIMEOUT),
+            None => true,
+        };
+        if !stopped {
+            error!("Wintun reader did not stop in {READER_STOP_TIMEOUT:?}, leaking session");
+            self.sender.close();
+            self.handle = HandleWrapper(ptr::null_mut());
+        }
+    }
+
+    fn end(&mut self) {
+        if !self.handle.0.is_null() {
+            self.sender.close();
+            unsafe {
+                self.wintun.WintunEndSession(self.handle.0);
+            }
+            self.handle = HandleWrapper(ptr::null_mut());
+        }
+    }
+
+    /// Ring wait may never be signaled after resume from sleep, so session is restarted
+    /// before the first read after resume. Restart closes the read event, so it is left to the
+    /// next read, while the reader waits on it. Writes never restart the session, see
+    /// [`check_started`](Self::check_started).
+    fn ensure_started(&mut self) -> io::Result<()> {
+        let waiting = self.reader.as_ref().map_or(false, |r| r.is_waiting());
+        if !waiting
+            && self
+                .power
+                .as_ref()
+                .map_or(false, |p| p.state().take_resumed())
+        {
+            warn!("Restarting session after resume from sleep");
+            if let Err(e) = self.restart() {
+                error!("Failed to restart session after resume: {e}");
+            }
+        }
+        self.check_started()
+    }
+
+    fn check_started(&self) -> io::Result<()> {
+        match self.handle.0.is_null() {
+            true => Err(io::ErrorKind::BrokenPipe.into()),
+            false => Ok(()),
+        }
+    }
+
+    /// Send side of the session for writes on another thread. It fails with `BrokenPipe`
+    /// after the session ends or is restarted.
+    pub(crate) fn sender(&self) -> Arc<RingSender> {
+        self.sender.clone()
+    }
+
+    pub(crate) fn emit(&self, kind: EventKind) {
+        self.events.emit(&self.name, kind);
+    }
+
+    pub(crate) fn power_state(&self) -> Option<Arc<PowerState>> {
+        self.power.as_ref().map(|p| p.state().clone())
+    }
+
+    /// Raw session handle for direct use of the Wintun API.
+    ///
+    /// Handle is valid while this session is alive and not restarted. It must not be passed
+    /// to `WintunEndSession`, and packets must not be received concurrently with
+    /// [`Read`] on this session.
+    pub fn handle(&self) -> WINTUN_SESSION_HANDLE {
+        self.handle.0
+    }
+
+    /// Event, that is signaled when the ring has packets to read. It is owned by the session
+    /// and has the same validity rules as [`handle`](Session::handle).
     pub fn read_event(&self) -> HANDLE {
         unsafe { self.wintun.WintunGetReadWaitEvent(self.handle.0) }
     }
 
+    /// Receives a packet without copying it from the ring. Fails with
+    /// [`WouldBlock`](io::ErrorKind::WouldBlock), when the ring is empty.
+    pub fn recv_ref(&mut self) -> io::Result<PacketReader<'_>> {
+        self.ensure_started()?;
+        PacketReader::read(self.handle.clone(), &self.wintun).map_err(|e| {
+            match error_eq(&e, ERROR_NO_MORE_ITEMS) {
+                true => io::ErrorKind::WouldBlock.into(),
+                false => e,
+            }
+        })
+    }
+
+    /// Copies a packet from the ring into `buf`, returning the copied length and `true`, if
+    /// the packet was longer than `buf` and its remainder is discarded. `buf` may be of any
+    /// length. Fails with [`WouldBlock`](io::ErrorKind::WouldBlock), when the ring is empty.
+    pub fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        let packet = self.recv_ref()?;
+        Ok(copy_packet(&packet, buf))
+    }
+
+    /// Releases packets, that are in the ring, without reading them, and returns their number.
+    pub fn drain(&mut self) -> io::Result<usize> {
+        let mut drained = 0;
+        loop {
+            match self.recv_ref() {
+                Ok(_) => drained += 1,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(drained),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
     pub fn validate_capacity(capacity: u32) -> Result<(), Error> {
         let range = WINTUN_MIN_RING_CAPACITY..=WINTUN_MAX_RING_CAPACITY;
         if !range.contains(&capacity) || !capacity.is_power_of_two() {
@@ -101,42 +418,25 @@ impl Session {
     }
 }
 
+/// Reads a single packet, truncating it to the buffer, like [`recv`](Session::recv).
 impl Read for Session {
-    fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
-        let packet = PacketReader::read(self.handle.clone(), &self.wintun);
-        match packet {
-            Ok(packet) => {
-                let packet_slice = packet.as_slice();
-                buf.put(packet_slice);
-                Ok(packet_slice.len())
-            }
-            Err(e) => match error_eq(&e, ERROR_NO_MORE_ITEMS) {
-                true => Err(io::ErrorKind::WouldBlock.into()),
-                false => Err(e),
-            },
-        }
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
     }
 }
 
 impl Write for Session {
-    // does not block, as WintunAllocateSendPacket and WintunSendPacket are executed right one ofter another
+    /// Allocates a packet in the ring and copies `buf` into it directly. Does not block: fails
+    /// with [`WouldBlock`](io::ErrorKind::WouldBlock), when the ring is full.
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let packet = unsafe {
-            self.wintun
-                .WintunAllocateSendPacket(self.handle.0, buf.len() as _)
-        };
-        if !packet.is_null() {
-            // Copy buffer to allocated packet
-            unsafe {
-                packet.copy_from_nonoverlapping(buf.as_ptr(), buf.len());
-                self.wintun.WintunSendPacket(self.handle.0, packet); // Deallocates packet
-            }
-            Ok(buf.len())
-        } else {
-            let e = io::Error::last_os_error();
-            match error_eq(&e, ERROR_BUFFER_OVERFLOW) {
-                true => panic!("send buffer overflow"),
-                false => Err(e),
+        self.check_started()?;
+        match self.sender.try_send(buf) {
+            Ok(()) => Ok(buf.len()),
+            // Driver drains the ring shortly, and the write is retried by the queue
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(e),
+            Err(e) => {
+                self.emit(EventKind::WriteFailed(e.kind()));
+                Err(e)
             }
         }
     }
@@ -148,15 +448,86 @@ impl Write for Session {
 
 impl Drop for Session {
     fn drop(&mut self) {
-        unsafe {
-            self.wintun.WintunEndSession(self.handle.0);
-        }
+        self.stop_reader();
+        self.end();
     }
 }
 
+fn start_session(
+    wintun: &wintun_sys::wintun,
+    adapter: &Adapter,
+    capacity: u32,
+) -> Result<WINTUN_SESSION_HANDLE, Error> {
+    let session_handle = unsafe { wintun.WintunStartSession(adapter.handle(), capacity) };
+
+    if session_handle.is_null() {
+        let err = last_error(format!("WintunStartSession(ring={})", ring_size(capacity)));
+        error!("Failed to create session: {err}");
+        return Err(err);
+    }
+    Ok(session_handle)
+}
+
+/// Ring capacity in the largest binary unit, that divides it, like `8MiB`.
+fn ring_size(capacity: u32) -> String {
+    match capacity {
+        c if c != 0 && c % (1 << 20) == 0 => format!("{}MiB", c >> 20),
+        c if c != 0 && c % (1 << 10) == 0 => format!("{}KiB", c >> 10),
+        c => format!("{c}B"),
+    }
+}
+
+/// Converts packet length to DWORD, failing for packets, that Wintun cannot send, instead of
+/// truncating the length on 64-bit targets.
+fn packet_len(len: usize) -> io::Result<u32> {
+    u32::try_from(len)
+        .ok()
+        .filter(|len| *len <= WINTUN_MAX_IP_PACKET_SIZE)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "packet is too large"))
+}
+
 fn error_eq(err: &io::Error, win32_error: WIN32_ERROR) -> bool {
-    match err.raw_os_error() {
-        None => false,
-        Some(os_error) => os_error == win32_error.0 as _,
+    // Error codes are DWORD, while io::Error stores them as i32
+    err.raw_os_error() == Some(win32_error.0 as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_len_is_checked() {
+        assert_eq!(packet_len(1500).unwrap(), 1500);
+        assert_eq!(packet_len(0xFFFF).unwrap(), 0xFFFF);
+        assert!(packet_len(0x10000).is_err());
+        assert!(packet_len(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn error_codes_compare_as_dword() {
+        let err = io::Error::from_raw_os_error(ERROR_NO_MORE_ITEMS.0 as i32);
+        assert!(error_eq(&err, ERROR_NO_MORE_ITEMS));
+        assert!(!error_eq(&err, ERROR_BUFFER_OVERFLOW));
+        assert!(!error_eq(&io::ErrorKind::Other.into(), ERROR_NO_MORE_ITEMS));
+    }
+
+    #[test]
+    fn ring_capacity_is_validated() {
+        assert!(Session::validate_capacity(WINTUN_MIN_RING_CAPACITY).is_ok());
+        assert!(Session::validate_capacity(WINTUN_MAX_RING_CAPACITY).is_ok());
+        assert!(Session::validate_capacity(WINTUN_MIN_RING_CAPACITY * 4).is_ok());
+        assert!(Session::validate_capacity(WINTUN_MIN_RING_CAPACITY / 2).is_err());
+        assert!(Session::validate_capacity(WINTUN_MAX_RING_CAPACITY * 2).is_err());
+        // Ring indices wrap with a mask, so the size must be a power of two
+        assert!(Session::validate_capacity(WINTUN_MIN_RING_CAPACITY + 1).is_err());
+    }
+
+    #[test]
+    fn send_backoff_doubles_up_to_limit() {
+        let mut backoff = SendBackoff::default();
+        for _ in 0..8 {
+            backoff.wait();
+        }
+        assert_eq!(backoff.0, SendBackoff::MAX);
     }
 }
@@ -0,0 +1,53 @@
+//! Settings of the current thread.
+use widestring::{U16CStr, U16CString};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::System::Memory::LocalFree;
+use windows::Win32::System::Threading::{
+    GetCurrentThread, GetThreadDescription, GetThreadPriority, SetThreadAffinityMask,
+    SetThreadDescription, SetThreadPriority, THREAD_PRIORITY,
+};
+
+/// Pseudo handle of the current thread. It refers to the thread, that uses it, so it is only
+/// meaningful on the thread, that created it.
+pub(crate) struct CurrentThread(HANDLE);
+
+impl CurrentThread {
+    pub fn get() -> Self {
+        // SAFETY: call has no preconditions
+        Self(unsafe { GetCurrentThread() })
+    }
+
+    /// Thread description, available since Windows 10 1607.
+    pub fn description(&self) -> Option<U16CString> {
+        // SAFETY: returned string is zero-terminated, copied and then freed once
+        unsafe { GetThreadDescription(self.0) }.ok().map(|ptr| {
+            let description = unsafe { U16CString::from_ptr_str(ptr.0) };
+            unsafe {
+                LocalFree(ptr.0 as isize);
+            }
+            description
+        })
+    }
+
+    pub fn set_description(&self, description: &U16CStr) -> bool {
+        // SAFETY: description is zero-terminated
+        unsafe { SetThreadDescription(self.0, PCWSTR(description.as_ptr())) }.is_ok()
+    }
+
+    pub fn priority(&self) -> THREAD_PRIORITY {
+        // SAFETY: call only reads the priority
+        THREAD_PRIORITY(unsafe { GetThreadPriority(self.0) })
+    }
+
+    pub fn set_priority(&self, priority: THREAD_PRIORITY) -> bool {
+        // SAFETY: call only changes scheduling of the thread
+        unsafe { SetThreadPriority(self.0, priority) }.as_bool()
+    }
+
+    /// Returns the previous mask, or zero on failure.
+    pub fn set_affinity(&self, mask: usize) -> usize {
+        // SAFETY: call only changes scheduling of the thread
+        unsafe { SetThreadAffinityMask(self.0, mask) }
+    }
+}
@@ -0,0 +1,92 @@
+//! Access token of the current process, for checks of elevation and privileges.
+use std::mem;
+use widestring::U16CString;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, LUID};
+use windows::Win32::Security::{
+    GetTokenInformation, LookupPrivilegeValueW, TokenElevation, TokenPrivileges,
+    LUID_AND_ATTRIBUTES, TOKEN_ELEVATION, TOKEN_PRIVILEGES, TOKEN_QUERY,
+};
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+/// Access token of the current process.
+pub(crate) struct ProcessToken(HANDLE);
+
+impl ProcessToken {
+    pub fn open() -> Option<Self> {
+        let mut handle = HANDLE::default();
+        // SAFETY: process pseudo handle needs no closing, and the token is owned by the result
+        unsafe { OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut handle) }
+            .as_bool()
+            .then_some(Self(handle))
+    }
+
+    pub fn is_elevated(&self) -> Option<bool> {
+        let mut elevation = TOKEN_ELEVATION::default();
+        let mut len = 0;
+        // SAFETY: buffer is a `TOKEN_ELEVATION` of the size, that is passed
+        unsafe {
+            GetTokenInformation(
+                self.0,
+                TokenElevation,
+                Some(&mut elevation as *mut _ as _),
+                mem::size_of::<TOKEN_ELEVATION>() as u32,
+                &mut len,
+            )
+        }
+        .as_bool()
+        .then_some(elevation.TokenIsElevated != 0)
+    }
+
+    /// Returns `true`, if the token holds the privilege, even if it is disabled: Wintun
+    /// enables it itself.
+    pub fn has_privilege(&self, name: &str) -> Option<bool> {
+        let name = U16CString::from_str(name).ok()?;
+        let mut luid = LUID::default();
+        // SAFETY: name is zero-terminated
+        unsafe {
+            LookupPrivilegeValueW(PCWSTR::null(), PCWSTR::from_raw(name.as_ptr()), &mut luid)
+        }
+        .as_bool()
+        .then_some(())?;
+
+        // First call returns the required buffer size
+        let mut len = 0;
+        // SAFETY: no buffer is passed, so only the length is written
+        unsafe { GetTokenInformation(self.0, TokenPrivileges, None, 0, &mut len) };
+        // Buffer of u64 keeps the structure aligned
+        let mut buf = vec![0u64; (len as usize + 7) / 8];
+        // SAFETY: buffer holds at least `len` bytes
+        unsafe {
+            GetTokenInformation(
+                self.0,
+                TokenPrivileges,
+                Some(buf.as_mut_ptr() as _),
+                len,
+                &mut len,
+            )
+        }
+        .as_bool()
+        .then_some(())?;
+
+        // SAFETY: the call filled the buffer with a `TOKEN_PRIVILEGES`, which is followed by
+        // `PrivilegeCount` entries
+        let privileges = unsafe { &*(buf.as_ptr() as *const TOKEN_PRIVILEGES) };
+        let entries: &[LUID_AND_ATTRIBUTES] = unsafe {
+            std::slice::from_raw_parts(
+                privileges.Privileges.as_ptr(),
+                privileges.PrivilegeCount as usize,
+            )
+        };
+        Some(entries.iter().any(|entry| {
+            entry.Luid.LowPart == luid.LowPart && entry.Luid.HighPart == luid.HighPart
+        }))
+    }
+}
+
+impl Drop for ProcessToken {
+    fn drop(&mut self) {
+        // SAFETY: handle is owned and closed once
+        unsafe { CloseHandle(self.0) };
+    }
+}
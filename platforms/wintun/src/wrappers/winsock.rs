@@ -0,0 +1,14 @@
+//! Options of WinSock sockets.
+use std::io;
+use windows::Win32::Networking::WinSock::{setsockopt, WSAGetLastError, SOCKET, SOCKET_ERROR};
+
+/// Sets socket option, which value is a `DWORD`.
+pub(crate) fn set_option(socket: SOCKET, level: i32, option: i32, value: u32) -> io::Result<()> {
+    // SAFETY: value is passed as a slice, so its length is known to the call
+    let result = unsafe { setsockopt(socket, level, option, Some(&value.to_ne_bytes())) };
+    match result {
+        // SAFETY: reads the error of the call above, which is made by this thread
+        SOCKET_ERROR => Err(io::Error::from_raw_os_error(unsafe { WSAGetLastError() }.0)),
+        _ => Ok(()),
+    }
+}
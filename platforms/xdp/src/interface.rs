@@ -0,0 +1,188 @@
+use super::program::RedirectProgram;
+use super::socket::{XdpPacket, XdpSocket};
+use super::sys;
+use super::Driver;
+use super::PlatformIfConfig;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use tunio_core::config::{IfConfig, Layer};
+use tunio_core::events::{EventEmitter, EventKind};
+use tunio_core::pause::PauseHandle;
+use tunio_core::stats::{QueueStats, StatsCounters};
+use tunio_core::traits::{InterfaceT, SyncQueueT};
+use tunio_core::Error;
+
+/// L2 interface, backed by an AF_XDP socket on a receive queue of an existing NIC.
+///
+/// Packets, redirected from the queue, are read from this interface, and packets, written to
+/// it, are sent by the NIC. Traffic of other queues, and of this queue before the interface
+/// is created or after it is dropped, passes to the kernel stack as usual.
+pub struct XdpInterface {
+    name: String,
+    index: u32,
+    events: EventEmitter,
+    pause: PauseHandle,
+    stats: StatsCounters,
+    // Socket is closed, before the program is detached
+    socket: XdpSocket,
+    _program: RedirectProgram,
+}
+
+pub type Interface = XdpInterface;
+
+impl XdpInterface {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Longest packet, that fits into a UMEM frame.
+    pub fn max_packet_len(&self) -> usize {
+        self.socket.max_packet_len()
+    }
+
+    /// Packet counters of this interface.
+    pub fn stats(&self) -> QueueStats {
+        self.stats.snapshot()
+    }
+
+    /// Stops taking packets from the RX ring, until [`resume`](Self::resume) is called.
+    /// NIC drops packets of the queue, once the ring is full.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Returns a handle, that pauses and resumes this interface from another thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    fn write_failed(&self, err: io::Error) -> io::Error {
+        self.events
+            .emit(&self.name, EventKind::WriteFailed(err.kind()));
+        err
+    }
+}
+
+impl Drop for XdpInterface {
+    fn drop(&mut self) {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+    }
+}
+
+impl InterfaceT for XdpInterface {
+    type PlatformDriver = Driver;
+    type PlatformIfConfig = PlatformIfConfig;
+
+    /// Attaches to an existing NIC with the name of `params`. Name conflict and overflow
+    /// settings are not used, as the interface is never created.
+    fn new(
+        driver: &mut Self::PlatformDriver,
+        params: IfConfig<Self::PlatformIfConfig>,
+    ) -> Result<Self, Error> {
+        if params.layer != Layer::L2 {
+            return Err(Error::LayerUnsupported(params.layer));
+        }
+        let index = netconfig::Interface::try_from_name(&params.name)?.index()?;
+
+        let config = &params.platform;
+        let program = RedirectProgram::attach(index, config.queue_id, config.mode)?;
+        let socket = XdpSocket::new(index, config)?;
+        program.register(config.queue_id, &socket)?;
+        driver.events.emit(&params.name, EventKind::Created);
+
+        Ok(Self {
+            name: params.name,
+            index,
+            events: driver.events.clone(),
+            pause: PauseHandle::default(),
+            stats: StatsCounters::new(params.layer),
+            socket,
+            _program: program,
+        })
+    }
+
+    fn up(&mut self) -> Result<(), Error> {
+        set_up(&self.name, true)?;
+        self.events.emit(&self.name, EventKind::SessionStarted);
+        Ok(())
+    }
+
+    fn down(&mut self) -> Result<(), Error> {
+        self.events.emit(&self.name, EventKind::ShutdownRequested);
+        set_up(&self.name, false)
+    }
+
+    fn handle(&self) -> netconfig::Interface {
+        netconfig::Interface::from_index_unchecked(self.index)
+    }
+
+    fn index(&self) -> Result<u32, Error> {
+        Ok(self.index)
+    }
+}
+
+/// Brings the NIC up or down with ioctls on a configuration socket.
+fn set_up(name: &str, up: bool) -> Result<(), Error> {
+    // IFF_UP fits into the short flags of ifreq
+    let up_flag = libc::IFF_UP as libc::c_short;
+    sys::update_if_flags(name, |flags| match up {
+        true => flags | up_flag,
+        false => flags & !up_flag,
+    })?;
+    Ok(())
+}
+
+impl SyncQueueT for XdpInterface {
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        self.pause.wait_resumed();
+        let (n, truncated) = self.socket.recv(buf)?;
+        self.stats.record_rx(&buf[..n]);
+        Ok((n, truncated))
+    }
+
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        self.socket.send(packet).map_err(|e| self.write_failed(e))?;
+        self.stats.record_tx(packet);
+        Ok(())
+    }
+
+    type PacketRef<'a> = XdpPacket<'a>;
+
+    fn recv_ref(&mut self) -> io::Result<Self::PacketRef<'_>> {
+        self.pause.wait_resumed();
+        let packet = self.socket.recv_ref()?;
+        self.stats.record_rx(&packet);
+        Ok(packet)
+    }
+
+    fn drain(&mut self) -> io::Result<usize> {
+        Ok(self.socket.drain())
+    }
+}
+
+impl Read for XdpInterface {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.recv(buf).map(|(n, _)| n)
+    }
+}
+
+impl Write for XdpInterface {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send(buf).map(|()| buf.len())
+    }
+
+    /// Packets are handed to the kernel on every write.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for XdpInterface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
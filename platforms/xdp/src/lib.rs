@@ -0,0 +1,117 @@
+//! # Experimental [AF_XDP](https://www.kernel.org/doc/html/latest/networking/af_xdp.html) backend for tunio.
+//!
+//! Unlike other backends, this one does not create virtual devices. An L2 interface is backed
+//! by an AF_XDP socket, bound to a receive queue of an existing NIC, so that Ethernet frames
+//! bypass the kernel stack. A small XDP program redirects the queue into the socket and is
+//! detached, when the interface is dropped.
+//!
+//! Supported features:
+//! - L2 mode only
+//! - Sync mode only
+//! - Generic (SKB) and native XDP, copy and zero-copy mode
+//!
+//! Requires Linux 5.9 or newer, `CAP_NET_ADMIN`, `CAP_NET_RAW` and `CAP_BPF` (or
+//! `CAP_SYS_ADMIN`). APIs of this backend may change between minor versions.
+
+#![deny(unsafe_code)]
+
+mod interface;
+mod program;
+// Rings and sockets are wrappers of memory, that is shared with the kernel
+#[allow(unsafe_code)]
+mod ring;
+#[allow(unsafe_code)]
+mod socket;
+mod sys;
+
+use derive_builder::Builder;
+use tunio_core::config::{Layer, Violation};
+use tunio_core::events::{EventEmitter, EventReceiver};
+use tunio_core::traits::{DriverT, PlatformIfConfigT};
+use tunio_core::Error;
+
+pub use interface::{Interface, XdpInterface};
+pub use socket::XdpPacket;
+
+pub struct Driver {
+    pub(crate) events: EventEmitter,
+}
+
+/// Way, the XDP program is attached to the NIC.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum XdpMode {
+    /// Native mode, if the NIC driver supports it, generic mode otherwise.
+    #[default]
+    Auto,
+    /// Generic mode, that works with any NIC, after the kernel has allocated socket buffers.
+    Generic,
+    /// Native mode in the NIC driver. Fails, if the driver does not support XDP.
+    Native,
+}
+
+/// It is generally better to use [`PlatformIfConfigBuilder`] to create a new PlatformIfConfig instance.
+#[derive(Builder, Clone)]
+pub struct PlatformIfConfig {
+    /// Receive queue of the NIC, that is redirected to the interface.
+    #[builder(default = "0")]
+    pub queue_id: u32,
+    /// Number of UMEM frames, a power of two. Half of them is used for receiving, another
+    /// half for sending.
+    #[builder(default = "4096")]
+    pub frame_count: u32,
+    /// Size of a UMEM frame, a power of two between 2048 and the page size. Longer packets
+    /// are not received or sent.
+    #[builder(default = "2048")]
+    pub frame_size: u32,
+    #[builder(default = "XdpMode::Auto")]
+    pub mode: XdpMode,
+    /// Shares UMEM with the NIC driver, instead of copying packets. Fails, if the driver does
+    /// not support it.
+    #[builder(default = "false")]
+    pub zero_copy: bool,
+}
+
+impl PlatformIfConfigT for PlatformIfConfig {
+    type Builder = PlatformIfConfigBuilder;
+
+    const MAX_NAME_LEN: usize = libc::IFNAMSIZ - 1;
+    const LAYERS: &'static [Layer] = &[Layer::L2];
+
+    fn validate(&self, violations: &mut Vec<Violation>) {
+        if self.frame_count < 2 || !self.frame_count.is_power_of_two() {
+            violations.push(Violation::new(
+                "frame_count",
+                self.frame_count,
+                "must be a power of two, not less than 2",
+            ));
+        }
+        let page_size = sys::page_size();
+        if !(2048..=page_size).contains(&self.frame_size) || !self.frame_size.is_power_of_two() {
+            violations.push(Violation::new(
+                "frame_size",
+                self.frame_size,
+                format!("must be a power of two between 2048 and {page_size}"),
+            ));
+        }
+    }
+}
+
+impl Default for PlatformIfConfig {
+    fn default() -> Self {
+        PlatformIfConfigBuilder::default().build().unwrap()
+    }
+}
+
+impl DriverT for Driver {
+    type PlatformIfConfig = PlatformIfConfig;
+
+    fn new() -> Result<Self, Error> {
+        Ok(Self {
+            events: EventEmitter::default(),
+        })
+    }
+
+    fn subscribe(&self) -> EventReceiver {
+        self.events.subscribe()
+    }
+}
@@ -0,0 +1,73 @@
+use crate::sys::{self, bpf_insn};
+use crate::XdpMode;
+use std::io;
+use std::os::unix::io::{AsRawFd, OwnedFd};
+
+// Instruction classes and modes of eBPF
+const BPF_LDX_W_MEM: u8 = 0x61;
+const BPF_LD_DW_IMM: u8 = 0x18;
+const BPF_ALU64_MOV_K: u8 = 0xb7;
+const BPF_JMP_CALL: u8 = 0x85;
+const BPF_JMP_EXIT: u8 = 0x95;
+const BPF_PSEUDO_MAP_FD: u8 = 1;
+
+/// Offset of `rx_queue_index` in `struct xdp_md`.
+const XDP_MD_RX_QUEUE_INDEX: i16 = 16;
+const BPF_FUNC_REDIRECT_MAP: i32 = 51;
+const XDP_PASS: i32 = 2;
+
+const fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> bpf_insn {
+    bpf_insn {
+        code,
+        regs: src << 4 | dst,
+        off,
+        imm,
+    }
+}
+
+/// XDP program, that redirects packets of a receive queue into the socket, registered for
+/// that queue in its XSKMAP. Packets of queues without a socket pass to the kernel stack.
+///
+/// Program is attached through a BPF link, so it is detached, when this is dropped, even if
+/// the process crashes.
+pub(crate) struct RedirectProgram {
+    map: OwnedFd,
+    _program: OwnedFd,
+    _link: OwnedFd,
+}
+
+impl RedirectProgram {
+    pub fn attach(ifindex: u32, queue_id: u32, mode: XdpMode) -> io::Result<Self> {
+        let map = sys::create_xsk_map(queue_id + 1)?;
+
+        let insns = [
+            // r2 = ctx->rx_queue_index
+            insn(BPF_LDX_W_MEM, 2, 1, XDP_MD_RX_QUEUE_INDEX, 0),
+            // r1 = map, 64-bit immediate takes two instructions
+            insn(BPF_LD_DW_IMM, 1, BPF_PSEUDO_MAP_FD, 0, map.as_raw_fd()),
+            insn(0, 0, 0, 0, 0),
+            // Lower bits of flags are the action, if no socket is registered for the queue
+            insn(BPF_ALU64_MOV_K, 3, 0, 0, XDP_PASS),
+            insn(BPF_JMP_CALL, 0, 0, 0, BPF_FUNC_REDIRECT_MAP),
+            insn(BPF_JMP_EXIT, 0, 0, 0, 0),
+        ];
+        let program = sys::load_xdp_program(&insns)?;
+
+        let flags = match mode {
+            XdpMode::Auto => 0,
+            XdpMode::Generic => sys::XDP_FLAGS_SKB_MODE,
+            XdpMode::Native => sys::XDP_FLAGS_DRV_MODE,
+        };
+        let link = sys::attach_xdp(program.as_raw_fd(), ifindex, flags)?;
+
+        Ok(Self {
+            map,
+            _program: program,
+            _link: link,
+        })
+    }
+
+    pub fn register(&self, queue_id: u32, socket: &impl AsRawFd) -> io::Result<()> {
+        sys::update_map(self.map.as_raw_fd(), queue_id, socket.as_raw_fd() as u32)
+    }
+}
@@ -0,0 +1,226 @@
+use crate::sys::{xdp_ring_offset, XDP_RING_NEED_WAKEUP};
+use std::io;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Shared memory of a ring, unmapped on drop.
+struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+/// Single-producer, single-consumer ring, shared with the kernel through mmap. Either the
+/// kernel or the socket produces into a ring, the other side consumes it.
+///
+/// Index pointers are valid and aligned, and `descs` has room for `size` entries, for as long
+/// as the ring exists: memory is owned by `_map`, or outlives the ring, if it is not mapped.
+pub(crate) struct Ring<T> {
+    _map: Option<Mapping>,
+    producer: *const AtomicU32,
+    consumer: *const AtomicU32,
+    flags: *const AtomicU32,
+    descs: *mut T,
+    size: u32,
+    /// Local copies of the indices, fetched from the shared ones, when they are exhausted.
+    cached_producer: u32,
+    cached_consumer: u32,
+}
+
+// Ring is only accessed through `&mut`, and the kernel side is synchronized by atomics
+unsafe impl<T: Send> Send for Ring<T> {}
+
+impl<T: Copy> Ring<T> {
+    /// Maps a ring of `size` entries, that is configured on the socket.
+    pub fn map(
+        fd: RawFd,
+        offset: &xdp_ring_offset,
+        page: libc::off_t,
+        size: u32,
+    ) -> io::Result<Self> {
+        let len = offset.desc as usize + size as usize * std::mem::size_of::<T>();
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                page,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let map = Mapping { addr, len };
+        // SAFETY: kernel lays the ring out at the offsets, that it reported for the socket
+        let mut ring = unsafe { Self::from_raw(addr as *mut u8, offset, size) };
+        ring._map = Some(map);
+        Ok(ring)
+    }
+
+    /// Creates a ring over memory, that is not owned by it.
+    ///
+    /// # Safety
+    ///
+    /// `base` with the offsets must point to aligned indices and to room for `size` entries,
+    /// that stay valid, while the ring exists. `size` must be a power of two.
+    unsafe fn from_raw(base: *mut u8, offset: &xdp_ring_offset, size: u32) -> Self {
+        let at = |offset: u64| base.add(offset as usize);
+        let mut ring = Self {
+            _map: None,
+            producer: at(offset.producer) as *const AtomicU32,
+            consumer: at(offset.consumer) as *const AtomicU32,
+            flags: at(offset.flags) as *const AtomicU32,
+            descs: at(offset.desc) as *mut T,
+            size,
+            cached_producer: 0,
+            cached_consumer: 0,
+        };
+        ring.cached_producer = ring.producer().load(Ordering::Acquire);
+        ring.cached_consumer = ring.consumer().load(Ordering::Acquire);
+        ring
+    }
+
+    fn producer(&self) -> &AtomicU32 {
+        // SAFETY: index is valid and aligned, while the ring exists
+        unsafe { &*self.producer }
+    }
+
+    fn consumer(&self) -> &AtomicU32 {
+        // SAFETY: index is valid and aligned, while the ring exists
+        unsafe { &*self.consumer }
+    }
+
+    /// Returns `true`, if the kernel waits for a syscall to process this ring.
+    pub fn needs_wakeup(&self) -> bool {
+        // SAFETY: flags are valid and aligned, while the ring exists
+        unsafe { &*self.flags }.load(Ordering::Acquire) & XDP_RING_NEED_WAKEUP != 0
+    }
+
+    /// Puts an entry into a ring, produced by the socket. Returns `false`, if it is full.
+    pub fn produce(&mut self, desc: T) -> bool {
+        if self.cached_producer.wrapping_sub(self.cached_consumer) == self.size {
+            self.cached_consumer = self.consumer().load(Ordering::Acquire);
+            if self.cached_producer.wrapping_sub(self.cached_consumer) == self.size {
+                return false;
+            }
+        }
+
+        let index = self.cached_producer & (self.size - 1);
+        // SAFETY: index is masked to the ring size, and the entry is owned by the producer
+        unsafe { self.descs.add(index as usize).write(desc) };
+        self.cached_producer = self.cached_producer.wrapping_add(1);
+        // Entry is visible to the kernel, before the index is advanced
+        self.producer()
+            .store(self.cached_producer, Ordering::Release);
+        true
+    }
+
+    /// Takes an entry from a ring, produced by the kernel.
+    pub fn consume(&mut self) -> Option<T> {
+        if self.cached_consumer == self.cached_producer {
+            self.cached_producer = self.producer().load(Ordering::Acquire);
+            if self.cached_consumer == self.cached_producer {
+                return None;
+            }
+        }
+
+        let index = self.cached_consumer & (self.size - 1);
+        // SAFETY: index is masked to the ring size, and the entry is owned by the consumer
+        let desc = unsafe { self.descs.add(index as usize).read() };
+        self.cached_consumer = self.cached_consumer.wrapping_add(1);
+        self.consumer()
+            .store(self.cached_consumer, Ordering::Release);
+        Some(desc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ring memory: producer, consumer and flags, followed by four entries.
+    #[repr(C)]
+    struct Shared {
+        indices: [AtomicU32; 4],
+        descs: [u64; 4],
+    }
+
+    const OFFSET: xdp_ring_offset = xdp_ring_offset {
+        producer: 0,
+        consumer: 4,
+        flags: 8,
+        desc: 16,
+    };
+
+    fn shared() -> Box<Shared> {
+        Box::new(Shared {
+            indices: Default::default(),
+            descs: [0; 4],
+        })
+    }
+
+    /// Producer and consumer sides of the same ring, like the socket and the kernel.
+    fn sides(shared: &mut Shared) -> (Ring<u64>, Ring<u64>) {
+        let base = shared as *mut Shared as *mut u8;
+        unsafe {
+            (
+                Ring::from_raw(base, &OFFSET, 4),
+                Ring::from_raw(base, &OFFSET, 4),
+            )
+        }
+    }
+
+    #[test]
+    fn entries_pass_in_order() {
+        let mut shared = shared();
+        let (mut producer, mut consumer) = sides(&mut shared);
+        assert_eq!(consumer.consume(), None);
+        assert!(producer.produce(1));
+        assert!(producer.produce(2));
+        assert_eq!(consumer.consume(), Some(1));
+        assert_eq!(consumer.consume(), Some(2));
+        assert_eq!(consumer.consume(), None);
+    }
+
+    #[test]
+    fn full_ring_rejects_entries() {
+        let mut shared = shared();
+        let (mut producer, mut consumer) = sides(&mut shared);
+        for desc in 0..4 {
+            assert!(producer.produce(desc));
+        }
+        assert!(!producer.produce(4));
+        assert_eq!(consumer.consume(), Some(0));
+        assert!(producer.produce(4));
+    }
+
+    #[test]
+    fn indices_wrap_around() {
+        let mut shared = shared();
+        shared.indices[0] = AtomicU32::new(u32::MAX - 1);
+        shared.indices[1] = AtomicU32::new(u32::MAX - 1);
+        let (mut producer, mut consumer) = sides(&mut shared);
+        for desc in 0..10 {
+            assert!(producer.produce(desc));
+            assert_eq!(consumer.consume(), Some(desc));
+        }
+    }
+
+    #[test]
+    fn wakeup_flag_is_read() {
+        let mut shared = shared();
+        let (producer, _) = sides(&mut shared);
+        assert!(!producer.needs_wakeup());
+        unsafe { &*producer.flags }.store(XDP_RING_NEED_WAKEUP, Ordering::Release);
+        assert!(producer.needs_wakeup());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::ring::Ring;
+use crate::sys::{self, sockaddr_xdp, xdp_desc, xdp_mmap_offsets, xdp_umem_reg};
+use crate::PlatformIfConfig;
+use std::io;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+use tunio_core::traits::copy_packet;
+
+/// Headroom, that the kernel reserves in front of received packets in every frame.
+const XDP_PACKET_HEADROOM: u32 = 256;
+
+/// Memory region with packet frames, shared with the kernel.
+struct Umem {
+    addr: *mut u8,
+    len: usize,
+}
+
+impl Umem {
+    fn new(len: usize) -> io::Result<Self> {
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_POPULATE,
+                -1,
+                0,
+            )
+        };
+        match addr {
+            libc::MAP_FAILED => Err(io::Error::last_os_error()),
+            addr => Ok(Self {
+                addr: addr as *mut u8,
+                len,
+            }),
+        }
+    }
+
+    fn frame(&self, addr: u64, len: u32) -> &[u8] {
+        self.check(addr, len);
+        // SAFETY: frame is inside of the mapping, which lives as long as self
+        unsafe { std::slice::from_raw_parts(self.addr.add(addr as usize), len as usize) }
+    }
+
+    fn frame_mut(&mut self, addr: u64, len: u32) -> &mut [u8] {
+        self.check(addr, len);
+        // SAFETY: frame is inside of the mapping, which is borrowed exclusively
+        unsafe { std::slice::from_raw_parts_mut(self.addr.add(addr as usize), len as usize) }
+    }
+}
+
+impl Umem {
+    fn contains(&self, addr: u64, len: u32) -> bool {
+        usize::try_from(addr)
+            .ok()
+            .and_then(|addr| addr.checked_add(len as usize))
+            .map_or(false, |end| end <= self.len)
+    }
+
+    /// Received descriptors are checked, when they are consumed, but a safe slice must never
+    /// leave the mapping.
+    fn check(&self, addr: u64, len: u32) {
+        assert!(self.contains(addr, len), "frame is outside of UMEM");
+    }
+}
+
+impl Drop for Umem {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// AF_XDP socket, bound to a single receive queue of a NIC, with its own UMEM.
+///
+/// Half of the frames is given to the kernel for receiving through the fill ring, another half
+/// is kept for sending and returns to the free list through the completion ring.
+pub(crate) struct XdpSocket {
+    // Rings are unmapped before the socket is closed
+    fill: Ring<u64>,
+    completion: Ring<u64>,
+    rx: Ring<xdp_desc>,
+    tx: Ring<xdp_desc>,
+    fd: OwnedFd,
+    umem: Umem,
+    frame_size: u32,
+    free_frames: Vec<u64>,
+}
+
+// UMEM is only accessed through `&mut`
+unsafe impl Send for XdpSocket {}
+
+impl XdpSocket {
+    pub fn new(ifindex: u32, config: &PlatformIfConfig) -> io::Result<Self> {
+        let fd = unsafe { libc::socket(sys::AF_XDP, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let frame_size = config.frame_size;
+        let umem = Umem::new(config.frame_count as usize * frame_size as usize)?;
+        let reg = xdp_umem_reg {
+            addr: umem.addr as u64,
+            len: umem.len as u64,
+            chunk_size: frame_size,
+            headroom: 0,
+        };
+        setsockopt(fd.as_raw_fd(), sys::XDP_UMEM_REG, &reg)?;
+
+        let ring_size = config.frame_count / 2;
+        for option in [
+            sys::XDP_UMEM_FILL_RING,
+            sys::XDP_UMEM_COMPLETION_RING,
+            sys::XDP_RX_RING,
+            sys::XDP_TX_RING,
+        ] {
+            setsockopt(fd.as_raw_fd(), option, &ring_size)?;
+        }
+
+        let offsets = mmap_offsets(fd.as_raw_fd())?;
+        let raw = fd.as_raw_fd();
+        let mut socket = Self {
+            fill: Ring::map(raw, &offsets.fr, sys::XDP_UMEM_PGOFF_FILL_RING, ring_size)?,
+            completion: Ring::map(
+                raw,
+                &offsets.cr,
+                sys::XDP_UMEM_PGOFF_COMPLETION_RING,
+                ring_size,
+            )?,
+            rx: Ring::map(raw, &offsets.rx, sys::XDP_PGOFF_RX_RING, ring_size)?,
+            tx: Ring::map(raw, &offsets.tx, sys::XDP_PGOFF_TX_RING, ring_size)?,
+            fd,
+            umem,
+            frame_size,
+            free_frames: Vec::with_capacity(ring_size as usize),
+        };
+
+        let frames = (0..config.frame_count as u64).map(|frame| frame * frame_size as u64);
+        for (i, addr) in frames.enumerate() {
+            match i < ring_size as usize {
+                true => {
+                    socket.fill.produce(addr);
+                }
+                false => socket.free_frames.push(addr),
+            }
+        }
+
+        let mut flags = sys::XDP_USE_NEED_WAKEUP;
+        if config.zero_copy {
+            flags |= sys::XDP_ZEROCOPY;
+        }
+        let addr = sockaddr_xdp {
+            sxdp_family: sys::AF_XDP as u16,
+            sxdp_flags: flags,
+            sxdp_ifindex: ifindex,
+            sxdp_queue_id: config.queue_id,
+            sxdp_shared_umem_fd: 0,
+        };
+        let result = unsafe {
+            libc::bind(
+                socket.fd.as_raw_fd(),
+                &addr as *const sockaddr_xdp as *const libc::sockaddr,
+                size_of::<sockaddr_xdp>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(socket)
+    }
+
+    /// Longest packet, that fits into a frame.
+    pub fn max_packet_len(&self) -> usize {
+        (self.frame_size - XDP_PACKET_HEADROOM) as usize
+    }
+
+    fn next_rx(&mut self) -> io::Result<xdp_desc> {
+        loop {
+            match self.rx.consume() {
+                // Frame of a corrupted descriptor is lost, it cannot be returned to the kernel
+                Some(desc) if !self.umem.contains(desc.addr, desc.len) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "received descriptor is outside of UMEM",
+                    ))
+                }
+                Some(desc) => return Ok(desc),
+                None => {}
+            }
+            self.wait(libc::POLLIN, -1)?;
+        }
+    }
+
+    /// Returns a frame of a received packet to the kernel.
+    fn recycle(&mut self, desc: &xdp_desc) {
+        let frame = desc.addr - desc.addr % self.frame_size as u64;
+        // Fill ring has a slot for every frame, that is not owned by the kernel
+        let produced = self.fill.produce(frame);
+        debug_assert!(produced);
+    }
+
+    pub fn recv(&mut self, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+        let desc = self.next_rx()?;
+        let (n, truncated) = copy_packet(self.umem.frame(desc.addr, desc.len), buf);
+        self.recycle(&desc);
+        Ok((n, truncated))
+    }
+
+    /// Returns frames of received packets to the kernel without reading them.
+    pub fn drain(&mut self) -> usize {
+        let mut drained = 0;
+        while let Some(desc) = self.rx.consume() {
+            self.recycle(&desc);
+            drained += 1;
+        }
+        drained
+    }
+
+    pub fn recv_ref(&mut self) -> io::Result<XdpPacket<'_>> {
+        let desc = self.next_rx()?;
+        Ok(XdpPacket { socket: self, desc })
+    }
+
+    pub fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        if packet.len() > self.frame_size as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "packet does not fit into a UMEM frame",
+            ));
+        }
+
+        let addr = loop {
+            self.reclaim();
+            if let Some(addr) = self.free_frames.pop() {
+                break addr;
+            }
+            // Kernel sends, and then completes frames only after a wakeup
+            self.kick()?;
+            self.reclaim();
+            if self.free_frames.is_empty() {
+                self.wait(libc::POLLOUT, 1)?;
+            }
+        };
+
+        let len = packet.len() as u32;
+        self.umem.frame_mut(addr, len).copy_from_slice(packet);
+        // TX ring has a slot for every frame on the free list
+        let produced = self.tx.produce(xdp_desc {
+            addr,
+            len,
+            options: 0,
+        });
+        debug_assert!(produced);
+        if self.tx.needs_wakeup() {
+            self.kick()?;
+        }
+        Ok(())
+    }
+
+    /// Takes frames of sent packets back to the free list.
+    fn reclaim(&mut self) {
+        while let Some(addr) = self.completion.consume() {
+            self.free_frames.push(addr);
+        }
+    }
+
+    /// Asks the kernel to process the TX ring.
+    fn kick(&self) -> io::Result<()> {
+        let result = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                ptr::null(),
+                0,
+                libc::MSG_DONTWAIT,
+                ptr::null(),
+                0,
+            )
+        };
+        match result {
+            -1 => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    // Kernel is busy with the ring or out of buffers, it is retried later
+                    Some(libc::EAGAIN | libc::EBUSY | libc::ENOBUFS) => Ok(()),
+                    _ => Err(err),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn wait(&self, events: libc::c_short, timeout_ms: libc::c_int) -> io::Result<()> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut pollfd, 1, timeout_ms) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::Interrupted => Ok(()),
+                    _ => Err(err),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl AsRawFd for XdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Received packet, borrowed from UMEM. Its frame returns to the kernel, when it is dropped.
+pub struct XdpPacket<'a> {
+    socket: &'a mut XdpSocket,
+    desc: xdp_desc,
+}
+
+impl Deref for XdpPacket<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.socket.umem.frame(self.desc.addr, self.desc.len)
+    }
+}
+
+impl Drop for XdpPacket<'_> {
+    fn drop(&mut self) {
+        self.socket.recycle(&self.desc);
+    }
+}
+
+fn setsockopt<T>(fd: RawFd, option: libc::c_int, value: &T) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            sys::SOL_XDP,
+            option,
+            value as *const T as *const libc::c_void,
+            size_of::<T>() as libc::socklen_t,
+        )
+    };
+    match result {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+fn mmap_offsets(fd: RawFd) -> io::Result<xdp_mmap_offsets> {
+    let mut offsets = xdp_mmap_offsets::default();
+    let mut len = size_of::<xdp_mmap_offsets>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            sys::SOL_XDP,
+            sys::XDP_MMAP_OFFSETS,
+            &mut offsets as *mut xdp_mmap_offsets as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    match result {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(offsets),
+    }
+}
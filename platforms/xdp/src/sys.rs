@@ -0,0 +1,238 @@
+//! Definitions of `linux/if_xdp.h` and `linux/bpf.h`, that are not exported by libc, and
+//! safe wrappers of the calls, that use them.
+#![allow(non_camel_case_types)]
+#![allow(unsafe_code)]
+
+use netconfig::sys::posix::ifreq::ifreq;
+use std::io;
+use std::mem::size_of;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+pub const AF_XDP: libc::c_int = 44;
+pub const SOL_XDP: libc::c_int = 283;
+
+// Socket options
+pub const XDP_MMAP_OFFSETS: libc::c_int = 1;
+pub const XDP_RX_RING: libc::c_int = 2;
+pub const XDP_TX_RING: libc::c_int = 3;
+pub const XDP_UMEM_REG: libc::c_int = 4;
+pub const XDP_UMEM_FILL_RING: libc::c_int = 5;
+pub const XDP_UMEM_COMPLETION_RING: libc::c_int = 6;
+
+// Offsets of rings for mmap
+pub const XDP_PGOFF_RX_RING: libc::off_t = 0;
+pub const XDP_PGOFF_TX_RING: libc::off_t = 0x80000000;
+pub const XDP_UMEM_PGOFF_FILL_RING: libc::off_t = 0x100000000;
+pub const XDP_UMEM_PGOFF_COMPLETION_RING: libc::off_t = 0x180000000;
+
+// Bind flags
+pub const XDP_ZEROCOPY: u16 = 1 << 2;
+pub const XDP_USE_NEED_WAKEUP: u16 = 1 << 3;
+
+/// Ring flag, set by the kernel, when it waits for a syscall to process the ring.
+pub const XDP_RING_NEED_WAKEUP: u32 = 1 << 0;
+
+#[repr(C)]
+pub struct sockaddr_xdp {
+    pub sxdp_family: u16,
+    pub sxdp_flags: u16,
+    pub sxdp_ifindex: u32,
+    pub sxdp_queue_id: u32,
+    pub sxdp_shared_umem_fd: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct xdp_ring_offset {
+    pub producer: u64,
+    pub consumer: u64,
+    pub desc: u64,
+    pub flags: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct xdp_mmap_offsets {
+    pub rx: xdp_ring_offset,
+    pub tx: xdp_ring_offset,
+    pub fr: xdp_ring_offset,
+    pub cr: xdp_ring_offset,
+}
+
+/// First version of the structure, accepted by all kernels with AF_XDP.
+#[repr(C)]
+pub struct xdp_umem_reg {
+    pub addr: u64,
+    pub len: u64,
+    pub chunk_size: u32,
+    pub headroom: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct xdp_desc {
+    pub addr: u64,
+    pub len: u32,
+    pub options: u32,
+}
+
+// BPF commands
+const BPF_MAP_CREATE: libc::c_int = 0;
+const BPF_MAP_UPDATE_ELEM: libc::c_int = 2;
+const BPF_PROG_LOAD: libc::c_int = 5;
+const BPF_LINK_CREATE: libc::c_int = 28;
+
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+const BPF_PROG_TYPE_XDP: u32 = 6;
+const BPF_XDP: u32 = 37;
+
+pub const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+pub const XDP_FLAGS_DRV_MODE: u32 = 1 << 2;
+
+#[repr(C)]
+pub struct bpf_insn {
+    pub code: u8,
+    /// Destination register in the low nibble, source register in the high one.
+    pub regs: u8,
+    pub off: i16,
+    pub imm: i32,
+}
+
+#[repr(C)]
+struct MapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+}
+
+#[repr(C)]
+struct MapUpdateAttr {
+    map_fd: u32,
+    _pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+}
+
+#[repr(C)]
+struct LinkCreateAttr {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+mod ioctls {
+    nix::ioctl_read_bad!(
+        siocgifflags,
+        libc::SIOCGIFFLAGS,
+        netconfig::sys::posix::ifreq::ifreq
+    );
+    nix::ioctl_write_ptr_bad!(
+        siocsifflags,
+        libc::SIOCSIFFLAGS,
+        netconfig::sys::posix::ifreq::ifreq
+    );
+}
+
+/// Size of memory pages, that UMEM frames must fit into.
+pub fn page_size() -> u32 {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as u32 }
+}
+
+/// Replaces the flags of the interface with `update` of them, with ioctls on a configuration
+/// socket.
+pub fn update_if_flags(
+    name: &str,
+    update: impl FnOnce(libc::c_short) -> libc::c_short,
+) -> io::Result<()> {
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if socket < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: descriptor is valid and owned by nothing else
+    let socket = unsafe { OwnedFd::from_raw_fd(socket) };
+
+    let mut req = ifreq::new(name);
+    // SAFETY: requests read and write whole ifreq structures, and flags are the member of the
+    // union, that SIOCGIFFLAGS fills
+    unsafe { ioctls::siocgifflags(socket.as_raw_fd(), &mut req) }?;
+    req.ifr_ifru.ifru_flags = update(unsafe { req.ifr_ifru.ifru_flags });
+    unsafe { ioctls::siocsifflags(socket.as_raw_fd(), &req) }?;
+    Ok(())
+}
+
+/// Runs a BPF command. Kernel accepts attributes, that are shorter than its `bpf_attr`.
+unsafe fn bpf<T>(cmd: libc::c_int, attr: &T) -> io::Result<libc::c_long> {
+    let result = libc::syscall(libc::SYS_bpf, cmd, attr as *const T, size_of::<T>() as u32);
+    match result {
+        -1 => Err(io::Error::last_os_error()),
+        result => Ok(result),
+    }
+}
+
+unsafe fn bpf_fd<T>(cmd: libc::c_int, attr: &T) -> io::Result<OwnedFd> {
+    let fd = bpf(cmd, attr)?;
+    Ok(OwnedFd::from_raw_fd(fd as RawFd))
+}
+
+pub fn create_xsk_map(max_entries: u32) -> io::Result<OwnedFd> {
+    let attr = MapCreateAttr {
+        map_type: BPF_MAP_TYPE_XSKMAP,
+        key_size: 4,
+        value_size: 4,
+        max_entries,
+        map_flags: 0,
+    };
+    unsafe { bpf_fd(BPF_MAP_CREATE, &attr) }
+}
+
+pub fn update_map(map: RawFd, key: u32, value: u32) -> io::Result<()> {
+    let attr = MapUpdateAttr {
+        map_fd: map as u32,
+        _pad: 0,
+        key: &key as *const u32 as u64,
+        value: &value as *const u32 as u64,
+        flags: 0,
+    };
+    unsafe { bpf(BPF_MAP_UPDATE_ELEM, &attr) }.map(drop)
+}
+
+pub fn load_xdp_program(insns: &[bpf_insn]) -> io::Result<OwnedFd> {
+    // Redirect helpers are available to GPL-compatible programs only
+    let license = b"GPL\0";
+    let attr = ProgLoadAttr {
+        prog_type: BPF_PROG_TYPE_XDP,
+        insn_cnt: insns.len() as u32,
+        insns: insns.as_ptr() as u64,
+        license: license.as_ptr() as u64,
+        log_level: 0,
+        log_size: 0,
+        log_buf: 0,
+    };
+    unsafe { bpf_fd(BPF_PROG_LOAD, &attr) }
+}
+
+/// Attaches an XDP program to an interface. Program is detached, when the link is closed.
+pub fn attach_xdp(prog: RawFd, ifindex: u32, flags: u32) -> io::Result<OwnedFd> {
+    let attr = LinkCreateAttr {
+        prog_fd: prog as u32,
+        target_ifindex: ifindex,
+        attach_type: BPF_XDP,
+        flags,
+    };
+    unsafe { bpf_fd(BPF_LINK_CREATE, &attr) }
+}
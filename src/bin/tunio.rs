@@ -0,0 +1,294 @@
+//! Device management tool, built on the library APIs: creates, deletes and lists interfaces,
+//! configures addresses and MTU, dumps packets to pcap and measures loopback throughput.
+use clap::{Arg, ArgMatches, Command};
+use netconfig::ipnet::IpNet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tunio::platform::mock;
+use tunio::platform::mock::pcap::PcapWriter;
+use tunio::simple::Options;
+use tunio::traits::{DriverT, InterfaceT, MAX_PACKET_LEN};
+use tunio::{DefaultDriver, DefaultInterface, Layer, NameConflict};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() {
+    let matches = cli().get_matches();
+    let result = match matches.subcommand() {
+        Some(("list", _)) => list(),
+        Some(("create", args)) => create(args),
+        Some(("delete", args)) => delete(args),
+        Some(("address", args)) => address(args),
+        Some(("mtu", args)) => mtu(args),
+        Some(("dump", args)) => dump(args),
+        Some(("bench", args)) => bench(args),
+        _ => unreachable!("subcommand is required"),
+    };
+    if let Err(err) = result {
+        eprintln!("tunio: {err}");
+        process::exit(1);
+    }
+}
+
+fn cli() -> Command<'static> {
+    let name = || Arg::new("name").required(true).help("Interface name");
+    let tap = || Arg::new("tap").long("tap").help("TAP (layer 2) interface");
+
+    Command::new("tunio")
+        .about("Manages TUN/TAP interfaces")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(Command::new("list").about("Lists interfaces with MTU and addresses"))
+        .subcommand(
+            Command::new("create")
+                .about("Creates an interface and keeps it until Enter is pressed")
+                .arg(name())
+                .arg(tap())
+                .arg(
+                    Arg::new("mtu")
+                        .long("mtu")
+                        .takes_value(true)
+                        .default_value("1500"),
+                )
+                .arg(
+                    Arg::new("address")
+                        .long("address")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .help("Address with prefix length, like 10.0.0.1/24"),
+                )
+                .arg(
+                    Arg::new("persist")
+                        .long("persist")
+                        .help("Keep the interface after exit (Linux only)"),
+                ),
+        )
+        .subcommand(
+            Command::new("delete")
+                .about("Deletes a persistent interface (Linux only)")
+                .arg(name())
+                .arg(tap()),
+        )
+        .subcommand(
+            Command::new("address")
+                .about("Adds or removes an address")
+                .arg(name())
+                .arg(
+                    Arg::new("action")
+                        .required(true)
+                        .possible_values(["add", "remove"]),
+                )
+                .arg(Arg::new("address").required(true)),
+        )
+        .subcommand(
+            Command::new("mtu")
+                .about("Sets MTU")
+                .arg(name())
+                .arg(Arg::new("mtu").required(true)),
+        )
+        .subcommand(
+            Command::new("dump")
+                .about("Writes packets, read from an interface, to a pcap file")
+                .arg(name())
+                .arg(Arg::new("file").required(true))
+                .arg(tap())
+                .arg(
+                    Arg::new("count")
+                        .long("count")
+                        .takes_value(true)
+                        .help("Stop after this number of packets"),
+                ),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Measures throughput over a pair of mock interfaces")
+                .arg(
+                    Arg::new("packets")
+                        .long("packets")
+                        .takes_value(true)
+                        .default_value("100000"),
+                )
+                .arg(
+                    Arg::new("size")
+                        .long("size")
+                        .takes_value(true)
+                        .default_value("1500"),
+                ),
+        )
+}
+
+fn layer(args: &ArgMatches) -> Layer {
+    match args.is_present("tap") {
+        true => Layer::L2,
+        false => Layer::L3,
+    }
+}
+
+fn parse<T>(args: &ArgMatches, name: &str) -> Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: Error + 'static,
+{
+    let value = args.value_of(name).ok_or(format!("{name} is missing"))?;
+    value
+        .parse()
+        .map_err(|err| format!("invalid {name} {value}: {err}").into())
+}
+
+fn handle(args: &ArgMatches) -> Result<netconfig::Interface> {
+    let name = args.value_of("name").unwrap_or_default();
+    Ok(netconfig::Interface::try_from_name(name)?)
+}
+
+fn list() -> Result<()> {
+    for interface in netconfig::list_interfaces()? {
+        let addresses = interface.addresses().unwrap_or_default();
+        let addresses: Vec<_> = addresses.iter().map(IpNet::to_string).collect();
+        println!(
+            "{}: {} mtu {} {}",
+            interface.index()?,
+            interface.name()?,
+            interface.mtu()?,
+            addresses.join(" ")
+        );
+    }
+    Ok(())
+}
+
+fn create(args: &ArgMatches) -> Result<()> {
+    let mut options = Options::new().layer(layer(args)).mtu(parse(args, "mtu")?);
+    for address in args.values_of("address").into_iter().flatten() {
+        options = options.address(address.parse()?);
+    }
+    let name = args.value_of("name").unwrap_or_default();
+    #[allow(unused_mut)]
+    let mut stream = options.open(name)?;
+    println!("{}", stream.name()?);
+
+    if args.is_present("persist") {
+        #[cfg(target_os = "linux")]
+        return Ok(stream.interface().set_persistent(true)?);
+        #[cfg(not(target_os = "linux"))]
+        return Err("persistent interfaces are only supported on Linux".into());
+    }
+    eprintln!("Press Enter to remove the interface");
+    io::stdin().read_line(&mut String::new())?;
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn delete(args: &ArgMatches) -> Result<()> {
+    use tunio::NameOutcome;
+
+    let mut driver = DefaultDriver::new()?;
+    let params = DefaultInterface::config_builder()
+        .name(args.value_of("name").unwrap_or_default().to_string())
+        .layer(layer(args))
+        .name_conflict(NameConflict::Adopt)
+        .build()?;
+    // Interface, that did not exist, is removed on drop as well
+    let mut interface = DefaultInterface::new(&mut driver, params)?;
+    if interface.name_outcome() != NameOutcome::Adopted {
+        return Err(format!("interface {} does not exist", interface.name()).into());
+    }
+    Ok(interface.set_persistent(false)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn delete(_args: &ArgMatches) -> Result<()> {
+    Err("persistent interfaces are only supported on Linux".into())
+}
+
+fn address(args: &ArgMatches) -> Result<()> {
+    let interface = handle(args)?;
+    let address = parse(args, "address")?;
+    match args.value_of("action") {
+        Some("add") => interface.add_address(address)?,
+        _ => interface.remove_address(address)?,
+    }
+    Ok(())
+}
+
+fn mtu(args: &ArgMatches) -> Result<()> {
+    Ok(handle(args)?.set_mtu(parse(args, "mtu")?)?)
+}
+
+/// Captures packets of an interface, that is created for the dump or adopted, if it is
+/// persistent and not in use.
+fn dump(args: &ArgMatches) -> Result<()> {
+    let layer = layer(args);
+    let count = match args.is_present("count") {
+        true => Some(parse::<u64>(args, "count")?),
+        false => None,
+    };
+    let mut driver = DefaultDriver::new()?;
+    let params = DefaultInterface::config_builder()
+        .name(args.value_of("name").unwrap_or_default().to_string())
+        .layer(layer)
+        .name_conflict(NameConflict::Adopt)
+        .build()?;
+    let mut interface = DefaultInterface::new_up(&mut driver, params)?;
+
+    let file = File::create(args.value_of("file").unwrap_or_default())?;
+    let mut pcap = PcapWriter::new(BufWriter::new(file), layer)?;
+    let mut buf = vec![0u8; MAX_PACKET_LEN];
+    let mut captured = 0;
+    while count.map_or(true, |count| captured < count) {
+        let n = interface.read(&mut buf)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        pcap.write_packet(timestamp, &buf[..n])?;
+        // Capture stays readable, when the tool is interrupted
+        pcap.flush()?;
+        captured += 1;
+    }
+    Ok(())
+}
+
+fn bench(args: &ArgMatches) -> Result<()> {
+    let packets: usize = parse(args, "packets")?;
+    let size: usize = parse(args, "size")?;
+    if size == 0 {
+        return Err("packet size must not be zero".into());
+    }
+
+    let mut driver = mock::Driver::new()?;
+    let config = |name: &str| {
+        mock::Interface::config_builder()
+            .name(name.to_string())
+            .build()
+    };
+    let (mut a, mut b) =
+        mock::Interface::new_pair(&mut driver, config("mock0")?, config("mock1")?)?;
+    a.up()?;
+    b.up()?;
+
+    let start = Instant::now();
+    let writer = thread::spawn(move || -> io::Result<()> {
+        let packet = vec![0u8; size];
+        for _ in 0..packets {
+            a.write_all(&packet)?;
+        }
+        Ok(())
+    });
+    let mut buf = vec![0u8; size];
+    let mut received = 0;
+    for _ in 0..packets {
+        received += b.read(&mut buf)?;
+    }
+    let elapsed = start.elapsed();
+    writer.join().map_err(|_| "writer thread panicked")??;
+
+    let seconds = elapsed.as_secs_f64();
+    println!(
+        "{packets} packets of {size} bytes in {elapsed:?}: {:.0} pps, {:.1} Mbit/s",
+        packets as f64 / seconds,
+        received as f64 * 8.0 / 1_000_000.0 / seconds
+    );
+    Ok(())
+}
@@ -1,11 +1,37 @@
 #![doc = include_str!("../README.md")]
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 pub mod platform;
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+pub mod simple;
+pub mod socket;
 
+pub use tunio_core::coalesce::ReadCoalescing;
 pub use tunio_core::config::*;
+pub use tunio_core::device::DeviceInfo;
+pub use tunio_core::pause::PauseHandle;
 pub use tunio_core::Error;
 
+#[cfg(all(target_os = "linux", feature = "helper"))]
+pub use tunio_linux::helper;
+#[cfg(target_os = "linux")]
+pub use tunio_linux::{profile, syscall_profile};
+
+pub use tunio_core::address;
 pub use tunio_core::config;
+pub use tunio_core::device;
+pub use tunio_core::diagnostics;
+pub use tunio_core::egress;
+pub use tunio_core::events;
+pub use tunio_core::framed;
+pub use tunio_core::guard;
+pub use tunio_core::hooks;
+pub use tunio_core::hotswap;
+pub use tunio_core::packet;
+pub use tunio_core::plugin;
+pub use tunio_core::snapshot;
+pub use tunio_core::stats;
+#[cfg(feature = "test-util")]
+pub use tunio_core::test_util;
 pub use tunio_core::traits;
 
 cfg_if::cfg_if! {
@@ -25,3 +51,47 @@ cfg_if::cfg_if! {
         pub type DefaultAsyncInterface = platform::utun::TokioInterface;
     }
 }
+
+/// Longest interface name of the default interface type. Longer names fail or are truncated,
+/// depending on [`IfConfig::name_overflow`].
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+pub fn max_name_len() -> usize {
+    use traits::InterfaceT;
+    DefaultInterface::max_name_len()
+}
+
+/// Lists TUN/TAP devices, that exist on the system, including ones, created by other
+/// processes, with the default driver of the platform. Persistent devices may be adopted with
+/// [`NameConflict::Adopt`].
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+pub fn enumerate() -> Result<Vec<DeviceInfo>, Error> {
+    use traits::DriverT;
+    DefaultDriver::new()?.enumerate()
+}
+
+/// Lists devices, created with [`IfConfig::tag`] set to `tag`.
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+pub fn enumerate_tagged(tag: &str) -> Result<Vec<DeviceInfo>, Error> {
+    let mut devices = enumerate()?;
+    devices.retain(|device| device.tag.as_deref() == Some(tag));
+    Ok(devices)
+}
+
+/// Removes every device, created with [`IfConfig::tag`] set to `tag`, for crash recovery and
+/// uninstallation, and returns the removed devices.
+///
+/// Persistent devices on Linux outlive their creator and are removed here. Wintun adapters
+/// and utun devices are removed by the system, when the creating process exits, so tagged
+/// devices, that still exist, belong to running processes, and removal fails with
+/// [`Unsupported`](std::io::ErrorKind::Unsupported).
+#[cfg(any(target_os = "windows", target_os = "linux", target_os = "macos"))]
+pub fn cleanup(tag: &str) -> Result<Vec<DeviceInfo>, Error> {
+    use traits::DriverT;
+    let driver = DefaultDriver::new()?;
+    let mut devices = driver.enumerate()?;
+    devices.retain(|device| device.tag.as_deref() == Some(tag));
+    for device in &devices {
+        driver.delete(device)?;
+    }
+    Ok(devices)
+}
@@ -2,6 +2,10 @@
 pub mod linux {
     pub use tunio_linux::*;
 }
+#[cfg(all(target_os = "linux", feature = "xdp"))]
+pub mod xdp {
+    pub use tunio_xdp::*;
+}
 #[cfg(target_os = "macos")]
 pub mod utun {
     pub use tunio_utun::*;
@@ -10,3 +14,11 @@ pub mod utun {
 pub mod wintun {
     pub use tunio_wintun::*;
 }
+#[cfg(feature = "mock")]
+pub mod mock {
+    pub use tunio_mock::*;
+}
+#[cfg(feature = "null")]
+pub mod null {
+    pub use tunio_null::*;
+}
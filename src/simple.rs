@@ -0,0 +1,137 @@
+//! Batteries-included facade for the common case: a single interface with addresses, that is
+//! opened, configured and brought up in one call.
+//!
+//! ```no_run
+//! use std::io::{Read, Write};
+//!
+//! let mut stream = tunio::simple::Options::new()
+//!     .address("10.0.0.1/24".parse().unwrap())
+//!     .open("tun0")?;
+//! let mut buf = [0u8; 1500];
+//! let n = stream.read(&mut buf)?;
+//! stream.write_all(&buf[..n])?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! Use drivers and interfaces directly for multiple queues, async I/O or platform options.
+use crate::traits::{DriverT, InterfaceT};
+use crate::{DefaultDriver, DefaultInterface, Layer};
+use netconfig::ipnet::IpNet;
+use std::io::{self, Read, Write};
+use tunio_core::Error;
+
+/// MTU of Ethernet, which is the common default of physical interfaces, so that tunneled
+/// traffic follows the same path MTU expectations.
+pub const DEFAULT_MTU: u32 = 1500;
+
+/// Opens a layer 3 interface without addresses and with [`DEFAULT_MTU`], and brings it up.
+pub fn open(name: &str) -> Result<PacketStream, Error> {
+    Options::new().open(name)
+}
+
+/// Options of [`open`](Options::open). Defaults are the same as of [`open`](self::open).
+#[derive(Debug, Clone)]
+pub struct Options {
+    layer: Layer,
+    mtu: u32,
+    addresses: Vec<IpNet>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            layer: Layer::L3,
+            mtu: DEFAULT_MTU,
+            addresses: vec![],
+        }
+    }
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layer(mut self, layer: Layer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn mtu(mut self, mtu: u32) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// Adds an address with prefix length, like `10.0.0.1/24`. May be called repeatedly.
+    pub fn address(mut self, address: IpNet) -> Self {
+        self.addresses.push(address);
+        self
+    }
+
+    /// Creates the interface, sets MTU and addresses and brings it up.
+    pub fn open(&self, name: &str) -> Result<PacketStream, Error> {
+        let mut driver = DefaultDriver::new()?;
+        let params = DefaultInterface::config_builder()
+            .name(name.to_string())
+            .layer(self.layer)
+            .build()
+            .map_err(|err| Error::InvalidConfigValue {
+                name: "name".to_string(),
+                value: name.to_string(),
+                reason: err.to_string(),
+            })?;
+        let mut interface = DefaultInterface::new(&mut driver, params)?;
+
+        let handle = interface.handle();
+        handle.set_mtu(self.mtu)?;
+        for address in &self.addresses {
+            handle.add_address(*address)?;
+        }
+        interface.up()?;
+
+        Ok(PacketStream {
+            interface,
+            _driver: driver,
+        })
+    }
+}
+
+/// Blocking packet stream of an interface, opened by [`open`]. Every read returns a single
+/// packet, and every write sends a single packet. Interface is removed on drop.
+pub struct PacketStream {
+    interface: DefaultInterface,
+    // Keeps the driver loaded, while the interface exists
+    _driver: DefaultDriver,
+}
+
+impl PacketStream {
+    /// Returns the name, assigned by the OS, which may differ from the requested one.
+    pub fn name(&self) -> Result<String, Error> {
+        Ok(self.interface.handle().name()?)
+    }
+
+    /// Handle for further configuration, like adding routes.
+    pub fn handle(&self) -> netconfig::Interface {
+        self.interface.handle()
+    }
+
+    pub fn interface(&mut self) -> &mut DefaultInterface {
+        &mut self.interface
+    }
+}
+
+impl Read for PacketStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interface.read(buf)
+    }
+}
+
+impl Write for PacketStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.interface.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.interface.flush()
+    }
+}
@@ -0,0 +1,47 @@
+//! Helpers for binding sockets to a physical interface.
+//!
+//! Traffic of a tunnel transport itself must bypass the tunnel, or it loops back into the
+//! interface once default route is installed. Binding the transport socket to the physical
+//! interface prevents it regardless of the routing table.
+pub use tunio_core::socket::SocketFamily;
+use tunio_core::Error;
+
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(windows)]
+use std::os::windows::io::AsRawSocket;
+
+/// Binds `socket` to the interface with OS index `index`.
+///
+/// Uses `SO_BINDTODEVICE` on Linux (requires `CAP_NET_RAW`), `IP_BOUND_IF`/`IPV6_BOUND_IF` on
+/// macOS and `IP_UNICAST_IF`/`IPV6_UNICAST_IF` on Windows.
+#[cfg(unix)]
+pub fn bind_to_interface<S: AsRawFd>(
+    socket: &S,
+    index: u32,
+    family: SocketFamily,
+) -> Result<(), Error> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            crate::platform::linux::bind_to_interface(socket.as_raw_fd(), index, family)
+        } else if #[cfg(target_os = "macos")] {
+            crate::platform::utun::bind_to_interface(socket.as_raw_fd(), index, family)
+        } else {
+            let _ = (socket, index, family);
+            Err(std::io::Error::from(std::io::ErrorKind::Unsupported).into())
+        }
+    }
+}
+
+/// Binds `socket` to the interface with OS index `index`.
+///
+/// Uses `SO_BINDTODEVICE` on Linux (requires `CAP_NET_RAW`), `IP_BOUND_IF`/`IPV6_BOUND_IF` on
+/// macOS and `IP_UNICAST_IF`/`IPV6_UNICAST_IF` on Windows.
+#[cfg(windows)]
+pub fn bind_to_interface<S: AsRawSocket>(
+    socket: &S,
+    index: u32,
+    family: SocketFamily,
+) -> Result<(), Error> {
+    crate::platform::wintun::bind_to_interface(socket.as_raw_socket(), index, family)
+}
@@ -1,4 +1,6 @@
+#[cfg(target_os = "windows")]
 use std::env;
+#[cfg(target_os = "windows")]
 use std::path::PathBuf;
 
 fn main() {
@@ -6,8 +8,11 @@ fn main() {
 
     #[cfg(target_os = "windows")]
     {
+        // Layouts must match the target, when cross-compiling, for example to ARM64 from x64
+        let target = env::var("TARGET").unwrap();
         let bindings = bindgen::Builder::default()
             .header("wintun/wintun_functions.h")
+            .clang_arg(format!("--target={target}"))
             .parse_callbacks(Box::new(bindgen::CargoCallbacks))
             .allowlist_function("Wintun.*")
             .allowlist_type("WINTUN_.*")